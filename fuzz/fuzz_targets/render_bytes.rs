@@ -0,0 +1,22 @@
+#![no_main]
+//! Feeds arbitrary bytes through decoding and every render mode, run with
+//!
+//!     cargo +nightly fuzz run render_bytes
+use libfuzzer_sys::fuzz_target;
+use wellenformer::render::{self, RenderMode, RenderOptions, TimeScale};
+
+fuzz_target!(|data: &[u8]| {
+    for mode in [RenderMode::MinMax, RenderMode::Mean, RenderMode::Asymmetry, RenderMode::Line, RenderMode::Dots, RenderMode::Bars, RenderMode::Envelope, RenderMode::Radial, RenderMode::Multiband, RenderMode::Density, RenderMode::Vectorscope, RenderMode::Spectrogram, RenderMode::Waterfall, RenderMode::Chroma, RenderMode::Loudness, RenderMode::Filmstrip] {
+        let options = RenderOptions {
+            width: 64,
+            height: 16,
+            oversample: 2,
+            sample_rate: 44100,
+            normalize: true,
+            time_scale: TimeScale::Log,
+            rms: Some(image::Rgba([0, 0, 255, 255])),
+            ..RenderOptions::new(mode)
+        };
+        let _ = render::render_bytes(data, &options);
+    }
+});
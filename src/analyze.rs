@@ -0,0 +1,350 @@
+use std::path::PathBuf;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+
+use crate::audio::{read_audio, Audio};
+use crate::bits;
+use crate::compare;
+use crate::describe;
+use crate::ir;
+use crate::json::Json;
+use crate::number::{parse_number, parse_ratio};
+use crate::segment;
+use crate::sine;
+use crate::spectrum;
+
+
+/// Analyze an audio file instead of rendering it
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Path of the audio file that should be analyzed
+    input: PathBuf,
+
+    /// Write suggested chapter starts (long pauses, music beds) to this file
+    #[arg(long, env = "WELLENFORMER_SUGGEST_CHAPTERS")]
+    suggest_chapters: Option<PathBuf>,
+
+    /// Format of the suggested chapters file
+    #[arg(long, value_enum, default_value_t = ChapterFormat::Podcast, env = "WELLENFORMER_CHAPTER_FORMAT")]
+    chapter_format: ChapterFormat,
+
+    /// Minimum length of a pause that starts a new chapter
+    #[arg(long, value_parser = crate::parse_duration, default_value = "2s", env = "WELLENFORMER_MIN_PAUSE")]
+    min_pause: f64,
+
+    /// Minimum length of a suggested chapter
+    #[arg(long, value_parser = crate::parse_duration, default_value = "30s", env = "WELLENFORMER_MIN_CHAPTER")]
+    min_chapter: f64,
+
+    /// Print a short description of the structure of the recording (length,
+    /// loud and quiet sections, silent gaps), e.g. to use as alt text
+    #[arg(long, env = "WELLENFORMER_DESCRIBE")]
+    describe: bool,
+
+    /// Undo CD pre-emphasis before analyzing
+    #[arg(long, env = "WELLENFORMER_DEEMPHASIS")]
+    deemphasis: bool,
+
+    /// Check the polarity of and the delay to this reference recording
+    #[arg(long, env = "WELLENFORMER_REFERENCE")]
+    reference: Option<PathBuf>,
+
+    /// Largest delay between channels (or to the reference) that is searched
+    #[arg(long, value_parser = crate::parse_duration, default_value = "50ms", env = "WELLENFORMER_MAX_DELAY")]
+    max_delay: f64,
+
+    /// Treat the input as an impulse response and report EDT, T20, T30 and RT60
+    #[arg(long, env = "WELLENFORMER_IR")]
+    ir: bool,
+
+    /// Plot the energy-time curve and the decay curve of the impulse response
+    /// to this png (implies --ir)
+    #[arg(long, env = "WELLENFORMER_ETC")]
+    etc: Option<PathBuf>,
+
+    /// Treat the input as a recording of a test tone at this frequency (Hz)
+    /// and report THD+N, THD, frequency error and level
+    #[arg(long, value_parser = parse_number, env = "WELLENFORMER_SINE")]
+    sine: Option<f64>,
+
+    /// Plot the spectrum of what is left after removing the test tone to this png
+    #[arg(long, requires = "sine", env = "WELLENFORMER_RESIDUAL_SPECTRUM")]
+    residual_spectrum: Option<PathBuf>,
+
+    /// Plot the smoothed frequency response measured from a sweep or noise
+    /// recording to this png
+    #[arg(long, env = "WELLENFORMER_FREQUENCY_RESPONSE")]
+    frequency_response: Option<PathBuf>,
+
+    /// Spectrum of the signal used for the measurement, pink (and log sweeps)
+    /// is compensated by +3 dB per octave
+    #[arg(long, value_enum, default_value_t = Excitation::Pink, env = "WELLENFORMER_EXCITATION")]
+    excitation: Excitation,
+
+    /// Width of the smoothing of the frequency response in octaves (e.g. "1/3")
+    #[arg(long, value_parser = parse_ratio, default_value = "1/6", env = "WELLENFORMER_SMOOTHING")]
+    smoothing: f64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Excitation {
+    /// Pink noise or a logarithmic sweep
+    Pink,
+    /// White noise or a linear sweep
+    White,
+}
+
+/// Number of samples per frame of the frequency response measurement
+const RESPONSE_FRAME: usize = 1 << 14;
+
+/// Correlation below which a signal is reported as polarity inverted
+const INVERTED_CORRELATION: f64 = -0.3;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChapterFormat {
+    /// JSON document listing the chapter starts with the reason for each
+    Json,
+    /// JSON chapters file as defined by the Podcast namespace
+    Podcast,
+}
+
+pub fn analyze(args: &AnalyzeArgs) {
+    crate::ensure_input_file(&args.input);
+    let Audio { channels, sample_rate, bits_per_sample, pre_emphasis, mut samples, .. } = read_audio(&args.input);
+    if args.deemphasis {
+        crate::audio::deemphasize(&mut samples, channels, sample_rate);
+    } else if pre_emphasis {
+        crate::hint_pre_emphasis();
+    }
+    let frames = samples.len() / channels.max(1);
+    let duration = frames as f64 / sample_rate.max(1) as f64;
+
+    let (peak, rms) = levels(&samples);
+
+    println!("{}", args.input.display().to_string().bold());
+    println!("Duration:    {:.3}s", duration);
+    println!("Channels:    {}", channels);
+    println!("Sample Rate: {} Hz", sample_rate);
+    println!("Emphasis:    {}", match (pre_emphasis, args.deemphasis) {
+        (_, true) => "removed (--deemphasis)",
+        (true, false) => "flagged as pre-emphasized",
+        (false, false) => "none flagged",
+    });
+    println!("Peak:        {:.2} dBFS", to_dbfs(peak));
+    println!("RMS:         {:.2} dBFS", to_dbfs(rms));
+
+    if let Some(container_bits) = bits_per_sample {
+        let effective = bits::effective_bit_depth(&samples, container_bits);
+        let dither = match bits::detect_dither(&samples, channels, effective) {
+            bits::Dither::Likely => "likely".green(),
+            bits::Dither::Unlikely => "unlikely (truncated or digital silence)".yellow(),
+            bits::Dither::Unknown => "unknown (no quiet passages)".normal(),
+        };
+        if effective < container_bits {
+            println!("Bit Depth:   {} bit in a {} bit container", effective, container_bits);
+        } else {
+            println!("Bit Depth:   {} bit", container_bits);
+        }
+        println!("Dither:      {}", dither);
+    }
+
+    // Polarity and delay of every channel relative to the first one
+    let max_lag = (args.max_delay * sample_rate as f64) as usize;
+    let first = compare::channel(&samples, channels, 0);
+    for c in 1..channels {
+        let other = compare::channel(&samples, channels, c);
+        let (lag, correlation) = compare::strongest_correlation(&first, &other, max_lag);
+        report_alignment(&format!("Channel {} vs. 1", c + 1), lag, correlation, sample_rate);
+    }
+
+    if let Some(path) = &args.reference {
+        crate::ensure_input_file(path);
+        let Audio { channels: reference_channels, sample_rate: reference_rate, samples: reference, .. } = read_audio(path);
+        if reference_rate != sample_rate {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}Cannot compare files with different sample rates ({} Hz vs {} Hz)", sample_rate, reference_rate);
+            std::process::exit(1);
+        }
+        let a = compare::downmix(&reference, reference_channels);
+        let b = compare::downmix(&samples, channels);
+        let (lag, correlation) = compare::strongest_correlation(&a, &b, max_lag);
+        report_alignment("Input vs. reference", lag, correlation, sample_rate);
+    }
+
+    if args.ir || args.etc.is_some() {
+        let energy = ir::energy_from_peak(&compare::downmix(&samples, channels));
+        let curve = ir::energy_decay_curve(&energy);
+        let reverb = ir::reverberation(&curve, sample_rate);
+        let format = |t: Option<f64>| match t {
+            Some(t) => format!("{:.3}s", t),
+            None => "n/a (decay too short)".to_string(),
+        };
+        println!("EDT:         {}", format(reverb.edt));
+        println!("T20:         {}", format(reverb.t20));
+        println!("T30:         {}", format(reverb.t30));
+        println!("RT60:        {}", format(reverb.rt60()));
+
+        if let Some(path) = &args.etc {
+            let colors = [image::Rgba([128, 128, 128, 255]), image::Rgba([255, 0, 0, 255]), image::Rgba([255, 255, 255, 255])];
+            let img = ir::render_etc(&energy, &curve, 1920, 480, -80.0, colors);
+            crate::output::save_image(&img, path);
+            println!("Saved energy-time curve to \"{}\"", path.display());
+        }
+    }
+
+    if let Some(expected) = args.sine {
+        let mono = compare::downmix(&samples, channels);
+        let measurement = match sine::measure(&mono, sample_rate, expected) {
+            Ok(m) => m,
+            Err(e) => {
+                let error = "Error: ".bold().red();
+                eprintln!("{error}{e}");
+                std::process::exit(1);
+            }
+        };
+        let error_hz = measurement.frequency - expected;
+        println!("Frequency:   {:.3} Hz ({:+.3} Hz, {:+.1} ppm)", measurement.frequency, error_hz, error_hz / expected * 1e6);
+        println!("Level:       {:.2} dBFS", measurement.level);
+        println!("THD+N:       {:.4}% ({:.1} dB)", measurement.thd_n * 100.0, to_dbfs(measurement.thd_n));
+        println!("THD:         {:.4}% ({:.1} dB)", measurement.thd * 100.0, to_dbfs(measurement.thd));
+
+        if let Some(path) = &args.residual_spectrum {
+            let nyquist = sample_rate as f64 / 2.0;
+            let colors = (image::Rgba([0, 0, 0, 255]), image::Rgba([255, 255, 255, 255]));
+            let img = spectrum::render_spectrum(&measurement.residual, sample_rate, measurement.fundamental_power, (20.0, nyquist), (1920, 480), -160.0, colors);
+            crate::output::save_image(&img, path);
+            println!("Saved residual spectrum to \"{}\"", path.display());
+        }
+    }
+
+    if let Some(path) = &args.frequency_response {
+        let mono = compare::downmix(&samples, channels);
+        let window = spectrum::hann(RESPONSE_FRAME);
+        let mut power = spectrum::averaged_power_spectrum(&mono, &window, RESPONSE_FRAME / 2);
+        if args.excitation == Excitation::Pink {
+            // Pink excitation loses 3 dB per octave, so give it back
+            for (bin, p) in power.iter_mut().enumerate() {
+                *p *= bin as f64;
+            }
+        }
+        let smoothed = spectrum::smooth_octaves(&power, args.smoothing);
+        let bin_width = sample_rate as f64 / RESPONSE_FRAME as f64;
+        let first = (20.0 / bin_width) as usize;
+        let reference = smoothed[first..].iter().fold(0.0f64, |a, &b| a.max(b));
+        let nyquist = sample_rate as f64 / 2.0;
+        let colors = (image::Rgba([0, 0, 0, 255]), image::Rgba([255, 255, 255, 255]));
+        let img = spectrum::render_spectrum(&smoothed, sample_rate, reference, (20.0, nyquist), (1920, 480), -60.0, colors);
+        crate::output::save_image(&img, path);
+        println!("Saved frequency response to \"{}\"", path.display());
+    }
+
+    if args.describe {
+        println!("Description: {}", describe::describe(&samples, channels, sample_rate));
+    }
+
+    if let Some(path) = &args.suggest_chapters {
+        let regions = segment::segment(&samples, channels, sample_rate);
+        let chapters = segment::suggest_chapters(&regions, args.min_pause, args.min_chapter);
+        let json = match args.chapter_format {
+            ChapterFormat::Json => Json::document("chapters", vec![
+                ("chapters", Json::Array(chapters.iter()
+                    .map(|(start, reason)| Json::object(vec![
+                        ("start", Json::from(*start)),
+                        ("reason", Json::from(reason.as_str())),
+                    ]))
+                    .collect())),
+            ]),
+            ChapterFormat::Podcast => Json::object(vec![
+                ("version", Json::from("1.2.0")),
+                ("chapters", Json::Array(chapters.iter().enumerate()
+                    .map(|(i, (start, _))| Json::object(vec![
+                        ("startTime", Json::from(*start)),
+                        ("title", Json::from(format!("Chapter {}", i + 1))),
+                    ]))
+                    .collect())),
+            ]),
+        };
+        crate::write_text_file(path, &format!("{json}\n"));
+        println!("Saved {} suggested chapters to \"{}\"", chapters.len(), path.display());
+    }
+}
+
+/// Polarity of one signal relative to another judged by their correlation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Polarity {
+    Same,
+    Inverted,
+    /// Too little in common to tell
+    Unclear,
+}
+
+fn polarity(correlation: f64) -> Polarity {
+    if correlation < INVERTED_CORRELATION {
+        Polarity::Inverted
+    } else if correlation.abs() < -INVERTED_CORRELATION {
+        Polarity::Unclear
+    } else {
+        Polarity::Same
+    }
+}
+
+/// Print polarity and delay of one signal relative to another, where `lag`
+/// is the number of frames the second one is behind
+fn report_alignment(label: &str, lag: isize, correlation: f64, sample_rate: u32) {
+    let verdict = match polarity(correlation) {
+        Polarity::Inverted => "inverted".bold().red(),
+        Polarity::Unclear => "unclear".yellow(),
+        Polarity::Same => "ok".green(),
+    };
+    println!("Polarity {label}: {verdict} (correlation {correlation:.2})");
+    let milliseconds = lag as f64 * 1000.0 / sample_rate as f64;
+    println!("Delay {label}: {lag:+} samples ({milliseconds:+.3} ms)");
+}
+
+/// Peak and RMS level of the samples as linear amplitudes
+pub fn levels(samples: &[f32]) -> (f64, f64) {
+    let peak = samples.iter().fold(0.0f32, |a, &b| a.max(b.abs())) as f64;
+    let rms = (samples.iter().map(|s| (s * s) as f64).sum::<f64>() / samples.len().max(1) as f64).sqrt();
+    (peak, rms)
+}
+
+/// Number of samples at or beyond full scale
+pub fn clipped_samples(samples: &[f32]) -> usize {
+    samples.iter().filter(|s| s.abs() >= 1.0).count()
+}
+
+/// Convert a linear amplitude to dBFS
+pub fn to_dbfs(value: f64) -> f64 {
+    20.0 * value.max(1e-10).log10()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::analyze::{polarity, Polarity};
+    use crate::compare::{channel, strongest_correlation};
+
+    #[test]
+    fn polarity_between_channels() {
+        // Stereo noise with the right channel in phase, inverted and
+        // delayed, or unrelated to the left
+        let mut state = 1u32;
+        let mut noise = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+        };
+        let left: Vec<f32> = (0..4000).map(|_| noise()).collect();
+        let unrelated: Vec<f32> = (0..4000).map(|_| noise()).collect();
+        let delayed: Vec<f32> = [0.0; 5].into_iter().chain(left.iter().map(|v| -v)).take(4000).collect();
+        let judge = |right: &[f32]| {
+            let stereo: Vec<f32> = left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect();
+            let (lag, correlation) = strongest_correlation(&channel(&stereo, 2, 0), &channel(&stereo, 2, 1), 50);
+            (lag, polarity(correlation))
+        };
+        assert_eq!(judge(&left), (0, Polarity::Same));
+        assert_eq!(judge(&left.iter().map(|v| -v).collect::<Vec<f32>>()), (0, Polarity::Inverted));
+        assert_eq!(judge(&delayed), (5, Polarity::Inverted));
+        assert_eq!(judge(&unrelated).1, Polarity::Unclear);
+    }
+}
@@ -1,12 +1,15 @@
 use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
 use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, MetadataRevision};
 use symphonia::core::probe::Hint;
 use symphonia_core::audio::SampleBuffer;
+use colored::Colorize;
 use std::io;
-use std::path::PathBuf;
+use std::path::Path;
+
+use crate::policy::Policy;
 
 
 // fn main() {
@@ -21,41 +24,188 @@ use std::path::PathBuf;
 // }
 
 
-pub fn read_audio(path: &PathBuf) -> (usize, Vec<f32>) {
-    // Open the media source.
-    let src = std::fs::File::open(&path).expect("failed to open media");
-    
-    // Create a probe hint using the file's extension. [Optional]
-    let mut hint = Hint::new();
-    match path.extension() {
-        Some(ext) => {
-            hint.with_extension(&ext.to_string_lossy());
+/// Decoded audio together with the properties of its source
+#[derive(Debug, Clone)]
+pub struct Audio {
+    pub channels: usize,
+    pub sample_rate: u32,
+    /// Bit depth of the source, if the codec has one
+    pub bits_per_sample: Option<u32>,
+    /// The tags flag the audio as pre-emphasized (e.g. old CD rips)
+    pub pre_emphasis: bool,
+    /// Interleaved samples
+    pub samples: Vec<f32>,
+    /// Stretches of skipped packets or missing stream data, filled with silence
+    pub gaps: Vec<Gap>,
+    /// Number of samples that were NaN or infinite, replaced with silence
+    pub non_finite: usize,
+    /// Error that stopped decoding before the end of the stream, the samples
+    /// decoded up to it are kept
+    pub truncated: Option<String>,
+}
+
+/// Frames missing from the decoded audio (packets that failed to decode or
+/// a jump in the timestamps), which are filled with silence to keep the time
+/// accurate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gap {
+    /// First missing frame
+    pub start: usize,
+    pub frames: usize,
+}
+
+/// Longest jump in the timestamps that is filled, larger ones are taken as
+/// broken timestamps instead of missing audio
+const MAX_GAP_SECONDS: u64 = 600;
+
+/// Tags of a metadata revision met while decoding, e.g. the title of an
+/// ICY stream or a tag change in the middle of a file. Passed to the
+/// callbacks of the decoding functions, which are internal to the binary
+/// (--show-stream-titles collects them), as there is no library target
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataEvent {
+    /// Number of frames decoded before the revision was read
+    pub frame: usize,
+    /// Keys and values of the tags as they appear in the file
+    pub tags: Vec<(String, String)>,
+}
+
+impl MetadataEvent {
+    fn new(frame: usize, revision: &MetadataRevision) -> MetadataEvent {
+        // RIFF INFO values keep their C string terminator
+        let tags = revision.tags().iter()
+            .map(|tag| (tag.key.clone(), tag.value.to_string().trim_end_matches('\0').to_string()))
+            .collect();
+        MetadataEvent { frame, tags }
+    }
+}
+
+/// Returns true if a revision contains a tag marking pre-emphasis
+fn flags_pre_emphasis(revision: &MetadataRevision) -> bool {
+    revision.tags().iter().any(|tag| {
+        let key = tag.key.to_lowercase().replace(['_', '-', ' '], "");
+        let value = tag.value.to_string().trim().to_lowercase();
+        (key == "emphasis" || key == "preemphasis")
+            && !matches!(&value[..], "" | "0" | "no" | "none" | "false" | "off")
+    })
+}
+
+/// Decode the first audio track of the file at `path` or exit with an error
+pub fn read_audio(path: &Path) -> Audio {
+    read_audio_with(path, Policy::default(), &mut |_| ())
+}
+
+/// Decode the first audio track of the file at `path` or exit with an
+/// error, calling `on_metadata` for every metadata revision in the file.
+/// Damage to the file is reported following the `policy`
+pub fn read_audio_with(path: &Path, policy: Policy, on_metadata: &mut dyn FnMut(&MetadataEvent)) -> Audio {
+    match decode_file(path, on_metadata) {
+        Ok(audio) => {
+            report_damage(path, &audio, policy);
+            audio
         },
-        _ => ()
+        Err(e) => {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}Could not decode \"{}\": {}", path.display(), e);
+            std::process::exit(1);
+        }
     }
+}
 
-    // Create the media source stream.
+/// Decode the first audio track of the file at `path`, calling
+/// `on_metadata` for every metadata revision in the file
+pub fn decode_file(path: &Path, on_metadata: &mut dyn FnMut(&MetadataEvent)) -> Result<Audio, String> {
+    std::fs::File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|src| {
+            // Create a probe hint using the file's extension. [Optional]
+            let mut hint = Hint::new();
+            if let Some(ext) = path.extension() {
+                hint.with_extension(&ext.to_string_lossy());
+            }
+            decode_audio(Box::new(src), hint, on_metadata)
+        })
+}
+
+/// Duration in seconds of the first audio track of the file at `path` as its
+/// container states it, without decoding it. None if the file can't be
+/// probed or doesn't state its length (e.g. some streams)
+pub fn probe_duration(path: &Path) -> Option<f64> {
+    let src = std::fs::File::open(path).ok()?;
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension() {
+        hint.with_extension(&ext.to_string_lossy());
+    }
     let mss = MediaSourceStream::new(Box::new(src), Default::default());
+    let fmt_opts = FormatOptions { enable_gapless: true, ..Default::default() };
+    let probed = symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &Default::default()).ok()?;
+    let track = probed.format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    let (frames, sample_rate) = (track.codec_params.n_frames?, track.codec_params.sample_rate?);
+    (sample_rate > 0).then(|| frames as f64 / sample_rate as f64)
+}
 
+/// Report the damage decoding the file at `path` into `audio` found
+/// following the `policy`
+pub fn report_damage(path: &Path, audio: &Audio, policy: Policy) {
+    if let Some(e) = &audio.truncated {
+        let seconds = audio.samples.len() as f64 / audio.channels as f64 / audio.sample_rate as f64;
+        policy.damaging(&format!("Could not decode \"{}\" past {seconds:.2} s: {e}", path.display()));
+    }
+    if audio.non_finite > 0 {
+        policy.recoverable(&format!("\"{}\" contains {} samples that are not a number or infinite, they were replaced with silence", path.display(), audio.non_finite));
+    }
+}
+
+/// Decode the first audio track of a file held in memory
+pub fn decode_bytes(bytes: &[u8]) -> Result<Audio, String> {
+    decode_bytes_with(bytes, &mut |_| ())
+}
 
-    // Use the default options for metadata and format readers.
+/// Decode the first audio track of a file held in memory, calling
+/// `on_metadata` for every metadata revision in the file
+pub fn decode_bytes_with(bytes: &[u8], on_metadata: &mut dyn FnMut(&MetadataEvent)) -> Result<Audio, String> {
+    decode_audio(Box::new(io::Cursor::new(bytes.to_vec())), Hint::new(), on_metadata)
+}
+
+/// Decode the first audio track of `src`, passing every metadata revision
+/// to `on_metadata` as it is read
+fn decode_audio(src: Box<dyn MediaSource>, hint: Hint, on_metadata: &mut dyn FnMut(&MetadataEvent)) -> Result<Audio, String> {
+    // Create the media source stream.
+    let mss = MediaSourceStream::new(src, Default::default());
+
+
+    // Use the default options for metadata and format readers, but let the
+    // readers trim encoder delay and padding (e.g. of MP3 and AAC)
     let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts: FormatOptions = Default::default();
+    let fmt_opts = FormatOptions { enable_gapless: true, ..Default::default() };
 
     // Probe the media source.
-    let probed = symphonia::default::get_probe()
+    let mut probed = symphonia::default::get_probe()
         .format(&hint, mss, &fmt_opts, &meta_opts)
-        .expect("unsupported format");
+        .map_err(|e| format!("unsupported format ({e})"))?;
+
+    // Metadata in front of the container (e.g. ID3v2) and in the container itself
+    let mut pre_emphasis = false;
+    let mut consume = |revision: &MetadataRevision, frame: usize| {
+        pre_emphasis |= flags_pre_emphasis(revision);
+        on_metadata(&MetadataEvent::new(frame, revision));
+    };
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        consume(revision, 0);
+    }
 
     // Get the instantiated format reader.
     let mut format = probed.format;
+    if let Some(revision) = format.metadata().current() {
+        consume(revision, 0);
+    }
 
     // Find the first audio track with a known (decodeable) codec.
     let track = format
         .tracks()
         .iter()
         .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .expect("no supported audio tracks");
+        .ok_or("no supported audio tracks")?;
 
     // Use the default options for the decoder.
     let dec_opts: DecoderOptions = Default::default();
@@ -63,13 +213,20 @@ pub fn read_audio(path: &PathBuf) -> (usize, Vec<f32>) {
     // Create a decoder for the track.
     let mut decoder = symphonia::default::get_codecs()
         .make(&track.codec_params, &dec_opts)
-        .expect("unsupported codec");
+        .map_err(|e| format!("unsupported codec ({e})"))?;
 
     // Store the track identifier, it will be used to filter packets.
     let track_id = track.id;
 
     let mut samples: Vec<f32> = vec![];
-    let mut channels = 0;
+    let mut channels = track.codec_params.channels.map_or(0, |c| c.count());
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    let bits_per_sample = track.codec_params.bits_per_sample;
+    let time_base = track.codec_params.time_base;
+    let mut gaps: Vec<Gap> = vec![];
+    let mut truncated = None;
+    // Timestamp of the first packet in frames, the start of the time line
+    let mut first_frame: Option<u64> = None;
 
     // The decode loop.
     loop {
@@ -81,20 +238,16 @@ pub fn read_audio(path: &PathBuf) -> (usize, Vec<f32>) {
                 // then restart the decode loop. This is an advanced feature and it is not
                 // unreasonable to consider this "the end." As of v0.5.0, the only usage of this is
                 // for chained OGG physical streams.
-                unimplemented!();
+                break;
             }
             Err(err) => {
                 // A unrecoverable error occured, halt decoding.
                 match err {
-                    Error::IoError(e) => {
-                        match e.kind() {
-                            io::ErrorKind::UnexpectedEof => break,
-                            _ => {
-                                panic!("{}", e)
-                            }
-                        }
+                    Error::IoError(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    _ => {
+                        truncated = Some(err.to_string());
+                        break;
                     },
-                    _ => panic!("{}", err)
                 }
             }
         };
@@ -104,6 +257,9 @@ pub fn read_audio(path: &PathBuf) -> (usize, Vec<f32>) {
             // Pop the old head of the metadata queue.
             format.metadata().pop();
             // Consume the new metadata at the head of the metadata queue.
+            if let Some(revision) = format.metadata().current() {
+                consume(revision, samples.len() / channels.max(1));
+            }
         }
 
         // If the packet does not belong to the selected track, skip over it.
@@ -111,40 +267,219 @@ pub fn read_audio(path: &PathBuf) -> (usize, Vec<f32>) {
             continue;
         }
 
+        // Timestamps count frames or units of the time base
+        let to_frames = |ts: u64| match time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * sample_rate as f64).round() as u64
+            },
+            None => ts,
+        };
+        // Timestamps in coarse time bases (e.g. milliseconds) are rounded
+        let tolerance = time_base.map_or(0, |tb| (sample_rate as u64 * tb.numer as u64).div_ceil(tb.denom as u64).saturating_sub(1));
+        let (start, duration) = (to_frames(packet.ts()), to_frames(packet.dur()));
+        let position = start.saturating_sub(*first_frame.get_or_insert(start));
+        let (missing, overlap) = placement(position, (samples.len() / channels.max(1)) as u64, tolerance);
+        if missing > 0 && missing <= MAX_GAP_SECONDS * sample_rate as u64 {
+            fill_gap(&mut samples, &mut gaps, channels, missing as usize);
+        }
+
         // Decode the packet into audio samples.
         match decoder.decode(&packet) {
             Ok(decoded) => {
                 // Create a sample buffer that matches the parameters of the decoded audio buffer.
                 let mut sample_buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
                 channels = decoded.spec().channels.count();
+                sample_rate = decoded.spec().rate;
+
+                // Encoder delay and padding, unless the decoder trimmed them already
+                let frames = decoded.frames();
+                let (trim_start, trim_end) = match frames as u64 == packet.block_dur() {
+                    true => (packet.trim_start() as usize, packet.trim_end() as usize),
+                    false => (0, 0),
+                };
 
                 // Copy the contents of the decoded audio buffer into the sample buffer whilst performing
                 // any required conversions.
                 sample_buf.copy_interleaved_ref(decoded);
 
-                // The interleaved f32 samples can be accessed as follows.
-                for sample in sample_buf.samples() {
-                    // println!("{:?}", sample);
-                    samples.push(sample.clone());
-                }
-                // samples.append();
+                // Only the frames that belong on the time line after the ones
+                // decoded before
+                let end = frames.saturating_sub(trim_end);
+                let first = (trim_start + overlap as usize).min(end);
+                samples.extend_from_slice(&sample_buf.samples()[first * channels..end * channels]);
             }
             Err(Error::IoError(_e)) => {
                 // The packet failed to decode due to an IO error, skip the packet.
-                eprintln!("IO-Error");
+                fill_gap(&mut samples, &mut gaps, channels, duration as usize);
                 continue;
             }
             Err(Error::DecodeError(_)) => {
                 // The packet failed to decode due to invalid data, skip the packet.
-                eprintln!("Decode-Error");
+                fill_gap(&mut samples, &mut gaps, channels, duration as usize);
                 continue;
             }
             Err(err) => {
                 // An unrecoverable error occured, halt decoding.
-                panic!("{:?}", err);
+                truncated = Some(err.to_string());
+                break;
             }
         }
     }
-    return (channels, samples)
+    if channels == 0 || sample_rate == 0 {
+        return Err(truncated.unwrap_or("no decodable audio".to_string()));
+    }
+    if samples.is_empty() {
+        if let Some(e) = truncated {
+            return Err(e);
+        }
+    }
+    let mut non_finite = 0;
+    for sample in samples.iter_mut().filter(|s| !s.is_finite()) {
+        *sample = 0.0;
+        non_finite += 1;
+    }
+    Ok(Audio { channels, sample_rate, bits_per_sample, pre_emphasis, samples, gaps, non_finite, truncated })
+}
+
+/// Frames missing before and frames overlapping the audio decoded so far for
+/// a packet starting at frame `position` of the time line, after `decoded`
+/// frames. Differences up to `tolerance` frames are rounding and ignored
+fn placement(position: u64, decoded: u64, tolerance: u64) -> (u64, u64) {
+    if position > decoded + tolerance {
+        (position - decoded, 0)
+    } else if decoded > position + tolerance {
+        (0, decoded - position)
+    } else {
+        (0, 0)
+    }
+}
+
+/// Append `frames` frames of silence to the interleaved `samples` and note
+/// them as a gap, joined with the previous gap if they follow it directly
+fn fill_gap(samples: &mut Vec<f32>, gaps: &mut Vec<Gap>, channels: usize, frames: usize) {
+    if channels == 0 || frames == 0 {
+        return;
+    }
+    let start = samples.len() / channels;
+    match gaps.last_mut() {
+        Some(last) if last.start + last.frames == start => last.frames += frames,
+        _ => gaps.push(Gap { start, frames }),
+    }
+    samples.resize(samples.len() + frames * channels, 0.0);
 }
 
+
+
+/// Time constants of the CD pre-emphasis (50 µs / 15 µs)
+const EMPHASIS_TAU_1: f64 = 50e-6;
+const EMPHASIS_TAU_2: f64 = 15e-6;
+
+/// Undo the CD pre-emphasis of interleaved samples in place with a first
+/// order shelving filter
+pub fn deemphasize(samples: &mut [f32], channels: usize, sample_rate: u32) {
+    let channels = channels.max(1);
+    // Bilinear transform of H(s) = (1 + s*tau2) / (1 + s*tau1)
+    let k = 2.0 * sample_rate as f64;
+    let a0 = 1.0 + EMPHASIS_TAU_1 * k;
+    let b0 = (1.0 + EMPHASIS_TAU_2 * k) / a0;
+    let b1 = (1.0 - EMPHASIS_TAU_2 * k) / a0;
+    let a1 = (1.0 - EMPHASIS_TAU_1 * k) / a0;
+    for c in 0..channels {
+        let (mut x1, mut y1) = (0.0f64, 0.0f64);
+        for sample in samples.iter_mut().skip(c).step_by(channels) {
+            let x = *sample as f64;
+            let y = b0 * x + b1 * x1 - a1 * y1;
+            x1 = x;
+            y1 = y;
+            *sample = y as f32;
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::meta::{MetadataBuilder, Tag, Value};
+    use crate::audio::{decode_bytes_with, deemphasize, fill_gap, flags_pre_emphasis, placement, Gap};
+
+    #[test]
+    fn metadata_reaches_the_hook() {
+        // Mono 16 bit WAV with a LIST INFO title in front of one frame
+        let mut wav = b"RIFF\x40\x00\x00\x00WAVEfmt \x10\x00\x00\x00\x01\x00\x01\x00\x40\x1f\x00\x00\x80\x3e\x00\x00\x02\x00\x10\x00".to_vec();
+        wav.extend_from_slice(b"LIST\x12\x00\x00\x00INFOINAM\x06\x00\x00\x00Title\x00");
+        wav.extend_from_slice(b"data\x02\x00\x00\x00\x00\x40");
+        let mut events = vec![];
+        let audio = decode_bytes_with(&wav, &mut |event| events.push(event.clone())).unwrap();
+        assert_eq!(audio.samples, vec![0.5]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].frame, 0);
+        assert_eq!(events[0].tags, vec![("INAM".to_string(), "Title".to_string())]);
+    }
+
+    #[test]
+    fn non_finite_samples_become_silence() {
+        // Mono 32 bit float WAV of 0.5, NaN and infinity
+        let mut wav = b"RIFF\x30\x00\x00\x00WAVEfmt \x10\x00\x00\x00\x03\x00\x01\x00\x40\x1f\x00\x00\x00\x7d\x00\x00\x04\x00\x20\x00".to_vec();
+        wav.extend_from_slice(b"data\x0c\x00\x00\x00");
+        for value in [0.5f32, f32::NAN, f32::INFINITY] {
+            wav.extend_from_slice(&value.to_le_bytes());
+        }
+        let audio = decode_bytes_with(&wav, &mut |_| ()).unwrap();
+        assert_eq!(audio.samples, vec![0.5, 0.0, 0.0]);
+        assert_eq!(audio.non_finite, 2);
+        assert_eq!(audio.truncated, None);
+    }
+
+    #[test]
+    fn missing_frames_become_silent_gaps() {
+        let mut samples = vec![0.5; 8];
+        let mut gaps = vec![];
+        fill_gap(&mut samples, &mut gaps, 2, 3);
+        fill_gap(&mut samples, &mut gaps, 2, 2);
+        samples.extend([0.5; 2]);
+        fill_gap(&mut samples, &mut gaps, 2, 1);
+        // Directly following gaps are joined
+        assert_eq!(gaps, vec![Gap { start: 4, frames: 5 }, Gap { start: 10, frames: 1 }]);
+        assert_eq!(samples.len(), 22);
+        assert!(samples[8..18].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn packets_are_placed_by_timestamp() {
+        // Right after the decoded audio, after a gap and overlapping it
+        assert_eq!(placement(1000, 1000, 0), (0, 0));
+        assert_eq!(placement(1500, 1000, 0), (500, 0));
+        assert_eq!(placement(900, 1000, 0), (0, 100));
+        // Rounded millisecond timestamps at 44.1 kHz
+        assert_eq!(placement(1040, 1000, 44), (0, 0));
+        assert_eq!(placement(960, 1000, 44), (0, 0));
+    }
+    #[test]
+    fn pre_emphasis_is_flagged_and_undone() {
+        let revision = |key: &str, value: &str| {
+            let mut builder = MetadataBuilder::new();
+            builder.add_tag(Tag::new(None, key, Value::from(value)));
+            builder.metadata()
+        };
+        assert!(flags_pre_emphasis(&revision("PRE_EMPHASIS", "1")));
+        assert!(flags_pre_emphasis(&revision("Emphasis", "50/15 us")));
+        assert!(!flags_pre_emphasis(&revision("EMPHASIS", "none")));
+        assert!(!flags_pre_emphasis(&revision("TITLE", "Emphasis")));
+
+        // The shelf keeps low frequencies and lowers high ones by about 9 dB
+        let sample_rate = 44100;
+        let level = |frequency: f64| {
+            let tone = |i: usize| (i as f64 * frequency * std::f64::consts::TAU / sample_rate as f64).sin() as f32;
+            let mut stereo: Vec<f32> = (0..sample_rate as usize).flat_map(|i| [tone(i), -tone(i)]).collect();
+            deemphasize(&mut stereo, 2, sample_rate);
+            // Both channels alike, past the settling of the filter
+            assert!(stereo.chunks(2).all(|frame| (frame[0] + frame[1]).abs() < 1e-6));
+            let peak = stereo[sample_rate as usize..].iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+            20.0 * (peak as f64).log10()
+        };
+        assert!(level(100.0).abs() < 0.1);
+        assert!((-10.0..-8.0).contains(&level(15000.0)), "{}", level(15000.0));
+    }
+}
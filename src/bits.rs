@@ -0,0 +1,85 @@
+//! Detection of the effective bit depth and of dither in integer sources
+
+
+/// Largest amplitude (in steps of the effective bit depth) that counts as quiet
+const QUIET_STEPS: i64 = 4;
+
+/// Minimum number of quiet samples needed to judge dithering
+const MIN_QUIET_SAMPLES: usize = 1000;
+
+/// Share of repeated consecutive quiet values above which the LSBs look
+/// truncated rather than dithered (TPDF dither repeats roughly 40% of the time)
+const TRUNCATED_REPEAT_RATIO: f64 = 0.7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// The lowest bits keep changing during quiet passages
+    Likely,
+    /// Quiet passages consist of repeated values or digital silence
+    Unlikely,
+    /// Not enough quiet material to tell
+    Unknown,
+}
+
+/// Number of bits that actually carry information in samples stored with
+/// `container_bits` bits per sample
+pub fn effective_bit_depth(samples: &[f32], container_bits: u32) -> u32 {
+    let scale = (1i64 << (container_bits - 1)) as f64;
+    let used = samples.iter().fold(0i64, |acc, &s| acc | (s as f64 * scale).round() as i64);
+    if used == 0 {
+        return 0;
+    }
+    container_bits - used.trailing_zeros().min(container_bits)
+}
+
+/// Guess whether the quiet parts of interleaved `samples` were dithered at
+/// `bits` effective bits
+pub fn detect_dither(samples: &[f32], channels: usize, bits: u32) -> Dither {
+    if bits == 0 {
+        return Dither::Unknown;
+    }
+    let channels = channels.max(1);
+    let scale = (1i64 << (bits - 1)) as f64;
+    let mut quiet = 0usize;
+    let mut repeats = 0usize;
+    for c in 0..channels {
+        let values: Vec<i64> = samples.iter().skip(c).step_by(channels)
+            .map(|&s| (s as f64 * scale).round() as i64)
+            .collect();
+        for pair in values.windows(2) {
+            if pair[0].abs() <= QUIET_STEPS && pair[1].abs() <= QUIET_STEPS {
+                quiet += 1;
+                if pair[0] == pair[1] {
+                    repeats += 1;
+                }
+            }
+        }
+    }
+    if quiet < MIN_QUIET_SAMPLES {
+        Dither::Unknown
+    } else if repeats as f64 / quiet as f64 > TRUNCATED_REPEAT_RATIO {
+        Dither::Unlikely
+    } else {
+        Dither::Likely
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::{detect_dither, effective_bit_depth, Dither};
+
+    #[test]
+    fn padded_and_dithered() {
+        // 16 bit values stored in a 24 bit container
+        let samples: Vec<f32> = (0..2000).map(|i| ((i % 7) as f32 - 3.0) / 32768.0).collect();
+        assert_eq!(effective_bit_depth(&samples, 24), 16);
+        assert_eq!(detect_dither(&samples, 1, 16), Dither::Likely);
+
+        let silence = vec![0.0f32; 2000];
+        assert_eq!(detect_dither(&silence, 1, 16), Dither::Unlikely);
+        assert_eq!(detect_dither(&silence[..10], 1, 16), Dither::Unknown);
+    }
+}
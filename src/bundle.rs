@@ -0,0 +1,163 @@
+//! Archive bundles: the image, the peaks, the statistics and the provenance
+//! of a rendering in one uncompressed zip file, so digital archives can
+//! attach a single artifact to every recording
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::audio::Audio;
+use crate::json::Json;
+
+
+/// Polynomial of the CRC-32 used by zip (reversed)
+const CRC_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// Date of every entry in MS-DOS format (1980-01-01, the earliest it can
+/// hold), the time of the rendering is in the provenance instead
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// Flag of entry names encoded as UTF-8
+const UTF8_NAMES: u16 = 1 << 11;
+
+/// CRC-32 of the `bytes` as used by zip and PNG
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ CRC_POLYNOMIAL } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Zip archive of the `entries` (name and content) stored without
+/// compression, as PNG is compressed already. Fails beyond the 4 GiB zip
+/// files without the 64 bit extension can hold
+pub fn zip(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let too_large = || "the bundle would exceed the 4 GiB a zip file can hold".to_string();
+    let mut archive = vec![];
+    let mut directory = vec![];
+    for (name, content) in entries {
+        let offset = u32::try_from(archive.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(content.len()).map_err(|_| too_large())?;
+        let crc = crc32(content);
+        // Fields shared by the local header and the central directory:
+        // version needed, flags, method (stored), time, date, CRC and sizes
+        let mut common = vec![];
+        common.extend(20u16.to_le_bytes());
+        common.extend(UTF8_NAMES.to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+        common.extend(DOS_DATE.to_le_bytes());
+        common.extend(crc.to_le_bytes());
+        common.extend(size.to_le_bytes());
+        common.extend(size.to_le_bytes());
+        common.extend((name.len() as u16).to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+
+        archive.extend(0x0403_4b50u32.to_le_bytes());
+        archive.extend(&common);
+        archive.extend(name.as_bytes());
+        archive.extend(content);
+
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        // Made by version 2.0
+        directory.extend(20u16.to_le_bytes());
+        directory.extend(&common);
+        // Comment length, disk, internal and external attributes
+        directory.extend(0u16.to_le_bytes());
+        directory.extend(0u16.to_le_bytes());
+        directory.extend(0u16.to_le_bytes());
+        directory.extend(0u32.to_le_bytes());
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+    let directory_offset = u32::try_from(archive.len()).map_err(|_| too_large())?;
+    archive.extend(&directory);
+    archive.extend(0x0605_4b50u32.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    Ok(archive)
+}
+
+/// Seconds since the Unix epoch of `time`
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs() as f64)
+}
+
+/// Where the bundle came from: the program and its command line, when it
+/// ran, the input file (with its `checksum` if it was verified) and what
+/// decoding it found
+pub fn provenance(input: &Path, checksum: Option<&str>, audio: &Audio) -> Json {
+    let file = std::fs::metadata(input).ok();
+    let frames = audio.samples.len() / audio.channels.max(1);
+    let missing: usize = audio.gaps.iter().map(|gap| gap.frames).sum();
+    let mut decoding = vec![
+        ("gaps", Json::from(audio.gaps.len() as f64)),
+        ("missing_frames", Json::from(missing as f64)),
+        ("non_finite_samples", Json::from(audio.non_finite as f64)),
+    ];
+    if let Some(error) = &audio.truncated {
+        decoding.push(("truncated_by", Json::from(error.as_str())));
+    }
+    let mut file_fields = vec![
+        ("name", Json::from(input.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default())),
+        ("size", Json::from(file.as_ref().map_or(0.0, |f| f.len() as f64))),
+        ("modified", Json::from(file.and_then(|f| f.modified().ok()).map_or(f64::NAN, unix_seconds))),
+    ];
+    if let Some(checksum) = checksum {
+        file_fields.push(("sha256", Json::from(checksum)));
+    }
+    Json::document("provenance", vec![
+        ("software", Json::object(vec![
+            ("name", Json::from(env!("CARGO_PKG_NAME"))),
+            ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+            ("command_line", Json::Array(std::env::args().map(Json::from).collect())),
+        ])),
+        ("rendered_at", Json::from(unix_seconds(SystemTime::now()))),
+        ("input", Json::object(file_fields)),
+        ("audio", Json::object(vec![
+            ("channels", Json::from(audio.channels as f64)),
+            ("sample_rate", Json::from(audio.sample_rate as f64)),
+            ("bits_per_sample", Json::from(audio.bits_per_sample.map_or(f64::NAN, |bits| bits as f64))),
+            ("duration", Json::from(frames as f64 / audio.sample_rate.max(1) as f64)),
+            ("decoding", Json::object(decoding)),
+        ])),
+    ])
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bundle::{crc32, zip};
+
+    #[test]
+    fn stored_entries() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let entries = vec![("a.txt".to_string(), b"hello".to_vec()), ("b.json".to_string(), b"{}".to_vec())];
+        let archive = zip(&entries).unwrap();
+        let u32_at = |i: usize| u32::from_le_bytes(archive[i..i + 4].try_into().unwrap());
+        let u16_at = |i: usize| u16::from_le_bytes(archive[i..i + 2].try_into().unwrap());
+        assert_eq!(u32_at(0), 0x0403_4b50);
+        assert_eq!(&archive[30..35], b"a.txt");
+        assert_eq!(&archive[35..40], b"hello");
+        assert_eq!(u32_at(14), crc32(b"hello"));
+
+        // The end record points at both entries in the central directory
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        assert_eq!(u16_at(end + 10), 2);
+        let directory = u32_at(end + 16) as usize;
+        assert_eq!(u32_at(directory), 0x0201_4b50);
+        assert_eq!(directory + u32_at(end + 12) as usize, end);
+        // The second entry starts after the first one
+        assert_eq!(u32_at(directory + 46 + 5 + 42), 40);
+    }
+}
@@ -0,0 +1,187 @@
+//! Assertions about the properties of an input file, used as a QC gate
+use std::fmt;
+use std::path::Path;
+
+use crate::audio::Audio;
+use crate::json::Json;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    SampleRate,
+    Channels,
+    BitDepth,
+    Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub property: Property,
+    pub operator: Operator,
+    pub value: f64,
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Property::SampleRate => "sample_rate",
+            Property::Channels => "channels",
+            Property::BitDepth => "bit_depth",
+            Property::Duration => "duration",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.property, self.operator, self.value)
+    }
+}
+
+impl Assertion {
+    fn holds(&self, actual: f64) -> bool {
+        match self.operator {
+            Operator::Equal => actual == self.value,
+            Operator::NotEqual => actual != self.value,
+            Operator::Greater => actual > self.value,
+            Operator::GreaterOrEqual => actual >= self.value,
+            Operator::Less => actual < self.value,
+            Operator::LessOrEqual => actual <= self.value,
+        }
+    }
+}
+
+/// A list of assertions that all have to hold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertions(pub Vec<Assertion>);
+
+/// Parse a comma separated list of assertions like "channels=2,bit_depth>=16"
+pub fn parse_assertions(argument: &str) -> Result<Assertions, String> {
+    argument.split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(parse_assertion)
+        .collect::<Result<Vec<_>, _>>()
+        .map(Assertions)
+}
+
+fn parse_assertion(part: &str) -> Result<Assertion, String> {
+    // Two character operators first, so ">=" is not read as ">"
+    let operators = [
+        (">=", Operator::GreaterOrEqual),
+        ("<=", Operator::LessOrEqual),
+        ("!=", Operator::NotEqual),
+        ("==", Operator::Equal),
+        ("=", Operator::Equal),
+        (">", Operator::Greater),
+        ("<", Operator::Less),
+    ];
+    let (key, operator, value) = operators.iter()
+        .find_map(|(symbol, operator)| {
+            part.split_once(symbol).map(|(key, value)| (key, *operator, value))
+        })
+        .ok_or_else(|| format!("\"{part}\" has no comparison operator (one of =, !=, >, >=, <, <=)"))?;
+    let property = match key.trim().to_lowercase().as_str() {
+        "sample_rate" | "samplerate" | "rate" => Property::SampleRate,
+        "channels" => Property::Channels,
+        "bit_depth" | "bitdepth" | "bits" => Property::BitDepth,
+        "duration" => Property::Duration,
+        other => return Err(format!("Unknown property \"{other}\" (expected sample_rate, channels, bit_depth or duration)")),
+    };
+    let value = value.trim().parse::<f64>()
+        .map_err(|_| format!("\"{}\" is not a number in \"{part}\"", value.trim()))?;
+    Ok(Assertion { property, operator, value })
+}
+
+fn actual_value(audio: &Audio, property: Property) -> Option<f64> {
+    match property {
+        Property::SampleRate => Some(audio.sample_rate as f64),
+        Property::Channels => Some(audio.channels as f64),
+        Property::BitDepth => audio.bits_per_sample.map(|b| b as f64),
+        Property::Duration => {
+            let frames = audio.samples.len() / audio.channels.max(1);
+            Some(frames as f64 / audio.sample_rate.max(1) as f64)
+        },
+    }
+}
+
+/// Return every assertion that does not hold for `audio` with the actual
+/// value (None if the property is unknown for this file)
+pub fn failures<'a>(audio: &Audio, assertions: &'a [Assertion]) -> Vec<(&'a Assertion, Option<f64>)> {
+    assertions.iter()
+        .map(|a| (a, actual_value(audio, a.property)))
+        .filter(|(a, actual)| !actual.map(|v| a.holds(v)).unwrap_or(false))
+        .collect()
+}
+
+/// Exit with a JSON error report on stderr if any assertion fails
+pub fn enforce(path: &Path, audio: &Audio, assertions: &Assertions) {
+    let failed = failures(audio, &assertions.0);
+    if failed.is_empty() {
+        return;
+    }
+    let report = Json::document("assertions", vec![
+        ("error", Json::from("assertion failed")),
+        ("input", Json::from(path.display().to_string())),
+        ("failures", Json::Array(failed.iter()
+            .map(|(assertion, actual)| Json::object(vec![
+                ("assertion", Json::from(assertion.to_string())),
+                ("actual", match actual {
+                    Some(v) => Json::from(*v),
+                    None => Json::from("unknown"),
+                }),
+            ]))
+            .collect())),
+    ]);
+    eprintln!("{report}");
+    std::process::exit(1);
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::Audio;
+    use crate::check::{failures, parse_assertions, Operator, Property};
+
+    #[test]
+    fn parse_and_check() {
+        let assertions = parse_assertions("sample_rate=48000, channels=2,bit_depth>=16").unwrap().0;
+        assert_eq!(assertions.len(), 3);
+        assert_eq!(assertions[2].property, Property::BitDepth);
+        assert_eq!(assertions[2].operator, Operator::GreaterOrEqual);
+        assert!(parse_assertions("loudness>3").is_err());
+        assert!(parse_assertions("channels~2").is_err());
+
+        let audio = Audio { channels: 2, sample_rate: 44100, bits_per_sample: None, pre_emphasis: false, samples: vec![], gaps: vec![], non_finite: 0, truncated: None };
+        let failed = failures(&audio, &assertions);
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].1, Some(44100.0));
+        assert_eq!(failed[1].1, None);
+    }
+}
@@ -0,0 +1,171 @@
+//! SHA-256 checksums of inputs and their verification against manifests in
+//! the format of sha256sum, for fixity checks in archive workflows
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+
+/// Round constants, the fractional parts of the cube roots of the first 64
+/// primes
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Initial state, the fractional parts of the square roots of the first 8
+/// primes
+const INITIAL: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+/// Incremental SHA-256, so large inputs don't need to be held in memory
+pub struct Sha256 {
+    state: [u32; 8],
+    /// Bytes of the block that is not complete yet
+    pending: Vec<u8>,
+    length: u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Sha256 {
+        Sha256 { state: INITIAL, pending: Vec::with_capacity(64), length: 0 }
+    }
+
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        if !self.pending.is_empty() {
+            let take = (64 - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            let block = std::mem::take(&mut self.pending);
+            self.compress(&block);
+        }
+        let mut blocks = bytes.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+        self.pending.extend_from_slice(blocks.remainder());
+    }
+
+    /// The digest as lowercase hex
+    pub fn finish(mut self) -> String {
+        let bits = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        padding.resize((55usize.wrapping_sub(self.pending.len()) % 64) + 1, 0);
+        padding.extend(bits.to_be_bytes());
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+        self.state.iter().map(|word| format!("{word:08x}")).collect()
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// SHA-256 of the file at `path` as lowercase hex
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+}
+
+/// Checksums and file names of a manifest written by sha256sum ("<hex>  name",
+/// or "<hex> *name" for binary mode), skipping empty lines and comments
+pub fn parse_manifest(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            let (hash, name) = line.split_once(' ')
+                .filter(|(hash, _)| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+                .ok_or_else(|| format!("line {} is not a SHA-256 checksum followed by a file name", i + 1))?;
+            let name = name.strip_prefix(' ').or_else(|| name.strip_prefix('*')).unwrap_or(name);
+            Ok((hash.to_lowercase(), name.to_string()))
+        })
+        .collect()
+}
+
+/// Confirm the checksum of `input` against the entry for it in the manifest
+/// at `manifest`, whose file names are relative to the manifest. Returns the
+/// checksum, or why the input can't be confirmed
+pub fn verify(input: &Path, manifest: &Path) -> Result<String, String> {
+    let text = std::fs::read_to_string(manifest).map_err(|e| format!("Could not read the manifest \"{}\": {e}", manifest.display()))?;
+    let entries = parse_manifest(&text).map_err(|e| format!("\"{}\" is not a valid manifest: {e}", manifest.display()))?;
+    let directory = manifest.parent().map_or(PathBuf::new(), Path::to_path_buf);
+    let resolve = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let target = resolve(input);
+    let (expected, _) = entries.iter()
+        .find(|(_, name)| resolve(&directory.join(name)) == target)
+        .ok_or_else(|| format!("\"{}\" is not listed in the manifest \"{}\"", input.display(), manifest.display()))?;
+    let actual = sha256_file(input).map_err(|e| format!("Could not read \"{}\": {e}", input.display()))?;
+    match &actual == expected {
+        true => Ok(actual),
+        false => Err(format!("The SHA-256 of \"{}\" is {actual}, but the manifest \"{}\" lists {expected}", input.display(), manifest.display())),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{parse_manifest, Sha256};
+
+    #[test]
+    fn known_digests() {
+        let digest = |bytes: &[u8]| {
+            let mut hasher = Sha256::new();
+            hasher.update(bytes);
+            hasher.finish()
+        };
+        assert_eq!(digest(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(digest(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // Two blocks of padding, fed in uneven pieces
+        let message = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        let mut hasher = Sha256::new();
+        for piece in message.chunks(7) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish(), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(digest(&[b'a'; 1000]), "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3");
+
+        let manifest = format!("# fixity\n{}  a b.wav\n{} *c.flac\n", "A".repeat(64), "0".repeat(64));
+        let entries = parse_manifest(&manifest).unwrap();
+        assert_eq!(entries[0], ("a".repeat(64), "a b.wav".to_string()));
+        assert_eq!(entries[1].1, "c.flac");
+        assert!(parse_manifest("abc  a.wav").is_err());
+    }
+}
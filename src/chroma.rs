@@ -0,0 +1,107 @@
+//! Chromagram: the energy of the twelve pitch classes over time, regardless
+//! of the octave they sound in, with C at the bottom and B at the top
+use image::{ImageBuffer, RgbaImage};
+use rayon::prelude::*;
+
+use crate::render::{time_column, RenderOptions, TimeAxis};
+use crate::spectrogram::{column_frames, frames, mono, DYNAMIC_RANGE_DB};
+
+
+/// Widest spacing of the bins in Hz, so neighbouring semitones fall into
+/// different bins down to the lowest frequency counted. Frames grow to the
+/// next power of two of samples that reaches it (8192 at 44.1 kHz)
+const MAX_BIN_WIDTH: f64 = 6.0;
+
+/// Lowest frequency counted in Hz (about C2), below which bins are wider
+/// than a semitone
+const MIN_FREQUENCY: f64 = 65.0;
+
+/// Highest frequency counted in Hz, above which mostly overtones and noise
+/// remain
+const MAX_FREQUENCY: f64 = 5000.0;
+
+/// Pitch class (0 for C to 11 for B) of the semitone closest to `frequency`
+fn pitch_class(frequency: f64) -> usize {
+    let note = (12.0 * (frequency / 440.0).log2() + 69.0).round() as i64;
+    note.rem_euclid(12) as usize
+}
+
+/// Power of every pitch class in a spectrum of levels in dB, `bin_width` Hz
+/// apart
+fn chroma(spectrum: &[f64], bin_width: f64) -> [f64; 12] {
+    let mut classes = [0.0; 12];
+    for (bin, db) in spectrum.iter().enumerate() {
+        let frequency = bin as f64 * bin_width;
+        if (MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+            classes[pitch_class(frequency)] += 10f64.powf(db / 10.0);
+        }
+    }
+    classes
+}
+
+/// Chromagram of the interleaved `samples` (averaged over channels) with time
+/// following the columns of `axis`. Every column is scaled to its strongest
+/// pitch class, so the harmonic content shows independent of the loudness;
+/// columns quieter than the dynamic range of the spectrogram stay empty
+pub fn render_chroma(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let channels = options.channels.max(1);
+    let mono = mono(samples, channels);
+
+    let settings = options.spectrogram;
+    let frame_size = ((options.sample_rate as f64 / MAX_BIN_WIDTH).ceil() as usize).next_power_of_two();
+    let window = settings.window.coefficients(frame_size);
+    let hop = ((frame_size as f64 * (1.0 - settings.overlap)).round() as usize).max(1);
+    let spectra = frames(&mono, &window, hop);
+    let gain_db = 20.0 * gain.log10();
+    let bin_width = options.sample_rate as f64 / frame_size as f64;
+
+    let (width, height) = (axis.columns, options.height);
+    let column_frames = column_frames(axis, samples.len(), channels, frame_size, hop, spectra.len());
+    let columns: Vec<Option<[f64; 12]>> = column_frames.par_iter()
+        .map(|frame| {
+            let classes = chroma(&spectra[(*frame)?], bin_width);
+            let loudest = classes.iter().cloned().fold(0.0, f64::max);
+            let audible = 10.0 * loudest.max(1e-30).log10() + gain_db > -DYNAMIC_RANGE_DB;
+            audible.then(|| classes.map(|power| power / loudest))
+        })
+        .collect();
+
+    let color = settings.color.unwrap_or(options.foreground);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        let Some(classes) = columns[column] else {
+            return options.background;
+        };
+        let class = ((height - 1 - y) as u64 * 12 / height as u64) as usize;
+        options.dither.blend(options.background, color, classes[class], x, y)
+    })
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::chroma::pitch_class;
+    use crate::render::{render_waveform, RenderMode, RenderOptions};
+
+    #[test]
+    fn tones_light_up_their_pitch_class() {
+        assert_eq!(pitch_class(440.0), 9);
+        assert_eq!(pitch_class(261.63), 0);
+        assert_eq!(pitch_class(130.81), 0);
+
+        // An E (329.63 Hz) with its octave, 24 rows make two per class
+        let sample_rate = 44100.0;
+        let samples: Vec<f32> = (0..44100)
+            .map(|i| {
+                let t = i as f64 / sample_rate;
+                (0.4 * (2.0 * std::f64::consts::PI * 329.63 * t).sin() + 0.2 * (2.0 * std::f64::consts::PI * 659.26 * t).sin()) as f32
+            })
+            .collect();
+        let options = RenderOptions { width: 4, height: 24, oversample: 1, sample_rate: 44100, normalize: false, ..RenderOptions::new(RenderMode::Chroma) };
+        let img = render_waveform(&samples, 44100.0 / 4.0, &options);
+        let alpha = |class: u32| img.get_pixel(2, 23 - class * 2)[3];
+        assert_eq!(alpha(4), 255);
+        assert!((0..12).filter(|&class| class != 4).all(|class| alpha(class) < 64));
+    }
+}
@@ -0,0 +1,318 @@
+//! Parsing of color arguments: names, hex notation, function notation
+//! (rgb(), rgba(), gray(), hsl()) and comma separated lists of gray or RGB
+//! values with optional alpha
+use image::Rgba;
+
+use crate::number::{parse_fraction, parse_number};
+
+
+/// Colors that can be given by name
+const NAMED: &[(&str, [u8; 4])] = &[
+    ("transparent", [0, 0, 0, 0]),
+    ("none", [0, 0, 0, 0]),
+    ("black", [0, 0, 0, 255]),
+    ("white", [255, 255, 255, 255]),
+    ("gray", [128, 128, 128, 255]),
+    ("grey", [128, 128, 128, 255]),
+    ("red", [255, 0, 0, 255]),
+    ("orange", [255, 165, 0, 255]),
+    ("yellow", [255, 255, 0, 255]),
+    ("green", [0, 255, 0, 255]),
+    ("cyan", [0, 255, 255, 255]),
+    ("blue", [0, 0, 255, 255]),
+    ("purple", [128, 0, 128, 255]),
+    ("magenta", [255, 0, 255, 255]),
+];
+
+const FORMATS: &str = "use a name (e.g. \"black\" or \"transparent\"), hex (#rgb, #rrggbb or #rrggbbaa), \
+    rgb(r, g, b), rgba(r, g, b, alpha), gray(l), gray(l, alpha), hsl(h, s%, l%) or 1 to 4 comma separated gray/RGB(A) values from 0 to 255 or 0.0 to 1.0 \
+    (separated by semicolons when using decimal commas, e.g. \"1,0;0,5;0\")";
+
+/// Split a list on semicolons if there are any, which leaves commas free to
+/// be decimal commas, otherwise on commas
+pub fn split_list(list: &str) -> Vec<&str> {
+    if list.contains(';') { list.split(';').collect() } else { list.split(',').collect() }
+}
+
+/// One component, either an integer from 0 to 255 or a fraction from 0.0 to
+/// 1.0 (anything with a decimal point or comma), clamped to the valid range
+fn component(value: &str) -> Result<u8, String> {
+    let value = value.trim();
+    if value.contains(['.', ',']) {
+        parse_number(value).map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+    } else {
+        value.parse::<u32>()
+            .map(|v| v.min(255) as u8)
+            .map_err(|_| format!("\"{value}\" is not a number"))
+    }
+}
+
+/// Hex notation without the leading '#', with one or two digits per component
+fn hex(digits: &str) -> Result<Rgba<u8>, String> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("\"#{digits}\" contains characters that are not hex digits"));
+    }
+    let width = match digits.len() {
+        3 | 4 => 1,
+        6 | 8 => 2,
+        n => return Err(format!("\"#{digits}\" has {n} hex digits instead of 3, 4, 6 or 8")),
+    };
+    let mut color = [255u8; 4];
+    for (i, chunk) in digits.as_bytes().chunks(width).enumerate() {
+        let value = u8::from_str_radix(std::str::from_utf8(chunk).unwrap(), 16).unwrap();
+        // Single digits stand for both digits, #f00 is #ff0000
+        color[i] = if width == 1 { value * 17 } else { value };
+    }
+    Ok(Rgba(color))
+}
+
+/// Percentage like "50%" as a fraction
+fn percentage(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    value.strip_suffix('%')
+        .and_then(|v| parse_number(v).ok())
+        .map(|v| (v / 100.0).clamp(0.0, 1.0))
+        .ok_or_else(|| format!("\"{value}\" is not a percentage"))
+}
+
+/// Color channel inside function notation: a percentage or a component
+fn channel(value: &str) -> Result<u8, String> {
+    if value.trim().ends_with('%') {
+        percentage(value).map(|v| (v * 255.0).round() as u8)
+    } else {
+        component(value)
+    }
+}
+
+/// Alpha inside function notation, a fraction from 0.0 to 1.0 or a
+/// percentage like in CSS, so "1" is opaque
+fn alpha(value: &str) -> Result<u8, String> {
+    let fraction = if value.trim().ends_with('%') { percentage(value)? } else { parse_number(value)? };
+    Ok((fraction.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Arguments of rgb(r, g, b) or rgba(r, g, b, alpha)
+fn rgb(arguments: &str, with_alpha: bool) -> Result<Rgba<u8>, String> {
+    match (&split_list(arguments)[..], with_alpha) {
+        ([r, g, b], false) => Ok(Rgba([channel(r)?, channel(g)?, channel(b)?, 255])),
+        ([r, g, b, a], true) => Ok(Rgba([channel(r)?, channel(g)?, channel(b)?, alpha(a)?])),
+        _ => Err(format!("expected {} values in \"{arguments}\"", if with_alpha { 4 } else { 3 })),
+    }
+}
+
+/// Arguments of gray(l) or gray(l, alpha)
+fn gray(arguments: &str) -> Result<Rgba<u8>, String> {
+    match &split_list(arguments)[..] {
+        [l] => channel(l).map(|l| Rgba([l, l, l, 255])),
+        [l, a] => Ok(Rgba([channel(l)?, channel(l)?, channel(l)?, alpha(a)?])),
+        _ => Err(format!("expected 1 or 2 values in \"{arguments}\"")),
+    }
+}
+
+/// Arguments of hsl(hue, saturation%, lightness%) or hsla(…, alpha)
+fn hsl(arguments: &str, with_alpha: bool) -> Result<Rgba<u8>, String> {
+    let parts = split_list(arguments);
+    let (h, s, l, alpha) = match (&parts[..], with_alpha) {
+        ([h, s, l], false) => (h, s, l, 255),
+        ([h, s, l, a], true) => (h, s, l, alpha(a)?),
+        _ => return Err(format!("expected {} values in \"{arguments}\"", if with_alpha { 4 } else { 3 })),
+    };
+    let hue = parse_number(h.trim().trim_end_matches("deg"))
+        .map_err(|_| format!("\"{}\" is not a hue in degrees", h.trim()))?
+        .rem_euclid(360.0);
+    let (saturation, lightness) = (percentage(s)?, percentage(l)?);
+
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let to_u8 = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    Ok(Rgba([to_u8(r), to_u8(g), to_u8(b), alpha]))
+}
+
+/// `color` with its hue rotated by `degrees`, keeping the luminance (like
+/// the hue-rotate() filter of CSS). Grays stay as they are
+pub fn rotate_hue(color: Rgba<u8>, degrees: f64) -> Rgba<u8> {
+    let (cos, sin) = (degrees.to_radians().cos(), degrees.to_radians().sin());
+    let matrix = [
+        [0.213 + cos * 0.787 - sin * 0.213, 0.715 - cos * 0.715 - sin * 0.715, 0.072 - cos * 0.072 + sin * 0.928],
+        [0.213 - cos * 0.213 + sin * 0.143, 0.715 + cos * 0.285 + sin * 0.140, 0.072 - cos * 0.072 - sin * 0.283],
+        [0.213 - cos * 0.213 - sin * 0.787, 0.715 - cos * 0.715 + sin * 0.715, 0.072 + cos * 0.928 + sin * 0.072],
+    ];
+    let mut result = color;
+    for (i, row) in matrix.iter().enumerate() {
+        let value: f64 = (0..3).map(|j| row[j] * color[j] as f64).sum();
+        result[i] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    result
+}
+
+/// Linear interpolation between two colors
+pub fn blend(a: Rgba<u8>, b: Rgba<u8>, t: f64) -> Rgba<u8> {
+    Rgba(std::array::from_fn(|i| (a[i] as f64 + (b[i] as f64 - a[i] as f64) * t).round() as u8))
+}
+
+/// `top` composited over the opaque color `bottom`
+fn over(top: Rgba<u8>, bottom: Rgba<u8>) -> Rgba<u8> {
+    let opaque = Rgba([top[0], top[1], top[2], 255]);
+    blend(bottom, opaque, top[3] as f64 / 255.0)
+}
+
+/// `top` with its alpha scaled by `opacity` composited over `bottom`, both
+/// of which may be translucent
+pub fn composite(top: Rgba<u8>, bottom: Rgba<u8>, opacity: f64) -> Rgba<u8> {
+    let top_alpha = top[3] as f64 / 255.0 * opacity;
+    let bottom_alpha = bottom[3] as f64 / 255.0;
+    let alpha = top_alpha + bottom_alpha * (1.0 - top_alpha);
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let mut result: [u8; 4] = std::array::from_fn(|i| {
+        ((top[i] as f64 * top_alpha + bottom[i] as f64 * bottom_alpha * (1.0 - top_alpha)) / alpha).round() as u8
+    });
+    result[3] = (alpha * 255.0).round() as u8;
+    Rgba(result)
+}
+
+/// Parse an opacity given in percent ("60%") or as a fraction ("0.6")
+pub fn parse_opacity(argument: &str) -> Result<f64, String> {
+    parse_fraction(argument, "opacity", 0.0..=1.0)
+}
+
+/// Relative luminance of an opaque color as defined by WCAG
+fn relative_luminance(color: Rgba<u8>) -> f64 {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * linear(color[0]) + 0.7152 * linear(color[1]) + 0.0722 * linear(color[2])
+}
+
+/// WCAG contrast ratio (1 to 21) between `foreground` drawn on `background`.
+/// A background that is not opaque shows whatever the image is placed on,
+/// so the lower ratio on a white and on a black page is returned
+pub fn contrast_ratio(foreground: Rgba<u8>, background: Rgba<u8>) -> f64 {
+    [Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255])]
+        .into_iter()
+        .map(|page| {
+            let back = over(background, page);
+            let (a, b) = (relative_luminance(over(foreground, back)), relative_luminance(back));
+            (a.max(b) + 0.05) / (a.min(b) + 0.05)
+        })
+        .fold(f64::MAX, f64::min)
+}
+
+/// Parse a color argument like "black", "#ff8000", "rgba(255, 128, 0, 0.5)",
+/// "gray(0.3)", "hsl(30, 100%, 50%)", "128" (gray), "128,0.5" (gray with
+/// alpha), "255,0,0" or "255,0,0,128". The function notation keeps its
+/// values apart from lists of several colors
+pub fn parse(argument: &str) -> Result<Rgba<u8>, String> {
+    let s = argument.trim().to_lowercase();
+    let result = if let Some(&(_, color)) = NAMED.iter().find(|(name, _)| *name == s) {
+        Ok(Rgba(color))
+    } else if let Some(digits) = s.strip_prefix('#') {
+        hex(digits)
+    } else if let Some((function, arguments)) = s.strip_suffix(')').and_then(|f| f.split_once('(')) {
+        match function.trim() {
+            "rgb" => rgb(arguments, false),
+            "rgba" => rgb(arguments, true),
+            "gray" | "grey" => gray(arguments),
+            "hsl" => hsl(arguments, false),
+            "hsla" => hsl(arguments, true),
+            _ => Err(format!("\"{}\" is not a color function", function.trim())),
+        }
+    } else {
+        let values = split_list(&s).into_iter().map(component).collect::<Result<Vec<u8>, String>>();
+        match values.as_deref() {
+            Ok(&[l]) => Ok(Rgba([l, l, l, 255])),
+            Ok(&[l, a]) => Ok(Rgba([l, l, l, a])),
+            Ok(&[r, g, b]) => Ok(Rgba([r, g, b, 255])),
+            Ok(&[r, g, b, a]) => Ok(Rgba([r, g, b, a])),
+            Ok(values) => Err(format!("{} values instead of 1 to 4", values.len())),
+            Err(e) => Err(e.clone()),
+        }
+    };
+    result.map_err(|e| format!("unknown color \"{}\": {e}; {FORMATS}", argument.trim()))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use crate::color::{composite, contrast_ratio, parse, parse_opacity};
+
+    #[test]
+    fn notations() {
+        assert_eq!(parse("transparent"), Ok(Rgba([0, 0, 0, 0])));
+        assert_eq!(parse(" Black "), Ok(Rgba([0, 0, 0, 255])));
+        assert_eq!(parse("0, 0, 0, 1.0"), Ok(Rgba([0, 0, 0, 255])));
+        assert_eq!(parse("128,0.5"), Ok(Rgba([128, 128, 128, 128])));
+        assert_eq!(parse("#f80"), Ok(Rgba([255, 136, 0, 255])));
+        assert_eq!(parse("#FF880080"), Ok(Rgba([255, 136, 0, 128])));
+        assert_eq!(parse("hsl(120, 100%, 50%)"), Ok(Rgba([0, 255, 0, 255])));
+        assert_eq!(parse("hsla(240, 100%, 25%, 0.5)"), Ok(Rgba([0, 0, 128, 128])));
+        assert_eq!(parse("1,0; 0,5; 0"), Ok(Rgba([255, 128, 0, 255])));
+        assert_eq!(parse("rgba(12, 34, 56, 0.5)"), Ok(Rgba([12, 34, 56, 128])));
+        assert_eq!(parse("RGB(100%, 0, 50%)"), Ok(Rgba([255, 0, 128, 255])));
+        assert_eq!(parse("rgba(0, 0, 0, 1)"), Ok(Rgba([0, 0, 0, 255])));
+        assert_eq!(parse("gray(0.3)"), Ok(Rgba([77, 77, 77, 255])));
+        assert_eq!(parse("grey(128, 50%)"), Ok(Rgba([128, 128, 128, 128])));
+        assert_eq!(parse("hsl(0; 100%; 37,5%)"), Ok(Rgba([191, 0, 0, 255])));
+        for invalid in ["0,0,", "0,0,0,0,0", "1,2x", "#12345", "#gg0000", "hsl(0, 1, 2)", "nope", "", "#ä", "rgb(1, 2)", "rgba(1, 2, 3)", "cmyk(0, 0, 0, 1)", "gray()"] {
+            assert!(parse(invalid).is_err(), "{invalid:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn contrast() {
+        let (black, white) = (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255]));
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 1e-9);
+        assert!((contrast_ratio(white, white) - 1.0).abs() < 1e-9);
+        // Black on a transparent background vanishes on a black page
+        assert!((contrast_ratio(black, Rgba([0, 0, 0, 0])) - 1.0).abs() < 1e-9);
+        // Light gray on white is below the 3:1 WCAG asks for graphics
+        assert!(contrast_ratio(Rgba([170, 170, 170, 255]), white) < 3.0);
+
+        // Layers keep what shows through them
+        let red = Rgba([255, 0, 0, 255]);
+        assert_eq!(composite(red, white, 1.0), red);
+        assert_eq!(composite(red, white, 0.0), white);
+        assert_eq!(composite(red, Rgba([0, 0, 0, 0]), 0.5), Rgba([255, 0, 0, 128]));
+        assert_eq!(composite(Rgba([0, 0, 0, 0]), Rgba([0, 0, 0, 0]), 1.0), Rgba([0, 0, 0, 0]));
+        assert_eq!(parse_opacity("60%"), Ok(0.6));
+        assert!(parse_opacity("1,5").is_err());
+    }
+
+    #[test]
+    fn properties() {
+        let mut random = crate::testing::random(0x2545F4914F6CDD1D);
+        let mut next = move || random.next().unwrap();
+        let junk: Vec<char> = "0123456789abcdefx,;.#%() -hslrgbayä".chars().collect();
+        for _ in 0..10000 {
+            let [r, g, b, a, ..] = next().to_le_bytes();
+            let color = Rgba([r, g, b, a]);
+            // Every notation that can express a color round-trips exactly
+            assert_eq!(parse(&format!("{r},{g},{b},{a}")), Ok(color));
+            assert_eq!(parse(&format!("#{r:02x}{g:02X}{b:02x}{a:02x}")), Ok(color));
+            assert_eq!(parse(&format!(" {r} , {g} , {b} ")), Ok(Rgba([r, g, b, 255])));
+            assert_eq!(parse(&format!("rgb({r}, {g}, {b})")), Ok(Rgba([r, g, b, 255])));
+            assert_eq!(parse(&format!("gray({r}, {})", a as f64 / 255.0)), Ok(Rgba([r, r, r, a])));
+            // Fully saturated hues at half lightness always keep one channel
+            // at full and one at zero
+            let Rgba([r, g, b, _]) = parse(&format!("hsl({}, 100%, 50%)", next() % 720)).unwrap();
+            assert!([r, g, b].contains(&255) && [r, g, b].contains(&0), "{r},{g},{b}");
+            // Arbitrary input is rejected or accepted but never panics
+            let length = next() % 16;
+            let text: String = (0..length).map(|_| junk[next() as usize % junk.len()]).collect();
+            let _ = parse(&text);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Comparison of two decoded files, e.g. an original and its lossy encode
+use std::path::PathBuf;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+
+use crate::analyze::to_dbfs;
+use crate::audio::{read_audio, Audio};
+use crate::fft::{fft, Complex};
+use crate::render::{self, RenderMode, RenderOptions, TimeAxis};
+
+
+/// Mix interleaved samples down to a single channel
+pub fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    samples.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// RMS of the sample-wise difference between two mono signals for each
+/// column of `axis` (in frames). Frames missing in one of the signals count
+/// as silence.
+pub fn difference_per_column(a: &[f32], b: &[f32], axis: &TimeAxis) -> Vec<f32> {
+    let frames = a.len().max(b.len());
+    (0..axis.columns)
+        .map(|x| {
+            let range = axis.range(x, frames);
+            let (start, end) = (range.start, range.end);
+            if start == end {
+                return 0.0;
+            }
+            let sum: f32 = (start..end)
+                .map(|i| {
+                    let d = a.get(i).unwrap_or(&0.0) - b.get(i).unwrap_or(&0.0);
+                    d * d
+                })
+                .sum();
+            (sum / (end - start) as f32).sqrt()
+        })
+        .collect()
+}
+
+/// What of a second file is drawn over the waveform of the input
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareStyle {
+    /// Its waveform
+    Overlay,
+    /// The input minus it, sample by sample
+    Difference,
+}
+
+/// Interleaved samples of `b` laid on the time line of the interleaved
+/// samples `a`, or the difference of `a` to them. Frames missing at the end
+/// of `b` count as silence, and with a different channel count `b` is mixed
+/// down and repeated on every channel of `a`
+pub fn comparison(a: &[f32], channels: usize, b: &[f32], b_channels: usize, style: CompareStyle) -> Vec<f32> {
+    let channels = channels.max(1);
+    let b = match b_channels == channels {
+        true => b.to_vec(),
+        false => downmix(b, b_channels).into_iter().flat_map(|s| std::iter::repeat_n(s, channels)).collect(),
+    };
+    a.iter().enumerate()
+        .map(|(i, &sample)| {
+            let other = b.get(i).copied().unwrap_or(0.0);
+            match style {
+                CompareStyle::Overlay => other,
+                CompareStyle::Difference => sample - other,
+            }
+        })
+        .collect()
+}
+
+/// Number of frames used to estimate the offset between two signals
+const CORRELATION_WINDOW: usize = 1 << 18;
+
+/// Normalized cross-correlation of `b` against `a` for all lags up to
+/// `max_lag` frames in both directions, computed over the first few seconds
+/// of both signals. Returns (lag, coefficient) pairs, where a positive lag
+/// means `b` is behind `a`.
+pub fn cross_correlation(a: &[f32], b: &[f32], max_lag: usize) -> Vec<(isize, f64)> {
+    let window = CORRELATION_WINDOW.min(a.len().max(b.len())).max(1);
+    let n = (2 * window).next_power_of_two();
+    let to_complex = |s: &[f32]| -> Vec<Complex> {
+        (0..n).map(|i| Complex::new(if i < window { *s.get(i).unwrap_or(&0.0) as f64 } else { 0.0 }, 0.0)).collect()
+    };
+    let mut fa = to_complex(a);
+    let mut fb = to_complex(b);
+    fft(&mut fa, false);
+    fft(&mut fb, false);
+    // Correlation of b against a, lag k ends up at index k (mod n)
+    let mut correlation: Vec<Complex> = fa.iter().zip(fb.iter()).map(|(x, y)| x.conj() * *y).collect();
+    fft(&mut correlation, true);
+
+    let energy = |s: &[f32]| s.iter().take(window).map(|v| (v * v) as f64).sum::<f64>();
+    let norm = (energy(a) * energy(b)).sqrt().max(f64::MIN_POSITIVE);
+    let max_lag = max_lag.min(window - 1) as isize;
+    (-max_lag..=max_lag)
+        .map(|lag| (lag, correlation[lag.rem_euclid(n as isize) as usize].re / norm))
+        .collect()
+}
+
+/// Estimate by how many frames `b` lags behind `a` (negative if `b` is ahead),
+/// searching up to `max_lag` frames in both directions
+pub fn find_lag(a: &[f32], b: &[f32], max_lag: usize) -> isize {
+    cross_correlation(a, b, max_lag).into_iter()
+        .max_by(|x, y| x.1.total_cmp(&y.1))
+        .map(|(lag, _)| lag)
+        .unwrap_or(0)
+}
+
+/// The lag with the strongest (positive or negative) correlation between the
+/// signals and its coefficient. A clearly negative coefficient means one of
+/// the signals has inverted polarity.
+pub fn strongest_correlation(a: &[f32], b: &[f32], max_lag: usize) -> (isize, f64) {
+    cross_correlation(a, b, max_lag).into_iter()
+        .max_by(|x, y| x.1.abs().total_cmp(&y.1.abs()))
+        .unwrap_or((0, 0.0))
+}
+
+/// Extract a single channel from interleaved samples
+pub fn channel(samples: &[f32], channels: usize, index: usize) -> Vec<f32> {
+    samples.iter().skip(index).step_by(channels.max(1)).copied().collect()
+}
+
+/// Null test two files that should be identical
+#[derive(Args, Debug)]
+pub struct NullArgs {
+    /// Reference file
+    a: PathBuf,
+
+    /// File that is subtracted from the reference
+    b: PathBuf,
+
+    /// Largest offset between the files that is compensated
+    #[arg(long, value_parser = crate::parse_duration, default_value = "1s", env = "WELLENFORMER_MAX_OFFSET")]
+    max_offset: f64,
+
+    /// Write a (normalized) waveform of the residual to this png
+    #[arg(long, env = "WELLENFORMER_RESIDUAL")]
+    residual: Option<PathBuf>,
+}
+
+pub fn null_test(args: &NullArgs) {
+    crate::ensure_input_file(&args.a);
+    crate::ensure_input_file(&args.b);
+    let Audio { channels: channels_a, sample_rate, samples: a, .. } = read_audio(&args.a);
+    let Audio { channels: channels_b, sample_rate: sample_rate_b, samples: b, .. } = read_audio(&args.b);
+    if sample_rate != sample_rate_b {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}Cannot null test files with different sample rates ({} Hz vs {} Hz)", sample_rate, sample_rate_b);
+        std::process::exit(1);
+    }
+
+    // Compare channel by channel if possible, otherwise fall back to mono
+    let (channels, a, b) = if channels_a == channels_b {
+        (channels_a.max(1), a, b)
+    } else {
+        (1, downmix(&a, channels_a), downmix(&b, channels_b))
+    };
+
+    let lag = find_lag(&downmix(&a, channels), &downmix(&b, channels), (args.max_offset * sample_rate as f64) as usize);
+    let frames = (a.len() / channels) as isize;
+    let residual: Vec<f32> = (0..frames)
+        .flat_map(|i| (0..channels).map(move |c| (i, c)))
+        .map(|(i, c)| {
+            let j = i + lag;
+            let other = if j >= 0 { *b.get(j as usize * channels + c).unwrap_or(&0.0) } else { 0.0 };
+            // Invert the second file and sum
+            a[i as usize * channels + c] - other
+        })
+        .collect();
+
+    let rms = |s: &[f32]| (s.iter().map(|v| (v * v) as f64).sum::<f64>() / s.len().max(1) as f64).sqrt();
+    let residual_peak = residual.iter().fold(0.0f32, |m, &v| m.max(v.abs())) as f64;
+    let residual_rms = rms(&residual);
+
+    println!("Offset:        {} samples ({:.3} ms)", lag, lag as f64 * 1000.0 / sample_rate as f64);
+    println!("Residual Peak: {:.2} dBFS", to_dbfs(residual_peak));
+    println!("Residual RMS:  {:.2} dBFS", to_dbfs(residual_rms));
+    println!("Null Depth:    {:.2} dB", to_dbfs(residual_rms) - to_dbfs(rms(&a)));
+
+    if let Some(path) = &args.residual {
+        let options = RenderOptions {
+            width: 1920,
+            height: 120,
+            oversample: 8,
+            channels: 1,
+            sample_rate,
+            normalize: true,
+            ..RenderOptions::new(RenderMode::Mean)
+        };
+        let samples_per_pixel = residual.len() as f64 / (options.width * options.oversample) as f64;
+        let img = render::render_waveform(&residual, samples_per_pixel, &options);
+        crate::output::save_image(&img, path);
+        println!("Saved residual waveform to \"{}\"", path.display());
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{comparison, difference_per_column, find_lag, strongest_correlation, CompareStyle};
+    use crate::render::TimeAxis;
+
+    #[test]
+    fn second_file_on_the_time_line_of_the_input() {
+        let a = [0.5, -0.5, 0.25, 0.25, 1.0, 0.0];
+        // Cut short, missing frames are silent
+        assert_eq!(comparison(&a, 2, &[0.5, 0.5], 2, CompareStyle::Overlay), vec![0.5, 0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(comparison(&a, 2, &[0.5, 0.5], 2, CompareStyle::Difference), vec![0.0, -1.0, 0.25, 0.25, 1.0, 0.0]);
+        // A mono file is repeated on both channels
+        assert_eq!(comparison(&a, 2, &[0.5, 0.25, 1.0, 2.0], 1, CompareStyle::Overlay), vec![0.5, 0.5, 0.25, 0.25, 1.0, 1.0]);
+    }
+    #[test]
+    fn difference_of_every_column() {
+        let a = [0.5, 0.5, 0.5, 0.5, 1.0, 1.0];
+        let b = [0.5, 0.5, 0.0, 1.0, 1.0];
+        // Identical, half a unit apart and cut short, where the missing
+        // frame counts as silence
+        let differences = difference_per_column(&a, &b, &TimeAxis::linear(4, 2.0));
+        assert_eq!(differences[..2], [0.0, 0.5]);
+        assert!((differences[2] - 0.5f32.sqrt()).abs() < 1e-6);
+        assert_eq!(differences[3], 0.0);
+    }
+
+    #[test]
+    fn correlation_recovers_offsets() {
+        // Noise, so only the true offset lines the signals up
+        let mut state = 1u32;
+        let a: Vec<f32> = (0..4000)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        let delayed: Vec<f32> = [0.0; 37].into_iter().chain(a.iter().copied()).collect();
+        assert_eq!(find_lag(&a, &delayed, 100), 37);
+        let (lag, coefficient) = strongest_correlation(&a, &delayed, 100);
+        assert_eq!(lag, 37);
+        assert!(coefficient > 0.95, "{coefficient}");
+
+        // Ahead and inverted
+        let inverted: Vec<f32> = a[12..].iter().map(|v| -v).collect();
+        let (lag, coefficient) = strongest_correlation(&a, &inverted, 100);
+        assert_eq!(lag, -12);
+        assert!(coefficient < -0.95, "{coefficient}");
+        // Out of the searched range nothing lines up
+        assert!(strongest_correlation(&a, &delayed, 20).1.abs() < 0.2);
+    }
+}
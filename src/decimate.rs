@@ -0,0 +1,49 @@
+//! Peak-preserving decimation: long stretches of audio shrunk to the lowest
+//! and the highest sample of short blocks, which is all the modes drawing
+//! peaks need from columns of many thousand frames
+
+
+/// Frames per output column from which the peak modes decimate
+pub const MIN_FRAMES_PER_PIXEL: f64 = 10_000.0;
+
+/// Blocks every internal column is split into, so the column boundaries are
+/// off by at most a block
+pub const BLOCKS_PER_COLUMN: f64 = 64.0;
+
+/// Lowest and highest sample of every channel of every block of `block`
+/// frames of the interleaved `samples`, as two frames per block (the lows,
+/// then the highs). The last block may be shorter
+pub fn decimate(samples: &[f32], channels: usize, block: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    let mut decimated = Vec::with_capacity(samples.len() / block.max(1) * 2 + 2 * channels);
+    for chunk in samples.chunks(block.max(1) * channels) {
+        let mut lows = vec![f32::MAX; channels];
+        let mut highs = vec![f32::MIN; channels];
+        for frame in chunk.chunks(channels) {
+            for (c, &s) in frame.iter().enumerate() {
+                lows[c] = lows[c].min(s);
+                highs[c] = highs[c].max(s);
+            }
+        }
+        // Channels missing in a cut off frame stay silent
+        let silent = |v: f32| if v == f32::MAX || v == f32::MIN { 0.0 } else { v };
+        decimated.extend(lows.into_iter().map(silent));
+        decimated.extend(highs.into_iter().map(silent));
+    }
+    decimated
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::decimate::decimate;
+
+    #[test]
+    fn extremes_of_every_block() {
+        // Stereo, blocks of three frames, the last one short
+        let samples = [0.1, -0.2, 0.5, 0.0, -0.3, 0.4, 0.2, 0.9];
+        assert_eq!(decimate(&samples, 2, 3), vec![-0.3, -0.2, 0.5, 0.4, 0.2, 0.9, 0.2, 0.9]);
+        assert!(decimate(&[], 2, 3).is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! Short plain language descriptions of recordings, e.g. as alt text for
+//! published waveform images
+use crate::analyze::to_dbfs;
+use crate::format_timestamp;
+use crate::segment::{self, Region, Segment};
+
+
+/// Sections this much louder than the typical level are called loud
+const LOUD_DB: f64 = 6.0;
+
+/// Sections this much quieter than the typical level are called quiet
+const QUIET_DB: f64 = 10.0;
+
+/// Shortest loud or quiet section that is mentioned, in seconds
+const MIN_SECTION: f64 = 3.0;
+
+/// Shortest silence that is mentioned, in seconds
+const MIN_GAP: f64 = 1.0;
+
+/// Most sections of one kind that are listed
+const MAX_LISTED: usize = 5;
+
+/// Duration in words, e.g. "3 minutes 12 seconds"
+fn duration_in_words(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    let unit = |n: u64, word: &str| format!("{n} {word}{}", if n == 1 { "" } else { "s" });
+    match (total / 3600, total / 60 % 60, total % 60) {
+        (0, 0, s) => unit(s.max(if seconds > 0.0 { 1 } else { 0 }), "second"),
+        (0, m, 0) => unit(m, "minute"),
+        (0, m, s) => format!("{} {}", unit(m, "minute"), unit(s, "second")),
+        (h, m, _) => format!("{} {}", unit(h, "hour"), unit(m, "minute")),
+    }
+}
+
+/// Join items as "a", "a and b" or "a, b and c"
+fn enumerate(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
+
+/// Runs of consecutive seconds for which `select` holds, as (start, end)
+/// in seconds, keeping only those of at least `MIN_SECTION`
+fn sections(levels: &[Option<f64>], select: impl Fn(f64) -> bool) -> Vec<(f64, f64)> {
+    let mut runs: Vec<(f64, f64)> = vec![];
+    let mut start = None;
+    for (i, level) in levels.iter().chain(std::iter::once(&None)).enumerate() {
+        match (start, level.is_some_and(&select)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                runs.push((s as f64, i as f64));
+                start = None;
+            },
+            _ => (),
+        }
+    }
+    runs.retain(|(s, e)| e - s >= MIN_SECTION);
+    runs
+}
+
+/// Time ranges as "0:45–1:30", at most `MAX_LISTED` of them
+fn ranges(runs: &[(f64, f64)], duration: f64) -> String {
+    let listed: Vec<String> = runs.iter()
+        .take(MAX_LISTED)
+        .map(|&(s, e)| format!("{}–{}", format_timestamp(s), format_timestamp(e.min(duration))))
+        .collect();
+    let more = runs.len().saturating_sub(MAX_LISTED);
+    if more > 0 {
+        format!("{} (and {more} more)", listed.join(", "))
+    } else {
+        enumerate(&listed)
+    }
+}
+
+/// A few sentences about the length, the content, the loud and quiet
+/// sections and the silences of the interleaved `samples`
+pub fn describe(samples: &[f32], channels: usize, sample_rate: u32) -> String {
+    let channels = channels.max(1);
+    let frames_per_second = sample_rate.max(1) as usize;
+    let duration = samples.len() as f64 / channels as f64 / frames_per_second as f64;
+    let layout = match channels {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        n => format!("{n} channel"),
+    };
+    let mut sentences = vec![format!("A {layout} recording of {}", duration_in_words(duration))];
+
+    let regions = segment::segment(samples, channels, sample_rate);
+    let share = |kind: Segment| regions.iter().filter(|r| r.kind == kind).map(|r| r.end - r.start).sum::<f64>() / duration.max(f64::EPSILON);
+    let (speech, music) = (share(Segment::Speech), share(Segment::Music));
+    if speech + music < 0.05 {
+        sentences.push("It is silent throughout".to_string());
+        return sentences.join(". ") + ".";
+    }
+    sentences.push(match (speech, music) {
+        (s, m) if s > 0.8 * (s + m) => "It consists mostly of speech".to_string(),
+        (s, m) if m > 0.8 * (s + m) => "It consists mostly of music".to_string(),
+        _ => "It mixes speech and music".to_string(),
+    });
+
+    // Level of every full second that is not silent, compared to the median
+    let levels: Vec<Option<f64>> = samples.chunks(frames_per_second * channels)
+        .zip(0..)
+        .map(|(second, i)| {
+            let (_, rms) = crate::analyze::levels(second);
+            (segment::kind_at(&regions, i as f64 + 0.5) != Segment::Silence).then(|| to_dbfs(rms))
+        })
+        .collect();
+    let mut sorted: Vec<f64> = levels.iter().flatten().cloned().collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    // Sounds shorter than the middle of their second have no level to compare
+    if let Some(&median) = sorted.get(sorted.len() / 2) {
+        let loud = sections(&levels, |l| l > median + LOUD_DB);
+        let quiet = sections(&levels, |l| l < median - QUIET_DB);
+        match (loud.is_empty(), quiet.is_empty()) {
+            (true, true) => sentences.push("The level stays fairly even".to_string()),
+            (false, true) => sentences.push(format!("It gets loud at {}", ranges(&loud, duration))),
+            (true, false) => sentences.push(format!("It gets quiet at {}", ranges(&quiet, duration))),
+            (false, false) => sentences.push(format!(
+                "It gets loud at {} and quiet at {}", ranges(&loud, duration), ranges(&quiet, duration)
+            )),
+        }
+    }
+
+    let silences: Vec<&Region> = regions.iter().filter(|r| r.kind == Segment::Silence && r.end - r.start >= MIN_GAP).collect();
+    // Silence at the very start and end is not a gap
+    let (opening, closing) = (silences.first().filter(|r| r.start <= 0.0), silences.last().filter(|r| r.end >= duration - 0.1));
+    if let Some(r) = opening {
+        sentences.push(format!("It opens with {} of silence", duration_in_words(r.end - r.start)));
+    }
+    if let Some(r) = closing {
+        sentences.push(format!("It ends with {} of silence", duration_in_words(r.end - r.start)));
+    }
+    let gaps: Vec<&&Region> = silences.iter()
+        .filter(|r| Some(r) != opening.as_ref() && Some(r) != closing.as_ref())
+        .collect();
+    let describe_gap = |r: &&&Region| format!("{} ({})", format_timestamp(r.start), duration_in_words(r.end - r.start));
+    match gaps.len() {
+        0 => sentences.push("There are no silent gaps".to_string()),
+        n if n <= MAX_LISTED => sentences.push(format!(
+            "It falls silent at {}", enumerate(&gaps.iter().map(describe_gap).collect::<Vec<_>>())
+        )),
+        n => sentences.push(format!(
+            "It falls silent {n} times, first at {}", enumerate(&gaps.iter().take(MAX_LISTED).map(describe_gap).collect::<Vec<_>>())
+        )),
+    }
+    sentences.join(". ") + "."
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::describe::{describe, duration_in_words};
+
+    #[test]
+    fn words_and_structure() {
+        assert_eq!(duration_in_words(192.0), "3 minutes 12 seconds");
+        assert_eq!(duration_in_words(60.4), "1 minute");
+        assert_eq!(duration_in_words(3725.0), "1 hour 2 minutes");
+
+        // 10s of a tone with a 3 second pause in the middle
+        let rate = 1000;
+        let samples: Vec<f32> = (0..10 * rate)
+            .map(|i| if (4000..7000).contains(&i) { 0.0 } else { 0.5 * (i as f32 * 0.3).sin() })
+            .collect();
+        let text = describe(&samples, 1, rate as u32);
+        assert!(text.starts_with("A mono recording of 10 seconds."), "{text}");
+        assert!(text.contains("It falls silent at 0:04 (3 seconds)"), "{text}");
+    }
+
+    #[test]
+    fn shorter_than_a_second() {
+        // No full second to take a level from
+        let rate = 8000;
+        let tone: Vec<f32> = (0..rate * 4 / 10).map(|i| 0.5 * (i as f32 * 0.3).sin()).collect();
+        let text = describe(&tone, 1, rate as u32);
+        assert!(text.starts_with("A mono recording of 1 second."), "{text}");
+        assert!(!text.contains("level"), "{text}");
+        assert_eq!(describe(&[], 2, rate as u32), "A stereo recording of 0 seconds. It is silent throughout.");
+    }
+}
@@ -0,0 +1,206 @@
+//! Dithering of smooth gradients down to 8 bits per channel, which turns the
+//! visible bands of slow fades (e.g. in dark spectrograms) into fine noise
+use std::sync::OnceLock;
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+
+
+/// Side length of the ordered dithering matrix
+const BAYER_SIZE: u32 = 8;
+
+/// Side length of the tiled blue noise texture
+const NOISE_SIZE: usize = 32;
+
+/// Spread of the energy function used to place the blue noise thresholds
+const NOISE_SIGMA: f64 = 1.5;
+
+/// How values between two 8 bit levels are rounded
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the nearest level
+    None,
+    /// Bayer matrix, a regular fine pattern
+    Ordered,
+    /// Blue noise, an irregular pattern without visible structure
+    BlueNoise,
+}
+
+/// Filter of the final downscale from the oversampled width
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Nearest pixel, hard edges without anti-aliasing
+    Nearest,
+    /// Linear, soft but without halos
+    Triangle,
+    /// Cubic, sharper with slight halos at hard edges
+    #[value(name = "catmullrom")]
+    CatmullRom,
+    /// Sharpest, with visible halos (ringing) at hard edges like bars
+    Lanczos3,
+}
+
+impl Filter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl Dither {
+    /// Threshold from 0.0 to 1.0 at which the pixel at `x`, `y` rounds up
+    fn threshold(self, x: u32, y: u32) -> f32 {
+        match self {
+            Dither::None => 0.5,
+            Dither::Ordered => (bayer(x % BAYER_SIZE, y % BAYER_SIZE) as f32 + 0.5) / (BAYER_SIZE * BAYER_SIZE) as f32,
+            Dither::BlueNoise => blue_noise()[(y as usize % NOISE_SIZE) * NOISE_SIZE + x as usize % NOISE_SIZE],
+        }
+    }
+
+    /// `value` from 0.0 to 255.0 rounded to a level for the pixel at `x`, `y`
+    pub fn quantize(self, value: f32, x: u32, y: u32) -> u8 {
+        (value + self.threshold(x, y)).floor().clamp(0.0, 255.0) as u8
+    }
+
+    /// Linear interpolation between two colors for the pixel at `x`, `y`
+    pub fn blend(self, a: Rgba<u8>, b: Rgba<u8>, t: f64, x: u32, y: u32) -> Rgba<u8> {
+        Rgba(std::array::from_fn(|i| self.quantize((a[i] as f64 + (b[i] as f64 - a[i] as f64) * t) as f32, x, y)))
+    }
+}
+
+/// Entry of the Bayer matrix at `x`, `y`, from 0 to BAYER_SIZE² - 1
+fn bayer(x: u32, y: u32) -> u32 {
+    (0..BAYER_SIZE.trailing_zeros()).fold(0, |value, bit| {
+        let (x, y) = ((x >> bit) & 1, (y >> bit) & 1);
+        (value << 2) | ((x ^ y) << 1) | y
+    })
+}
+
+/// Thresholds of the blue noise texture, computed once
+fn blue_noise() -> &'static [f32] {
+    static NOISE: OnceLock<Vec<f32>> = OnceLock::new();
+    NOISE.get_or_init(|| void_and_cluster(NOISE_SIZE))
+}
+
+/// Blue noise thresholds for a tileable `size` × `size` texture by the
+/// void-and-cluster method: every pixel is ranked by the order in which it
+/// fills the largest void left by the pixels ranked before it
+fn void_and_cluster(size: usize) -> Vec<f32> {
+    let n = size * size;
+    // Energy one pixel adds at every (wrapped) offset
+    let kernel: Vec<f64> = (0..n)
+        .map(|i| {
+            let (dx, dy) = (i % size, i / size);
+            let (dx, dy) = (dx.min(size - dx) as f64, dy.min(size - dy) as f64);
+            (-(dx * dx + dy * dy) / (2.0 * NOISE_SIGMA * NOISE_SIGMA)).exp()
+        })
+        .collect();
+    let update = |energy: &mut [f64], p: usize, sign: f64| {
+        for (i, e) in energy.iter_mut().enumerate() {
+            let dx = (i % size + size - p % size) % size;
+            let dy = (i / size + size - p / size) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+        (0..n).filter(|&i| pattern[i]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f64]| {
+        (0..n).filter(|&i| !pattern[i]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Deterministic pseudo random initial pattern
+    let mut state = 0x9E3779B97F4A7C15u64;
+    let (mut pattern, mut energy) = (vec![false; n], vec![0.0; n]);
+    let initial = n / 10;
+    let mut count = 0;
+    while count < initial {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let p = state as usize % n;
+        if !pattern[p] {
+            pattern[p] = true;
+            update(&mut energy, p, 1.0);
+            count += 1;
+        }
+    }
+    // Move pixels from clusters into voids until the pattern is even
+    for _ in 0..n {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; n];
+    let (even, even_energy) = (pattern.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    (pattern, energy) = (even, even_energy);
+    for r in initial..n {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+    rank.into_iter().map(|r| (r as f32 + 0.5) / n as f32).collect()
+}
+
+/// Resize `img` with the `filter`. Dithering keeps the precision of the
+/// filtered values instead of rounding them to the nearest level
+pub fn resize(img: &RgbaImage, width: u32, height: u32, filter: Filter, dither: Dither) -> RgbaImage {
+    let filter = filter.filter_type();
+    if dither == Dither::None {
+        return image::imageops::resize(img, width, height, filter);
+    }
+    let precise = DynamicImage::ImageRgba8(img.clone()).into_rgba32f();
+    let resized = image::imageops::resize(&precise, width, height, filter);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let pixel = resized.get_pixel(x, y);
+        Rgba(std::array::from_fn(|i| dither.quantize(pixel[i] * 255.0, x, y)))
+    })
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::dither::{blue_noise, Dither, BAYER_SIZE, NOISE_SIZE};
+
+    #[test]
+    fn levels_average_out() {
+        // Every threshold appears exactly once per tile
+        for (dither, size) in [(Dither::Ordered, BAYER_SIZE), (Dither::BlueNoise, NOISE_SIZE as u32)] {
+            let mut thresholds: Vec<f32> = (0..size * size).map(|i| dither.threshold(i % size, i / size)).collect();
+            thresholds.sort_by(f32::total_cmp);
+            let n = thresholds.len() as f32;
+            assert!(thresholds.iter().enumerate().all(|(i, &t)| (t - (i as f32 + 0.5) / n).abs() < 1e-6), "{dither:?}");
+
+            // A value between two levels is reproduced on average, whole
+            // levels stay untouched
+            let sum: u32 = (0..size * size).map(|i| dither.quantize(100.25, i % size, i / size) as u32).sum();
+            assert!((sum as f32 / n - 100.25).abs() < 0.02, "{dither:?}");
+            assert!((0..size * size).all(|i| dither.quantize(100.0, i % size, i / size) == 100));
+        }
+        assert_eq!(Dither::None.quantize(100.5, 3, 7), 101);
+
+        // Neighbors in blue noise are far apart in rank
+        let noise = blue_noise();
+        let close = (0..noise.len()).filter(|&i| (noise[i] - noise[(i + 1) % noise.len()]).abs() < 0.02).count();
+        assert!(close < noise.len() / 20);
+    }
+}
@@ -0,0 +1,157 @@
+//! `wellenformer doctor`: the build, the codecs, the CPU features and thread
+//! counts in use and a short benchmark, to include with bug reports and to
+//! check a deployment
+use std::time::{Duration, Instant};
+use colored::Colorize;
+use symphonia::core::codecs::{
+    CodecType, CODEC_TYPE_AAC, CODEC_TYPE_ADPCM_IMA_WAV, CODEC_TYPE_ALAC, CODEC_TYPE_FLAC, CODEC_TYPE_MP1,
+    CODEC_TYPE_MP2, CODEC_TYPE_MP3, CODEC_TYPE_OPUS, CODEC_TYPE_PCM_S16LE, CODEC_TYPE_VORBIS,
+};
+
+use crate::render::{render_waveform, RenderMode, RenderOptions};
+
+
+/// Codecs worth asking about, by the name users know them by
+const CODECS: &[(&str, CodecType)] = &[
+    ("PCM", CODEC_TYPE_PCM_S16LE),
+    ("ADPCM", CODEC_TYPE_ADPCM_IMA_WAV),
+    ("MP1", CODEC_TYPE_MP1),
+    ("MP2", CODEC_TYPE_MP2),
+    ("MP3", CODEC_TYPE_MP3),
+    ("AAC", CODEC_TYPE_AAC),
+    ("Vorbis", CODEC_TYPE_VORBIS),
+    ("Opus", CODEC_TYPE_OPUS),
+    ("FLAC", CODEC_TYPE_FLAC),
+    ("ALAC", CODEC_TYPE_ALAC),
+];
+
+/// Length of the signal rendered by the benchmark in seconds
+const BENCHMARK_SECONDS: usize = 10;
+
+/// Sample rate of the benchmark signal
+const BENCHMARK_SAMPLE_RATE: usize = 44100;
+
+/// Names of the `CODECS` the decoder registry holds, then of those it lacks
+fn codecs() -> (Vec<&'static str>, Vec<&'static str>) {
+    let registry = symphonia::default::get_codecs();
+    let (available, missing): (Vec<_>, Vec<_>) = CODECS.iter().partition(|(_, codec)| registry.get_codec(*codec).is_some());
+    let names = |codecs: Vec<&(&'static str, CodecType)>| codecs.into_iter().map(|(name, _)| *name).collect();
+    (names(available), names(missing))
+}
+
+/// SIMD extensions the binary was compiled to use
+fn compiled_features() -> Vec<&'static str> {
+    let features = [
+        ("sse2", cfg!(target_feature = "sse2")),
+        ("sse4.1", cfg!(target_feature = "sse4.1")),
+        ("avx", cfg!(target_feature = "avx")),
+        ("avx2", cfg!(target_feature = "avx2")),
+        ("fma", cfg!(target_feature = "fma")),
+        ("avx512f", cfg!(target_feature = "avx512f")),
+        ("neon", cfg!(target_feature = "neon")),
+        ("simd128", cfg!(target_feature = "simd128")),
+    ];
+    features.into_iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name).collect()
+}
+
+/// SIMD extensions the CPU offers beyond the compiled ones
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detected_features() -> Vec<&'static str> {
+    let features = [
+        ("sse4.1", std::is_x86_feature_detected!("sse4.1")),
+        ("avx", std::is_x86_feature_detected!("avx")),
+        ("avx2", std::is_x86_feature_detected!("avx2")),
+        ("fma", std::is_x86_feature_detected!("fma")),
+        ("avx512f", std::is_x86_feature_detected!("avx512f")),
+    ];
+    features.into_iter().filter(|(_, detected)| *detected).map(|(name, _)| name).collect()
+}
+
+/// SIMD extensions the CPU offers beyond the compiled ones
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn detected_features() -> Vec<&'static str> {
+    vec![]
+}
+
+/// A stereo tone with a slow tremolo, so every column differs
+fn benchmark_signal() -> Vec<f32> {
+    let frames = BENCHMARK_SECONDS * BENCHMARK_SAMPLE_RATE;
+    (0..frames)
+        .flat_map(|i| {
+            let t = i as f32 / BENCHMARK_SAMPLE_RATE as f32;
+            let level = 0.5 + 0.4 * (t * 0.7).sin();
+            let s = level * (t * 2.0 * std::f32::consts::PI * 220.0).sin();
+            [s, 0.8 * s]
+        })
+        .collect()
+}
+
+/// Time taken to render the `samples` in `mode` at 1920 × 120 pixels with
+/// eightfold oversampling
+fn benchmark(samples: &[f32], mode: RenderMode) -> Duration {
+    let options = RenderOptions {
+        width: 1920,
+        height: 120,
+        oversample: 8,
+        channels: 2,
+        sample_rate: BENCHMARK_SAMPLE_RATE as u32,
+        ..RenderOptions::new(mode)
+    };
+    let start = Instant::now();
+    render_waveform(samples, samples.len() as f64 / (options.width * options.oversample) as f64, &options);
+    start.elapsed()
+}
+
+/// Print the report
+pub fn run() {
+    println!("{}", format!("wellenformer {}", env!("CARGO_PKG_VERSION")).bold());
+    let profile = if cfg!(debug_assertions) { "debug (slow, build with --release)" } else { "release" };
+    println!("Build:       {profile} for {} on {}", std::env::consts::ARCH, std::env::consts::OS);
+    println!("Features:    none, every build includes all modes and formats");
+
+    let (available, missing) = codecs();
+    println!("Codecs:      {}", available.join(", "));
+    if !missing.is_empty() {
+        println!("Missing:     {}", missing.join(", ").dimmed());
+    }
+
+    let compiled = compiled_features();
+    let unused: Vec<_> = detected_features().into_iter().filter(|feature| !compiled.contains(feature)).collect();
+    println!("SIMD:        no hand-written paths, loops are vectorized by the compiler for {}", match compiled.is_empty() {
+        true => "the baseline of the target".to_string(),
+        false => compiled.join(", "),
+    });
+    if !unused.is_empty() {
+        println!("             the CPU also offers {} (build with RUSTFLAGS=\"-C target-cpu=native\" to use them)", unused.join(", "));
+    }
+
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let source = match std::env::var("RAYON_NUM_THREADS") {
+        Ok(value) => format!(" (RAYON_NUM_THREADS={value})"),
+        Err(_) => String::new(),
+    };
+    println!("Threads:     {} rendering threads on {cores} available cores{source}", rayon::current_num_threads());
+
+    let samples = benchmark_signal();
+    for (name, mode) in [("minmax", RenderMode::MinMax), ("spectrogram", RenderMode::Spectrogram)] {
+        let elapsed = benchmark(&samples, mode);
+        let speed = BENCHMARK_SECONDS as f64 / elapsed.as_secs_f64().max(1e-9);
+        println!("Benchmark:   {BENCHMARK_SECONDS} s of stereo audio in the {name} mode in {elapsed:.2?} ({speed:.0}× real time)");
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::doctor::codecs;
+
+    #[test]
+    fn codecs_of_the_enabled_formats() {
+        let (available, missing) = codecs();
+        for codec in ["PCM", "MP3", "AAC", "Vorbis", "FLAC"] {
+            assert!(available.contains(&codec), "{codec}");
+        }
+        assert!(missing.iter().all(|codec| !available.contains(codec)));
+    }
+}
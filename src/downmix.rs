@@ -0,0 +1,86 @@
+//! Downmixing of surround audio to fewer channels with the coefficients of
+//! ITU-R BS.775, so 5.1 or 7.1 content renders like its stereo version
+use std::f32::consts::FRAC_1_SQRT_2;
+use clap::ValueEnum;
+
+
+/// Channel layout to mix down to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Mono,
+    Stereo,
+}
+
+impl Target {
+    /// Number of channels of the layout
+    pub fn channels(self) -> usize {
+        match self {
+            Target::Mono => 1,
+            Target::Stereo => 2,
+        }
+    }
+}
+
+/// Weight of every input channel in the left and the right output. Layouts
+/// follow the WAV channel order (front left, front right, center, LFE, rear
+/// left, rear right, side left, side right), the LFE is left out. Layouts
+/// with more than eight channels alternate between left and right
+fn matrix(channels: usize) -> Vec<[f32; 2]> {
+    let (left, right, both, surround_left, surround_right) = ([1.0, 0.0], [0.0, 1.0], [FRAC_1_SQRT_2; 2], [FRAC_1_SQRT_2, 0.0], [0.0, FRAC_1_SQRT_2]);
+    let lfe = [0.0; 2];
+    match channels {
+        1 => vec![[1.0; 2]],
+        2 => vec![left, right],
+        3 => vec![left, right, both],
+        4 => vec![left, right, surround_left, surround_right],
+        5 => vec![left, right, both, surround_left, surround_right],
+        6 => vec![left, right, both, lfe, surround_left, surround_right],
+        7 => vec![left, right, both, lfe, both, surround_left, surround_right],
+        8 => vec![left, right, both, lfe, surround_left, surround_right, surround_left, surround_right],
+        _ => (0..channels).map(|c| if c % 2 == 0 { left } else { right }).collect(),
+    }
+}
+
+/// Mix the interleaved `samples` of `channels` channels down to `target`.
+/// Every output is scaled so its weights sum to one and full scale inputs
+/// can't clip
+pub fn downmix(samples: &[f32], channels: usize, target: Target) -> Vec<f32> {
+    let channels = channels.max(1);
+    let matrix = matrix(channels);
+    let totals: [f32; 2] = std::array::from_fn(|side| matrix.iter().map(|weights| weights[side]).sum::<f32>().max(f32::MIN_POSITIVE));
+    let mut mixed = Vec::with_capacity(samples.len() / channels * target.channels());
+    for frame in samples.chunks_exact(channels) {
+        let [left, right]: [f32; 2] = std::array::from_fn(|side| {
+            frame.iter().zip(&matrix).map(|(sample, weights)| sample * weights[side]).sum::<f32>() / totals[side]
+        });
+        match target {
+            Target::Mono => mixed.push((left + right) / 2.0),
+            Target::Stereo => mixed.extend([left, right]),
+        }
+    }
+    mixed
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::downmix::{downmix, Target};
+
+    #[test]
+    fn surround_folds_into_stereo() {
+        // One 5.1 frame with only the center and one with only the LFE
+        let samples = [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let stereo = downmix(&samples, 6, Target::Stereo);
+        assert_eq!(stereo.len(), 4);
+        assert!((stereo[0] - stereo[1]).abs() < 1e-6 && stereo[0] > 0.2);
+        assert_eq!(&stereo[2..], &[0.0, 0.0]);
+        // Full scale on every channel stays at full scale
+        let loud = downmix(&[1.0; 6], 6, Target::Stereo);
+        assert!(loud.iter().all(|s| (s - 1.0).abs() < 1e-6));
+
+        assert_eq!(downmix(&[0.5, -0.5, 1.0, 0.0], 2, Target::Stereo), vec![0.5, -0.5, 1.0, 0.0]);
+        assert_eq!(downmix(&[0.5, 1.0], 2, Target::Mono), vec![0.75]);
+        assert_eq!(downmix(&[0.25], 1, Target::Stereo), vec![0.25, 0.25]);
+    }
+}
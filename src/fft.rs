@@ -0,0 +1,116 @@
+//! A small radix-2 FFT, enough for correlation and spectral analysis without
+//! pulling in an extra dependency
+use std::f64::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(self.re * o.re - self.im * o.im, self.re * o.im + self.im * o.re)
+    }
+}
+
+/// In-place FFT of `data`, whose length must be a power of two.
+/// With `inverse` set the inverse transform is computed (including the 1/n scaling)
+pub fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    assert!(n.is_power_of_two(), "FFT length must be a power of two");
+    if n < 2 {
+        return;
+    }
+
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut length = 2;
+    while length <= n {
+        let angle = sign * 2.0 * PI / length as f64;
+        let step = Complex::new(angle.cos(), angle.sin());
+        for chunk in data.chunks_mut(length) {
+            let mut w = Complex::new(1.0, 0.0);
+            let (a, b) = chunk.split_at_mut(length / 2);
+            for k in 0..length / 2 {
+                let t = w * b[k];
+                b[k] = a[k] - t;
+                a[k] = a[k] + t;
+                w = w * step;
+            }
+        }
+        length <<= 1;
+    }
+
+    if inverse {
+        let scale = 1.0 / n as f64;
+        for c in data.iter_mut() {
+            c.re *= scale;
+            c.im *= scale;
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fft::{fft, Complex};
+
+    #[test]
+    fn round_trip_and_single_bin() {
+        let n = 16;
+        let original: Vec<Complex> = (0..n)
+            .map(|i| Complex::new((2.0 * std::f64::consts::PI * 3.0 * i as f64 / n as f64).cos(), 0.0))
+            .collect();
+        let mut data = original.clone();
+        fft(&mut data, false);
+        // A cosine with three periods puts half of its energy into bin 3
+        assert!((data[3].re.hypot(data[3].im) - n as f64 / 2.0).abs() < 1e-9);
+        assert!(data[5].re.hypot(data[5].im) < 1e-9);
+        fft(&mut data, true);
+        for (a, b) in data.iter().zip(original.iter()) {
+            assert!((a.re - b.re).abs() < 1e-9 && a.im.abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Crossover filters splitting a signal into low, mid and high bands, like
+//! the colored waveforms of DJ software
+use crate::color::split_list;
+use crate::number::parse_number;
+
+
+/// Highest crossover frequency as a share of the sample rate, which keeps
+/// the filters stable
+const MAX_CROSSOVER: f64 = 0.45;
+
+/// Second order filter section (transposed direct form II)
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    /// Section with the normalized feedforward `b` and feedback `a` coefficients
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Biquad {
+        Biquad { b, a, state: [0.0; 2] }
+    }
+
+    /// Butterworth low pass (`high == false`) or high pass at `frequency`
+    fn butterworth(frequency: f64, sample_rate: u32, high: bool) -> Biquad {
+        let frequency = frequency.clamp(1.0, sample_rate as f64 * MAX_CROSSOVER);
+        let w = 2.0 * std::f64::consts::PI * frequency / sample_rate as f64;
+        let alpha = w.sin() / std::f64::consts::SQRT_2;
+        let a0 = 1.0 + alpha;
+        let cos = w.cos();
+        let b = if high {
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0]
+        } else {
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0]
+        };
+        Biquad::new(b.map(|b| b / a0), [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Fourth order Linkwitz-Riley crossover (two Butterworth sections per
+/// side), whose bands add up to the input with a flat magnitude
+#[derive(Debug, Clone, Copy)]
+struct Crossover {
+    low: [Biquad; 2],
+    high: [Biquad; 2],
+}
+
+impl Crossover {
+    fn new(frequency: f64, sample_rate: u32) -> Crossover {
+        Crossover {
+            low: [Biquad::butterworth(frequency, sample_rate, false); 2],
+            high: [Biquad::butterworth(frequency, sample_rate, true); 2],
+        }
+    }
+
+    /// The part of `x` below and above the crossover frequency
+    fn process(&mut self, x: f64) -> (f64, f64) {
+        let low = self.low.iter_mut().fold(x, |x, section| section.process(x));
+        let high = self.high.iter_mut().fold(x, |x, section| section.process(x));
+        (low, high)
+    }
+}
+
+/// Split the interleaved `samples` into low, mid and high bands (interleaved
+/// as well) at the two `crossovers` in Hz
+pub fn split(samples: &[f32], channels: usize, sample_rate: u32, crossovers: (f64, f64)) -> [Vec<f32>; 3] {
+    let channels = channels.max(1);
+    let mut filters = vec![(Crossover::new(crossovers.0, sample_rate), Crossover::new(crossovers.1, sample_rate)); channels];
+    let mut bands: [Vec<f32>; 3] = std::array::from_fn(|_| Vec::with_capacity(samples.len()));
+    for (i, &sample) in samples.iter().enumerate() {
+        let (lower, upper) = &mut filters[i % channels];
+        let (low, rest) = lower.process(sample as f64);
+        let (mid, high) = upper.process(rest);
+        for (band, value) in bands.iter_mut().zip([low, mid, high]) {
+            band.push(value as f32);
+        }
+    }
+    bands
+}
+
+/// Parse the two crossover frequencies in Hz between the low, mid and high
+/// bands, e.g. "200,2000"
+pub fn parse_crossovers(argument: &str) -> Result<(f64, f64), String> {
+    let error = || format!("\"{argument}\" are not valid crossovers (expected two rising frequencies in Hz, e.g. \"200,2000\")");
+    let frequencies: Vec<f64> = split_list(argument).into_iter()
+        .map(|f| parse_number(f.trim().trim_end_matches("Hz").trim()).map_err(|_| error()))
+        .collect::<Result<_, _>>()?;
+    match frequencies[..] {
+        [low, high] if low > 0.0 && high > low => Ok((low, high)),
+        _ => Err(error()),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::filterbank::{parse_crossovers, split};
+
+    #[test]
+    fn tones_land_in_their_band() {
+        assert_eq!(parse_crossovers("200, 2000Hz"), Ok((200.0, 2000.0)));
+        assert_eq!(parse_crossovers("150,5;2000"), Ok((150.5, 2000.0)));
+        assert!(parse_crossovers("2000,200").is_err());
+
+        let sample_rate = 16000;
+        let level = |band: &[f32]| band[4000..].iter().fold(0.0f32, |a, s| a.max(s.abs()));
+        for (frequency, expected) in [(50.0, 0), (700.0, 1), (6000.0, 2)] {
+            let tone: Vec<f32> = (0..8000).map(|i| (2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate as f64).sin() as f32).collect();
+            let bands = split(&tone, 1, sample_rate, (200.0, 2000.0));
+            for (i, band) in bands.iter().enumerate() {
+                let level = level(band);
+                assert!(if i == expected { level > 0.8 } else { level < 0.2 }, "{frequency} Hz in band {i}: {level}");
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Hatched marking of time ranges missing from the input (skipped packets,
+//! stream dropouts), so the image stays time accurate for forensic review
+use std::ops::Range;
+use image::{Rgba, RgbaImage};
+
+use crate::audio::Gap;
+
+
+/// Distance between the hatching lines in pixels
+const HATCH_SPACING: u32 = 6;
+
+/// Width of the hatching lines in pixels
+const HATCH_WIDTH: u32 = 2;
+
+/// Whether any of the `gaps` lies within the `frames`
+pub fn overlaps(gaps: &[Gap], frames: Range<usize>) -> bool {
+    gaps.iter().any(|gap| gap.start < frames.end && gap.start + gap.frames > frames.start)
+}
+
+/// Replace the columns of `img` for which `missing` is true with diagonal
+/// lines in `color` on the `background`
+pub fn hatch(img: &mut RgbaImage, missing: impl Fn(u32) -> bool, color: Rgba<u8>, background: Rgba<u8>) {
+    for x in (0..img.width()).filter(|&x| missing(x)) {
+        for y in 0..img.height() {
+            let on_line = (x + y) % HATCH_SPACING < HATCH_WIDTH;
+            img.put_pixel(x, y, if on_line { color } else { background });
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::audio::Gap;
+    use crate::gaps::{hatch, overlaps};
+
+    #[test]
+    fn gaps_are_hatched() {
+        let gaps = [Gap { start: 100, frames: 50 }];
+        assert!(overlaps(&gaps, 140..160) && overlaps(&gaps, 90..101));
+        assert!(!overlaps(&gaps, 0..100) && !overlaps(&gaps, 150..200));
+
+        let (ink, paper) = (Rgba([128, 128, 128, 255]), Rgba([0, 0, 0, 0]));
+        let mut img = RgbaImage::from_pixel(20, 12, Rgba([0, 0, 0, 255]));
+        hatch(&mut img, |x| (5..10).contains(&x), ink, paper);
+        // Untouched outside, lines and background inside
+        assert!((0..12).all(|y| img.get_pixel(4, y)[3] == 255 && img.get_pixel(10, y)[3] == 255));
+        let inked = (5..10).flat_map(|x| (0..12).map(move |y| (x, y))).filter(|&(x, y)| img.get_pixel(x, y) == &ink).count();
+        assert_eq!(inked, 5 * 12 * 2 / 6);
+    }
+}
@@ -0,0 +1,104 @@
+//! Interpolation between the samples when zoomed in so far that a pixel
+//! column covers less than a frame, so the waveform is drawn as the
+//! continuous signal the samples stand for instead of scattered dots
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+
+/// Frames per column below which the samples are interpolated
+pub const MIN_FRAMES_PER_PIXEL: f64 = 1.0;
+
+/// Frames every internal column holds after interpolating
+pub const INTERPOLATED_FRAMES_PER_PIXEL: f64 = 4.0;
+
+/// Upper bound of the number of samples after interpolating, which keeps
+/// the strong zoom of the first columns of logarithmic time scales in check
+pub const MAX_INTERPOLATED_SAMPLES: usize = 1 << 24;
+
+/// Zero crossings of the windowed sinc on either side
+const SINC_TAPS: isize = 8;
+
+/// How the signal between two samples is reconstructed
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Band-limited (Lanczos windowed sinc), the signal a DAC would output
+    Sinc,
+    /// Straight lines from sample to sample
+    Linear,
+    /// Every sample held until the next one, showing the stored values as steps
+    Hold,
+}
+
+/// Normalized sinc, sin(πx) / πx
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        return 1.0;
+    }
+    let x = std::f64::consts::PI * x;
+    x.sin() / x
+}
+
+/// The interleaved `samples` with `factor` frames per original frame, the
+/// first of them at the original frame. Frames past the edges count as the
+/// first and the last frame, so the edges don't ring
+pub fn upsample(samples: &[f32], channels: usize, factor: usize, interpolation: Interpolation) -> Vec<f32> {
+    let channels = channels.max(1);
+    let factor = factor.max(1);
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return vec![];
+    }
+    let at = |frame: isize, channel: usize| samples[frame.clamp(0, frames as isize - 1) as usize * channels + channel] as f64;
+
+    (0..frames * factor).into_par_iter()
+        .flat_map_iter(|j| {
+            let frame = (j / factor) as isize;
+            let t = (j % factor) as f64 / factor as f64;
+            (0..channels).map(move |c| {
+                let value = match interpolation {
+                    Interpolation::Hold => at(frame, c),
+                    Interpolation::Linear => at(frame, c) * (1.0 - t) + at(frame + 1, c) * t,
+                    Interpolation::Sinc if t == 0.0 => at(frame, c),
+                    Interpolation::Sinc => {
+                        // Normalized by the weights, so a constant stays constant
+                        let (sum, total) = (frame - SINC_TAPS + 1..=frame + SINC_TAPS)
+                            .map(|k| {
+                                let x = t + (frame - k) as f64;
+                                let weight = sinc(x) * sinc(x / SINC_TAPS as f64);
+                                (at(k, c) * weight, weight)
+                            })
+                            .fold((0.0, 0.0), |(sum, total), (v, w)| (sum + v, total + w));
+                        sum / total
+                    },
+                };
+                value as f32
+            })
+        })
+        .collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::interpolate::{upsample, Interpolation};
+
+    #[test]
+    fn between_the_samples() {
+        // Stereo, the right channel the negated left one
+        let samples = [0.0, 0.0, 1.0, -1.0];
+        assert_eq!(upsample(&samples, 2, 2, Interpolation::Linear), vec![0.0, 0.0, 0.5, -0.5, 1.0, -1.0, 1.0, -1.0]);
+        assert_eq!(upsample(&samples, 2, 2, Interpolation::Hold), vec![0.0, 0.0, 0.0, 0.0, 1.0, -1.0, 1.0, -1.0]);
+
+        // A band-limited sine is reconstructed between its samples
+        let frequency = 0.05;
+        let sine: Vec<f32> = (0..200).map(|i| (2.0 * std::f64::consts::PI * frequency * i as f64).sin() as f32).collect();
+        let upsampled = upsample(&sine, 1, 8, Interpolation::Sinc);
+        assert_eq!(upsampled.len(), 1600);
+        assert_eq!(upsampled[800], sine[100]);
+        for (j, &s) in upsampled.iter().enumerate().skip(400).take(800) {
+            let expected = (2.0 * std::f64::consts::PI * frequency * j as f64 / 8.0).sin();
+            assert!((s as f64 - expected).abs() < 0.01, "{j}: {s} instead of {expected}");
+        }
+    }
+}
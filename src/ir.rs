@@ -0,0 +1,123 @@
+//! Impulse response analysis: energy decay, reverberation times and the
+//! energy-time curve
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+
+/// Reverberation times of an impulse response in seconds, None where the
+/// decay does not reach the required level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reverberation {
+    pub edt: Option<f64>,
+    pub t20: Option<f64>,
+    pub t30: Option<f64>,
+}
+
+impl Reverberation {
+    /// Best available RT60 estimate
+    pub fn rt60(&self) -> Option<f64> {
+        self.t30.or(self.t20)
+    }
+}
+
+/// Squared mono signal starting at its peak (the direct sound)
+pub fn energy_from_peak(mono: &[f32]) -> Vec<f64> {
+    let peak = mono.iter().enumerate()
+        .fold((0, 0.0f32), |(i, m), (j, &s)| if s.abs() > m { (j, s.abs()) } else { (i, m) })
+        .0;
+    mono[peak..].iter().map(|&s| (s as f64) * (s as f64)).collect()
+}
+
+/// Schroeder backward integrated energy decay curve in dB (0 dB at the start)
+pub fn energy_decay_curve(energy: &[f64]) -> Vec<f64> {
+    let mut remaining = 0.0;
+    let mut curve: Vec<f64> = energy.iter().rev()
+        .map(|e| { remaining += e; remaining })
+        .collect();
+    curve.reverse();
+    let total = curve.first().copied().unwrap_or(0.0).max(f64::MIN_POSITIVE);
+    curve.iter().map(|e| 10.0 * (e / total).max(1e-30).log10()).collect()
+}
+
+/// Time in seconds for a decay of 60 dB, extrapolated from a linear fit of
+/// the decay curve between `start` and `end` dB
+fn decay_time(curve: &[f64], sample_rate: u32, start: f64, end: f64) -> Option<f64> {
+    let first = curve.iter().position(|&db| db <= start)?;
+    let last = curve.iter().position(|&db| db <= end)?;
+    if last <= first + 1 {
+        return None;
+    }
+    // Least squares fit of dB over time
+    let n = (last - first) as f64;
+    let (mut sum_t, mut sum_db, mut sum_tt, mut sum_tdb) = (0.0, 0.0, 0.0, 0.0);
+    for (i, db) in curve[first..last].iter().enumerate() {
+        let t = (first + i) as f64 / sample_rate as f64;
+        sum_t += t;
+        sum_db += db;
+        sum_tt += t * t;
+        sum_tdb += t * db;
+    }
+    let slope = (n * sum_tdb - sum_t * sum_db) / (n * sum_tt - sum_t * sum_t);
+    if slope < 0.0 { Some(-60.0 / slope) } else { None }
+}
+
+pub fn reverberation(curve: &[f64], sample_rate: u32) -> Reverberation {
+    Reverberation {
+        edt: decay_time(curve, sample_rate, 0.0, -10.0),
+        t20: decay_time(curve, sample_rate, -5.0, -25.0),
+        t30: decay_time(curve, sample_rate, -5.0, -35.0),
+    }
+}
+
+/// Plot the energy-time curve (as filled columns) and the decay curve (as a
+/// line) on a dB scale from 0 dB at the top to `range` dB at the bottom
+pub fn render_etc(energy: &[f64], curve: &[f64], width: u32, height: u32, range: f64, colors: [Rgba<u8>; 3]) -> RgbaImage {
+    let [etc_color, decay_color, background] = colors;
+    let peak = energy.iter().fold(0.0f64, |a, &b| a.max(b)).max(f64::MIN_POSITIVE);
+    let samples_per_column = energy.len() as f64 / width as f64;
+    let to_y = |db: f64| ((db / range).clamp(0.0, 1.0) * (height - 1) as f64).round() as u32;
+
+    let mut img = ImageBuffer::from_pixel(width, height, background);
+    let mut previous: Option<u32> = None;
+    for x in 0..width {
+        let start = (x as f64 * samples_per_column) as usize;
+        let end = (((x + 1) as f64 * samples_per_column) as usize).max(start + 1).min(energy.len());
+        if start >= end {
+            break;
+        }
+        let column_peak = energy[start..end].iter().fold(0.0f64, |a, &b| a.max(b));
+        let top = to_y(10.0 * (column_peak / peak).max(1e-30).log10());
+        for y in top..height {
+            img.put_pixel(x, y, etc_color);
+        }
+        // Connect to the previous column so steep parts stay continuous
+        let y = to_y(curve[start]);
+        let (from, to) = match previous {
+            Some(p) => (p.min(y), p.max(y)),
+            None => (y, y),
+        };
+        for y in from..=to {
+            img.put_pixel(x, y, decay_color);
+        }
+        previous = Some(y);
+    }
+    img
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{energy_decay_curve, reverberation};
+
+    #[test]
+    fn exponential_decay() {
+        // Energy decaying by 60 dB per second
+        let sample_rate = 1000;
+        let energy: Vec<f64> = (0..3000).map(|i| 10f64.powf(-6.0 * i as f64 / sample_rate as f64)).collect();
+        let curve = energy_decay_curve(&energy);
+        let reverb = reverberation(&curve, sample_rate);
+        assert!((reverb.t30.unwrap() - 1.0).abs() < 0.02);
+        assert!((reverb.edt.unwrap() - 1.0).abs() < 0.02);
+    }
+}
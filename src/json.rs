@@ -0,0 +1,137 @@
+use std::fmt;
+
+
+/// Version of the layout of the documents written for other programs (see
+/// "Machine-readable outputs" in the README). Raised whenever a field is
+/// removed, renamed or changes its meaning, while new fields may appear
+/// within a version
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A minimal JSON value used for all machine-readable output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Build an object from key/value pairs, keeping their order
+    pub fn object<K: Into<String>>(pairs: Vec<(K, Json)>) -> Json {
+        Json::Object(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Build a top-level document of the given `schema` (e.g. "stats"),
+    /// which starts with the name and version of its schema
+    pub fn document<K: Into<String>>(schema: &str, pairs: Vec<(K, Json)>) -> Json {
+        let header = [("schema".to_string(), Json::from(schema)), ("schema_version".to_string(), Json::from(SCHEMA_VERSION as f64))];
+        Json::Object(header.into_iter().chain(pairs.into_iter().map(|(k, v)| (k.into(), v))).collect())
+    }
+
+    /// The object with `key` and `value` appended, anything else unchanged
+    pub fn with<K: Into<String>>(self, key: K, value: Json) -> Json {
+        match self {
+            Json::Object(mut pairs) => {
+                pairs.push((key.into(), value));
+                Json::Object(pairs)
+            },
+            other => other,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pad = "  ".repeat(indent + 1);
+        let end_pad = "  ".repeat(indent);
+        match self {
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) if items.is_empty() => write!(f, "[]"),
+            Json::Array(items) => {
+                writeln!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{pad}")?;
+                    item.write(f, indent + 1)?;
+                    writeln!(f, "{}", if i + 1 < items.len() { "," } else { "" })?;
+                }
+                write!(f, "{end_pad}]")
+            },
+            Json::Object(pairs) if pairs.is_empty() => write!(f, "{{}}"),
+            Json::Object(pairs) => {
+                writeln!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    write!(f, "{pad}")?;
+                    write_string(f, key)?;
+                    write!(f, ": ")?;
+                    value.write(f, indent + 1)?;
+                    writeln!(f, "{}", if i + 1 < pairs.len() { "," } else { "" })?;
+                }
+                write!(f, "{end_pad}}}")
+            },
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{Json, SCHEMA_VERSION};
+
+    #[test]
+    fn documents_start_with_their_schema() {
+        let document = Json::document("stats", vec![("duration", Json::from(1.5)), ("name", Json::from("a \"b\""))]);
+        let expected = format!("{{\n  \"schema\": \"stats\",\n  \"schema_version\": {SCHEMA_VERSION},\n  \"duration\": 1.5,\n  \"name\": \"a \\\"b\\\"\"\n}}");
+        assert_eq!(document.to_string(), expected);
+    }
+}
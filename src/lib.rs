@@ -0,0 +1,26 @@
+//! Decoding and rendering of wellenformer as a library, for embedding the
+//! renderer (e.g. custom column aggregations via
+//! [`render::render_aggregated`]) and for fuzzing (see
+//! fuzz/fuzz_targets/render_bytes.rs). The command line tool in main.rs is
+//! built on top of it
+
+pub mod audio;
+pub mod chroma;
+pub mod color;
+pub mod decimate;
+pub mod dither;
+pub mod fft;
+pub mod filterbank;
+pub mod font;
+pub mod interpolate;
+pub mod loudness;
+pub mod number;
+pub mod policy;
+pub mod raster;
+pub mod render;
+pub mod smoothing;
+pub mod spectrogram;
+pub mod spectrum;
+
+#[cfg(test)]
+mod testing;
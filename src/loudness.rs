@@ -0,0 +1,215 @@
+//! Loudness over time after ITU-R BS.1770 (momentary or short-term LUFS),
+//! drawn as a curve so overly loud segments stand out
+use clap::ValueEnum;
+use image::{ImageBuffer, RgbaImage};
+
+use crate::color::blend;
+use crate::filterbank::Biquad;
+use crate::raster::{distance_to_segment, stroke_coverage, Point};
+use crate::render::{time_column, RenderOptions, TimeAxis};
+
+
+/// Loudness at the bottom edge in LUFS, the top edge being 0 LUFS
+const LOUDNESS_FLOOR: f64 = -60.0;
+
+/// Blocks below this loudness in LUFS don't count for the integrated loudness
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks this many LU below the loudness of the blocks above the absolute
+/// gate don't count either
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Length of the window the loudness is measured over
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoudnessWindow {
+    /// 400 ms
+    Momentary,
+    /// 3 s
+    ShortTerm,
+}
+
+impl LoudnessWindow {
+    pub fn seconds(self) -> f64 {
+        match self {
+            LoudnessWindow::Momentary => 0.4,
+            LoudnessWindow::ShortTerm => 3.0,
+        }
+    }
+}
+
+/// The two stages of the K-weighting filter at `sample_rate`: a high shelf
+/// modelling the head and a high pass (RLB weighting)
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate.max(1) as f64;
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+    [shelf, high_pass]
+}
+
+/// Weight of `channel` in the sum over channels: surround channels of 5.0
+/// and 5.1 count 1.41 times, the LFE not at all
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) | (5, 3 | 4) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Sums of the weighted mean square of the K-weighted interleaved `samples`
+/// over the first 0, 1, 2, … frames
+fn power_sums(samples: &[f32], channels: usize, sample_rate: u32) -> Vec<f64> {
+    let channels = channels.max(1);
+    let mut filters = vec![k_weighting(sample_rate); channels];
+    let mut sums = Vec::with_capacity(samples.len() / channels + 1);
+    let mut sum = 0.0;
+    sums.push(sum);
+    for frame in samples.chunks_exact(channels) {
+        for (channel, (&sample, filter)) in frame.iter().zip(&mut filters).enumerate() {
+            let y = filter.iter_mut().fold(sample as f64, |x, stage| stage.process(x));
+            sum += channel_weight(channel, channels) * y * y;
+        }
+        sums.push(sum);
+    }
+    sums
+}
+
+/// Loudness in LUFS of the window of `length` frames centered on `frame`,
+/// shortened where it reaches past either end
+fn loudness_at(sums: &[f64], frame: usize, length: usize) -> f64 {
+    let frames = sums.len() - 1;
+    let start = frame.saturating_sub(length / 2).min(frames);
+    let end = (frame + length.div_ceil(2)).min(frames);
+    let power = if end > start { (sums[end] - sums[start]).max(0.0) / (end - start) as f64 } else { 0.0 };
+    lufs(power)
+}
+
+/// Loudness in LUFS of a weighted mean square `power`
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(1e-30).log10()
+}
+
+/// Integrated loudness in LUFS of the interleaved `samples` after BS.1770:
+/// the mean power of 400 ms blocks overlapping by 75 %, leaving out blocks
+/// below -70 LUFS and then those more than 10 LU below the mean of the rest.
+/// None when no block is left, e.g. for silence or less than 400 ms
+pub fn integrated_loudness(samples: &[f32], channels: usize, sample_rate: u32) -> Option<f64> {
+    let sums = power_sums(samples, channels, sample_rate);
+    let frames = sums.len() - 1;
+    let block = (0.4 * sample_rate as f64).round() as usize;
+    if block == 0 || frames < block {
+        return None;
+    }
+    let powers: Vec<f64> = (0..=frames - block).step_by((block / 4).max(1))
+        .map(|start| (sums[start + block] - sums[start]).max(0.0) / block as f64)
+        .collect();
+    let mean_above = |threshold: f64| {
+        let (sum, count) = powers.iter().filter(|&&p| lufs(p) > threshold).fold((0.0, 0), |(sum, count), p| (sum + p, count + 1));
+        (count > 0).then(|| sum / count as f64)
+    };
+    let absolute = mean_above(ABSOLUTE_GATE)?;
+    mean_above(lufs(absolute) + RELATIVE_GATE).map(lufs)
+}
+
+/// Momentary or short-term loudness of the interleaved `samples` as a curve
+/// over the columns of `axis`, rising towards 0 LUFS at the top edge. The
+/// loudness is measured before any normalization, and an optional target is
+/// drawn as a horizontal line in the negative color
+pub fn render_loudness(samples: &[f32], axis: &TimeAxis, options: &RenderOptions) -> RgbaImage {
+    let channels = options.channels.max(1);
+    let width = axis.columns;
+    let height = options.height;
+    let oversample = options.oversample.max(1) as f64;
+    let stroke = options.stroke_width.max(0.0);
+    let sums = power_sums(samples, channels, options.sample_rate);
+    let length = ((options.loudness_window.seconds() * options.sample_rate as f64).round() as usize).max(1);
+    let to_y = |lufs: f64| (lufs / LOUDNESS_FLOOR).clamp(0.0, 1.0) * height as f64;
+
+    // Point of every column in output pixels, in image order
+    let points: Vec<Option<Point>> = (0..width)
+        .map(|x| {
+            let range = axis.range(time_column(x, width, options.time_direction), samples.len());
+            (!range.is_empty()).then(|| {
+                let frame = (range.start + range.end) / 2 / channels;
+                ((x as f64 + 0.5) / oversample, to_y(loudness_at(&sums, frame, length)))
+            })
+        })
+        .collect();
+    let target = options.target_lufs.map(to_y);
+    // Columns on either side whose segments can reach a pixel
+    let reach = ((stroke / 2.0 + 1.0) * oversample).ceil() as i64;
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let p = ((x as f64 + 0.5) / oversample, y as f64 + 0.5);
+        let mut coverage = 0.0f64;
+        let first = (x as i64 - reach).max(0) as usize;
+        let last = ((x as i64 + reach) as usize).min(width as usize - 1);
+        for i in first..=last {
+            let Some(point) = points[i] else { continue };
+            let next = points.get(i + 1).copied().flatten().unwrap_or(point);
+            coverage = coverage.max(stroke_coverage(distance_to_segment(p, point, next), stroke));
+        }
+        let below = match target {
+            Some(target) => blend(options.background, options.negative, stroke_coverage((p.1 - target).abs(), stroke)),
+            None => options.background,
+        };
+        blend(below, options.foreground, coverage)
+    })
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::loudness::{integrated_loudness, loudness_at, power_sums};
+
+    #[test]
+    fn full_scale_sine_reads_minus_three() {
+        // A full scale 997 Hz sine on one channel is -3.01 LUFS
+        let sample_rate = 48000;
+        let sine: Vec<f32> = (0..sample_rate)
+            .map(|i| (2.0 * std::f64::consts::PI * 997.0 * i as f64 / sample_rate as f64).sin() as f32)
+            .collect();
+        let sums = power_sums(&sine, 1, sample_rate as u32);
+        let lufs = loudness_at(&sums, 24000, 19200);
+        assert!((lufs + 3.01).abs() < 0.05, "{lufs}");
+
+        // The same sine on both channels of a stereo file is 3 dB louder,
+        // silence is far below the bottom edge
+        let stereo: Vec<f32> = sine.iter().flat_map(|&s| [s, s]).collect();
+        assert!((loudness_at(&power_sums(&stereo, 2, 48000), 24000, 19200) - 0.0).abs() < 0.05);
+        assert!(loudness_at(&power_sums(&[0.0; 1000], 1, 48000), 500, 100) < -100.0);
+    }
+    #[test]
+    fn quiet_stretches_are_gated() {
+        let sample_rate = 48000;
+        let sine = |amplitude: f64, seconds: usize| (0..sample_rate * seconds)
+            .map(move |i| (amplitude * (2.0 * std::f64::consts::PI * 997.0 * i as f64 / sample_rate as f64).sin()) as f32);
+        // A sine at -20 dBFS is -23 LUFS
+        let take: Vec<f32> = sine(0.1, 4).collect();
+        let lufs = integrated_loudness(&take, 1, sample_rate as u32).unwrap();
+        assert!((lufs + 23.01).abs() < 0.05, "{lufs}");
+        // Silence and passages more than 10 LU down barely count, only the
+        // blocks across the changes pull the loudness down a little
+        let padded: Vec<f32> = std::iter::repeat_n(0.0, sample_rate * 4).chain(sine(0.1, 4)).chain(std::iter::repeat_n(0.0, sample_rate * 4)).collect();
+        let dynamic: Vec<f32> = sine(0.1, 4).chain(sine(0.01, 4)).collect();
+        for samples in [padded, dynamic] {
+            let lufs = integrated_loudness(&samples, 1, sample_rate as u32).unwrap();
+            assert!(lufs > -23.5 && lufs < -23.0, "{lufs}");
+        }
+
+        assert_eq!(integrated_loudness(&[0.0; 48000], 1, 48000), None);
+        assert_eq!(integrated_loudness(&take[..1000], 1, 48000), None);
+    }
+}
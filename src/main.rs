@@ -1,138 +1,936 @@
 use std::{
     f32,
-    path::PathBuf,
+    path::{Path, PathBuf},
     fs::create_dir_all,
 };
 use image::ImageBuffer;
-use clap::Parser;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use inquire::Confirm;
 use rayon::prelude::*;
 
-mod audio;
-use audio::read_audio;
+mod analyze;
+mod bits;
+mod bundle;
+mod check;
+mod checksum;
+mod compare;
+mod describe;
+mod doctor;
+mod downmix;
+mod gaps;
+mod ir;
+mod json;
+mod metadata;
+mod midi;
+mod onsets;
+mod output;
+mod palette;
+mod pdf;
+mod peakhold;
+mod peaks;
+mod pitch;
+mod segment;
+mod selftest;
+mod sheet;
+mod sine;
+mod size;
+mod stream;
+mod terminal;
+#[cfg(test)]
+mod testing;
+mod tiles;
+mod timecode;
+mod titles;
+mod variant;
+mod vector;
+mod wav;
+use wellenformer::{audio, color, dither, fft, filterbank, font, interpolate, loudness, number, policy, raster, render, smoothing, spectrogram, spectrum};
+use audio::{read_audio_with, Audio};
+use interpolate::Interpolation;
+use json::Json;
+use output::OutputFormat;
+use policy::Policy;
+use raster::Rasterizer;
+use render::{Layout, Oversample, RenderMode, RenderOptions, TimeAxis, TimeDirection, TimeScale};
+use smoothing::Kernel;
+use spectrogram::SpectrogramOptions;
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-   /// Path of the audio file that should be rendered
-   #[arg(short, long)]
-   input: PathBuf,
+   #[command(subcommand)]
+   command: Option<Command>,
+
+   /// Path of the audio file that should be rendered (MIDI files are rendered
+   /// as a piano roll), or of the folder rendered as a contact sheet
+   #[arg(short, long, required_unless_present = "selftest", env = "WELLENFORMER_INPUT")]
+   input: Option<PathBuf>,
 
    /// Path where the resulting png image should be written
-   #[arg(short, long)]
-   output: PathBuf,
+   #[arg(short, long, required_unless_present = "selftest", env = "WELLENFORMER_OUTPUT")]
+   output: Option<PathBuf>,
+
+   /// Render synthetic signals (silence, DC, square, sine, impulses) and
+   /// check the resulting pixels instead of rendering a file
+   #[arg(long, env = "WELLENFORMER_SELFTEST")]
+   selftest: bool,
+
+   /// Write a raster image or the outline of the waveform as vector graphics
+   #[arg(long, value_enum, default_value_t = OutputFormat::Png, env = "WELLENFORMER_FORMAT")]
+   format: OutputFormat,
+
+   /// Quality of JPEG outputs from 1 to 100
+   #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100), env = "WELLENFORMER_QUALITY")]
+   quality: u8,
+
+   /// Creator written into the EXIF and XMP metadata of JPEG outputs
+   #[arg(long, env = "WELLENFORMER_CREATOR")]
+   creator: Option<String>,
+
+   /// Physical width of vector outputs (e.g. 200mm, 20cm or 8in), the height
+   /// follows the aspect ratio of --width and --height
+   #[arg(long, default_value = "200mm", value_parser = vector::parse_length, env = "WELLENFORMER_PHYSICAL_WIDTH")]
+   physical_width: f64,
+
+   /// Largest deviation from the exact outline allowed when simplifying
+   /// vector outputs (e.g. 0.05mm)
+   #[arg(long, default_value = "0.05mm", value_parser = vector::parse_length, env = "WELLENFORMER_TOLERANCE")]
+   tolerance: f64,
+
+   /// Fill of SVG outputs, hatching draws only lines for pen plotters
+   #[arg(long, value_enum, default_value_t = vector::SvgStyle::Solid, env = "WELLENFORMER_SVG_STYLE")]
+   svg_style: vector::SvgStyle,
+
+   /// Distance between hatch lines of SVG outputs (e.g. 0.5mm)
+   #[arg(long, default_value = "0.5mm", value_parser = vector::parse_length, env = "WELLENFORMER_HATCH_SPACING")]
+   hatch_spacing: f64,
+
+   /// Angle of hatch lines in degrees (0 is horizontal, 45 diagonal)
+   #[arg(long, default_value_t = 0.0, allow_hyphen_values = true, value_parser = number::parse_number, env = "WELLENFORMER_HATCH_ANGLE")]
+   hatch_angle: f64,
+
+   /// Thickness of the extruded mesh in STL outputs (e.g. 5mm)
+   #[arg(long, default_value = "5mm", value_parser = vector::parse_length, env = "WELLENFORMER_DEPTH")]
+   depth: f64,
+
+   /// Depth below the surface at which G-code outputs engrave (e.g. 0.2mm)
+   #[arg(long, default_value = "0.2mm", value_parser = vector::parse_length, env = "WELLENFORMER_CUT_DEPTH")]
+   cut_depth: f64,
+
+   /// Feed rate of G-code outputs in mm per minute (plunging uses half of it)
+   #[arg(long, default_value_t = 600.0, value_parser = number::parse_number, env = "WELLENFORMER_FEED_RATE")]
+   feed_rate: f64,
+
+   /// Time shown on every page of PDF outputs (e.g. 60s or 5min)
+   #[arg(long, default_value = "60s", value_parser = parse_duration, env = "WELLENFORMER_SECONDS_PER_PAGE")]
+   seconds_per_page: f64,
+
+   /// Label the time axis of PDF outputs in SMPTE timecode at this frame
+   /// rate, optionally starting at a timecode other than 00:00:00:00
+   /// (e.g. "25fps" or "25fps@01:00:00:00", non-drop-frame)
+   #[arg(long, value_parser = timecode::parse_timecode, env = "WELLENFORMER_TIMECODE")]
+   timecode: Option<timecode::Timecode>,
+
+   /// Width of Braille outputs in characters
+   #[arg(long, default_value_t = 80, env = "WELLENFORMER_COLUMNS")]
+   columns: u32,
+
+   /// Height of Braille outputs in lines
+   #[arg(long, default_value_t = 4, env = "WELLENFORMER_ROWS")]
+   rows: u32,
+
+   /// Cells in every row of contact sheets, which split --width between them
+   #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..), env = "WELLENFORMER_SHEET_COLUMNS")]
+   sheet_columns: u32,
+
+   /// Badge every take of contact sheets with its integrated loudness and
+   /// clipped samples, the dot turning red for clipping and yellow for more
+   /// than 1 LU off --target-lufs
+   #[arg(long, env = "WELLENFORMER_SHEET_BADGES")]
+   sheet_badges: bool,
+
+   /// Amount of oversampling to be applied (more takes longer), "auto" picks
+   /// it from the length of the input, the size of the image and the mode.
+   /// The minmax, asymmetry and multiband modes fill the span of every output
+   /// column and are never oversampled
+   #[arg(short='s', long, default_value = "auto", value_parser = render::parse_oversample, env = "WELLENFORMER_OVERSAMPLE")]
+   oversample: Oversample,
+
+   /// How the minmax mode turns the span of every column into pixels, with
+   /// hard or with anti-aliased ends
+   #[arg(long, value_enum, default_value_t = Rasterizer::Oversample, env = "WELLENFORMER_RASTERIZER")]
+   rasterizer: Rasterizer,
+
+   /// Draw from every sample even where a pixel column covers more than
+   /// 10000 frames, instead of from the peaks of short blocks (e.g. for
+   /// forensic renders)
+   #[arg(long, env = "WELLENFORMER_NO_DECIMATE")]
+   no_decimate: bool,
+
+   /// How the signal between the samples is drawn when zoomed in so far
+   /// that a pixel covers less than a sample
+   #[arg(long, value_enum, default_value_t = Interpolation::Sinc, env = "WELLENFORMER_INTERPOLATION")]
+   interpolation: Interpolation,
+
+   /// Background color as a name, hex, hsl() or comma separated RGBA values
+   #[arg(long, default_value = "0,0,0,0", value_parser = color::parse, env = "WELLENFORMER_BACKGROUND")]
+   background: image::Rgba<u8>,
+
+   /// Foreground color as a name, hex, hsl() or comma separated RGBA values
+   #[arg(long, default_value = "0,0,0,255", value_parser = color::parse, env = "WELLENFORMER_FOREGROUND")]
+   foreground: image::Rgba<u8>,
+
+   /// Derive the background, foreground and negative colors from the dominant
+   /// colors of an image (e.g. cover art). Colors given explicitly take precedence
+   #[arg(long, value_name = "IMAGE", env = "WELLENFORMER_COLORS_FROM")]
+   colors_from: Option<PathBuf>,
+
+   /// Undo CD pre-emphasis before rendering
+   #[arg(long, env = "WELLENFORMER_DEEMPHASIS")]
+   deemphasis: bool,
+
+   /// Mix surround content down to fewer channels before rendering, with the
+   /// coefficients of ITU-R BS.775 (e.g. 5.1 as stereo)
+   #[arg(long, value_enum, env = "WELLENFORMER_DOWNMIX_TO")]
+   downmix_to: Option<downmix::Target>,
+
+   /// How the waveform is drawn
+   #[arg(short, long, value_enum, default_value_t = RenderMode::MinMax, env = "WELLENFORMER_MODE")]
+   mode: RenderMode,
+
+   /// Color of negative excursions in the asymmetry mode and of the target
+   /// line in the loudness mode
+   #[arg(long, default_value = "255,0,0,255", value_parser = color::parse, env = "WELLENFORMER_NEGATIVE_COLOR")]
+   negative_color: image::Rgba<u8>,
+
+   /// Draw the RMS level of every column in this color on top of the peaks
+   /// of the min/max mode
+   #[arg(long, value_parser = color::parse, env = "WELLENFORMER_RMS_COLOR")]
+   rms_color: Option<image::Rgba<u8>>,
+
+   /// Fade every column by its RMS level, so the height shows the peaks and
+   /// the opacity the average level of the waveform modes
+   #[arg(long, env = "WELLENFORMER_RMS_ALPHA")]
+   rms_alpha: bool,
+
+   /// How much of every column the minmax and mean modes show: "full" draws
+   /// the min/max silhouette with the RMS level shaded inside (in --rms-color,
+   /// by default a lighter shade of the foreground)
+   #[arg(long, value_enum, default_value_t = Detail::Mode, env = "WELLENFORMER_DETAIL")]
+   detail: Detail,
+
+   /// Frequencies in Hz between the low, mid and high band of the multiband mode
+   #[arg(long, default_value = "200,2000", value_parser = filterbank::parse_crossovers, env = "WELLENFORMER_CROSSOVERS")]
+   crossovers: (f64, f64),
+
+   /// Color of the low band in the multiband mode
+   #[arg(long, default_value = "220,40,40", value_parser = color::parse, env = "WELLENFORMER_LOW_COLOR")]
+   low_color: image::Rgba<u8>,
+
+   /// Color of the mid band in the multiband mode
+   #[arg(long, default_value = "40,180,60", value_parser = color::parse, env = "WELLENFORMER_MID_COLOR")]
+   mid_color: image::Rgba<u8>,
+
+   /// Color of the high band in the multiband mode
+   #[arg(long, default_value = "40,90,230", value_parser = color::parse, env = "WELLENFORMER_HIGH_COLOR")]
+   high_color: image::Rgba<u8>,
+
+   /// Window the loudness mode measures over: momentary (400 ms) or
+   /// short-term (3 s)
+   #[arg(long, value_enum, default_value_t = loudness::LoudnessWindow::Momentary, env = "WELLENFORMER_LOUDNESS_WINDOW")]
+   loudness_window: loudness::LoudnessWindow,
+
+   /// Loudness in LUFS marked by a line in the loudness mode (e.g. -16)
+   #[arg(long, allow_hyphen_values = true, value_parser = number::parse_number, env = "WELLENFORMER_TARGET_LUFS")]
+   target_lufs: Option<f64>,
+
+   /// Number of rows the filmstrip mode cuts the audio into, each --height
+   /// pixels tall
+   #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..), env = "WELLENFORMER_FILMSTRIP_ROWS")]
+   filmstrip_rows: u32,
+
+   /// Color of the hatching over stretches of missing audio (packets that
+   /// failed to decode or dropouts in a stream)
+   #[arg(long, default_value = "128,128,128,255", value_parser = color::parse, env = "WELLENFORMER_GAP_COLOR")]
+   gap_color: image::Rgba<u8>,
+
+   /// Mark the loudest sample of every division of this duration with a
+   /// tick, like the peak-hold of a meter (e.g. 1s or 500ms)
+   #[arg(long, value_parser = parse_duration, env = "WELLENFORMER_PEAK_HOLD")]
+   peak_hold: Option<f64>,
+
+   /// Color of the --peak-hold ticks
+   #[arg(long, default_value = "255,0,0,255", value_parser = color::parse, env = "WELLENFORMER_PEAK_HOLD_COLOR")]
+   peak_hold_color: image::Rgba<u8>,
+
+   /// Draw analyses over the image, e.g. the pitch contour for vocal
+   /// coaching and transcription (several separated by commas)
+   #[arg(long, value_enum, value_delimiter = ',', env = "WELLENFORMER_OVERLAY")]
+   overlay: Vec<Overlay>,
+
+   /// Color of the pitch contour of --overlay pitch
+   #[arg(long, default_value = "0,160,255,255", value_parser = color::parse, env = "WELLENFORMER_PITCH_COLOR")]
+   pitch_color: image::Rgba<u8>,
+
+   /// How readily --overlay onsets marks a rise in the spectrum as an onset
+   /// (e.g. 50% or 0.5)
+   #[arg(long, default_value = "50%", value_parser = onsets::parse_sensitivity, env = "WELLENFORMER_ONSET_SENSITIVITY")]
+   onset_sensitivity: f64,
+
+   /// Color of the ticks of --overlay onsets
+   #[arg(long, default_value = "255,128,0,255", value_parser = color::parse, env = "WELLENFORMER_ONSET_COLOR")]
+   onset_color: image::Rgba<u8>,
+
+   /// Vary the style reproducibly by this seed (hue of the drawn colors,
+   /// phase of the bars, start angle of the radial mode), so images made in
+   /// bulk look distinct but the same seed always looks the same
+   #[arg(long, value_name = "N", env = "WELLENFORMER_VARIANT_SEED")]
+   variant_seed: Option<u64>,
+
+   /// Offset of the bars against the left edge as a share of a bar and its gap
+   #[arg(skip)]
+   bar_phase: f64,
+
+   /// Width of every bar of the bars mode in pixels
+   #[arg(long, default_value_t = 3, env = "WELLENFORMER_BAR_WIDTH")]
+   bar_width: u32,
+
+   /// Space between the bars of the bars mode in pixels
+   #[arg(long, default_value_t = 2, env = "WELLENFORMER_BAR_GAP")]
+   bar_gap: u32,
 
-   /// Amount of oversampling to be applied (more takes longer)
-   #[arg(short='s', long, default_value_t = 32)]
-   oversample: u32,
+   /// Width of the lines of the line and loudness modes in pixels
+   #[arg(long, default_value_t = 1.5, value_parser = number::parse_number, env = "WELLENFORMER_STROKE_WIDTH")]
+   stroke_width: f64,
 
-   /// Background color in RGBA format
-   #[arg(long, default_value = "0,0,0,0")]
-   background: String,
+   /// Radius of the dots of the dots mode in pixels
+   #[arg(long, default_value_t = 1.5, value_parser = number::parse_number, env = "WELLENFORMER_DOT_RADIUS")]
+   dot_radius: f64,
 
-   /// Background color in RGBA format
-   #[arg(long, default_value = "0,0,0,255")]
-   foreground: String,
+   /// Release time of the envelope follower of the envelope mode, in
+   /// milliseconds or with a unit (e.g. 300 or 1.5s)
+   #[arg(long, default_value = "200", value_parser = parse_milliseconds, env = "WELLENFORMER_SMOOTH")]
+   smooth: f64,
+
+   /// Smooth the column heights of the minmax, mean, asymmetry, line and
+   /// multiband modes over a window of this many pixels, e.g. for short
+   /// files drawn wider than they have detail
+   #[arg(long, default_value_t = 0.0, value_parser = number::parse_number, env = "WELLENFORMER_SMOOTH_COLUMNS")]
+   smooth_columns: f64,
+
+   /// Weighting of the columns within the --smooth-columns window
+   #[arg(long, value_enum, default_value_t = Kernel::Gaussian, env = "WELLENFORMER_SMOOTH_KERNEL")]
+   smooth_kernel: Kernel,
+
+   /// Radius of the circle the radial mode grows from, as a share of the
+   /// outer radius (e.g. 40% or 0.4)
+   #[arg(long, default_value = "50%", value_parser = render::parse_inner_radius, env = "WELLENFORMER_INNER_RADIUS")]
+   inner_radius: f64,
+
+   /// Angle at which the radial mode starts in degrees, clockwise from the top
+   #[arg(long, default_value_t = 0.0, allow_hyphen_values = true, value_parser = number::parse_number, env = "WELLENFORMER_START_ANGLE")]
+   start_angle: f64,
+
+   /// Whether the mean mode grows from the bottom edge or shows positive and
+   /// negative excursions around a baseline
+   #[arg(long, value_enum, default_value_t = Layout::Bottom, env = "WELLENFORMER_LAYOUT")]
+   layout: Layout,
+
+   /// Position of the zero line from the top (0%) to the bottom (100%) edge,
+   /// e.g. 70% for artwork with more room for the positive excursions. Moves
+   /// the minmax, asymmetry, line, dots, bars and envelope modes and the mean
+   /// mode with --layout centered
+   #[arg(long, default_value = "50%", value_parser = render::parse_baseline, env = "WELLENFORMER_BASELINE")]
+   baseline: f64,
+
+   /// Window applied to every frame of the spectrogram mode
+   /// (hann, hamming, blackman or kaiser:<beta>)
+   #[arg(long, default_value = "hann", value_parser = spectrogram::parse_window, env = "WELLENFORMER_WINDOW")]
+   window: spectrogram::Window,
+
+   /// Overlap of consecutive frames in the spectrogram mode (e.g. 75% or 0.5)
+   #[arg(long, default_value = "75%", value_parser = spectrogram::parse_overlap, env = "WELLENFORMER_OVERLAP")]
+   overlap: f64,
+
+   /// Lowest frequency shown in the spectrogram mode in Hz
+   #[arg(long, default_value_t = 0.0, value_parser = number::parse_number, env = "WELLENFORMER_FMIN")]
+   fmin: f64,
+
+   /// Highest frequency shown in the spectrogram mode in Hz (defaults to
+   /// half the sample rate, e.g. 96000 for bat recordings at 192 kHz)
+   #[arg(long, value_parser = number::parse_number, env = "WELLENFORMER_FMAX")]
+   fmax: Option<f64>,
+
+   /// Frequency axis of the spectrogram mode
+   #[arg(long, value_enum, default_value_t = spectrogram::FreqScale::Linear, env = "WELLENFORMER_FREQ_SCALE")]
+   freq_scale: spectrogram::FreqScale,
+
+   /// Label the frequency axis of the spectrogram mode along the left edge
+   #[arg(long, env = "WELLENFORMER_FREQUENCY_LABELS")]
+   frequency_labels: bool,
+
+   /// Number of mel bands with --freq-scale mel (defaults to one per row)
+   #[arg(long, value_parser = clap::value_parser!(u32).range(1..), env = "WELLENFORMER_MEL_BANDS")]
+   mel_bands: Option<u32>,
+
+   /// Number of spectra stacked by the waterfall mode
+   #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..), env = "WELLENFORMER_SLICES")]
+   slices: u32,
+
+   /// Share of the width and height the waterfall mode recedes into the
+   /// distance (e.g. 30% or 0.3)
+   #[arg(long, default_value = "30%", value_parser = spectrogram::parse_perspective, env = "WELLENFORMER_PERSPECTIVE")]
+   perspective: f64,
+
+   /// Color of the loudest levels of spectrograms (defaults to the foreground
+   /// color in the spectrogram, waterfall and chroma modes and orange under
+   /// --over-spectrogram)
+   #[arg(long, value_parser = color::parse, env = "WELLENFORMER_SPECTROGRAM_COLOR")]
+   spectrogram_color: Option<image::Rgba<u8>>,
+
+   /// Draw the waveform with this opacity (e.g. 60% or 0.6) over a
+   /// spectrogram of the audio, which replaces the background
+   #[arg(long, value_name = "OPACITY", value_parser = color::parse_opacity, env = "WELLENFORMER_OVER_SPECTROGRAM")]
+   over_spectrogram: Option<f64>,
+
+   /// Dither smooth gradients (spectrograms, anti-aliased edges) instead of
+   /// rounding them to 8 bits, which avoids banding in slow fades
+   #[arg(long, value_enum, default_value_t = dither::Dither::None, env = "WELLENFORMER_DITHER")]
+   dither: dither::Dither,
+
+   /// Filter scaling the oversampled rendering down to --width, softer
+   /// filters avoid the halos Lanczos draws around hard edges like bars
+   #[arg(long, value_enum, default_value_t = dither::Filter::Lanczos3, env = "WELLENFORMER_FILTER")]
+   filter: dither::Filter,
 
    /// Width of the resulting image in pixels
-   #[arg(long, default_value_t = 1920)]
+   #[arg(long, default_value_t = 1920, env = "WELLENFORMER_WIDTH")]
    width: u32,
 
+   /// Fixed zoom level instead of --width (e.g. 100 for 10 ms per column):
+   /// the width follows from the duration, so files of any length line up
+   /// on a timeline
+   #[arg(long, value_parser = parse_pixels_per_second, conflicts_with_all = ["width", "size", "bucket_duration"], env = "WELLENFORMER_PIXELS_PER_SECOND")]
+   pixels_per_second: Option<f64>,
+
+   /// Fixed zoom level instead of --width in frames per pixel column, as
+   /// audiowaveform and wavesurfer count it (e.g. 256)
+   #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["width", "size", "bucket_duration", "pixels_per_second"], env = "WELLENFORMER_SAMPLES_PER_PIXEL")]
+   samples_per_pixel: Option<u32>,
+
    /// Height of the resulting image in pixels
-   #[arg(long, default_value_t = 120)]
+   #[arg(long, default_value_t = 120, env = "WELLENFORMER_HEIGHT")]
    height: u32,
 
+   /// Size the image for a platform instead of --width and --height, the
+   /// waveform is drawn inside safe margins
+   #[arg(long, value_enum, conflicts_with_all = ["width", "height"], env = "WELLENFORMER_SIZE")]
+   size: Option<size::SizePreset>,
+
+   /// Render very wide PNGs in strips and write them row by row instead of
+   /// holding the whole image in memory (linear time scale, the minmax,
+   /// mean, asymmetry and line modes only, no lanes)
+   #[arg(long, conflicts_with_all = ["size", "segments", "diff_against", "compare", "crest", "show_stream_titles"], env = "WELLENFORMER_STREAM")]
+   stream: bool,
+
+   /// Split raster images wider than this into numbered tiles listed in a
+   /// "<name>.tiles.json" manifest (wider images than a PNG viewer or the
+   /// JPEG format can handle are always split)
+   #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..), env = "WELLENFORMER_MAX_IMAGE_WIDTH")]
+   max_image_width: Option<u32>,
+
    /// Overwrite existing files without prompt?
-   #[arg(short='y', long)]
+   #[arg(short='y', long, env = "WELLENFORMER_OVERWRITE")]
    overwrite: bool,
 
+   /// Warn when the colors of the waveform have too little contrast to the
+   /// background (WCAG contrast ratio, see --min-contrast)
+   #[arg(long, env = "WELLENFORMER_CHECK_CONTRAST")]
+   check_contrast: bool,
+
+   /// Lowest acceptable contrast ratio for --check-contrast (3 is the WCAG
+   /// minimum for graphics, 4.5 for text)
+   #[arg(long, default_value_t = 3.0, value_parser = number::parse_number, env = "WELLENFORMER_MIN_CONTRAST")]
+   min_contrast: f64,
+
+   /// Fail instead of warning about recoverable problems: skipped packets,
+   /// samples that are not a number, missing stream titles, audio cut off
+   /// by --bucket-duration and colors with too little contrast
+   #[arg(long, env = "WELLENFORMER_STRICT")]
+   strict: bool,
+
+   /// Only warn about damage that stops decoding the input early and draw
+   /// the audio decoded up to it
+   #[arg(long, conflicts_with = "strict", env = "WELLENFORMER_LENIENT")]
+   lenient: bool,
+
    /// Normalize the audio waveform to fill the vertical space
-   #[arg(short='n', long)]
+   #[arg(short='n', long, env = "WELLENFORMER_NORMALIZE")]
    normalize: bool,
+
+   /// Scale the samples by this fixed factor instead, e.g. the normalization
+   /// factor printed for a preview so the final render matches it exactly.
+   /// Takes precedence over --normalize
+   #[arg(long, value_name = "FACTOR", value_parser = parse_scale_factor, env = "WELLENFORMER_SCALE_FACTOR")]
+   scale_factor: Option<f64>,
+
+   /// Draw the magnitude of the samples growing from the bottom edge
+   /// (unipolar) in every mode, instead of positive and negative excursions
+   /// around the baseline. Takes precedence over --layout centered
+   #[arg(long, env = "WELLENFORMER_RECTIFY")]
+   rectify: bool,
+
+   /// Fixed duration each pixel column represents (e.g. "100ms", "0.5s"),
+   /// independent of the audio length. Unused space is padded with the background
+   #[arg(long, value_parser = parse_duration, env = "WELLENFORMER_BUCKET_DURATION")]
+   bucket_duration: Option<f64>,
+
+   /// Direction in which time flows along the horizontal axis
+   #[arg(long, value_enum, default_value_t = TimeDirection::Ltr, env = "WELLENFORMER_TIME_DIRECTION")]
+   time_direction: TimeDirection,
+
+   /// Draw a lane below the waveform marking speech, music and silence
+   #[arg(long, env = "WELLENFORMER_SEGMENTS")]
+   segments: bool,
+
+   /// Height of the segmentation lane in pixels
+   #[arg(long, default_value_t = 8, env = "WELLENFORMER_SEGMENT_HEIGHT")]
+   segment_height: u32,
+
+   /// Color of speech regions in the segmentation lane
+   #[arg(long, default_value = "255,160,0", value_parser = color::parse, env = "WELLENFORMER_SPEECH_COLOR")]
+   speech_color: image::Rgba<u8>,
+
+   /// Color of music regions in the segmentation lane
+   #[arg(long, default_value = "0,140,255", value_parser = color::parse, env = "WELLENFORMER_MUSIC_COLOR")]
+   music_color: image::Rgba<u8>,
+
+   /// Color of silent regions in the segmentation lane
+   #[arg(long, default_value = "transparent", value_parser = color::parse, env = "WELLENFORMER_SILENCE_COLOR")]
+   silence_color: image::Rgba<u8>,
+
+   /// Encoded (or otherwise processed) version of the input. Draws a strip
+   /// below the waveform whose intensity shows how much it differs from the input
+   #[arg(long, env = "WELLENFORMER_DIFF_AGAINST")]
+   diff_against: Option<PathBuf>,
+
+   /// Height of the difference strip in pixels
+   #[arg(long, default_value_t = 6, env = "WELLENFORMER_DIFF_HEIGHT")]
+   diff_height: u32,
+
+   /// Color of the difference strip at maximum difference
+   #[arg(long, default_value = "red", value_parser = color::parse, env = "WELLENFORMER_DIFF_COLOR")]
+   diff_color: image::Rgba<u8>,
+
+   /// Difference level in dBFS that is drawn at full intensity,
+   /// the strip fades out over the 50 dB below it
+   #[arg(long, default_value_t = -20.0, allow_hyphen_values = true, value_parser = number::parse_number, env = "WELLENFORMER_DIFF_CEILING")]
+   diff_ceiling: f64,
+
+   /// Second file (e.g. the master of the input) drawn over the waveform
+   /// in --compare-color, with the same scaling as the input
+   #[arg(long, env = "WELLENFORMER_COMPARE")]
+   compare: Option<PathBuf>,
+
+   /// Draw the waveform of the --compare file or its sample-wise difference
+   /// to the input
+   #[arg(long, value_enum, default_value_t = compare::CompareStyle::Overlay, env = "WELLENFORMER_COMPARE_STYLE")]
+   compare_style: compare::CompareStyle,
+
+   /// Color of the --compare waveform, translucent so the input shows
+   /// through where both overlap
+   #[arg(long, default_value = "255,0,0,160", value_parser = color::parse, env = "WELLENFORMER_COMPARE_COLOR")]
+   compare_color: image::Rgba<u8>,
+
+   /// Draw a strip below the waveform that lights up where the crest factor
+   /// (peak to RMS ratio) is low, revealing heavily compressed sections
+   #[arg(long, env = "WELLENFORMER_CREST")]
+   crest: bool,
+
+   /// Height of the crest factor strip in pixels
+   #[arg(long, default_value_t = 6, env = "WELLENFORMER_CREST_HEIGHT")]
+   crest_height: u32,
+
+   /// Mark where the stream title changes (e.g. songs in a recording of an
+   /// internet radio stream) and write the titles in a lane below
+   #[arg(long, env = "WELLENFORMER_SHOW_STREAM_TITLES")]
+   show_stream_titles: bool,
+
+   /// Color of the crest factor strip where the dynamic range is lowest
+   #[arg(long, default_value = "255,0,0", value_parser = color::parse, env = "WELLENFORMER_CREST_COLOR")]
+   crest_color: image::Rgba<u8>,
+
+   /// Fail unless the input matches all of these comma separated conditions,
+   /// e.g. "sample_rate=48000,channels=2,bit_depth>=16". Keys are
+   /// sample_rate, channels, bit_depth and duration (in seconds)
+   #[arg(long, value_parser = check::parse_assertions, env = "WELLENFORMER_ASSERT")]
+   assert: Option<check::Assertions>,
+
+   /// Confirm the SHA-256 of the input against this manifest in the format
+   /// of sha256sum (file names relative to it) before rendering, and record
+   /// it in the image metadata (PNG and JPEG), the --stats and bundles
+   #[arg(long, value_name = "PATH", env = "WELLENFORMER_VERIFY_MANIFEST")]
+   verify_manifest: Option<PathBuf>,
+
+   /// Write statistics about the rendered audio (levels, clipping,
+   /// normalization, per-column peaks) as JSON to this file
+   #[arg(long, env = "WELLENFORMER_STATS")]
+   stats: Option<PathBuf>,
+
+   /// Scale of the time axis
+   #[arg(long, value_enum, default_value_t = TimeScale::Linear, env = "WELLENFORMER_TIME_SCALE")]
+   time_scale: TimeScale,
+
+   /// Number of decades spanned by the logarithmic time scales (larger
+   /// values stretch the start even more)
+   #[arg(long, default_value_t = 3.0, value_parser = parse_time_decades, env = "WELLENFORMER_TIME_DECADES")]
+   time_decades: f64,
+
+   /// Additionally write the envelope as a mono WAV control signal
+   /// with one sample per pixel column
+   #[arg(long, env = "WELLENFORMER_EXPORT_ENVELOPE")]
+   export_envelope: Option<PathBuf>,
+
+   /// Which envelope to write with --export-envelope
+   #[arg(long, value_enum, default_value_t = Envelope::Peak, env = "WELLENFORMER_ENVELOPE")]
+   envelope: Envelope,
+
+   /// Additionally write the peaks of every pixel column in the format of
+   /// audiowaveform, binary for a ".dat" file and JSON otherwise
+   #[arg(long, value_name = "PATH", env = "WELLENFORMER_ALSO_PEAKS")]
+   also_peaks: Option<PathBuf>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print information about an audio file and export analysis results
+    Analyze(analyze::AnalyzeArgs),
+    /// Align two files, subtract them and report what is left
+    Null(compare::NullArgs),
+    /// Print the codecs, CPU features and threads in use and a short benchmark
+    Doctor,
+}
 
+/// Values drawn for every column with --detail
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Detail {
+    /// Whatever the mode draws
+    Mode,
+    /// Minimum, maximum and RMS level
+    Full,
+}
 
-fn parse_into_color(argument: &str) -> image::Rgba<u8> {
+/// Analysis drawn over the image with --overlay
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Overlay {
+    /// Contour of the fundamental frequency from 50 Hz at the bottom to
+    /// 1 kHz at the top (on a logarithmic axis)
+    Pitch,
+    /// Ticks at the top and bottom edge where sounds start, found by the
+    /// spectral flux
+    Onsets,
+}
+
+/// Kind of envelope computed per pixel column for --export-envelope
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Envelope {
+    /// Highest absolute sample value
+    Peak,
+    /// Root mean square of the samples
+    Rms,
+}
+
+
+
+/// Parse a duration like "100ms", "1.5s", "2min" or a plain number of seconds
+/// into seconds
+fn parse_duration(argument: &str) -> Result<f64, String> {
     let s = argument.trim().to_lowercase();
-    match &s[..] {
-        "transparent" => image::Rgba([0u8, 0u8, 0u8, 0u8]),
-        "none" => image::Rgba([0u8, 0u8, 0u8, 0u8]),
-        "red" => image::Rgba([255u8, 0u8, 0u8, 255u8]),
-        "yellow" => image::Rgba([255u8, 255u8, 0u8, 255u8]),
-        "green" => image::Rgba([0u8, 255u8, 0u8, 255u8]),
-        "blue" => image::Rgba([0u8, 0u8, 255u8, 255u8]),
-        "cyan" => image::Rgba([0u8, 255u8, 255u8, 255u8]),
-        "magenta" => image::Rgba([255u8, 0u8, 255u8, 255u8]),
-        "white" => image::Rgba([255u8, 255u8, 255u8, 255u8]),
-        "black" => image::Rgba([0u8, 0u8, 0u8, 255u8]),
-        _ => {
-            match s.split(",").collect::<Vec<&str>>()[..] {
-                [lum] => {
-                    let l = parse_to_u8(lum);
-                    image::Rgba([l, l, l, 255u8])
-                },
-                [lum, alpha] => {
-                    let l = parse_to_u8(lum);
-                    let a = parse_to_u8(alpha);
-                    image::Rgba([l, l, l, a])
-                },
-                [red, green, blue] => {
-                    let r = parse_to_u8(red);
-                    let g = parse_to_u8(green);
-                    let b = parse_to_u8(blue);
-                    image::Rgba([r, g, b, 255u8])
-                },
-                [red, green, blue, alpha] => {
-                    let r = parse_to_u8(red);
-                    let g = parse_to_u8(green);
-                    let b = parse_to_u8(blue);
-                    let a = parse_to_u8(alpha);
-                    image::Rgba([r, g, b, a])
-                },
-                _ => panic!("Unknown Color \"{s}\"")
-            }
-        }
+    let (number, scale) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = s.strip_suffix("min") {
+        (n, 60.0)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1.0)
+    } else {
+        (&s[..], 1.0)
+    };
+    match number::parse_number(number) {
+        Ok(value) if value > 0.0 => Ok(value * scale),
+        _ => Err(format!("\"{argument}\" is not a valid duration (expected e.g. \"100ms\", \"2s\" or \"1min\")")),
     }
+}
 
+/// Parse a zoom level in pixel columns per second like "100" or "12.5"
+fn parse_pixels_per_second(argument: &str) -> Result<f64, String> {
+    match number::parse_number(argument) {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("\"{argument}\" is not a valid number of pixels per second (expected a number above 0, e.g. \"100\")")),
+    }
+}
 
+/// Set the width of the image to hold `frames` frames at `sample_rate` at
+/// the fixed zoom level of --pixels-per-second or --samples-per-pixel, every
+/// column covering the same time
+fn apply_zoom(args: &mut Args, frames: f64, sample_rate: f64) {
+    let frames_per_column = match (args.pixels_per_second, args.samples_per_pixel) {
+        (Some(pixels_per_second), _) => sample_rate / pixels_per_second,
+        (None, Some(samples_per_pixel)) => samples_per_pixel as f64,
+        (None, None) => return,
+    };
+    args.width = ((frames / frames_per_column).ceil() as u32).max(1);
+    args.bucket_duration = Some(frames_per_column / sample_rate);
 }
 
-fn parse_to_u8(string: &str) -> u8 {
-    let string = string.trim();
-    if string.contains(".") {
-        match string.parse::<f32>() {
-            Ok(num) => (num.min(1.0).max(0.0) * 255.0) as u8,
-            Err(_e) => {
-                let error = "Error: ".bold().red();
-                let msg = format!("Failed to parse value \"{string}\" for color.");
-                eprintln!("{error}{msg}");
-                let hint = "Hint:  ".bold().green();
-                let msg = "Provide either a color literal (e.g. \"black\" or \"transparent\") or a comma-seperated list of colors in RGB or RGBA format with values ranging either from 0.0 to 1.0 or from 0 - 255.";
-                eprintln!("{hint}{msg}");
-                std::process::exit(1);
-            }
+/// Parse a duration in seconds where a number without unit is in
+/// milliseconds, e.g. "300" or "1.5s"
+fn parse_milliseconds(argument: &str) -> Result<f64, String> {
+    if argument.trim().ends_with(|c: char| c.is_ascii_alphabetic()) {
+        parse_duration(argument)
+    } else {
+        parse_duration(argument).map(|seconds| seconds / 1000.0)
+    }
+}
+
+/// Parse a positive gain factor like "1.37"
+fn parse_scale_factor(argument: &str) -> Result<f64, String> {
+    match number::parse_number(argument) {
+        Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(factor),
+        _ => Err(format!("\"{argument}\" is not a valid scale factor (expected a number above 0, e.g. \"1.37\")")),
+    }
+}
+
+/// Parse the number of decades of the logarithmic time scales, which must
+/// be above 0 and small enough for 10 to their power to stay finite
+fn parse_time_decades(argument: &str) -> Result<f64, String> {
+    match number::parse_number(argument) {
+        Ok(decades) if decades > 0.0 && decades <= MAX_TIME_DECADES => Ok(decades),
+        _ => Err(format!("\"{argument}\" is not a valid number of decades (expected a number above 0 and up to {MAX_TIME_DECADES}, e.g. \"3\")")),
+    }
+}
+
+/// Warn (or fail with --strict) about every color that is drawn in the
+/// selected mode and has too little contrast to the background
+fn check_contrast(args: &Args) {
+    let mut colors = vec![("foreground", args.foreground)];
+    if args.mode == RenderMode::Asymmetry || (args.mode == RenderMode::Loudness && args.target_lufs.is_some()) {
+        colors.push(("negative", args.negative_color));
+    }
+    if let Some(rms) = args.rms_color.filter(|_| matches!(args.mode, RenderMode::MinMax | RenderMode::Filmstrip)) {
+        colors.push(("RMS", rms));
+    }
+    if args.mode == RenderMode::Multiband {
+        colors = vec![("low band", args.low_color), ("mid band", args.mid_color), ("high band", args.high_color)];
+    }
+    let page = if args.background[3] < 255 { " behind the transparent background" } else { "" };
+    let mut insufficient = false;
+    for (name, color) in colors {
+        let ratio = color::contrast_ratio(color, args.background);
+        if ratio < args.min_contrast {
+            insufficient = true;
+            let label = Policy::new(args.strict, args.lenient).label();
+            eprintln!(
+                "{label}The {name} color has a contrast ratio of {ratio:.2}:1 to the background{}, below {:.2}:1",
+                if page.is_empty() { String::new() } else { format!(" (on a white or black page{page})") },
+                args.min_contrast
+            );
         }
+    }
+    if insufficient && args.strict {
+        std::process::exit(1);
+    }
+}
+
+/// Save a rendered image in the raster format chosen in `args`, with
+/// metadata about the `duration` seconds long `input` (and its verified
+/// `checksum`) where supported
+fn save_raster(img: &image::RgbaImage, output: &Path, args: &Args, input: &Path, duration: f64, checksum: Option<&str>) {
+    let metadata = image_metadata(args, input, duration, checksum);
+    let extension = output.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match args.format {
+        OutputFormat::Jpeg => output::save_jpeg(img, output, args.quality, &metadata),
+        // Contact sheets are PNGs unless they are named as JPEGs
+        OutputFormat::ContactSheet if OutputFormat::Jpeg.has_extension(&extension) => output::save_jpeg(img, output, args.quality, &metadata),
+        _ if checksum.is_some() => output::save_png(img, output, &metadata),
+        _ => output::save_image(img, output),
+    }
+}
+
+/// Metadata embedded into the images rendered from `input`
+fn image_metadata(args: &Args, input: &Path, duration: f64, checksum: Option<&str>) -> metadata::ImageMetadata {
+    metadata::ImageMetadata {
+        creator: args.creator.clone(),
+        source: input.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        duration,
+        source_sha256: checksum.map(str::to_string),
+    }
+}
+
+/// Format seconds as m:ss (or h:mm:ss from an hour on)
+fn format_timestamp(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
     } else {
-        match string.parse::<u32>() {
-            Ok(num) => num.min(255).max(0) as u8,
-            Err(_e) => {
-                let error = "Error: ".bold().red();
-                let msg = format!("Failed to parse value \"{string}\" for color.");
-                eprintln!("{error}{msg}");
-                let hint = "Hint:  ".bold().green();
-                let msg = "Provide either a color literal (e.g. \"black\" or \"transparent\") or a comma-seperated list of colors in RGB or RGBA format with values ranging either from 0.0 to 1.0 or from 0 - 255.";
-                eprintln!("{hint}{msg}");
-                std::process::exit(1);
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Compute the envelope of the audio for every column of `axis`
+fn compute_envelope(samples: &[f32], axis: &TimeAxis, kind: Envelope) -> Vec<f32> {
+    (0..axis.columns).into_par_iter()
+        .map(|x| {
+            let bucket = &samples[axis.range(x, samples.len())];
+            if bucket.is_empty() {
+                return 0.0;
             }
+            match kind {
+                Envelope::Peak => bucket.iter().fold(0.0f32, |a, &b| a.max(b.abs())),
+                Envelope::Rms => (bucket.iter().map(|s| s * s).sum::<f32>() / bucket.len() as f32).sqrt(),
+            }
+        })
+        .collect()
+}
+
+/// Machine-readable summary of a render
+fn render_stats(input: &Path, output: &Path, samples: &[f32], channels: usize, sample_rate: u32, axis: &TimeAxis, options: &RenderOptions) -> Json {
+    let (peak, rms) = analyze::levels(samples);
+    let frames = samples.len() / channels.max(1);
+    let column_peaks = compute_envelope(samples, axis, Envelope::Peak);
+    let columns = column_peaks.len().max(1) as f64;
+    let column_min = column_peaks.iter().fold(f32::INFINITY, |a, &b| a.min(b)) as f64;
+    let column_max = column_peaks.iter().fold(0.0f32, |a, &b| a.max(b)) as f64;
+    let column_mean = column_peaks.iter().map(|&p| p as f64).sum::<f64>() / columns;
+    Json::document("stats", vec![
+        ("input", Json::from(input.display().to_string())),
+        ("output", Json::from(output.display().to_string())),
+        ("duration", Json::from(frames as f64 / sample_rate.max(1) as f64)),
+        ("channels", Json::from(channels as f64)),
+        ("sample_rate", Json::from(sample_rate as f64)),
+        ("peak_dbfs", Json::from(analyze::to_dbfs(peak))),
+        ("rms_dbfs", Json::from(analyze::to_dbfs(rms))),
+        ("normalization_factor", Json::from(options.gain(samples))),
+        ("clipped_samples", Json::from(analyze::clipped_samples(samples) as f64)),
+        ("buckets", Json::object(vec![
+            ("count", Json::from(options.width as f64)),
+            ("samples_per_bucket", Json::from(axis.samples_per_column / channels.max(1) as f64)),
+            ("peak_min", Json::from(column_min)),
+            ("peak_mean", Json::from(column_mean)),
+            ("peak_max", Json::from(column_max)),
+        ])),
+    ])
+}
+
+/// Return a copy of `img` with an extra lane of `lane_height` pixels at the
+/// bottom, colored column by column by `color_at`
+fn append_lane<F>(img: &image::RgbaImage, lane_height: u32, color_at: F) -> image::RgbaImage
+    where F: Fn(u32) -> image::Rgba<u8>
+{
+    let (width, height) = img.dimensions();
+    ImageBuffer::from_fn(width, height + lane_height, |x, y| {
+        if y < height {
+            *img.get_pixel(x, y)
+        } else {
+            color_at(x)
         }
+    })
+}
+
+/// Exit with an error if `path` is not an existing file
+fn ensure_input_file(path: &Path) {
+    if !path.is_file() {
+        let error = "Error: ".bold().red();
+        let msg = format!("The input file \"{}\" does not exist (or is not a file)", path.to_string_lossy().yellow());
+        eprintln!("{error}{msg}");
+        std::process::exit(1);
+    }
+}
+
+/// Exit with an error if `path` is not an existing folder
+fn ensure_input_directory(path: &Path) {
+    if !path.is_dir() {
+        let error = "Error: ".bold().red();
+        let msg = format!("The input folder \"{}\" does not exist (or is not a folder)", path.to_string_lossy().yellow());
+        eprintln!("{error}{msg}");
+        std::process::exit(1);
+    }
+}
+
+/// Crest factors (in dB) at and below which the crest strip is fully opaque
+/// and at and above which it is transparent. A sine has 3 dB, lively
+/// unmastered music often more than 18 dB
+const CREST_OPAQUE: f64 = 6.0;
+const CREST_TRANSPARENT: f64 = 18.0;
+
+/// Crest factor in dB of every column of `axis`, None for silent columns
+fn crest_factors(samples: &[f32], axis: &TimeAxis) -> Vec<Option<f64>> {
+    (0..axis.columns)
+        .map(|x| {
+            let (peak, rms) = analyze::levels(&samples[axis.range(x, samples.len())]);
+            (rms > 0.0).then(|| analyze::to_dbfs(peak / rms))
+        })
+        .collect()
+}
+
+/// `color` of the crest strip faded by the `crest` factor of a column.
+/// Silent columns have no crest factor and stay empty
+fn crest_lane_color(color: image::Rgba<u8>, crest: Option<f64>) -> image::Rgba<u8> {
+    let Some(crest) = crest else {
+        return image::Rgba([0, 0, 0, 0]);
+    };
+    let intensity = ((CREST_TRANSPARENT - crest) / (CREST_TRANSPARENT - CREST_OPAQUE)).clamp(0.0, 1.0);
+    let mut color = color;
+    color.0[3] = (color.0[3] as f64 * intensity).round() as u8;
+    color
+}
+
+/// Color of the loudest levels of the spectrogram under --over-spectrogram,
+/// which has to stand apart from the waveform in the foreground color
+const SPECTROGRAM_OVERLAY_COLOR: image::Rgba<u8> = image::Rgba([255, 140, 0, 255]);
+
+/// Most decades of the logarithmic time scales
+const MAX_TIME_DECADES: f64 = 300.0;
+
+/// Point out that the input is flagged as pre-emphasized
+fn hint_pre_emphasis() {
+    let hint = "Hint:  ".bold().green();
+    eprintln!("{hint}The input is flagged as pre-emphasized, use --deemphasis to undo it");
+}
+
+/// The `samples` de-emphasized and downmixed as --deemphasis and
+/// --downmix-to ask, along with their number of channels afterwards
+fn preprocess(args: &Args, mut samples: Vec<f32>, channels: usize, sample_rate: u32) -> (Vec<f32>, usize) {
+    if args.deemphasis {
+        audio::deemphasize(&mut samples, channels, sample_rate);
+    }
+    match args.downmix_to {
+        Some(target) => (downmix::downmix(&samples, channels, target), target.channels()),
+        None => (samples, channels),
+    }
+}
+
+/// Write `content` to `path` or exit with an error
+fn write_text_file(path: &Path, content: &str) {
+    write_file(path, content.as_bytes())
+}
+
+/// Write the bytes in `content` to `path` or exit with an error
+fn write_file(path: &Path, content: &[u8]) {
+    let result = output::write_atomically(path, |temporary| {
+        std::fs::write(temporary, content).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}Could not write \"{}\": {}", path.display(), e);
+        std::process::exit(1);
     }
 }
 
-fn create_output_directories(path: &PathBuf) {
-    let mut p = path.clone();
+fn create_output_directories(path: &Path) {
+    let mut p = path.to_path_buf();
     if p.pop() && p.parent().is_some() {
         // There are directories in this path that may or may not need to be created
         if !p.exists() && p.to_string_lossy() == "" {
@@ -150,113 +948,838 @@ fn create_output_directories(path: &PathBuf) {
 }
 
 
-fn prepare_output_path(path: &PathBuf) -> PathBuf {
-    let mut p = path.clone();
+/// Ask before any of the `paths` that already exist are overwritten (unless
+/// --overwrite is given) and exit if the answer is no
+fn confirm_overwrite(paths: &[PathBuf], args: &Args) {
+    let existing: Vec<&PathBuf> = paths.iter().filter(|path| path.is_file()).collect();
+    if existing.is_empty() || args.overwrite {
+        return;
+    }
+    // The files exist and should not be overwritten without prompt
+    let msg = match (&existing[..], paths.len()) {
+        (_, 1) => "There is already a file at the specified output path!".to_string(),
+        ([path], _) => format!("There is already a file at \"{}\"!", path.display()),
+        (existing, _) => format!("There are already {} files at the output paths, e.g. \"{}\"!", existing.len(), existing[0].display()),
+    };
+    let msg = format!("{}{} {}", "Warning: ".red(), msg.red(), "Overwrite?".red());
+    let ans = Confirm::new(&msg)
+    .with_default(false)
+    .prompt();
+
+    if !matches!(ans, Ok(true)) {
+        std::process::exit(1);
+    }
+}
+
+
+fn prepare_output_path(path: &Path, format: OutputFormat) -> PathBuf {
+    let mut p = path.to_path_buf();
+    let extension = format.extension();
     if p.extension().is_none() {
-        p.set_extension("png");
-    } else if p.extension().unwrap().to_str().expect("REASON").to_lowercase() != "png" {
-        let new_extension = format!("{}.png", p.extension().unwrap().to_string_lossy());
+        p.set_extension(extension);
+    } else if !format.has_extension(&p.extension().unwrap().to_string_lossy().to_lowercase()) {
+        let new_extension = format!("{}.{extension}", p.extension().unwrap().to_string_lossy());
         p.set_extension(new_extension);
     }
     p
 }
 
 
+/// Options for rendering `channels` channels at `sample_rate` into an image
+/// `width` × `height` pixels large as the arguments ask for
+fn render_options(args: &Args, width: u32, height: u32, oversample: u32, channels: usize, sample_rate: u32) -> RenderOptions {
+    RenderOptions {
+        width,
+        height,
+        oversample,
+        channels,
+        sample_rate,
+        normalize: args.normalize,
+        scale_factor: args.scale_factor,
+        rectify: args.rectify,
+        mode: args.mode,
+        layout: args.layout,
+        baseline: args.baseline,
+        time_direction: args.time_direction,
+        time_scale: args.time_scale,
+        time_decades: args.time_decades,
+        foreground: args.foreground,
+        background: args.background,
+        negative: args.negative_color,
+        rms: args.rms_color,
+        rms_alpha: args.rms_alpha,
+        bar_width: args.bar_width,
+        bar_gap: args.bar_gap,
+        stroke_width: args.stroke_width,
+        dot_radius: args.dot_radius,
+        smooth: args.smooth,
+        inner_radius: args.inner_radius,
+        start_angle: args.start_angle,
+        bar_phase: args.bar_phase,
+        crossovers: args.crossovers,
+        band_colors: [args.low_color, args.mid_color, args.high_color],
+        loudness_window: args.loudness_window,
+        target_lufs: args.target_lufs,
+        filmstrip_rows: args.filmstrip_rows,
+        spectrogram: SpectrogramOptions {
+            window: args.window,
+            overlap: args.overlap,
+            min_frequency: args.fmin,
+            max_frequency: args.fmax,
+            scale: args.freq_scale,
+            mel_bands: args.mel_bands,
+            color: args.spectrogram_color.or_else(|| {
+                let overlay = args.over_spectrogram.is_some() && args.mode.draws_over_spectrogram();
+                overlay.then_some(SPECTROGRAM_OVERLAY_COLOR)
+            }),
+            slices: args.slices,
+            perspective: args.perspective,
+        },
+        over_spectrogram: args.over_spectrogram,
+        dither: args.dither,
+        filter: args.filter,
+        smooth_columns: args.smooth_columns,
+        smooth_kernel: args.smooth_kernel,
+        interpolation: args.interpolation,
+        rasterizer: args.rasterizer,
+        decimate: !args.no_decimate,
+    }
+}
+
+
+/// Render every audio file in `directory` into a cell of a contact sheet,
+/// all on the time scale of the longest one, and save it to `output`
+fn render_contact_sheet(args: &Args, directory: &Path, output: &Path) {
+    let error = "Error: ".bold().red();
+    let policy = Policy::new(args.strict, args.lenient);
+    let files = match sheet::audio_files(directory) {
+        Ok(files) if files.is_empty() => {
+            eprintln!("{error}There are no audio files in \"{}\"", directory.display());
+            std::process::exit(1);
+        },
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{error}Could not read the folder \"{}\": {}", directory.display(), e);
+            std::process::exit(1);
+        },
+    };
+
+    // The durations come first, so every take can be drawn at its share of
+    // the width of a cell right away. Files that don't state their length
+    // are decoded to measure it
+    let mut durations = vec![];
+    for path in files {
+        let duration = match audio::probe_duration(&path) {
+            Some(duration) => duration,
+            None => match audio::decode_file(&path, &mut |_| ()) {
+                Ok(audio) => audio.samples.len() as f64 / audio.channels as f64 / audio.sample_rate as f64,
+                Err(e) => {
+                    policy.recoverable(&format!("Left out \"{}\", it could not be decoded: {e}", path.display()));
+                    continue;
+                },
+            },
+        };
+        match duration > 0.0 {
+            true => durations.push((path, duration)),
+            false => policy.recoverable(&format!("Left out \"{}\", it holds no audio", path.display())),
+        }
+    }
+    let longest = durations.iter().fold(0.0f64, |a, (_, duration)| a.max(*duration));
+
+    let cell_width = sheet::cell_width(args.width, args.sheet_columns);
+    let total = durations.iter().map(|(_, duration)| duration).sum();
+    let mut cells = vec![];
+    for (path, probed) in durations {
+        let audio = match audio::decode_file(&path, &mut |_| ()) {
+            Ok(audio) => audio,
+            Err(e) => {
+                policy.recoverable(&format!("Left out \"{}\", it could not be decoded: {e}", path.display()));
+                continue;
+            },
+        };
+        audio::report_damage(&path, &audio, policy);
+        let Audio { channels, sample_rate, samples, .. } = audio;
+        let (samples, channels) = preprocess(args, samples, channels, sample_rate);
+        let frames = samples.len() / channels;
+        if frames == 0 {
+            policy.recoverable(&format!("Left out \"{}\", it holds no audio", path.display()));
+            continue;
+        }
+        // Decoding may find a little more or less audio than the container
+        // states, the share is only taken from the probed durations
+        let duration = frames as f64 / sample_rate as f64;
+        let width = ((probed / longest * cell_width as f64).round() as u32).clamp(1, cell_width);
+        let oversample = args.oversample.factor(frames as f64 / width as f64, width, args.height, args.mode);
+        let options = render_options(args, width, args.height, oversample, channels, sample_rate);
+        let samples_per_pixel = samples.len() as f64 / (width * oversample) as f64;
+        let badge = args.sheet_badges.then(|| sheet::Badge {
+            loudness: loudness::integrated_loudness(&samples, channels, sample_rate),
+            clipped: analyze::clipped_samples(&samples),
+        });
+        cells.push(sheet::Cell {
+            name: path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            duration: format_timestamp(duration),
+            waveform: render::render_waveform(&samples, samples_per_pixel, &options),
+            badge,
+        });
+    }
+
+    if cells.is_empty() {
+        eprintln!("{error}None of the audio files in \"{}\" could be decoded", directory.display());
+        std::process::exit(1);
+    }
+    let img = sheet::compose(&cells, args.sheet_columns, cell_width, args.height, args.target_lufs, args.foreground, args.background);
+    println!("Processed {} takes, the longest lasting {}", cells.len(), format_timestamp(longest));
+    println!("Saving contact sheet to \"{}\"", output.display());
+    save_raster(&img, output, args, directory, total, None);
+}
+
+
 fn main() {
     use std::time::Instant;
     let now = Instant::now();
 
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    output::install_signal_handler();
 
-    // Ensure that the input file is a file
-    if !args.input.is_file() {
-        let error = "Error: ".bold().red();
-        let msg = format!("The input file \"{}\" does not exist (or is not a file)", args.input.to_string_lossy().yellow());
-        eprintln!("{error}{msg}");
-        std::process::exit(1);
+    if let Some(path) = &args.colors_from {
+        let colors = match palette::colors_from(path, args.min_contrast) {
+            Ok(colors) => colors,
+            Err(e) => {
+                let error = "Error: ".bold().red();
+                eprintln!("{error}Could not take colors from \"{}\": {}", path.display(), e);
+                std::process::exit(1);
+            },
+        };
+        let derived = |id: &str| matches.value_source(id) == Some(ValueSource::DefaultValue);
+        if derived("background") {
+            args.background = colors.background;
+        }
+        if derived("foreground") {
+            args.foreground = colors.foreground;
+        }
+        if derived("negative_color") {
+            args.negative_color = colors.negative;
+        }
     }
 
-    let output = prepare_output_path(&args.output);
+    if let Some(seed) = args.variant_seed {
+        let variant = variant::Variant::from_seed(seed);
+        args.foreground = variant.color(args.foreground);
+        args.negative_color = variant.color(args.negative_color);
+        args.rms_color = args.rms_color.map(|color| variant.color(color));
+        args.low_color = variant.color(args.low_color);
+        args.mid_color = variant.color(args.mid_color);
+        args.high_color = variant.color(args.high_color);
+        // Given explicitly the start angle is kept
+        if matches.value_source("start_angle") == Some(ValueSource::DefaultValue) {
+            args.start_angle = variant.start_angle;
+        }
+        args.bar_phase = variant.bar_phase;
+    }
 
-    // Exit if we don't want to overwrite
-    if output.is_file() && !args.overwrite {
-        // The file exists and should not be overwritten without prompt
-        let msg = format!("{}There is already a file at the specified output path! {}", "Warning: ".red(), "Overwrite?".red());
-        let ans = Confirm::new(&msg)
-        .with_default(false)
-        .prompt();
-
-        match ans {
-            Ok(true) => {
-                ()
+    // Full detail is the min/max silhouette with the RMS shaded inside, which
+    // shows clipping and transients that averaging would hide
+    if args.detail == Detail::Full {
+        match args.mode {
+            RenderMode::MinMax | RenderMode::Mean => {
+                args.mode = RenderMode::MinMax;
+                args.rms_color.get_or_insert(color::composite(args.foreground, args.background, 0.5));
             },
             _ => {
-                std::process::exit(1);
-            }
+                let warning = "Warning: ".bold().yellow();
+                eprintln!("{warning}--detail full only applies to the minmax and mean modes");
+            },
+        }
+    }
+
+    // The waveform and its lanes fill the area inside the margins of a size
+    // preset, the rest of the image is added when saving
+    if let Some(preset) = args.size {
+        let (width, height) = preset.inner();
+        let lanes = [
+            (args.show_stream_titles, titles::LANE_HEIGHT),
+            (args.segments, args.segment_height),
+            (args.diff_against.is_some(), args.diff_height),
+            (args.crest, args.crest_height),
+        ];
+        let lane_height: u32 = lanes.iter().filter(|(enabled, _)| *enabled).map(|(_, h)| h).sum();
+        args.width = width;
+        args.height = height.saturating_sub(lane_height).max(1);
+        // The rows of the filmstrip share the height
+        if args.mode == RenderMode::Filmstrip {
+            args.height = (args.height / args.filmstrip_rows).max(1);
+        }
+    }
+
+    if let Some(command) = &args.command {
+        match command {
+            Command::Analyze(analyze_args) => analyze::analyze(analyze_args),
+            Command::Null(null_args) => compare::null_test(null_args),
+            Command::Doctor => doctor::run(),
         }
+        return;
+    }
+
+    if args.selftest {
+        selftest::run();
+        return;
+    }
+
+    // Both are required by clap unless a subcommand or --selftest is given
+    let input = args.input.clone().unwrap();
+    let output = prepare_output_path(args.output.as_ref().unwrap(), args.format);
+    // Text output can go straight to the terminal
+    let to_stdout = args.format == OutputFormat::Braille && args.output.as_deref() == Some(Path::new("-"));
+
+    // Ensure that the input file is a file (a folder for contact sheets)
+    match args.format {
+        OutputFormat::ContactSheet => ensure_input_directory(&input),
+        _ => ensure_input_file(&input),
+    }
+    if args.format == OutputFormat::ContactSheet && args.verify_manifest.is_some() {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}--verify-manifest checks a single file and can't be combined with --format contact-sheet");
+        std::process::exit(1);
+    }
+
+    // An input that isn't what the archive holds is not worth rendering
+    let checksum = args.verify_manifest.as_ref().map(|manifest| match checksum::verify(&input, manifest) {
+        Ok(checksum) => {
+            println!("Verified the SHA-256 of \"{}\" against \"{}\"", input.display(), manifest.display());
+            checksum
+        },
+        Err(e) => {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}{e}");
+            std::process::exit(1);
+        },
+    });
+
+    // Exit if we don't want to overwrite
+    if !to_stdout {
+        confirm_overwrite(std::slice::from_ref(&output), &args);
     }
 
-    create_output_directories(&output);
+    if !to_stdout {
+        create_output_directories(&output);
+    }
 
     // Parse the colors
-    let background_color = parse_into_color(&args.background);
-    let foreground_color = parse_into_color(&args.foreground);
+    let background_color = args.background;
+    let foreground_color = args.foreground;
+    if args.check_contrast {
+        check_contrast(&args);
+    }
 
-    // Caluculate the internal width
-    let width = args.width as u32 * args.oversample;
-    let height = args.height as u32;
+    if args.format == OutputFormat::ContactSheet {
+        render_contact_sheet(&args, &input, &output);
+        let msg = format!("Finished after {:.2?}", now.elapsed()).green();
+        println!("{}", msg);
+        return;
+    }
+
+    if midi::is_midi(&input) {
+        if !args.format.is_raster() || args.format == OutputFormat::Bundle {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}MIDI files can only be rendered as PNG or JPEG");
+            std::process::exit(1);
+        }
+        let notes = match midi::read_midi(&input) {
+            Ok(notes) => notes,
+            Err(e) => {
+                let error = "Error: ".bold().red();
+                eprintln!("{error}Could not read MIDI file \"{}\": {}", input.display(), e);
+                std::process::exit(1);
+            }
+        };
+        let duration = notes.iter().fold(0.0f64, |a, n| a.max(n.end));
+        if args.samples_per_pixel.is_some() {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}MIDI files have no samples, use --pixels-per-second for a fixed zoom level");
+            std::process::exit(1);
+        }
+        // Seconds as the frames of a time line at one frame per second
+        apply_zoom(&mut args, duration, 1.0);
+        let seconds_per_column = match args.bucket_duration {
+            Some(duration) => duration,
+            None => (duration / args.width as f64).max(f64::EPSILON),
+        };
+        let rtl = args.time_direction == TimeDirection::Rtl;
+        let img = midi::render_piano_roll(&notes, args.width, args.height, seconds_per_column, rtl, foreground_color, background_color);
+        println!("Processed {} MIDI Notes", notes.len());
+        println!("Saving image to \"{}\" )", &output.display());
+        let img = match args.size.map(|preset| preset.dimensions()) {
+            Some((width, height)) => size::place(&img, width, height, background_color),
+            None => img,
+        };
+        save_raster(&img, &output, &args, &input, duration, checksum.as_deref());
+        let msg = format!("Finished after {:.2?}", now.elapsed()).green();
+        println!("{}", msg);
+        return;
+    }
 
-    let (channels, samples) = read_audio(&args.input);
+    let policy = Policy::new(args.strict, args.lenient);
+    let mut events = vec![];
+    let audio = match args.show_stream_titles {
+        true => read_audio_with(&input, policy, &mut |event| events.push(event.clone())),
+        false => read_audio_with(&input, policy, &mut |_| ()),
+    };
+    if let Some(assertions) = &args.assert {
+        check::enforce(&input, &audio, assertions);
+    }
+    let provenance = (args.format == OutputFormat::Bundle).then(|| bundle::provenance(&input, checksum.as_deref(), &audio));
+    let Audio { channels, sample_rate, pre_emphasis, samples, gaps, .. } = audio;
+    if pre_emphasis && !args.deemphasis {
+        hint_pre_emphasis();
+    }
+    let (samples, channels) = preprocess(&args, samples, channels, sample_rate);
+    if !gaps.is_empty() {
+        let missing: usize = gaps.iter().map(|gap| gap.frames).sum();
+        policy.recoverable(&format!("{} stretches of missing audio ({:.2} s in total) were filled with silence and are drawn hatched", gaps.len(), missing as f64 / sample_rate as f64));
+    }
     
     let sample_count = samples.len();
+    apply_zoom(&mut args, (sample_count / channels) as f64, sample_rate as f64);
 
-    let samples_per_pixel = sample_count  as f64/ (width as f64);
+    // Caluculate the internal width
+    let frames_per_pixel = match args.bucket_duration {
+        Some(duration) => duration * sample_rate as f64,
+        None => sample_count as f64 / channels as f64 / args.width as f64,
+    };
+    // Every row of the filmstrip covers only its share of the time
+    let frames_per_column = match args.mode {
+        RenderMode::Filmstrip => frames_per_pixel / args.filmstrip_rows as f64,
+        _ => frames_per_pixel,
+    };
+    let oversample = args.oversample.factor(frames_per_column, args.width, args.height, args.mode);
+    let width = args.width * oversample;
+    // Every row of the filmstrip is as tall as a waveform on its own
+    let height = match args.mode {
+        RenderMode::Filmstrip => args.height.saturating_mul(args.filmstrip_rows),
+        _ => args.height,
+    };
 
-    let (minimum, maximum) = (-1.0, 1.0);
+    let samples_per_pixel = match args.bucket_duration {
+        Some(duration) => {
+            // Every output column covers the same amount of time, the internal
+            // columns split that further by the oversampling factor
+            let samples_per_pixel = duration * sample_rate as f64 * channels as f64 / oversample as f64;
+            if samples_per_pixel * width as f64 + 0.5 < sample_count as f64 {
+                let seconds = duration * args.width as f64;
+                policy.recoverable(&format!("Only the first {seconds:.2}s of the audio fit into the image at this bucket duration"));
+            }
+            samples_per_pixel
+        },
+        None => sample_count as f64 / (width as f64),
+    };
 
-    let factor = if args.normalize {
-        let factor = samples.iter().fold(0.0f32, |a, &b| a.abs().max(b.abs())) as f64;
-        // Times two because we render half the waveform here
-        factor * 2.0
-    } else {
-        2.0
+    if matches!(args.mode, RenderMode::Spectrogram | RenderMode::Waterfall) {
+        let nyquist = sample_rate as f64 / 2.0;
+        if args.fmin < 0.0 || args.fmax.is_some_and(|fmax| fmax <= args.fmin) {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}--fmin must be at least 0 Hz and below --fmax");
+            std::process::exit(1);
+        }
+        if args.fmax.is_some_and(|fmax| fmax > nyquist) || args.fmin >= nyquist {
+            let warning = "Warning: ".bold().yellow();
+            eprintln!("{warning}The input contains no frequencies above {nyquist} Hz (half its sample rate), the frequency range is cropped");
+        }
+    }
+
+    if args.frequency_labels && args.mode != RenderMode::Spectrogram {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--frequency-labels only applies to the spectrogram mode");
+    }
+
+    if args.smooth_columns > args.width as f64 {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--smooth-columns {} is wider than the image, smoothing over {} columns instead", args.smooth_columns, args.width);
+        args.smooth_columns = args.width as f64;
+    }
+
+    if args.stream && (args.format != OutputFormat::Png || args.time_scale != TimeScale::Linear) {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}--stream writes PNG images on a linear time scale only");
+        std::process::exit(1);
+    }
+
+    if args.stream && (!args.mode.renders_in_strips() || args.over_spectrogram.is_some()) {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}--stream only splits the minmax, mean, asymmetry and line modes into strips, without --over-spectrogram");
+        std::process::exit(1);
+    }
+
+    if args.rms_color.is_some() && !matches!(args.mode, RenderMode::MinMax | RenderMode::Filmstrip) {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--rms-color is only drawn in the minmax and filmstrip modes");
+    }
+
+    if args.peak_hold.is_some() && (args.stream || !args.mode.has_columns()) {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--peak-hold is only drawn over the waveform modes and not with --stream");
+    }
+
+    if !args.overlay.is_empty() && (args.stream || matches!(args.mode, RenderMode::Radial | RenderMode::Vectorscope | RenderMode::Waterfall | RenderMode::Filmstrip)) {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--overlay needs time running along the image and is not drawn with --stream");
+    }
+
+    if args.compare.is_some() && !args.mode.has_columns() {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--compare is only drawn in the waveform modes");
+    }
+
+    if args.rasterizer == Rasterizer::Analytic && !matches!(args.mode, RenderMode::MinMax | RenderMode::Filmstrip) {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--rasterizer only applies to the minmax and filmstrip modes");
+    }
+
+    if args.rms_alpha && !args.mode.has_columns() && args.mode != RenderMode::Filmstrip {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--rms-alpha only fades the columns of the waveform modes");
+    }
+
+    if args.timecode.is_some() && args.format != OutputFormat::Pdf {
+        let warning = "Warning: ".bold().yellow();
+        eprintln!("{warning}--timecode only labels the time axis of PDF outputs");
+    }
+
+    let options = render_options(&args, args.width, height, oversample, channels, sample_rate);
+
+    let gain = options.gain(&samples);
+    // Printed in full precision so it can be given to --scale-factor
+    let report_gain = |gain: f64| if args.normalize || args.scale_factor.is_some() {
+        println!("Scaled the samples by a factor of {gain}");
     };
 
-    let graph: Vec<u32> = 
-    samples.par_iter()
-           // .step_by(channels.into())
-           .map(|s| {
-                let sample = if s < &0.0 {
-                    // (4.0 * (s as f64 / minimum as f64)).tanh()
-                    factor * *s as f64 / minimum as f64
-                } else {
-                    // (4.0 * ( s as f64 / maximum as f64)).tanh()
-                    factor * *s as f64 / maximum as f64
-                };
-                let pixel_height = (sample * args.height as f64).round();
-                pixel_height as u32
-            })
-           .collect();
-
-    // TODO: Add parallel creation of image buffer
-    let mut img = ImageBuffer::from_fn(width, height, |x, y| {
-        let start_sample_index = (x as f64 * samples_per_pixel).round() as usize;
-        let end_sample_index = (((x+1) as f64 * samples_per_pixel).round() as usize).min(sample_count);
-
-        let range = end_sample_index - start_sample_index;
-        let pixel_height = (graph[start_sample_index..end_sample_index].iter()
-                                .sum::<u32>() as f64 / range as f64).round() as usize;
-        if (height - (y+1)) < pixel_height  as u32{
-            foreground_color
-        } else {
-            background_color
+    if args.format == OutputFormat::Braille {
+        // Every character holds 2×4 pixels of the regular rendering
+        let options = RenderOptions { width: args.columns * 2, height: args.rows * 4, ..options };
+        let samples_per_pixel = samples_per_pixel * width as f64 / (options.width * options.oversample) as f64;
+        let img = render::render_waveform(&samples, samples_per_pixel, &options);
+        let text = terminal::to_braille(&img, foreground_color, background_color);
+        if to_stdout {
+            print!("{text}");
+            return;
         }
-    });
+        println!("Processed {} Audio Samples", sample_count/channels);
+        report_gain(render::waveform_gain(&samples, samples_per_pixel, &options));
+        println!("Saving text to \"{}\"", output.display());
+        write_text_file(&output, &text);
+        let msg = format!("Finished after {:.2?}", now.elapsed()).green();
+        println!("{}", msg);
+        return;
+    }
+
+    if args.format == OutputFormat::Pdf {
+        let title = input.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let options = pdf::PdfOptions { seconds_per_page: args.seconds_per_page, timecode: args.timecode, title, color: foreground_color };
+        let pdf = pdf::to_pdf(&samples, channels, sample_rate, gain, &options);
+        println!("Processed {} Audio Samples", sample_count/channels);
+        report_gain(gain);
+        println!("Saving document to \"{}\"", output.display());
+        write_file(&output, &pdf);
+        let msg = format!("Finished after {:.2?}", now.elapsed()).green();
+        println!("{}", msg);
+        return;
+    }
+
+    if !args.format.is_raster() {
+        let axis = options.time_axis(args.width, samples_per_pixel * oversample as f64);
+        let size = (args.physical_width, args.physical_width * args.height as f64 / args.width as f64);
+        let outline = vector::outline(&samples, &axis, gain, args.time_direction, size, args.tolerance);
+        let content = match args.format {
+            OutputFormat::Svg => {
+                let hatch = vector::Hatch { spacing: args.hatch_spacing, angle: args.hatch_angle };
+                let hatch = (args.svg_style == vector::SvgStyle::Hatch).then_some(hatch);
+                vector::to_svg(&outline, foreground_color, hatch).into_bytes()
+            },
+            OutputFormat::Stl => vector::to_stl(&outline, args.depth),
+            OutputFormat::Csv => vector::to_csv(&outline).into_bytes(),
+            OutputFormat::Gcode => vector::to_gcode(&outline, args.cut_depth, args.feed_rate).into_bytes(),
+            _ => vector::to_dxf(&outline).into_bytes(),
+        };
+        println!("Processed {} Audio Samples", sample_count/channels);
+        report_gain(gain);
+        println!("Saving outline with {} points to \"{}\"", outline.points.len(), output.display());
+        write_file(&output, &content);
+        let msg = format!("Finished after {:.2?}", now.elapsed()).green();
+        println!("{}", msg);
+        return;
+    }
 
     println!("Processed {} Audio Samples", sample_count/channels);
+    report_gain(render::waveform_gain(&samples, samples_per_pixel, &options));
+    // Printed in full precision so timelines can align to it exactly
+    println!("Drew {} samples per pixel", samples_per_pixel * oversample as f64 / channels as f64);
     println!("Saving image to \"{}\" )", &output.display());
-    img = image::imageops::resize(&img, args.width, height,  image::imageops::FilterType::Lanczos3);
-    img.save(output).unwrap();
+
+    // Time axis of the output columns in interleaved samples
+    let output_axis = options.time_axis(args.width, samples_per_pixel * oversample as f64);
+    let stats = |samples: &[f32]| {
+        let json = render_stats(&input, &output, samples, channels, sample_rate, &output_axis, &options);
+        match &checksum {
+            Some(checksum) => json.with("input_sha256", Json::from(checksum.as_str())),
+            None => json,
+        }
+    };
+    // Mirror output columns when time flows from right to left
+    let time_column = |x: u32| match args.time_direction {
+        TimeDirection::Ltr => x,
+        TimeDirection::Rtl => args.width - 1 - x,
+    };
+    let column_time = |x: u32| {
+        output_axis.position(time_column(x) as f64 + 0.5) / (sample_rate as f64 * channels as f64)
+    };
+    // The column whose center is closest to the frame
+    let column_at = |frame: usize| {
+        let time = frame as f64 / sample_rate as f64;
+        (0..args.width).min_by(|&a, &b| (column_time(a) - time).abs().total_cmp(&(column_time(b) - time).abs())).unwrap_or(0)
+    };
+
+    // Players expect a whole number of frames per column on a linear scale
+    let frames_per_column = (samples_per_pixel * oversample as f64 / channels as f64).round().max(1.0) as usize;
+
+    let max_image_width = tiles::max_width(args.format, args.max_image_width);
+    // The tiles and the manifest replace the output, so they are what
+    // could be overwritten
+    let confirm_tiles = |tiles: &[tiles::Tile]| {
+        if tiles.len() > 1 {
+            let mut paths: Vec<PathBuf> = tiles.iter().map(|tile| tile.path.clone()).collect();
+            paths.push(tiles::manifest_path(&output));
+            confirm_overwrite(&paths, &args);
+        }
+    };
+    let save_manifest = |tiles: &[tiles::Tile], width: u32, height: u32| {
+        if tiles.len() > 1 {
+            let path = tiles::manifest_path(&output);
+            write_text_file(&path, &format!("{}\n", tiles::manifest(tiles, width, height, column_time)));
+            println!("Split the image into {} tiles listed in \"{}\"", tiles.len(), path.display());
+        }
+    };
+
+    if args.stream {
+        let tiles = tiles::split(&output, args.width, max_image_width);
+        confirm_tiles(&tiles);
+        let duration = sample_count as f64 / channels as f64 / sample_rate as f64;
+        let metadata = checksum.as_deref().map(|checksum| image_metadata(&args, &input, duration, Some(checksum)));
+        if let Err(e) = stream::save_png(&samples, samples_per_pixel, &options, &tiles, metadata.as_ref()) {
+            let error = "Error: ".bold().red();
+            eprintln!("{error}Could not write \"{}\": {}", output.display(), e);
+            std::process::exit(1);
+        }
+        save_manifest(&tiles, args.width, height);
+    } else {
+        let mut img = render::render_waveform(&samples, samples_per_pixel, &options);
+        if args.frequency_labels && args.mode == RenderMode::Spectrogram {
+            spectrogram::draw_frequency_labels(&mut img, &options, foreground_color);
+        }
+
+        if let Some(path) = args.compare.as_ref().filter(|_| args.mode.has_columns()) {
+            ensure_input_file(path);
+            let Audio { channels: other_channels, sample_rate: other_sample_rate, samples: other_samples, .. } = read_audio_with(path, policy, &mut |_| ());
+            if other_sample_rate != sample_rate {
+                let error = "Error: ".bold().red();
+                eprintln!("{error}Cannot compare files with different sample rates ({} Hz vs {} Hz)", sample_rate, other_sample_rate);
+                std::process::exit(1);
+            }
+            // Prepared like the input, so both are drawn alike
+            let (other_samples, other_channels) = preprocess(&args, other_samples, other_channels, other_sample_rate);
+            let compared = compare::comparison(&samples, channels, &other_samples, other_channels, args.compare_style);
+            // Scaled like the input, so levels can be compared
+            let compare_options = RenderOptions {
+                normalize: false,
+                scale_factor: Some(gain),
+                foreground: args.compare_color,
+                background: image::Rgba([0, 0, 0, 0]),
+                negative: args.compare_color,
+                rms: None,
+                band_colors: [args.compare_color; 3],
+                ..options.clone()
+            };
+            let other = render::render_waveform(&compared, samples_per_pixel, &compare_options);
+            image::imageops::overlay(&mut img, &other, 0, 0);
+        }
+
+        if !gaps.is_empty() {
+            let frames = |x: u32| {
+                let range = output_axis.range(time_column(x), samples.len());
+                range.start / channels..range.end.div_ceil(channels)
+            };
+            gaps::hatch(&mut img, |x| gaps::overlaps(&gaps, frames(x)), args.gap_color, background_color);
+        }
+
+        if let Some(division) = args.peak_hold.filter(|_| args.mode.has_columns()) {
+            let frames_per_division = (division * sample_rate as f64).round().max(1.0) as usize;
+            let mut holds = peakhold::holds(&samples, channels, frames_per_division);
+            // Rectified and bottom aligned waveforms grow upwards from the
+            // bottom edge
+            let unipolar = args.rectify || (args.mode == RenderMode::Mean && args.layout == Layout::Bottom);
+            if unipolar {
+                holds.iter_mut().for_each(|hold| hold.value = hold.value.abs());
+            }
+            let row_at = |value: f32| match unipolar {
+                true => height as f64 * (1.0 - (value as f64 * gain).min(1.0)),
+                false => options.sample_y(value as f64 * gain),
+            };
+            peakhold::draw(&mut img, &holds, column_at, row_at, args.peak_hold_color);
+        }
+
+        let overlays = match args.mode {
+            RenderMode::Radial | RenderMode::Vectorscope | RenderMode::Waterfall | RenderMode::Filmstrip => &[][..],
+            _ => &args.overlay[..],
+        };
+        let mono = if overlays.is_empty() { vec![] } else { compare::downmix(&samples, channels) };
+        if overlays.contains(&Overlay::Onsets) {
+            let onsets = onsets::onsets(&mono, sample_rate, args.onset_sensitivity);
+            onsets::draw(&mut img, &onsets, column_at, args.onset_color);
+        }
+        if overlays.contains(&Overlay::Pitch) {
+            let centers: Vec<usize> = (0..args.width)
+                .map(|x| {
+                    let range = output_axis.range(time_column(x), samples.len());
+                    (range.start + range.end) / 2 / channels
+                })
+                .collect();
+            pitch::draw(&mut img, &pitch::contour(&mono, sample_rate, &centers), args.pitch_color);
+        }
+
+        if args.show_stream_titles {
+            let stream_titles = titles::stream_titles(&events);
+            if stream_titles.is_empty() {
+                policy.recoverable(&format!("\"{}\" contains no stream titles", input.display()));
+            }
+            titles::draw_boundaries(&mut img, &stream_titles, column_at, foreground_color);
+            let spans = titles::spans(&stream_titles, sample_count / channels, column_at);
+            let lane = titles::title_lane(args.width, &stream_titles, &spans, foreground_color, background_color);
+            let mut stacked = image::RgbaImage::new(img.width(), img.height() + lane.height());
+            image::imageops::replace(&mut stacked, &img, 0, 0);
+            image::imageops::replace(&mut stacked, &lane, 0, img.height() as i64);
+            img = stacked;
+        }
+        if args.segments {
+            let regions = segment::segment(&samples, channels, sample_rate);
+            let speech_color = args.speech_color;
+            let music_color = args.music_color;
+            let silence_color = args.silence_color;
+            img = append_lane(&img, args.segment_height, |x| {
+                match segment::kind_at(&regions, column_time(x)) {
+                    segment::Segment::Speech => speech_color,
+                    segment::Segment::Music => music_color,
+                    segment::Segment::Silence => silence_color,
+                }
+            });
+        }
+        if let Some(path) = &args.diff_against {
+            ensure_input_file(path);
+            let Audio { channels: other_channels, sample_rate: other_sample_rate, samples: other_samples, .. } = read_audio_with(path, policy, &mut |_| ());
+            if other_sample_rate != sample_rate {
+                let error = "Error: ".bold().red();
+                eprintln!("{error}Cannot compare files with different sample rates ({} Hz vs {} Hz)", sample_rate, other_sample_rate);
+                std::process::exit(1);
+            }
+            // Prepared like the input, so both are drawn alike
+            let (other_samples, other_channels) = preprocess(&args, other_samples, other_channels, other_sample_rate);
+            let a = compare::downmix(&samples, channels);
+            let b = compare::downmix(&other_samples, other_channels);
+            let frame_axis = TimeAxis { samples_per_column: output_axis.samples_per_column / channels as f64, ..output_axis };
+            let differences = compare::difference_per_column(&a, &b, &frame_axis);
+            let diff_color = args.diff_color;
+            img = append_lane(&img, args.diff_height, |x| {
+                let db = analyze::to_dbfs(differences[time_column(x) as usize] as f64);
+                let intensity = ((db - args.diff_ceiling + 50.0) / 50.0).clamp(0.0, 1.0);
+                let mut color = diff_color;
+                color.0[3] = (color.0[3] as f64 * intensity).round() as u8;
+                color
+            });
+        }
+
+        if args.crest {
+            let crest_factors = crest_factors(&samples, &output_axis);
+            img = append_lane(&img, args.crest_height, |x| crest_lane_color(args.crest_color, crest_factors[time_column(x) as usize]));
+        }
+
+        if let Some((width, height)) = args.size.map(|preset| preset.dimensions()) {
+            img = size::place(&img, width, height, background_color);
+        }
+        let duration = sample_count as f64 / channels as f64 / sample_rate as f64;
+        if let Some(provenance) = &provenance {
+            let tiles = tiles::split(Path::new("waveform.png"), img.width(), max_image_width);
+            let mut entries = vec![];
+            for tile in &tiles {
+                let part = image::imageops::crop_imm(&img, tile.left, 0, tile.width, img.height()).to_image();
+                let mut png = std::io::Cursor::new(vec![]);
+                if let Err(e) = part.write_to(&mut png, image::ImageOutputFormat::Png) {
+                    let error = "Error: ".bold().red();
+                    eprintln!("{error}Could not encode \"{}\": {}", tile.path.display(), e);
+                    std::process::exit(1);
+                }
+                entries.push((tile.path.display().to_string(), png.into_inner()));
+            }
+            if tiles.len() > 1 {
+                let manifest = tiles::manifest(&tiles, img.width(), img.height(), column_time);
+                entries.push(("waveform.tiles.json".to_string(), format!("{manifest}\n").into_bytes()));
+            }
+            let peaks = peaks::peaks(&samples, channels, sample_rate, frames_per_column);
+            entries.push(("peaks.dat".to_string(), peaks::to_dat(&peaks)));
+            entries.push(("stats.json".to_string(), format!("{}\n", stats(&samples)).into_bytes()));
+            entries.push(("provenance.json".to_string(), format!("{provenance}\n").into_bytes()));
+            match bundle::zip(&entries) {
+                Ok(zip) => write_file(&output, &zip),
+                Err(e) => {
+                    let error = "Error: ".bold().red();
+                    eprintln!("{error}Could not write \"{}\": {}", output.display(), e);
+                    std::process::exit(1);
+                },
+            }
+        } else {
+            let tiles = tiles::split(&output, img.width(), max_image_width);
+            confirm_tiles(&tiles);
+            if let [tile] = &tiles[..] {
+                save_raster(&img, &tile.path, &args, &input, duration, checksum.as_deref());
+            } else {
+                for tile in &tiles {
+                    let part = image::imageops::crop_imm(&img, tile.left, 0, tile.width, img.height()).to_image();
+                    save_raster(&part, &tile.path, &args, &input, duration, checksum.as_deref());
+                }
+            }
+            save_manifest(&tiles, img.width(), img.height());
+        }
+    }
+
+    if let Some(path) = &args.stats {
+        let json = stats(&samples);
+        write_text_file(path, &format!("{json}\n"));
+        println!("Saved statistics to \"{}\"", path.display());
+    }
+
+    if let Some(path) = &args.export_envelope {
+        // A control signal needs evenly spaced values, so the time scale is ignored here
+        let output_samples_per_pixel = samples_per_pixel * oversample as f64;
+        let axis = TimeAxis::linear(args.width, output_samples_per_pixel);
+        let envelope = compute_envelope(&samples, &axis, args.envelope);
+        // One envelope value per output column, so the rate is columns per second
+        let rate = (sample_rate as f64 * channels as f64 / output_samples_per_pixel).round().max(1.0) as u32;
+        let result = output::write_atomically(path, |temporary| {
+            wav::write_mono_f32(temporary, rate, &envelope).map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(_) => println!("Saved envelope to \"{}\" ({} Hz)", path.display(), rate),
+            Err(e) => {
+                let error = "Error: ".bold().red();
+                eprintln!("{error}Could not write envelope to \"{}\": {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &args.also_peaks {
+        let peaks = peaks::peaks(&samples, channels, sample_rate, frames_per_column);
+        write_file(path, &peaks::encode(&peaks, path));
+        println!("Saved peaks of {} columns to \"{}\"", peaks.length(), path.display());
+    }
 
     let elapsed = now.elapsed();
     let msg = format!("Finished after {:.2?}", elapsed).green();
@@ -269,27 +1792,145 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use crate::parse_into_color;
+    use clap::Parser;
+    use std::path::{Path, PathBuf};
+    use crate::{apply_zoom, compute_envelope, crest_factors, crest_lane_color, parse_duration, parse_milliseconds, parse_pixels_per_second, parse_scale_factor, parse_time_decades, prepare_output_path, preprocess, Args, Envelope};
+    use crate::output::OutputFormat;
+    use crate::render::{normalization_gain, render_waveform, waveform_gain, RenderMode, RenderOptions, TimeAxis};
 
     #[test]
-    fn is_transparent() {
-        let color = parse_into_color("0,0,0,0");
-        assert_eq!(color, image::Rgba([0,0,0,0]));
-        let color = parse_into_color("0, 0, 0, 0");
-        assert_eq!(color, image::Rgba([0,0,0,0]));
-        let color = parse_into_color("none");
-        assert_eq!(color, image::Rgba([0,0,0,0]));
-        let color = parse_into_color("transparent");
-        assert_eq!(color, image::Rgba([0,0,0,0]));
+    fn durations() {
+        assert_eq!(parse_duration("100ms"), Ok(0.1));
+        assert_eq!(parse_duration("2s"), Ok(2.0));
+        assert_eq!(parse_duration(" 1.5 "), Ok(1.5));
+        assert_eq!(parse_duration("2min"), Ok(120.0));
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("fast").is_err());
+        assert_eq!(parse_milliseconds("300"), Ok(0.3));
+        assert_eq!(parse_milliseconds("1,5s"), Ok(1.5));
+
+        let mut random = crate::testing::random(0x2545F4914F6CDD1D);
+        let mut next = move || random.next().unwrap();
+        for _ in 0..10000 {
+            let value = (next() % 100000 + 1) as f64 / 4.0;
+            let close = |parsed: Result<f64, String>, expected: f64| parsed.is_ok_and(|p| (p - expected).abs() <= expected * 1e-12);
+            // Every unit scales the number, in any case and with or without
+            // blanks around it
+            assert!(close(parse_duration(&format!("{value}")), value));
+            assert!(close(parse_duration(&format!(" {value}S ")), value));
+            assert!(close(parse_duration(&format!("{value}ms")), value / 1000.0));
+            assert!(close(parse_duration(&format!("{value}MIN")), value * 60.0));
+            assert!(close(parse_milliseconds(&format!("{value}")), value / 1000.0));
+            // Durations are never zero or negative
+            assert!(parse_duration(&format!("-{value}s")).is_err());
+            assert!(parse_duration(&format!("0{}", ["", "s", "ms", "min"][next() as usize % 4])).is_err());
+        }
     }
 
     #[test]
-    fn is_black() {
-        let color = parse_into_color("0,0,0,255");
-        assert_eq!(color, image::Rgba([0,0,0,255]));
-        let color = parse_into_color("0, 0, 0, 1.0");
-        assert_eq!(color, image::Rgba([0,0,0,255]));
-        let color = parse_into_color("black");
-        assert_eq!(color, image::Rgba([0,0,0,255]));
-    }
-}
\ No newline at end of file
+    fn every_option_has_an_environment_variable() {
+        use clap::CommandFactory;
+        for arg in Args::command().get_arguments().filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version")) {
+            let name = arg.get_id().as_str().to_uppercase();
+            assert_eq!(arg.get_env().and_then(|env| env.to_str()), Some(format!("WELLENFORMER_{name}").as_str()), "--{}", arg.get_id());
+        }
+    }
+
+    #[test]
+    fn envelope_per_column() {
+        let samples = [0.5, -1.0, 0.5, 0.5, 0.0, 0.0, 0.25];
+        let axis = TimeAxis::linear(4, 2.0);
+        assert_eq!(compute_envelope(&samples, &axis, Envelope::Peak), vec![1.0, 0.5, 0.0, 0.25]);
+        let rms = compute_envelope(&samples, &axis, Envelope::Rms);
+        assert!((rms[0] - 0.625f32.sqrt()).abs() < 1e-6);
+        // Columns past the end of the audio are silent
+        assert_eq!(compute_envelope(&samples, &TimeAxis::linear(6, 2.0), Envelope::Rms)[4..], [0.0, 0.0]);
+    }
+
+    #[test]
+    fn crest_strip_lights_up_where_dynamics_are_squashed() {
+        // A square wave, a sine, a lone click and silence
+        let mut samples: Vec<f32> = (0..100).map(|i| if i % 10 < 5 { 0.5 } else { -0.5 }).collect();
+        samples.extend((0..100).map(|i| (i as f32 * std::f32::consts::FRAC_PI_2).sin()));
+        samples.extend((0..100).map(|i| if i == 50 { 1.0 } else { 0.0 }));
+        samples.extend([0.0; 100]);
+        let crests = crest_factors(&samples, &TimeAxis::linear(4, 100.0));
+        assert!(crests[0].is_some_and(|c| c.abs() < 1e-6));
+        assert!(crests[1].is_some_and(|c| (c - 3.01).abs() < 0.1));
+        assert!(crests[2].is_some_and(|c| (c - 20.0).abs() < 1e-6));
+        assert_eq!(crests[3], None);
+        let red = image::Rgba([255, 0, 0, 200]);
+        let alphas: Vec<u8> = crests.into_iter().map(|crest| crest_lane_color(red, crest)[3]).collect();
+        assert_eq!(alphas, [200, 200, 0, 0]);
+    }
+
+    #[test]
+    fn inputs_are_prepared_alike() {
+        let parse = |flags: &[&str]| Args::try_parse_from(["wellenformer", "-i", "a.wav", "-o", "a.png"].iter().chain(flags)).unwrap();
+        let stereo: Vec<f32> = (0..200).map(|i| if i % 2 == 0 { 0.5 } else { -0.25 }).collect();
+        assert_eq!(preprocess(&parse(&[]), stereo.clone(), 2, 44100), (stereo.clone(), 2));
+        let (mono, channels) = preprocess(&parse(&["--downmix-to", "mono"]), stereo.clone(), 2, 44100);
+        assert_eq!((mono.len(), channels), (100, 1));
+        // De-emphasis runs on the channels before they are mixed
+        let mut deemphasized = stereo.clone();
+        crate::audio::deemphasize(&mut deemphasized, 2, 44100);
+        let expected = crate::downmix::downmix(&deemphasized, 2, crate::downmix::Target::Mono);
+        assert_eq!(preprocess(&parse(&["--deemphasis", "--downmix-to", "mono"]), stereo, 2, 44100), (expected, 1));
+    }
+
+    #[test]
+    fn scale_factor_matches_normalization() {
+        assert_eq!(parse_scale_factor("1,37"), Ok(1.37));
+        assert!(parse_scale_factor("0").is_err());
+        let samples: Vec<f32> = (0..8000).map(|i| (i as f32 * 0.01).sin() * 0.3).collect();
+        let normalized = RenderOptions { normalize: true, ..RenderOptions::new(RenderMode::MinMax) };
+        let factor = waveform_gain(&samples, 80.0, &normalized);
+        assert_eq!(factor, normalization_gain(&samples, true));
+        // The printed factor reproduces the normalized image on another render
+        let fixed = RenderOptions { normalize: false, scale_factor: Some(factor), ..normalized.clone() };
+        assert_eq!(render_waveform(&samples, 80.0, &normalized), render_waveform(&samples, 80.0, &fixed));
+
+        // Zoomed in, the interpolated square wave overshoots its samples, so
+        // the factor is measured after interpolating
+        let square: Vec<f32> = (0..40).map(|i| if i / 4 % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        let factor = waveform_gain(&square, 0.4, &normalized);
+        assert!(factor < normalization_gain(&square, true));
+        let fixed = RenderOptions { normalize: false, scale_factor: Some(factor), ..normalized.clone() };
+        assert_eq!(render_waveform(&square, 0.4, &normalized), render_waveform(&square, 0.4, &fixed));
+        let unprepared = RenderOptions { scale_factor: Some(normalization_gain(&square, true)), ..fixed };
+        assert_ne!(render_waveform(&square, 0.4, &normalized), render_waveform(&square, 0.4, &unprepared));
+    }
+
+    #[test]
+    fn width_follows_from_the_zoom_level() {
+        assert_eq!(parse_pixels_per_second("12,5"), Ok(12.5));
+        assert!(parse_pixels_per_second("0").is_err());
+        assert_eq!(parse_time_decades("1,5"), Ok(1.5));
+        assert!(parse_time_decades("0").is_err() && parse_time_decades("-1").is_err() && parse_time_decades("400").is_err());
+        let parse = |zoom: &[&str]| Args::try_parse_from(["wellenformer", "-i", "a.wav", "-o", "a.png"].iter().chain(zoom)).unwrap();
+        let mut args = parse(&["--pixels-per-second", "100"]);
+        // The last column is only partly covered
+        apply_zoom(&mut args, 16040.0, 8000.0);
+        assert_eq!(args.width, 201);
+        assert_eq!(args.bucket_duration, Some(0.01));
+
+        let mut args = parse(&["--samples-per-pixel", "256"]);
+        apply_zoom(&mut args, 44100.0, 44100.0);
+        assert_eq!(args.width, 173);
+        assert_eq!(args.bucket_duration, Some(256.0 / 44100.0));
+    }
+
+    #[test]
+    fn output_extensions() {
+        let output = |path: &str, format: OutputFormat| prepare_output_path(Path::new(path), format);
+        assert_eq!(output("x", OutputFormat::Png), PathBuf::from("x.png"));
+        assert_eq!(output("x.PNG", OutputFormat::Png), PathBuf::from("x.PNG"));
+        // Both common spellings name a JPEG
+        assert_eq!(output("x.jpeg", OutputFormat::Jpeg), PathBuf::from("x.jpeg"));
+        assert_eq!(output("x.jpg", OutputFormat::Jpeg), PathBuf::from("x.jpg"));
+        assert_eq!(output("x.jpeg", OutputFormat::Png), PathBuf::from("x.jpeg.png"));
+        // Contact sheets can be either
+        assert_eq!(output("x", OutputFormat::ContactSheet), PathBuf::from("x.png"));
+        assert_eq!(output("x.jpg", OutputFormat::ContactSheet), PathBuf::from("x.jpg"));
+    }
+}
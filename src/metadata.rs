@@ -0,0 +1,203 @@
+//! Descriptive metadata embedded into image outputs as EXIF and XMP, so asset
+//! management systems can index generated images by creator, source file and
+//! duration
+use std::fmt::Write;
+
+
+/// What is known about a rendered image
+#[derive(Debug, Clone)]
+pub struct ImageMetadata {
+    pub creator: Option<String>,
+    /// File name of the rendered audio
+    pub source: String,
+    /// Duration of the rendered audio in seconds
+    pub duration: f64,
+    /// SHA-256 of the audio file as lowercase hex, if it was verified
+    pub source_sha256: Option<String>,
+}
+
+/// Name and version written as the creating software
+const SOFTWARE: &str = concat!("wellenformer ", env!("CARGO_PKG_VERSION"));
+
+/// Header of JPEG APP1 segments holding XMP
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Text with characters that are special in XML escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl ImageMetadata {
+    fn description(&self) -> String {
+        format!("Waveform of {} ({})", self.source, crate::format_timestamp(self.duration))
+    }
+
+    /// EXIF block (a little endian TIFF structure with a single IFD) holding
+    /// the source as document name, a description, the software and the
+    /// creator as artist. EXIF text is ASCII, other characters become '?'
+    pub fn exif(&self) -> Vec<u8> {
+        let ascii = |text: &str| -> Vec<u8> {
+            text.chars().map(|c| if c.is_ascii() && !c.is_ascii_control() { c as u8 } else { b'?' }).chain([0]).collect()
+        };
+        // Tags in ascending order as required by TIFF
+        let mut entries = vec![
+            (0x010D, ascii(&self.source)),
+            (0x010E, ascii(&self.description())),
+            (0x0131, ascii(SOFTWARE)),
+        ];
+        if let Some(creator) = &self.creator {
+            entries.push((0x013B, ascii(creator)));
+        }
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        // Values longer than four bytes follow the IFD and its next IFD offset
+        let data_start = 8 + 2 + 12 * entries.len() + 4;
+        let mut data = vec![];
+        for (tag, value) in &entries {
+            tiff.extend_from_slice(&(*tag as u16).to_le_bytes());
+            tiff.extend_from_slice(&2u16.to_le_bytes());
+            tiff.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                tiff.extend_from_slice(&inline);
+            } else {
+                tiff.extend_from_slice(&((data_start + data.len()) as u32).to_le_bytes());
+                data.extend_from_slice(value);
+                // Offsets are word aligned
+                if value.len() % 2 == 1 {
+                    data.push(0);
+                }
+            }
+        }
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(&data);
+        tiff
+    }
+
+    /// XMP packet with Dublin Core creator, source and description, the
+    /// creating software, the duration in milliseconds and the checksum of
+    /// the source
+    pub fn xmp(&self) -> String {
+        let mut xmp = String::new();
+        writeln!(xmp, "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>").unwrap();
+        writeln!(xmp, "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">").unwrap();
+        writeln!(xmp, " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">").unwrap();
+        writeln!(
+            xmp,
+            "  <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:xmpDM=\"http://ns.adobe.com/xmp/1.0/DynamicMedia/\" \
+             xmlns:wellenformer=\"https://github.com/atoav/wellenformer/ns/1.0/\">"
+        ).unwrap();
+        if let Some(creator) = &self.creator {
+            writeln!(xmp, "   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>", escape(creator)).unwrap();
+        }
+        writeln!(xmp, "   <dc:source>{}</dc:source>", escape(&self.source)).unwrap();
+        writeln!(xmp, "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>", escape(&self.description())).unwrap();
+        writeln!(xmp, "   <xmp:CreatorTool>{SOFTWARE}</xmp:CreatorTool>").unwrap();
+        writeln!(
+            xmp,
+            "   <xmpDM:duration rdf:parseType=\"Resource\"><xmpDM:value>{}</xmpDM:value><xmpDM:scale>1/1000</xmpDM:scale></xmpDM:duration>",
+            (self.duration * 1000.0).round() as u64
+        ).unwrap();
+        if let Some(checksum) = &self.source_sha256 {
+            writeln!(xmp, "   <wellenformer:SourceSHA256>{checksum}</wellenformer:SourceSHA256>").unwrap();
+        }
+        writeln!(xmp, "  </rdf:Description>").unwrap();
+        writeln!(xmp, " </rdf:RDF>").unwrap();
+        writeln!(xmp, "</x:xmpmeta>").unwrap();
+        xmp.push_str("<?xpacket end=\"w\"?>");
+        xmp
+    }
+}
+
+/// APP1 segment with `header` followed by `payload`
+fn app1(header: &[u8], payload: &[u8]) -> Result<Vec<u8>, String> {
+    let length = 2 + header.len() + payload.len();
+    if length > u16::MAX as usize {
+        return Err("metadata too large for a JPEG segment".to_string());
+    }
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&(length as u16).to_be_bytes());
+    segment.extend_from_slice(header);
+    segment.extend_from_slice(payload);
+    Ok(segment)
+}
+
+/// Insert EXIF and XMP segments into an encoded JPEG, after the JFIF header
+/// if there is one
+pub fn embed_in_jpeg(jpeg: &[u8], metadata: &ImageMetadata) -> Result<Vec<u8>, String> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err("not a JPEG".to_string());
+    }
+    let mut position = 2;
+    if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let length = jpeg.get(4..6).map(|l| u16::from_be_bytes([l[0], l[1]]) as usize).unwrap_or(0);
+        position = (4 + length).min(jpeg.len());
+    }
+    let mut result = jpeg[..position].to_vec();
+    result.extend(app1(b"Exif\0\0", &metadata.exif())?);
+    result.extend(app1(XMP_HEADER, metadata.xmp().as_bytes())?);
+    result.extend_from_slice(&jpeg[position..]);
+    Ok(result)
+}
+
+
+/// Add the XMP packet as an iTXt chunk under the keyword PNG readers look
+/// for XMP under
+pub fn add_to_png<W: std::io::Write>(encoder: &mut png::Encoder<W>, metadata: &ImageMetadata) -> Result<(), String> {
+    encoder.add_itxt_chunk("XML:com.adobe.xmp".to_string(), metadata.xmp()).map_err(|e| e.to_string())
+}
+
+/// Encode an image as a PNG with embedded metadata
+pub fn encode_png(img: &image::RgbaImage, metadata: &ImageMetadata) -> Result<Vec<u8>, String> {
+    let mut png = vec![];
+    let mut encoder = png::Encoder::new(&mut png, img.width(), img.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    add_to_png(&mut encoder, metadata)?;
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(img.as_raw()).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::{embed_in_jpeg, encode_png, ImageMetadata};
+
+    #[test]
+    fn jpeg_keeps_decoding() {
+        let metadata = ImageMetadata { creator: Some("A & B".to_string()), source: "söng.wav".to_string(), duration: 61.5, source_sha256: Some("ab".repeat(32)) };
+        let exif = metadata.exif();
+        assert!(exif.starts_with(b"II*\0"));
+        assert!(exif.windows(9).any(|w| w == b"s?ng.wav\0"));
+        let xmp = metadata.xmp();
+        assert!(xmp.contains("<rdf:li>A &amp; B</rdf:li>") && xmp.contains("<dc:source>söng.wav</dc:source>"));
+        assert!(xmp.contains("<xmpDM:value>61500</xmpDM:value>"));
+        assert!(xmp.contains(&format!("<wellenformer:SourceSHA256>{}</wellenformer:SourceSHA256>", "ab".repeat(32))));
+
+        let img = image::RgbImage::from_pixel(8, 8, image::Rgb([10, 20, 30]));
+        let mut jpeg = vec![];
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg).encode_image(&img).unwrap();
+        let tagged = embed_in_jpeg(&jpeg, &metadata).unwrap();
+        assert!(tagged.windows(6).any(|w| w == b"Exif\0\0"));
+        assert_eq!(image::load_from_memory(&tagged).unwrap().to_rgb8().dimensions(), (8, 8));
+        assert!(embed_in_jpeg(b"not a jpeg", &metadata).is_err());
+    }
+
+    #[test]
+    fn png_carries_the_checksum() {
+        let metadata = ImageMetadata { creator: None, source: "take.wav".to_string(), duration: 2.0, source_sha256: Some("cd".repeat(32)) };
+        let img = image::RgbaImage::from_pixel(8, 4, image::Rgba([10, 20, 30, 255]));
+        let png = encode_png(&img, &metadata).unwrap();
+        let reader = png::Decoder::new(&png[..]).read_info().unwrap();
+        let xmp = &reader.info().utf8_text[0];
+        assert_eq!(xmp.keyword, "XML:com.adobe.xmp");
+        assert!(xmp.get_text().unwrap().contains(&format!("<wellenformer:SourceSHA256>{}</wellenformer:SourceSHA256>", "cd".repeat(32))));
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), img);
+    }
+}
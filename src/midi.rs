@@ -0,0 +1,295 @@
+use std::path::Path;
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+
+/// A single note of a MIDI file with its timing in seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub start: f64,
+    pub end: f64,
+    pub key: u8,
+    pub velocity: u8,
+}
+
+/// Returns true if the path looks like a standard MIDI file
+pub fn is_midi(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "mid" || ext == "midi"
+        },
+        None => false
+    }
+}
+
+/// Read a standard MIDI file and return all of its notes
+pub fn read_midi(path: &Path) -> Result<Vec<Note>, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    parse_midi(&data)
+}
+
+/// Minimal reader over the raw bytes of a MIDI file
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self.data.get(self.pos).ok_or("unexpected end of MIDI data")?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.data.len() {
+            return Err("unexpected end of MIDI data".to_string());
+        }
+        let slice = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Variable length quantity as used for delta times and lengths
+    fn vlq(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let b = self.byte()?;
+            value = (value << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("invalid variable length quantity".to_string())
+    }
+}
+
+/// Parse the bytes of a standard MIDI file into notes
+pub fn parse_midi(data: &[u8]) -> Result<Vec<Note>, String> {
+    let mut r = Reader { data, pos: 0 };
+    if r.bytes(4)? != b"MThd" {
+        return Err("not a standard MIDI file".to_string());
+    }
+    let header_length = r.u32()? as usize;
+    let _format = r.u16()?;
+    let track_count = r.u16()?;
+    let division = r.u16()?;
+    r.bytes(header_length.saturating_sub(6))?;
+    // Ticks per quarter note, or frames per second and ticks per frame
+    let ticks_per_unit = match division & 0x8000 {
+        0 => division as i32,
+        _ => (division & 0xff) as i32 * -((division >> 8) as i8 as i32),
+    };
+    if ticks_per_unit == 0 {
+        return Err(format!("invalid MIDI time division 0x{division:04x}"));
+    }
+
+    // (tick, key, velocity) for note starts and (tick, key) for note ends
+    let mut starts: Vec<(u64, u8, u8, u8)> = vec![];
+    let mut ends: Vec<(u64, u8, u8)> = vec![];
+    let mut tempos: Vec<(u64, u32)> = vec![];
+
+    for _ in 0..track_count {
+        let id = r.bytes(4)?;
+        let length = r.u32()? as usize;
+        if id != b"MTrk" {
+            // Unknown chunks are skipped as required by the spec
+            r.bytes(length)?;
+            continue;
+        }
+        let end = r.pos + length;
+        let mut tick = 0u64;
+        let mut running_status = 0u8;
+        while r.pos < end {
+            tick += r.vlq()? as u64;
+            let mut status = r.byte()?;
+            if status < 0x80 {
+                // Running status: the byte we just read is already data
+                status = running_status;
+                r.pos -= 1;
+            }
+            match status {
+                0xff => {
+                    let kind = r.byte()?;
+                    let len = r.vlq()? as usize;
+                    let payload = r.bytes(len)?;
+                    if kind == 0x51 && len == 3 {
+                        let tempo = u32::from_be_bytes([0, payload[0], payload[1], payload[2]]);
+                        tempos.push((tick, tempo));
+                    }
+                },
+                0xf0 | 0xf7 => {
+                    let len = r.vlq()? as usize;
+                    r.bytes(len)?;
+                },
+                0x80..=0xef => {
+                    running_status = status;
+                    let channel = status & 0x0f;
+                    match status & 0xf0 {
+                        0x80 => {
+                            let key = r.byte()? & 0x7f;
+                            r.byte()?;
+                            ends.push((tick, channel, key));
+                        },
+                        0x90 => {
+                            // Data bytes are seven bits wide
+                            let key = r.byte()? & 0x7f;
+                            let velocity = r.byte()? & 0x7f;
+                            if velocity == 0 {
+                                ends.push((tick, channel, key));
+                            } else {
+                                starts.push((tick, channel, key, velocity));
+                            }
+                        },
+                        0xc0 | 0xd0 => { r.byte()?; },
+                        _ => { r.bytes(2)?; },
+                    }
+                },
+                _ => return Err(format!("invalid MIDI status byte 0x{status:02x}")),
+            }
+        }
+        r.pos = end;
+    }
+
+    tempos.sort_by_key(|t| t.0);
+    let to_seconds = |tick: u64| -> f64 {
+        if division & 0x8000 != 0 {
+            // SMPTE based division: frames per second and ticks per frame
+            let fps = -((division >> 8) as i8 as f64);
+            let ticks_per_frame = (division & 0xff) as f64;
+            return tick as f64 / (fps * ticks_per_frame);
+        }
+        let ticks_per_quarter = division as f64;
+        let mut seconds = 0.0;
+        let mut last_tick = 0u64;
+        let mut tempo = 500_000.0;
+        for &(t, new_tempo) in tempos.iter().take_while(|t| t.0 < tick) {
+            seconds += (t - last_tick) as f64 * tempo / ticks_per_quarter / 1_000_000.0;
+            last_tick = t;
+            tempo = new_tempo as f64;
+        }
+        seconds + (tick - last_tick) as f64 * tempo / ticks_per_quarter / 1_000_000.0
+    };
+
+    // Pair every note start with the next matching note end
+    starts.sort_by_key(|s| s.0);
+    ends.sort_by_key(|e| e.0);
+    let mut used = vec![false; ends.len()];
+    let mut notes = vec![];
+    for (tick, channel, key, velocity) in starts {
+        let end = ends.iter().enumerate()
+            .find(|(i, e)| !used[*i] && e.0 >= tick && e.1 == channel && e.2 == key)
+            .map(|(i, e)| { used[i] = true; e.0 })
+            .unwrap_or(tick);
+        notes.push(Note {
+            start: to_seconds(tick),
+            end: to_seconds(end),
+            key,
+            velocity,
+        });
+    }
+    Ok(notes)
+}
+
+/// Render the notes as a piano roll with the lowest note at the bottom.
+///
+/// `seconds_per_column` sets the horizontal scale, `rtl` mirrors the time axis
+pub fn render_piano_roll(notes: &[Note], width: u32, height: u32, seconds_per_column: f64, rtl: bool, foreground: Rgba<u8>, background: Rgba<u8>) -> RgbaImage {
+    let mut img = ImageBuffer::from_pixel(width, height, background);
+    if notes.is_empty() {
+        return img;
+    }
+    let lowest = notes.iter().map(|n| n.key).min().unwrap();
+    let highest = notes.iter().map(|n| n.key).max().unwrap();
+    let row_height = height as f64 / (highest as u32 - lowest as u32 + 1) as f64;
+
+    for note in notes {
+        let row = (highest - note.key) as f64;
+        let y0 = (row * row_height).round() as u32;
+        let y1 = (((row + 1.0) * row_height).round() as u32).max(y0 + 1).min(height);
+        let x0 = (note.start / seconds_per_column).floor() as u32;
+        let x1 = ((note.end / seconds_per_column).ceil() as u32).max(x0.saturating_add(1)).min(width);
+        for x in x0..x1 {
+            let x = if rtl { width - 1 - x } else { x };
+            for y in y0..y1 {
+                img.put_pixel(x, y, foreground);
+            }
+        }
+    }
+    img
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::midi::{parse_midi, render_piano_roll, Note};
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    #[test]
+    fn parses_single_note() {
+        let mut data = vec![];
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&[0, 0, 0, 6, 0, 0, 0, 1, 0, 96]);
+        let track = [
+            0x00, 0x90, 60, 100,      // note on at tick 0
+            0x60, 60, 0,              // running status note off after a quarter
+            0x00, 0xff, 0x2f, 0x00,   // end of track
+        ];
+        data.extend_from_slice(b"MTrk");
+        data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        data.extend_from_slice(&track);
+
+        let notes = parse_midi(&data).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].key, 60);
+        assert_eq!(notes[0].velocity, 100);
+        // 96 ticks at the default tempo of 120 bpm is half a second
+        assert!((notes[0].end - 0.5).abs() < 1e-9);
+    }
+
+    /// A file with one track of `track` and the time `division`
+    fn file(division: u16, track: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&[0, 0, 0, 6, 0, 0, 0, 1]);
+        data.extend_from_slice(&division.to_be_bytes());
+        data.extend_from_slice(b"MTrk");
+        data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        data.extend_from_slice(track);
+        data
+    }
+
+    #[test]
+    fn malformed_files_are_an_error() {
+        // Data bytes with the high bit set are cut to seven bits
+        let notes = parse_midi(&file(96, &[0x00, 0x90, 0xff, 0xff, 0x60, 0x80, 0xff, 0x00])).unwrap();
+        assert_eq!((notes[0].key, notes[0].velocity), (127, 127));
+        assert!(notes[0].end > notes[0].start);
+        let roll = render_piano_roll(&[Note { key: 0, ..notes[0].clone() }, notes[0].clone()], 10, 10, 0.1, false, WHITE, BLACK);
+        assert_eq!(roll.dimensions(), (10, 10));
+        // A note on cut off after its key
+        assert!(parse_midi(&file(96, &[0x00, 0x90, 60])).is_err());
+        // No ticks per quarter note, and no ticks per frame at 25 fps
+        assert!(parse_midi(&file(0, &[0x00, 0x90, 60, 100])).is_err());
+        assert!(parse_midi(&file(0xe700, &[0x00, 0x90, 60, 100])).is_err());
+        assert!(parse_midi(&file(0x8028, &[0x00, 0x90, 60, 100, 0x60, 60, 0])).is_ok());
+        // Notes far beyond the end of the image are left out
+        let late = Note { start: 1e12, end: f64::INFINITY, key: 60, velocity: 100 };
+        assert_eq!(render_piano_roll(&[late], 10, 10, 0.1, true, WHITE, BLACK), RgbaImage::from_pixel(10, 10, BLACK));
+    }
+}
@@ -0,0 +1,130 @@
+//! Parsing of numbers in option values, which may be written with a decimal
+//! point or a decimal comma ("0.5" and "0,5") so users in comma locales do
+//! not have to think about it
+use std::ops::{Bound, RangeBounds};
+
+/// Parse a number with a decimal point or a decimal comma. Either separator
+/// followed by exactly three digits, like "1,000" or "1.000", could also be
+/// digit grouping in some locale and is rejected instead of guessing, so both
+/// spellings behave the same (there is no option choosing a separator)
+pub fn parse_number(argument: &str) -> Result<f64, String> {
+    let text = argument.trim();
+    let separators: Vec<usize> = text.match_indices(['.', ',']).map(|(i, _)| i).collect();
+    let normalized = match separators[..] {
+        [] => text.to_string(),
+        [i] => {
+            let (integer, fraction) = (&text[..i], &text[i + 1..]);
+            let grouped = fraction.len() == 3 && fraction.chars().all(|c| c.is_ascii_digit());
+            // "0,125" can only be a fraction
+            if grouped && !integer.trim_start_matches(['-', '+', '0']).is_empty() {
+                return Err(format!("\"{text}\" is ambiguous, write it without digit grouping (e.g. \"{integer}{fraction}\") or with fewer decimals"));
+            }
+            format!("{integer}.{fraction}")
+        },
+        _ => return Err(format!("\"{text}\" has more than one decimal separator")),
+    };
+    normalized.parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| format!("\"{text}\" is not a number"))
+}
+
+
+/// Parse a share given in percent ("60%") or as a fraction ("0.6") that has
+/// to lie in `range`, naming `what` it is in the error
+pub fn parse_fraction(argument: &str, what: &str, range: impl RangeBounds<f64>) -> Result<f64, String> {
+    let value = match argument.trim().strip_suffix('%') {
+        Some(percent) => parse_number(percent).ok().map(|p| p / 100.0),
+        None => parse_number(argument).ok(),
+    };
+    match value {
+        Some(v) if range.contains(&v) => Ok(v),
+        _ => {
+            let upper = match range.end_bound() {
+                Bound::Included(end) => format!("to {}%", end * 100.0),
+                Bound::Excluded(end) => format!("up to below {}%", end * 100.0),
+                Bound::Unbounded => "or more".to_string(),
+            };
+            Err(format!("\"{argument}\" is not a valid {what} (expected e.g. \"60%\" or \"0.6\", from 0% {upper})"))
+        },
+    }
+}
+
+/// Parse a non-negative number or a ratio of two numbers like "1/3"
+pub fn parse_ratio(argument: &str) -> Result<f64, String> {
+    let value = match argument.split_once('/') {
+        Some((a, b)) => parse_number(a).ok().zip(parse_number(b).ok()).map(|(a, b)| a / b),
+        None => parse_number(argument).ok(),
+    };
+    match value {
+        Some(v) if v.is_finite() && v >= 0.0 => Ok(v),
+        _ => Err(format!("\"{argument}\" is not a valid ratio (expected e.g. \"1/3\" or \"0.5\")")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::number::{parse_fraction, parse_number, parse_ratio};
+
+    #[test]
+    fn both_separators() {
+        assert_eq!(parse_number("0.5"), Ok(0.5));
+        assert_eq!(parse_number(" 0,5 "), Ok(0.5));
+        assert_eq!(parse_number("-20,25"), Ok(-20.25));
+        assert_eq!(parse_number("0,125"), Ok(0.125));
+        assert_eq!(parse_number("600"), Ok(600.0));
+        // Grouping or decimals, whichever the separator
+        assert!(parse_number("1,000").is_err());
+        assert!(parse_number("1.000").is_err());
+        assert_eq!(parse_number("1.00"), parse_number("1,00"));
+        assert_eq!(parse_number("0.250"), Ok(0.25));
+        assert!(parse_number("1,000.5").is_err());
+        assert!(parse_number("inf").is_err());
+        assert!(parse_number("").is_err());
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(parse_fraction("60%", "opacity", 0.0..=1.0), Ok(0.6));
+        assert_eq!(parse_fraction(" 0,25 ", "overlap", 0.0..1.0), Ok(0.25));
+        assert_eq!(parse_fraction("100%", "opacity", 0.0..=1.0), Ok(1.0));
+        assert_eq!(parse_fraction("100%", "overlap", 0.0..1.0), Err("\"100%\" is not a valid overlap (expected e.g. \"60%\" or \"0.6\", from 0% up to below 100%)".to_string()));
+        assert_eq!(parse_fraction("-1%", "opacity", 0.0..=1.0), Err("\"-1%\" is not a valid opacity (expected e.g. \"60%\" or \"0.6\", from 0% to 100%)".to_string()));
+        assert!(parse_fraction("half", "opacity", 0.0..=1.0).is_err());
+    }
+
+    #[test]
+    fn ratios() {
+        assert_eq!(parse_ratio("1/4"), Ok(0.25));
+        assert_eq!(parse_ratio(" 0,5 "), Ok(0.5));
+        assert_eq!(parse_ratio("1,5/3"), Ok(0.5));
+        assert!(parse_ratio("1/0").is_err());
+        assert!(parse_ratio("-1/3").is_err());
+        assert!(parse_ratio("1/3/2").is_err());
+    }
+
+    #[test]
+    fn properties() {
+        let mut random = crate::testing::random(0x9E3779B97F4A7C15);
+        let mut next = move || random.next().unwrap();
+        let junk: Vec<char> = "0123456789.,-+e ".chars().collect();
+        for _ in 0..10000 {
+            // Numbers with any number of decimals but three parse to what
+            // they say, whichever the separator
+            let integer = (next() % 200000) as i64 - 100000;
+            let decimals = [1, 2, 4, 5, 6][next() as usize % 5];
+            let fraction = format!("{:0decimals$}", next() % 10u64.pow(decimals as u32));
+            let expected = format!("{integer}.{fraction}").parse::<f64>().unwrap();
+            assert_eq!(parse_number(&format!("{integer}.{fraction}")), Ok(expected));
+            assert_eq!(parse_number(&format!(" {integer},{fraction} ")), Ok(expected));
+            assert_eq!(parse_number(&integer.to_string()), Ok(integer as f64));
+            // Swapping the separators never changes the result and arbitrary
+            // input is rejected or accepted but never panics
+            let length = next() % 12;
+            let text: String = (0..length).map(|_| junk[next() as usize % junk.len()]).collect();
+            let swapped: String = text.chars().map(|c| match c { '.' => ',', ',' => '.', c => c }).collect();
+            assert_eq!(parse_number(&text).ok(), parse_number(&swapped).ok(), "{text:?}");
+            assert!(parse_number(&text).map_or(true, f64::is_finite), "{text:?}");
+        }
+    }
+}
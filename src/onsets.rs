@@ -0,0 +1,99 @@
+//! Onset detection by spectral flux, the rise of the magnitude spectrum from
+//! one frame to the next, and ticks marking the onsets along the image edges
+use image::{Rgba, RgbaImage};
+
+use crate::number::parse_fraction;
+use crate::spectrogram::frames;
+use crate::spectrum::hann;
+
+
+/// Length of a frame in seconds (1024 samples at 44.1 kHz)
+const FRAME_SECONDS: f64 = 0.023;
+
+/// Frames on either side the flux is averaged over for the threshold
+const AVERAGE_FRAMES: usize = 10;
+
+/// Frames on either side an onset needs to be the largest flux in, which
+/// keeps onsets at least that far apart
+const PEAK_FRAMES: usize = 3;
+
+/// Length of a tick in pixels
+const TICK_LENGTH: u32 = 6;
+
+/// Parse a sensitivity in percent ("50%") or as a fraction ("0.5")
+pub fn parse_sensitivity(argument: &str) -> Result<f64, String> {
+    parse_fraction(argument, "sensitivity", 0.0..=1.0)
+}
+
+/// Frames of the `mono` signal at which a sound starts. The flux has to be
+/// the largest around and exceed its local average by a margin, which the
+/// `sensitivity` (0.0 to 1.0) lowers
+pub fn onsets(mono: &[f32], sample_rate: u32, sensitivity: f64) -> Vec<usize> {
+    let size = ((FRAME_SECONDS * sample_rate as f64) as usize).next_power_of_two().max(64);
+    let hop = size / 2;
+    let magnitudes: Vec<Vec<f64>> = frames(mono, &hann(size), hop).into_iter()
+        .map(|frame| frame.into_iter().map(|db| 10f64.powf(db / 20.0)).collect())
+        .collect();
+    let flux: Vec<f64> = std::iter::once(0.0)
+        .chain(magnitudes.windows(2).map(|pair| pair[0].iter().zip(&pair[1]).map(|(a, b)| (b - a).max(0.0)).sum()))
+        .collect();
+    let largest = flux.iter().cloned().fold(0.0, f64::max);
+    if largest <= 0.0 {
+        return vec![];
+    }
+    let margin = (1.0 - sensitivity.clamp(0.0, 1.0)) * 0.5 * largest;
+
+    (0..flux.len())
+        .filter(|&i| {
+            let around = &flux[i.saturating_sub(AVERAGE_FRAMES)..(i + AVERAGE_FRAMES + 1).min(flux.len())];
+            let average = around.iter().sum::<f64>() / around.len() as f64;
+            // The first of equal maxima counts
+            let (start, end) = (i.saturating_sub(PEAK_FRAMES), (i + PEAK_FRAMES + 1).min(flux.len()));
+            let peak = flux[start..i].iter().all(|&f| f < flux[i]) && flux[i + 1..end].iter().all(|&f| f <= flux[i]);
+            flux[i] > 0.0 && peak && flux[i] >= average + margin
+        })
+        // The middle of the first frame the rise shows in
+        .map(|i| i * hop + hop)
+        .collect()
+}
+
+/// Ticks in `color` at the top and the bottom edge of the columns
+/// `column_at` the `onsets`
+pub fn draw(img: &mut RgbaImage, onsets: &[usize], column_at: impl Fn(usize) -> u32, color: Rgba<u8>) {
+    let (width, height) = img.dimensions();
+    let length = TICK_LENGTH.min(height / 2);
+    for &onset in onsets {
+        let x = column_at(onset).min(width.saturating_sub(1));
+        for y in (0..length).chain(height - length..height) {
+            img.put_pixel(x, y, color);
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::onsets::{onsets, parse_sensitivity};
+
+    #[test]
+    fn bursts_in_silence() {
+        assert_eq!(parse_sensitivity("80%"), Ok(0.8));
+        assert!(parse_sensitivity("2").is_err());
+
+        // Short bursts of noise every half second
+        let sample_rate = 8000;
+        let mut state = 1u32;
+        let signal: Vec<f32> = (0..sample_rate * 2)
+            .map(|i| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                if i % 4000 >= 1000 && i % 4000 < 1400 { (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5 } else { 0.0 }
+            })
+            .collect();
+        let found = onsets(&signal, sample_rate as u32, 0.5);
+        assert_eq!(found.len(), 4, "{found:?}");
+        for (onset, expected) in found.iter().zip([1000, 5000, 9000, 13000]) {
+            assert!((*onset as i64 - expected).abs() <= 200, "{onset}");
+        }
+    }
+}
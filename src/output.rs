@@ -0,0 +1,222 @@
+//! Output formats, atomic writing of output files and cleanup on interruption
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use clap::ValueEnum;
+use colored::Colorize;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use crate::metadata::{embed_in_jpeg, encode_png, ImageMetadata};
+
+
+/// Kind of file the waveform is written as
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Raster image
+    Png,
+    /// Raster image with lossy compression, flattened onto white and tagged
+    /// with EXIF and XMP metadata (see --quality and --creator)
+    Jpeg,
+    /// Outline of the waveform as a filled path in physical units
+    Svg,
+    /// Outline of the waveform as a closed polyline for CAD and laser cutters
+    Dxf,
+    /// Outline of the waveform extruded into a 3D mesh (see --depth)
+    Stl,
+    /// Outline points in mm as a comma separated list
+    Csv,
+    /// Outline traced once as G-code for CNC engraving (see --cut-depth)
+    Gcode,
+    /// Printable document with --seconds-per-page of waveform and a time
+    /// axis on every page
+    Pdf,
+    /// Text made of Braille characters with 2×4 dots each (see --columns and
+    /// --rows), printed to the terminal when the output is "-"
+    Braille,
+    /// Zip archive of the PNG, the peaks, the statistics and the provenance
+    /// of the rendering, one artifact per recording for archive ingest
+    Bundle,
+    /// PNG grid of every audio file in the input folder with its name and
+    /// duration, all on one time scale (see --sheet-columns). Written as a
+    /// JPEG (see --quality) when the output is named .jpg
+    ContactSheet,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Dxf => "dxf",
+            OutputFormat::Stl => "stl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Gcode => "gcode",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Braille => "txt",
+            OutputFormat::Bundle => "zip",
+            OutputFormat::ContactSheet => "png",
+        }
+    }
+
+    /// Whether an output path ending in `extension` (lower case) is already
+    /// named for this format
+    pub fn has_extension(&self, extension: &str) -> bool {
+        match self {
+            OutputFormat::Jpeg => matches!(extension, "jpg" | "jpeg"),
+            OutputFormat::ContactSheet => matches!(extension, "png" | "jpg" | "jpeg"),
+            _ => extension == self.extension(),
+        }
+    }
+
+    /// Whether the waveform is rendered as pixels
+    pub fn is_raster(&self) -> bool {
+        matches!(self, OutputFormat::Png | OutputFormat::Jpeg | OutputFormat::Bundle)
+    }
+}
+
+/// Temporary files that are currently being written
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Outputs that were written completely
+static WRITTEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Stop cleanly on SIGINT/SIGTERM: remove partially written outputs and exit
+pub fn install_signal_handler() {
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        // Without a handler we still never leave partial files at the final path
+        Err(_) => return,
+    };
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+            for path in pending.iter() {
+                let _ = std::fs::remove_file(path);
+            }
+            let written = WRITTEN.lock().unwrap_or_else(|e| e.into_inner());
+            eprintln!("\n{}{}", "Warning: ".bold().yellow(), interruption_message(&written, pending.len()));
+            std::process::exit(128 + signal);
+        }
+    });
+}
+
+/// What is left on disk after an interruption with the `written` outputs
+/// complete and `removed` unfinished files deleted
+fn interruption_message(written: &[PathBuf], removed: usize) -> String {
+    let removed = match removed {
+        0 => String::new(),
+        1 => ", the unfinished one was removed".to_string(),
+        n => format!(", the {n} unfinished ones were removed"),
+    };
+    match written {
+        [] => format!("Interrupted, no output was written{removed}"),
+        [path] => format!("Interrupted, kept the finished \"{}\"{removed}", path.display()),
+        written => format!("Interrupted, kept the {} finished outputs up to \"{}\"{removed}", written.len(), written[written.len() - 1].display()),
+    }
+}
+
+/// Temporary sibling of `path` that keeps its extension (so image formats can
+/// still be inferred from it)
+fn temporary_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!(".{}.part-{}.{}", stem, std::process::id(), ext.to_string_lossy()),
+        None => format!(".{}.part-{}", stem, std::process::id()),
+    };
+    path.with_file_name(name)
+}
+
+/// Write a file through `write`, which receives a temporary path. The result
+/// is only moved to `path` once writing succeeded, so readers never see a
+/// partial file
+pub fn write_atomically<F>(path: &Path, write: F) -> Result<(), String>
+    where F: FnOnce(&Path) -> Result<(), String>
+{
+    let temporary = temporary_path(path);
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).push(temporary.clone());
+    let result = write(&temporary)
+        .and_then(|_| std::fs::rename(&temporary, path).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => WRITTEN.lock().unwrap_or_else(|e| e.into_inner()).push(path.to_path_buf()),
+        Err(_) => {
+            let _ = std::fs::remove_file(&temporary);
+        },
+    }
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).retain(|p| p != &temporary);
+    result
+}
+
+/// Call `use_file` with the path of a scratch file next to `path`, which is
+/// removed afterwards (or on interruption)
+pub fn with_scratch_file<F, T>(path: &Path, use_file: F) -> Result<T, String>
+    where F: FnOnce(&Path) -> Result<T, String>
+{
+    let scratch = temporary_path(&path.with_extension("scratch"));
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).push(scratch.clone());
+    let result = use_file(&scratch);
+    let _ = std::fs::remove_file(&scratch);
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).retain(|p| p != &scratch);
+    result
+}
+
+/// Save an image as a PNG with embedded metadata atomically or exit with an
+/// error
+pub fn save_png(img: &image::RgbaImage, path: &Path, metadata: &ImageMetadata) {
+    let result = write_atomically(path, |temporary| std::fs::write(temporary, encode_png(img, metadata)?).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}Could not write \"{}\": {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// Save an image as a JPEG with embedded metadata atomically or exit with
+/// an error. Transparent parts are flattened onto white
+pub fn save_jpeg(img: &image::RgbaImage, path: &Path, quality: u8, metadata: &ImageMetadata) {
+    let flattened = image::RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let p = img.get_pixel(x, y);
+        let alpha = p[3] as f64 / 255.0;
+        image::Rgb(std::array::from_fn(|i| (p[i] as f64 * alpha + 255.0 * (1.0 - alpha)).round() as u8))
+    });
+    let result = write_atomically(path, |temporary| {
+        let mut jpeg = vec![];
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+            .encode_image(&flattened)
+            .map_err(|e| e.to_string())?;
+        let jpeg = embed_in_jpeg(&jpeg, metadata)?;
+        std::fs::write(temporary, jpeg).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}Could not write \"{}\": {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// Save an image atomically or exit with an error
+pub fn save_image(img: &image::RgbaImage, path: &Path) {
+    let result = write_atomically(path, |temporary| img.save(temporary).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}Could not write \"{}\": {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::output::interruption_message;
+
+    #[test]
+    fn interruptions_tell_what_is_left() {
+        assert_eq!(interruption_message(&[], 0), "Interrupted, no output was written");
+        assert_eq!(interruption_message(&[], 1), "Interrupted, no output was written, the unfinished one was removed");
+        let written = [PathBuf::from("a.png"), PathBuf::from("a.peaks.json")];
+        assert_eq!(interruption_message(&written[..1], 0), "Interrupted, kept the finished \"a.png\"");
+        assert_eq!(interruption_message(&written, 2), "Interrupted, kept the 2 finished outputs up to \"a.peaks.json\", the 2 unfinished ones were removed");
+    }
+}
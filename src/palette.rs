@@ -0,0 +1,121 @@
+//! Dominant colors of an image (e.g. the cover art of an episode), used to
+//! derive the colors of the waveform so it matches the artwork
+use std::path::Path;
+use image::{Rgba, RgbaImage};
+
+use crate::color::{blend, contrast_ratio};
+
+
+/// Number of bits kept of every channel when counting colors
+const BITS: u32 = 4;
+
+/// Largest width and height of the scaled down copy that is analyzed
+const ANALYSIS_SIZE: u32 = 128;
+
+/// Smallest squared RGB distance between two colors of the palette
+const MIN_DISTANCE: i32 = 48 * 48;
+
+/// Number of colors in a palette at most
+const PALETTE_SIZE: usize = 6;
+
+/// Colors of the waveform derived from an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DerivedColors {
+    pub background: Rgba<u8>,
+    pub foreground: Rgba<u8>,
+    /// Accent for negative excursions of the asymmetry mode
+    pub negative: Rgba<u8>,
+}
+
+/// Squared RGB distance of two colors
+fn distance(a: Rgba<u8>, b: Rgba<u8>) -> i32 {
+    (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2)).sum()
+}
+
+/// Distinct colors covering the largest areas of `img`, most common first.
+/// Transparent pixels are ignored
+pub fn dominant_colors(img: &RgbaImage) -> Vec<Rgba<u8>> {
+    let img = image::imageops::thumbnail(img, ANALYSIS_SIZE.min(img.width()), ANALYSIS_SIZE.min(img.height()));
+    // Sum of the channels and count of the pixels falling into every bucket
+    let mut buckets = vec![([0u64; 3], 0u64); 1 << (3 * BITS)];
+    for pixel in img.pixels().filter(|p| p[3] >= 128) {
+        let index = (0..3).fold(0, |index, i| (index << BITS) | (pixel[i] >> (8 - BITS)) as usize);
+        let (sum, count) = &mut buckets[index];
+        for i in 0..3 {
+            sum[i] += pixel[i] as u64;
+        }
+        *count += 1;
+    }
+    let mut buckets: Vec<_> = buckets.into_iter().filter(|(_, count)| *count > 0).collect();
+    buckets.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let mut palette: Vec<Rgba<u8>> = vec![];
+    for (sum, count) in buckets {
+        let color = Rgba([(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8, 255]);
+        if palette.iter().all(|&c| distance(c, color) >= MIN_DISTANCE) {
+            palette.push(color);
+            if palette.len() == PALETTE_SIZE {
+                break;
+            }
+        }
+    }
+    palette
+}
+
+/// The most common color as background, the palette color with the highest
+/// contrast to it as foreground (pushed towards black or white until it has
+/// a contrast ratio of at least `min_contrast`) and the next one as accent
+pub fn derive(palette: &[Rgba<u8>], min_contrast: f64) -> Option<DerivedColors> {
+    let background = *palette.first()?;
+    let mut candidates: Vec<Rgba<u8>> = palette[1..].to_vec();
+    candidates.sort_by(|a, b| contrast_ratio(*b, background).total_cmp(&contrast_ratio(*a, background)));
+    let white = Rgba([255, 255, 255, 255]);
+    let black = Rgba([0, 0, 0, 255]);
+    let extreme = if contrast_ratio(white, background) > contrast_ratio(black, background) { white } else { black };
+    let legible = |color: Rgba<u8>| {
+        (0..=10).map(|step| blend(color, extreme, step as f64 / 10.0))
+            .find(|&c| contrast_ratio(c, background) >= min_contrast)
+            .unwrap_or(extreme)
+    };
+    let foreground = legible(candidates.first().copied().unwrap_or(extreme));
+    let negative = candidates.get(1).copied().map(legible).unwrap_or(foreground);
+    Some(DerivedColors { background, foreground, negative })
+}
+
+/// Read the image at `path` and derive the waveform colors from it
+pub fn colors_from(path: &Path, min_contrast: f64) -> Result<DerivedColors, String> {
+    let img = image::open(path).map_err(|e| e.to_string())?.to_rgba8();
+    derive(&dominant_colors(&img), min_contrast).ok_or_else(|| "the image has no opaque pixels".to_string())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::color::contrast_ratio;
+    use crate::palette::{derive, dominant_colors};
+
+    #[test]
+    fn cover_colors() {
+        let navy = Rgba([20, 30, 80, 255]);
+        let orange = Rgba([240, 140, 20, 255]);
+        let teal = Rgba([30, 60, 90, 255]);
+        let img = RgbaImage::from_fn(100, 100, |x, y| match (x, y) {
+            (_, 0..=9) => orange,
+            (0..=19, _) => teal,
+            _ => navy,
+        });
+        let palette = dominant_colors(&img);
+        assert_eq!(palette[0], navy);
+        // Teal is too close to navy to count as a color of its own
+        assert_eq!(palette, vec![navy, orange]);
+
+        let colors = derive(&palette, 3.0).unwrap();
+        assert_eq!((colors.background, colors.foreground), (navy, orange));
+        // A low contrast color is lightened until it is legible
+        let colors = derive(&[navy, Rgba([60, 60, 120, 255])], 4.5).unwrap();
+        assert!(contrast_ratio(colors.foreground, navy) >= 4.5);
+        assert!(derive(&[], 3.0).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! Paginated PDF output with one stretch of the waveform and a time axis per
+//! page, written by hand since PDF only needs a few objects for this
+use std::fmt::Write;
+use image::Rgba;
+
+use crate::format_timestamp;
+use crate::render::{TimeAxis, TimeDirection};
+use crate::timecode::Timecode;
+use crate::vector::outline;
+
+
+/// A4 landscape in points
+const PAGE_WIDTH: f64 = 842.0;
+const PAGE_HEIGHT: f64 = 595.0;
+const MARGIN: f64 = 42.0;
+/// Height of the waveform band below the page title
+const BAND_HEIGHT: f64 = 160.0;
+/// Columns sampled across a full page
+const COLUMNS: u32 = 2000;
+
+/// Tick interval in seconds giving roughly ten ticks per page
+fn tick_interval(seconds_per_page: f64) -> f64 {
+    [1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0]
+        .into_iter()
+        .find(|&step| seconds_per_page / step <= 12.0)
+        .unwrap_or(3600.0)
+}
+
+/// Text as a PDF string literal, non-ASCII characters replaced
+fn literal(text: &str) -> String {
+    let mut escaped = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => { escaped.push('\\'); escaped.push(c); },
+            c if c.is_ascii() && !c.is_ascii_control() => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped.push(')');
+    escaped
+}
+
+/// How the pages of a document are laid out and labelled
+pub struct PdfOptions {
+    /// Time shown on every page
+    pub seconds_per_page: f64,
+    /// Labels the times in SMPTE timecode instead of minutes and seconds
+    pub timecode: Option<Timecode>,
+    /// Title at the top of every page
+    pub title: String,
+    /// Color of the waveform
+    pub color: Rgba<u8>,
+}
+
+/// Label of the time `seconds` into the audio, as SMPTE timecode if given
+fn time_label(seconds: f64, timecode: Option<Timecode>) -> String {
+    match timecode {
+        Some(timecode) => timecode.format(seconds),
+        None => format_timestamp(seconds),
+    }
+}
+
+/// Content stream of a page headed `heading` showing `samples`, which start
+/// at `start` seconds and fill the page if there are `samples_per_page` of
+/// them
+fn page_content(samples: &[f32], samples_per_page: f64, gain: f64, start: f64, heading: &str, options: &PdfOptions) -> String {
+    let PdfOptions { seconds_per_page, timecode, color, .. } = *options;
+    let share = samples.len() as f64 / samples_per_page;
+
+    let area_width = PAGE_WIDTH - 2.0 * MARGIN;
+    let band_top = PAGE_HEIGHT - MARGIN - 30.0;
+    let columns = ((COLUMNS as f64 * share).ceil() as u32).max(1);
+    let axis = TimeAxis::linear(columns, samples_per_page / COLUMNS as f64);
+    let shape = outline(samples, &axis, gain, TimeDirection::Ltr, (area_width * share, BAND_HEIGHT), 0.1);
+
+    let mut content = String::new();
+    let [r, g, b, _] = color.0.map(|c| c as f64 / 255.0);
+    writeln!(content, "BT /F1 12 Tf {MARGIN} {} Td {} Tj ET", PAGE_HEIGHT - MARGIN - 12.0, literal(heading)).unwrap();
+
+    // Waveform
+    writeln!(content, "{r:.3} {g:.3} {b:.3} rg").unwrap();
+    for (i, (x, y)) in shape.points.iter().enumerate() {
+        writeln!(content, "{:.2} {:.2} {}", MARGIN + x, band_top - y, if i == 0 { "m" } else { "l" }).unwrap();
+    }
+    content.push_str("h f\n");
+
+    // Time axis with a labelled tick every few seconds
+    let axis_y = band_top - BAND_HEIGHT - 8.0;
+    writeln!(content, "0 0 0 RG 0 0 0 rg 0.5 w {MARGIN} {axis_y} m {} {axis_y} l S", MARGIN + area_width).unwrap();
+    let step = tick_interval(seconds_per_page);
+    let mut tick = (start / step).ceil() * step;
+    while tick <= start + seconds_per_page + 1e-9 {
+        let x = MARGIN + (tick - start) / seconds_per_page * area_width;
+        writeln!(content, "{x:.2} {axis_y} m {x:.2} {} l S", axis_y - 4.0).unwrap();
+        // Centered under the tick, digits being about 4 points wide
+        let label = time_label(tick, timecode);
+        writeln!(content, "BT /F1 8 Tf {:.2} {} Td {} Tj ET", x - 2.0 * label.len() as f64, axis_y - 14.0, literal(&label)).unwrap();
+        tick += step;
+    }
+    content
+}
+
+/// PDF document with `options.seconds_per_page` of the interleaved `samples`
+/// on every page
+pub fn to_pdf(samples: &[f32], channels: usize, sample_rate: u32, gain: f64, options: &PdfOptions) -> Vec<u8> {
+    let PdfOptions { seconds_per_page, timecode, .. } = *options;
+    let channels = channels.max(1);
+    let duration = samples.len() as f64 / channels as f64 / sample_rate as f64;
+    let pages = ((duration / seconds_per_page).ceil() as usize).max(1);
+
+    // Objects 1 to 3 are the catalog, the page tree and the font, followed
+    // by a page and its content stream for every page
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {pages} >>",
+            (0..pages).map(|p| format!("{} 0 R", 4 + 2 * p)).collect::<Vec<_>>().join(" ")
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+    ];
+    let samples_per_page = seconds_per_page * sample_rate as f64 * channels as f64;
+    for page in 0..pages {
+        let start = page as f64 * seconds_per_page;
+        let end = (start + seconds_per_page).min(duration);
+        let first = ((start * sample_rate as f64).round() as usize * channels).min(samples.len());
+        let last = ((end * sample_rate as f64).round() as usize * channels).min(samples.len());
+        let heading = format!("{}   {} - {}   ({}/{pages})", options.title, time_label(start, timecode), time_label(end, timecode), page + 1);
+        let content = page_content(&samples[first..last], samples_per_page, gain, start, &heading, options);
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            5 + 2 * page
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{content}endstream", content.len()));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        write!(pdf, "{} 0 obj\n{object}\nendobj\n", i + 1).unwrap();
+    }
+    let xref = pdf.len();
+    write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+    for offset in offsets {
+        writeln!(pdf, "{offset:010} 00000 n ").unwrap();
+    }
+    write!(pdf, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n", objects.len() + 1).unwrap();
+    pdf.into_bytes()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pdf::{literal, tick_interval, to_pdf, PdfOptions};
+    use crate::timecode::parse_timecode;
+
+    #[test]
+    fn pages_and_escaping() {
+        assert_eq!(literal("a (b) \\ ä"), "(a \\(b\\) \\\\ ?)");
+        assert_eq!(tick_interval(60.0), 5.0);
+
+        // 2.5 pages of audio need three pages
+        let samples = vec![0.5f32; 2 * 100 * 25];
+        let mut options = PdfOptions { seconds_per_page: 10.0, timecode: None, title: "test".to_string(), color: image::Rgba([0, 0, 0, 255]) };
+        let pdf = String::from_utf8(to_pdf(&samples, 2, 100, 1.0, &options)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4") && pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("/Count 3"));
+        assert_eq!(pdf.matches("/Type /Page ").count(), 3);
+        assert!(pdf.contains("(0:10)"));
+
+        // The pages and the ticks are labelled in timecode
+        options.timecode = parse_timecode("25fps@01:00:00:00").ok();
+        let pdf = String::from_utf8(to_pdf(&samples, 2, 100, 1.0, &options)).unwrap();
+        assert!(pdf.contains("(test   01:00:10:00 - 01:00:20:00   \\(2/3\\))"));
+        assert!(pdf.contains("(01:00:05:00)") && !pdf.contains("(0:10)"));
+    }
+}
@@ -0,0 +1,80 @@
+//! Peak-hold markers like on a level meter: a tick at the loudest sample of
+//! every division of time, so the biggest hit of each stands out even where
+//! the waveform is dense
+use image::{Rgba, RgbaImage};
+
+
+/// Width of a tick in pixels
+const TICK_WIDTH: u32 = 5;
+
+/// Height of a tick in pixels, drawn outside of the peak it marks
+const TICK_HEIGHT: u32 = 2;
+
+/// The loudest sample of a division
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hold {
+    /// Frame of the sample
+    pub frame: usize,
+    /// Value of the sample, negative if the peak points downwards
+    pub value: f32,
+}
+
+/// Loudest sample (over all channels) of every division of
+/// `frames_per_division` frames of the interleaved `samples`
+pub fn holds(samples: &[f32], channels: usize, frames_per_division: usize) -> Vec<Hold> {
+    let channels = channels.max(1);
+    samples.chunks(frames_per_division.max(1) * channels).enumerate()
+        .map(|(division, chunk)| {
+            let (index, value) = chunk.iter().enumerate()
+                .fold((0, 0.0f32), |(i, peak), (j, &s)| if s.abs() > peak.abs() { (j, s) } else { (i, peak) });
+            Hold { frame: division * frames_per_division.max(1) + index / channels, value }
+        })
+        .collect()
+}
+
+/// Draw a tick in `color` for every hold, centered on the column
+/// `column_at` its frame and just outside of the row `row_at` its value
+pub fn draw(img: &mut RgbaImage, holds: &[Hold], column_at: impl Fn(usize) -> u32, row_at: impl Fn(f32) -> f64, color: Rgba<u8>) {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    for hold in holds.iter().filter(|hold| hold.value != 0.0) {
+        let center = column_at(hold.frame) as i64;
+        let row = row_at(hold.value).round() as i64;
+        // Above peaks pointing upwards, below those pointing downwards
+        let top = if hold.value > 0.0 { row - TICK_HEIGHT as i64 } else { row };
+        let top = top.clamp(0, (height - TICK_HEIGHT as i64).max(0));
+        for x in center - (TICK_WIDTH / 2) as i64..=center + (TICK_WIDTH / 2) as i64 {
+            for y in top..top + TICK_HEIGHT as i64 {
+                if (0..width).contains(&x) && (0..height).contains(&y) {
+                    img.put_pixel(x as u32, y as u32, color);
+                }
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::peakhold::{draw, holds, Hold};
+
+    #[test]
+    fn loudest_sample_of_every_division() {
+        // Stereo, three frames per division, the last one cut short
+        let samples = [0.1, 0.2, -0.9, 0.3, 0.0, 0.0, 0.5, 0.1, 0.0, 0.7, 0.0, 0.0, 0.0, -0.2];
+        assert_eq!(holds(&samples, 2, 3), vec![
+            Hold { frame: 1, value: -0.9 },
+            Hold { frame: 4, value: 0.7 },
+            Hold { frame: 6, value: -0.2 },
+        ]);
+
+        let (ink, paper) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 0, 0]));
+        let mut img = RgbaImage::from_pixel(20, 20, paper);
+        draw(&mut img, &[Hold { frame: 10, value: 0.5 }, Hold { frame: 0, value: -1.0 }], |frame| frame as u32, |v| 10.0 - 10.0 * v as f64, ink);
+        // A tick above row 5 around column 10, one at the bottom edge
+        assert!((8..=12).all(|x| img.get_pixel(x, 3) == &ink && img.get_pixel(x, 4) == &ink));
+        assert_eq!(img.get_pixel(13, 3), &paper);
+        assert_eq!(img.get_pixel(0, 19), &ink);
+    }
+}
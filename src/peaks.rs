@@ -0,0 +1,112 @@
+//! Peak data in the format of audiowaveform (as JSON or binary .dat), so
+//! web players like peaks.js can show the waveform without decoding the
+//! audio again
+use std::path::Path;
+
+use crate::json::Json;
+
+
+/// Version of the audiowaveform format that is written
+const VERSION: u32 = 2;
+
+/// Lowest and highest sample of every channel in every column, scaled to
+/// 16 bits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peaks {
+    pub channels: usize,
+    pub sample_rate: u32,
+    /// Frames summarized by every column
+    pub samples_per_pixel: usize,
+    /// Minimum and maximum of channel 0, of channel 1, … of column 0, then
+    /// of column 1 and so on
+    pub data: Vec<i16>,
+}
+
+impl Peaks {
+    /// Number of columns
+    pub fn length(&self) -> usize {
+        self.data.len() / (2 * self.channels.max(1))
+    }
+}
+
+/// Peaks of the interleaved `samples` in columns of `samples_per_pixel`
+/// frames (at least one)
+pub fn peaks(samples: &[f32], channels: usize, sample_rate: u32, samples_per_pixel: usize) -> Peaks {
+    let channels = channels.max(1);
+    let samples_per_pixel = samples_per_pixel.max(1);
+    let to_i16 = |s: f32| (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+    let mut data = vec![];
+    for column in samples.chunks(samples_per_pixel * channels) {
+        for channel in 0..channels {
+            let (low, high) = column.iter().skip(channel).step_by(channels)
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &s| (low.min(s), high.max(s)));
+            // A frame cut off at the end may leave a channel without samples
+            let (low, high) = if low <= high { (low, high) } else { (0.0, 0.0) };
+            data.extend([to_i16(low), to_i16(high)]);
+        }
+    }
+    Peaks { channels, sample_rate, samples_per_pixel, data }
+}
+
+/// The peaks as audiowaveform JSON, which players ignoring unknown fields
+/// read despite the schema header
+pub fn to_json(peaks: &Peaks) -> Json {
+    Json::document("peaks", vec![
+        ("version", Json::from(VERSION as f64)),
+        ("channels", Json::from(peaks.channels as f64)),
+        ("sample_rate", Json::from(peaks.sample_rate as f64)),
+        ("samples_per_pixel", Json::from(peaks.samples_per_pixel as f64)),
+        ("bits", Json::from(16.0)),
+        ("length", Json::from(peaks.length() as f64)),
+        ("data", Json::Array(peaks.data.iter().map(|&v| Json::from(v as f64)).collect())),
+    ])
+}
+
+/// The peaks as binary audiowaveform data (little endian, 16 bits)
+pub fn to_dat(peaks: &Peaks) -> Vec<u8> {
+    let mut dat = vec![];
+    dat.extend(VERSION.to_le_bytes());
+    // Flags, bit 0 cleared for 16 bit values
+    dat.extend(0u32.to_le_bytes());
+    dat.extend((peaks.sample_rate as i32).to_le_bytes());
+    dat.extend((peaks.samples_per_pixel as i32).to_le_bytes());
+    dat.extend((peaks.length() as u32).to_le_bytes());
+    dat.extend((peaks.channels as i32).to_le_bytes());
+    for value in &peaks.data {
+        dat.extend(value.to_le_bytes());
+    }
+    dat
+}
+
+/// The peaks in the format chosen by the extension of `path`: binary for
+/// ".dat", JSON otherwise
+pub fn encode(peaks: &Peaks, path: &Path) -> Vec<u8> {
+    match path.extension().map(|e| e.to_string_lossy().to_lowercase()) {
+        Some(extension) if extension == "dat" => to_dat(peaks),
+        _ => format!("{}\n", to_json(peaks)).into_bytes(),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::peaks::{peaks, to_dat};
+
+    #[test]
+    fn stereo_columns() {
+        // Left rises, right falls, three frames per column
+        let samples: Vec<f32> = (0..8).flat_map(|i| [i as f32 / 8.0, -(i as f32) / 8.0]).collect();
+        let peaks = peaks(&samples, 2, 8000, 3);
+        assert_eq!(peaks.length(), 3);
+        let q = |s: f32| (s * 32767.0).round() as i16;
+        assert_eq!(&peaks.data[..4], &[0, q(0.25), q(-0.25), 0]);
+        // The last column only holds two frames
+        assert_eq!(&peaks.data[8..], &[q(0.75), q(0.875), q(-0.875), q(-0.75)]);
+
+        let dat = to_dat(&peaks);
+        assert_eq!(dat.len(), 24 + 12 * 2);
+        assert_eq!(&dat[..4], &2u32.to_le_bytes());
+        assert_eq!(&dat[16..20], &3u32.to_le_bytes());
+    }
+}
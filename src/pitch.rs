@@ -0,0 +1,133 @@
+//! Fundamental frequency tracking with the YIN algorithm and the contour of
+//! it drawn over the waveform, on a logarithmic axis from the lowest pitch at
+//! the bottom to the highest at the top
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::color::composite;
+use crate::raster::{distance_to_segment, stroke_coverage};
+
+
+/// Lowest fundamental tracked in Hz, at the bottom edge
+const MIN_PITCH: f64 = 50.0;
+
+/// Highest fundamental tracked in Hz, at the top edge
+const MAX_PITCH: f64 = 1000.0;
+
+/// Dip of the normalized difference below which a lag counts as a period
+const THRESHOLD: f64 = 0.15;
+
+/// Frames quieter than this RMS level (about -50 dBFS) are unvoiced
+const SILENCE: f64 = 0.003;
+
+/// Width of the contour in pixels
+const STROKE_WIDTH: f64 = 1.5;
+
+/// Fundamental frequency in Hz of the `frame`, which needs to hold at least
+/// two periods of the lowest pitch, None if it has no clear period
+pub fn yin(frame: &[f32], sample_rate: u32) -> Option<f64> {
+    let sample_rate = sample_rate as f64;
+    let max_lag = ((sample_rate / MIN_PITCH).ceil() as usize).min(frame.len() / 2);
+    let min_lag = ((sample_rate / MAX_PITCH).floor() as usize).max(2);
+    if max_lag <= min_lag + 1 {
+        return None;
+    }
+    let window = frame.len() - max_lag;
+    let rms = (frame[..window].iter().map(|&s| s as f64 * s as f64).sum::<f64>() / window as f64).sqrt();
+    if rms < SILENCE {
+        return None;
+    }
+
+    // Difference of the frame and its copy shifted by every lag, normalized
+    // by the running mean so short lags don't win
+    let difference: Vec<f64> = (0..=max_lag)
+        .map(|lag| (0..window).map(|i| (frame[i] - frame[i + lag]) as f64).map(|d| d * d).sum())
+        .collect();
+    let mut normalized = vec![1.0; max_lag + 1];
+    let mut sum = 0.0;
+    for lag in 1..=max_lag {
+        sum += difference[lag];
+        normalized[lag] = if sum > 0.0 { difference[lag] * lag as f64 / sum } else { 1.0 };
+    }
+
+    // The bottom of the first dip below the threshold
+    let mut lag = (min_lag..max_lag).find(|&lag| normalized[lag] < THRESHOLD)?;
+    while lag + 1 < max_lag && normalized[lag + 1] < normalized[lag] {
+        lag += 1;
+    }
+    // Parabola through the dip and its neighbours
+    let (a, b, c) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let shift = if a + c - 2.0 * b != 0.0 { (a - c) / (2.0 * (a + c - 2.0 * b)) } else { 0.0 };
+    Some(sample_rate / (lag as f64 + shift.clamp(-1.0, 1.0)))
+}
+
+/// Fundamental frequency of the `mono` signal around every frame in
+/// `centers`, None where it is unvoiced
+pub fn contour(mono: &[f32], sample_rate: u32, centers: &[usize]) -> Vec<Option<f64>> {
+    let length = 2 * (sample_rate as f64 / MIN_PITCH).ceil() as usize;
+    centers.par_iter()
+        .map(|&center| {
+            let start = center.saturating_sub(length / 2).min(mono.len().saturating_sub(length));
+            let frame = &mono[start..(start + length).min(mono.len())];
+            yin(frame, sample_rate)
+        })
+        .collect()
+}
+
+/// Draw the `contour` (one frequency per column) as a line in `color`,
+/// broken where the signal is unvoiced
+pub fn draw(img: &mut RgbaImage, contour: &[Option<f64>], color: Rgba<u8>) {
+    let height = img.height() as f64;
+    let range = (MAX_PITCH / MIN_PITCH).log2();
+    let row = |frequency: f64| height * (1.0 - ((frequency / MIN_PITCH).log2() / range).clamp(0.0, 1.0));
+    let points: Vec<Option<(f64, f64)>> = contour.iter().enumerate()
+        .map(|(x, frequency)| frequency.map(|f| (x as f64 + 0.5, row(f))))
+        .collect();
+
+    let reach = STROKE_WIDTH / 2.0 + 1.0;
+    for (i, point) in points.iter().enumerate() {
+        let Some(a) = *point else { continue };
+        // A lone voiced column is drawn as a dot
+        let b = points.get(i + 1).copied().flatten().unwrap_or(a);
+        let (left, right) = ((a.0.min(b.0) - reach).floor().max(0.0) as u32, ((a.0.max(b.0) + reach).ceil() as u32).min(img.width()));
+        let (top, bottom) = ((a.1.min(b.1) - reach).floor().max(0.0) as u32, ((a.1.max(b.1) + reach).ceil() as u32).min(img.height()));
+        for y in top..bottom {
+            for x in left..right {
+                let coverage = stroke_coverage(distance_to_segment((x as f64 + 0.5, y as f64 + 0.5), a, b), STROKE_WIDTH);
+                if coverage > 0.0 {
+                    let pixel = img.get_pixel_mut(x, y);
+                    *pixel = composite(color, *pixel, coverage);
+                }
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::pitch::{contour, yin};
+
+    #[test]
+    fn sung_notes_are_tracked() {
+        let sample_rate = 16000;
+        let tone = |frequency: f64| -> Vec<f32> {
+            (0..4000).map(|i| {
+                let t = 2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate as f64;
+                (0.5 * t.sin() + 0.3 * (2.0 * t).sin() + 0.2 * (3.0 * t).sin()) as f32
+            }).collect()
+        };
+        // The fundamental is found although the overtones are strong
+        for frequency in [110.0, 220.0, 440.0, 700.0] {
+            let f0 = yin(&tone(frequency)[..640], sample_rate).unwrap();
+            assert!((f0 - frequency).abs() < frequency * 0.01, "{frequency}: {f0}");
+        }
+        assert_eq!(yin(&[0.0; 640], sample_rate), None);
+
+        let mut signal = tone(220.0);
+        signal.extend(vec![0.0; 4000]);
+        let f0 = contour(&signal, sample_rate, &[2000, 6000]);
+        assert!(f0[0].is_some() && f0[1].is_none());
+    }
+}
@@ -0,0 +1,57 @@
+//! How problems that rendering can recover from are reported: as warnings by
+//! default, as errors that stop with --strict (e.g. when validating an
+//! archive) and, with --lenient, even damage that stops decoding only warns
+//! and the audio read up to it is drawn (e.g. for a quick preview)
+use colored::{ColoredString, Colorize};
+
+
+/// Treatment of recoverable problems
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Every problem is an error
+    Strict,
+    /// Problems the output can be made despite are warnings
+    #[default]
+    Warn,
+    /// Problems are warnings, also those that cut the input short
+    Lenient,
+}
+
+impl Policy {
+    pub fn new(strict: bool, lenient: bool) -> Policy {
+        match (strict, lenient) {
+            (true, _) => Policy::Strict,
+            (false, true) => Policy::Lenient,
+            (false, false) => Policy::Warn,
+        }
+    }
+
+    /// Label of a recoverable problem, "Error: " with --strict
+    pub fn label(self) -> ColoredString {
+        match self {
+            Policy::Strict => "Error: ".bold().red(),
+            _ => "Warning: ".bold().yellow(),
+        }
+    }
+
+    /// Report a problem the output can be made despite, which exits with
+    /// --strict
+    pub fn recoverable(self, message: &str) {
+        eprintln!("{}{message}", self.label());
+        if self == Policy::Strict {
+            std::process::exit(1);
+        }
+    }
+
+    /// Report a problem that leaves part of the input unread, which exits
+    /// unless --lenient
+    pub fn damaging(self, message: &str) {
+        match self {
+            Policy::Lenient => eprintln!("{}{message}", "Warning: ".bold().yellow()),
+            _ => {
+                eprintln!("{}{message}", "Error: ".bold().red());
+                std::process::exit(1);
+            },
+        }
+    }
+}
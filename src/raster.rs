@@ -0,0 +1,60 @@
+//! Anti-aliased drawing of strokes, where the coverage of a pixel follows from
+//! the distance of its center to the center line of the stroke, and of
+//! filled spans, where it follows from their overlap with the pixel
+use clap::ValueEnum;
+
+
+/// How the columns of the min/max mode are turned into pixels
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rasterizer {
+    /// Every pixel the span of a column reaches is filled completely
+    Oversample,
+    /// Pixels at the ends of the span are covered by the share of their row
+    /// the span overlaps, which anti-aliases the top and bottom edges
+    Analytic,
+}
+
+/// Point in pixels
+pub type Point = (f64, f64);
+
+/// Distance of the point `p` to the line segment from `a` to `b`
+pub fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+    let (x, y) = (a.0 + t * dx, a.1 + t * dy);
+    ((p.0 - x).powi(2) + (p.1 - y).powi(2)).sqrt()
+}
+
+/// Share of a pixel covered by a stroke of `width` whose center line is
+/// `distance` away from the pixel center, falling off over one pixel
+pub fn stroke_coverage(distance: f64, width: f64) -> f64 {
+    (width / 2.0 + 0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// Share of the pixel row starting at `y` covered by the span from `top` to
+/// `bottom`
+pub fn span_coverage(top: f64, bottom: f64, y: f64) -> f64 {
+    (bottom.min(y + 1.0) - top.max(y)).max(0.0)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::raster::{distance_to_segment, span_coverage, stroke_coverage};
+
+    #[test]
+    fn distances_and_coverage() {
+        assert_eq!(distance_to_segment((1.0, 1.0), (0.0, 0.0), (2.0, 0.0)), 1.0);
+        assert_eq!(distance_to_segment((5.0, 4.0), (0.0, 0.0), (2.0, 0.0)), 5.0);
+        assert_eq!(distance_to_segment((3.0, 4.0), (0.0, 0.0), (0.0, 0.0)), 5.0);
+        assert_eq!(stroke_coverage(0.0, 1.0), 1.0);
+        assert_eq!(stroke_coverage(0.75, 1.0), 0.25);
+        assert_eq!(stroke_coverage(2.0, 1.0), 0.0);
+        assert_eq!(span_coverage(2.5, 7.5, 2.0), 0.5);
+        assert_eq!(span_coverage(2.5, 7.5, 4.0), 1.0);
+        assert_eq!(span_coverage(2.5, 2.75, 2.0), 0.25);
+        assert_eq!(span_coverage(2.5, 7.5, 8.0), 0.0);
+    }
+}
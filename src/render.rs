@@ -0,0 +1,1438 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use image::{ImageBuffer, Rgba, RgbaImage};
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+use crate::color::{blend, composite};
+use crate::decimate::{decimate, BLOCKS_PER_COLUMN, MIN_FRAMES_PER_PIXEL};
+use crate::dither::{self, Dither, Filter};
+use crate::filterbank;
+use crate::interpolate::{self, upsample, Interpolation, INTERPOLATED_FRAMES_PER_PIXEL, MAX_INTERPOLATED_SAMPLES};
+use crate::loudness::{render_loudness, LoudnessWindow};
+use crate::number::parse_fraction;
+use crate::raster::{distance_to_segment, span_coverage, stroke_coverage, Point, Rasterizer};
+use crate::smoothing::{self, Kernel};
+use crate::chroma::render_chroma;
+use crate::spectrogram::{render_spectrogram, render_waterfall, SpectrogramOptions};
+
+
+/// Horizontal direction of the time axis
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeDirection {
+    /// Start of the audio on the left, end on the right
+    Ltr,
+    /// Start of the audio on the right, end on the left (for right-to-left UIs)
+    Rtl,
+}
+
+/// How horizontal positions map to time
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeScale {
+    /// Every column covers the same amount of time
+    Linear,
+    /// Early time is stretched and later time compressed logarithmically
+    /// (e.g. for impulse responses and reverb tails)
+    Log,
+    /// Early time is compressed and later time stretched
+    LogReverse,
+}
+
+/// How the samples of a column are turned into pixels
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Full span between the lowest and the highest sample of every column
+    /// around a centered baseline, like in a DAW
+    #[value(name = "minmax")]
+    MinMax,
+    /// Rectified waveform from the bottom edge showing the mean level per column
+    Mean,
+    /// Positive and negative excursions above and below a centered baseline
+    /// in different colors (see --negative-color) to reveal asymmetry
+    Asymmetry,
+    /// Anti-aliased lines along the highest and the lowest sample of every
+    /// column instead of a filled area (see --stroke-width)
+    Line,
+    /// Small dots at the highest and the lowest sample of evenly spaced
+    /// slices of time (see --dot-radius)
+    Dots,
+    /// Discrete rounded bars around the baseline, one per time slice, sized
+    /// by the peak level of the slice (see --bar-width and --bar-gap)
+    Bars,
+    /// Peaks smoothed by an attack/release envelope follower (see --smooth)
+    /// and drawn as a filled blob around the baseline
+    Envelope,
+    /// Peaks growing outwards from a circle, time running clockwise from the
+    /// start angle (see --inner-radius and --start-angle)
+    Radial,
+    /// Peaks of the low, mid and high band drawn on top of each other in
+    /// their own colors (see --crossovers)
+    Multiband,
+    /// Histogram of the sample values in every column, brighter where more
+    /// samples pass through, which shows the crest factor as a glow
+    Density,
+    /// Left against right channel as a goniometer, colored by how many
+    /// samples fall on every pixel
+    Vectorscope,
+    /// Short-time spectrum with frequency rising from the bottom, louder
+    /// components drawn closer to the foreground color
+    Spectrogram,
+    /// Spectra of successive slices of time stacked in perspective, frequency
+    /// rising to the right (see --slices and --perspective)
+    Waterfall,
+    /// Energy of the twelve pitch classes from C at the bottom to B at the
+    /// top, every column scaled to its strongest class
+    Chroma,
+    /// Momentary or short-term loudness in LUFS as a curve, with an optional
+    /// target line (see --loudness-window and --target-lufs)
+    Loudness,
+    /// The audio cut into equal stretches drawn in the minmax mode as rows
+    /// below each other, like a strip of film (see --filmstrip-rows)
+    Filmstrip,
+}
+
+impl RenderMode {
+    /// Whether every column of the internal image shows the samples of one
+    /// slice of time and nothing else, as in the waveform modes
+    pub fn has_columns(self) -> bool {
+        !matches!(self, RenderMode::Radial | RenderMode::Vectorscope | RenderMode::Spectrogram | RenderMode::Waterfall | RenderMode::Chroma | RenderMode::Loudness | RenderMode::Filmstrip)
+    }
+
+    /// Whether every column is filled over the span of its peaks. These
+    /// modes are drawn at the output width, as scaling oversampled columns
+    /// down would average the span of a transient with its empty neighbours
+    pub fn fills_spans(self) -> bool {
+        matches!(self, RenderMode::MinMax | RenderMode::Asymmetry | RenderMode::Multiband)
+    }
+
+    /// Whether every column only depends on its own samples and those of
+    /// its close neighbours, so the image can be rendered in strips (see
+    /// --stream). The other modes carry state across the image, fit slices
+    /// of time to it or scale every column to the loudest one
+    pub fn renders_in_strips(self) -> bool {
+        matches!(self, RenderMode::MinMax | RenderMode::Mean | RenderMode::Asymmetry | RenderMode::Line)
+    }
+
+    /// Whether the mode measures the signal itself (the spectral modes, the
+    /// loudness curve and the vectorscope), which rectifying or interpolating
+    /// the samples would falsify
+    pub fn analyzes_signal(self) -> bool {
+        matches!(self, RenderMode::Spectrogram | RenderMode::Waterfall | RenderMode::Chroma | RenderMode::Loudness | RenderMode::Vectorscope)
+    }
+
+    /// Whether --over-spectrogram puts a spectrogram under the mode, which
+    /// the spectral modes already are and the filmstrip has no room for
+    pub fn draws_over_spectrogram(self) -> bool {
+        !matches!(self, RenderMode::Spectrogram | RenderMode::Waterfall | RenderMode::Chroma | RenderMode::Filmstrip)
+    }
+
+    /// Whether the mode is drawn at the output size instead of being scaled
+    /// down from the oversampled width
+    pub fn drawn_at_output_size(self) -> bool {
+        matches!(self, RenderMode::Radial | RenderMode::Vectorscope | RenderMode::Waterfall | RenderMode::Filmstrip)
+    }
+}
+
+/// Horizontal oversampling, either a fixed factor or picked from the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oversample {
+    Auto,
+    Factor(u32),
+}
+
+/// Largest internal image `--oversample auto` creates, in pixels
+const AUTO_MAX_PIXELS: f64 = 64_000_000.0;
+
+/// Fewest frames per internal column `--oversample auto` goes down to, as
+/// columns of only a few samples no longer show the extent of the waveform
+const AUTO_MIN_FRAMES: f64 = 16.0;
+
+/// Parse an oversampling factor or "auto"
+pub fn parse_oversample(argument: &str) -> Result<Oversample, String> {
+    let argument = argument.trim().to_lowercase();
+    if argument == "auto" {
+        return Ok(Oversample::Auto);
+    }
+    match argument.parse::<u32>() {
+        Ok(factor) if factor >= 1 => Ok(Oversample::Factor(factor)),
+        _ => Err(format!("\"{argument}\" is not a valid oversampling (expected \"auto\" or a factor of at least 1)")),
+    }
+}
+
+impl Oversample {
+    /// Factor for an image of `width` × `height` pixels with `frames_per_pixel`
+    /// frames per output column in the given mode. The automatic factor stops
+    /// where internal columns would hold too few frames, where the mode
+    /// stops gaining detail (spectrogram columns come from overlapping frames,
+    /// bars only need smooth edges) and where the image would get too large
+    pub fn factor(self, frames_per_pixel: f64, width: u32, height: u32, mode: RenderMode) -> u32 {
+        if mode.fills_spans() {
+            return 1;
+        }
+        let limit = match mode {
+            RenderMode::Spectrogram | RenderMode::Chroma => 4,
+            RenderMode::Bars => 8,
+            // Narrow density columns hold too few samples for a histogram
+            RenderMode::Density => 4,
+            // Only the time resolution grows, which the vectorscope and the
+            // waterfall ignore
+            RenderMode::Vectorscope | RenderMode::Waterfall => 1,
+            _ => 32,
+        };
+        match self {
+            Oversample::Factor(factor) => factor,
+            Oversample::Auto => {
+                let memory = AUTO_MAX_PIXELS / (width.max(1) as f64 * height.max(1) as f64);
+                ((frames_per_pixel / AUTO_MIN_FRAMES).floor().min(memory) as u32).clamp(1, limit)
+            },
+        }
+    }
+}
+
+/// Where the waveform of the mean mode grows from
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Rectified, growing up from the bottom edge
+    Bottom,
+    /// Positive excursions above and negative excursions below the baseline
+    Centered,
+}
+
+/// Parse the position of the baseline from the top ("0%" or "0.0") to the
+/// bottom edge ("100%" or "1.0")
+pub fn parse_baseline(argument: &str) -> Result<f64, String> {
+    parse_fraction(argument, "baseline", 0.0..=1.0)
+}
+
+/// Parse the inner radius of the radial mode as a share of the outer radius
+/// ("40%" or "0.4")
+pub fn parse_inner_radius(argument: &str) -> Result<f64, String> {
+    parse_fraction(argument, "inner radius", 0.0..1.0)
+}
+
+/// Everything that decides how a waveform image looks
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Width of the resulting image in pixels
+    pub width: u32,
+    /// Height of the resulting image in pixels
+    pub height: u32,
+    /// Internal horizontal oversampling factor
+    pub oversample: u32,
+    /// Number of channels interleaved in the samples
+    pub channels: usize,
+    pub sample_rate: u32,
+    /// Scale the waveform to fill the vertical space
+    pub normalize: bool,
+    /// Fixed gain applied instead of the normalization, e.g. the factor a
+    /// previous render was normalized with
+    pub scale_factor: Option<f64>,
+    /// Draw the magnitude of the samples growing from the bottom edge
+    pub rectify: bool,
+    pub mode: RenderMode,
+    pub layout: Layout,
+    /// Position of the zero line of the modes drawing both polarities, from
+    /// the top (0.0) to the bottom (1.0) edge
+    pub baseline: f64,
+    pub time_direction: TimeDirection,
+    pub time_scale: TimeScale,
+    /// Decades spanned by the logarithmic time scales
+    pub time_decades: f64,
+    pub foreground: Rgba<u8>,
+    pub background: Rgba<u8>,
+    /// Color of negative excursions where they are drawn separately
+    pub negative: Rgba<u8>,
+    /// Color of the RMS level drawn on top of the min/max silhouette
+    pub rms: Option<Rgba<u8>>,
+    /// Fade every column by its RMS level, so the height shows the peaks and
+    /// the opacity the average level
+    pub rms_alpha: bool,
+    /// Width of every bar of the bars mode in output pixels
+    pub bar_width: u32,
+    /// Space between the bars of the bars mode in output pixels
+    pub bar_gap: u32,
+    /// Width of the lines of the line mode in output pixels
+    pub stroke_width: f64,
+    /// Radius of the dots of the dots mode in output pixels
+    pub dot_radius: f64,
+    /// Release time of the envelope mode in seconds
+    pub smooth: f64,
+    /// Radius of the circle the radial mode grows from, as a share of the
+    /// outer radius
+    pub inner_radius: f64,
+    /// Angle in degrees, clockwise from the top, at which the radial mode starts
+    pub start_angle: f64,
+    /// Offset of the bars against the left edge as a share of a bar and its gap
+    pub bar_phase: f64,
+    /// Frequencies in Hz between the low, mid and high band of the multiband mode
+    pub crossovers: (f64, f64),
+    /// Colors of the low, mid and high band of the multiband mode
+    pub band_colors: [Rgba<u8>; 3],
+    /// Window the loudness mode measures over
+    pub loudness_window: LoudnessWindow,
+    /// Loudness in LUFS marked by a line in the loudness mode
+    pub target_lufs: Option<f64>,
+    /// Number of rows the filmstrip mode cuts the audio into
+    pub filmstrip_rows: u32,
+    pub spectrogram: SpectrogramOptions,
+    /// Draw the waveform with this opacity over a spectrogram of the audio
+    pub over_spectrogram: Option<f64>,
+    /// How gradients are rounded to 8 bits per channel
+    pub dither: Dither,
+    /// Filter of the downscale from the oversampled width
+    pub filter: Filter,
+    /// Width in output pixels of the window the column values of the
+    /// min/max, mean, asymmetry, line and multiband modes are smoothed over,
+    /// no smoothing below 2
+    pub smooth_columns: f64,
+    pub smooth_kernel: Kernel,
+    /// Reconstruction of the signal between the samples where a column
+    /// covers less than a frame
+    pub interpolation: Interpolation,
+    /// How the min/max mode turns its columns into pixels
+    pub rasterizer: Rasterizer,
+    /// Shrink columns of very many frames to the peaks of short blocks in
+    /// the modes that only draw peaks
+    pub decimate: bool,
+}
+
+/// Maps the columns of an image to ranges of samples
+#[derive(Debug, Clone, Copy)]
+pub struct TimeAxis {
+    pub columns: u32,
+    /// Samples per column if the scale were linear
+    pub samples_per_column: f64,
+    pub scale: TimeScale,
+    /// Number of decades spanned by the logarithmic scales
+    pub decades: f64,
+    /// Column of a longer linear axis this one starts at, for images
+    /// rendered in strips
+    pub first: u32,
+}
+
+impl TimeAxis {
+    pub fn linear(columns: u32, samples_per_column: f64) -> TimeAxis {
+        TimeAxis { columns, samples_per_column, scale: TimeScale::Linear, decades: 3.0, first: 0 }
+    }
+
+    /// Share of the covered duration that lies before the horizontal
+    /// position `p` (0.0 is the left edge, 1.0 the right edge)
+    fn fraction(&self, p: f64) -> f64 {
+        let log = |p: f64| (10f64.powf(self.decades * p) - 1.0) / (10f64.powf(self.decades) - 1.0);
+        match self.scale {
+            TimeScale::Linear => p,
+            TimeScale::Log => log(p),
+            TimeScale::LogReverse => 1.0 - log(1.0 - p),
+        }
+    }
+
+    /// Sample position at the (fractional) column `x`
+    pub fn position(&self, x: f64) -> f64 {
+        match self.scale {
+            // The same product for a column of a strip and of the whole axis
+            TimeScale::Linear => (self.first as f64 + x) * self.samples_per_column,
+            _ => self.fraction(x / self.columns as f64) * self.samples_per_column * self.columns as f64,
+        }
+    }
+
+    /// Range of sample indices that fall into the column `x`
+    pub fn range(&self, x: u32, sample_count: usize) -> Range<usize> {
+        let start = (self.position(x as f64).round() as usize).min(sample_count);
+        let end = (self.position((x + 1) as f64).round() as usize).min(sample_count);
+        start..end
+    }
+}
+
+impl RenderOptions {
+    /// Options with the built-in defaults for a small render in `mode`, black
+    /// on transparent; callers override the size, the rate and whatever else
+    /// they need
+    pub fn new(mode: RenderMode) -> RenderOptions {
+        RenderOptions {
+            width: 100,
+            height: 40,
+            oversample: 1,
+            channels: 1,
+            sample_rate: 8000,
+            normalize: false,
+            scale_factor: None,
+            rectify: false,
+            mode,
+            layout: Layout::Bottom,
+            baseline: 0.5,
+            time_direction: TimeDirection::Ltr,
+            time_scale: TimeScale::Linear,
+            time_decades: 3.0,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([0, 0, 0, 0]),
+            negative: Rgba([255, 0, 0, 255]),
+            rms: None,
+            rms_alpha: false,
+            bar_width: 3,
+            bar_gap: 2,
+            stroke_width: 1.5,
+            dot_radius: 1.5,
+            smooth: 0.2,
+            inner_radius: 0.5,
+            start_angle: 0.0,
+            bar_phase: 0.0,
+            crossovers: (200.0, 2000.0),
+            band_colors: [Rgba([220, 40, 40, 255]), Rgba([40, 180, 60, 255]), Rgba([40, 90, 230, 255])],
+            loudness_window: LoudnessWindow::Momentary,
+            target_lufs: None,
+            filmstrip_rows: 4,
+            spectrogram: SpectrogramOptions::default(),
+            over_spectrogram: None,
+            dither: Dither::None,
+            filter: Filter::Lanczos3,
+            smooth_columns: 0.0,
+            smooth_kernel: Kernel::Gaussian,
+            interpolation: Interpolation::Sinc,
+            rasterizer: Rasterizer::Oversample,
+            decimate: true,
+        }
+    }
+
+    /// Row of the baseline, the zero line of the modes drawing both polarities
+    pub fn baseline_y(&self) -> f64 {
+        self.baseline * self.height as f64
+    }
+
+    /// Vertical position of the (gain adjusted) sample value `s` relative to
+    /// the baseline, positive values using the space above and negative values
+    /// the space below it
+    pub fn sample_y(&self, s: f64) -> f64 {
+        let base = self.baseline_y();
+        let space = if s >= 0.0 { base } else { self.height as f64 - base };
+        base - s.clamp(-1.0, 1.0) * space
+    }
+
+    /// Time axis with `columns` columns of `samples_per_column` samples each
+    /// (on a linear scale) using the configured time scale
+    pub fn time_axis(&self, columns: u32, samples_per_column: f64) -> TimeAxis {
+        TimeAxis { columns, samples_per_column, scale: self.time_scale, decades: self.time_decades, first: 0 }
+    }
+
+    /// Gain applied to the `samples` before rendering: the fixed scale
+    /// factor if there is one, otherwise the normalization gain
+    pub fn gain(&self, samples: &[f32]) -> f64 {
+        self.scale_factor.unwrap_or_else(|| normalization_gain(samples, self.normalize))
+    }
+}
+
+/// Gain applied to the samples before rendering: with `normalize` the peak is
+/// scaled to full height, otherwise the samples are used as they are
+pub fn normalization_gain(samples: &[f32], normalize: bool) -> f64 {
+    if !normalize {
+        return 1.0;
+    }
+    let peak = samples.iter().fold(0.0f32, |a, &b| a.max(b.abs())) as f64;
+    if peak > 0.0 { 1.0 / peak } else { 1.0 }
+}
+
+/// Render the interleaved `samples` into an image of the size given in the
+/// options. `samples_per_pixel` is the number of samples per internal
+/// (oversampled) column.
+pub fn render_waveform(samples: &[f32], samples_per_pixel: f64, options: &RenderOptions) -> RgbaImage {
+    let (samples, samples_per_pixel, options) = prepare(samples, samples_per_pixel, options);
+    render_columns(&samples, samples_per_pixel, &options, 0)
+}
+
+/// Gain `render_waveform` applies to the `samples`. Normalizing measures the
+/// peak of the prepared samples, as interpolating them can overshoot the
+/// peak of the input
+pub fn waveform_gain(samples: &[f32], samples_per_pixel: f64, options: &RenderOptions) -> f64 {
+    if options.scale_factor.is_some() || !options.normalize {
+        return options.gain(samples);
+    }
+    let (samples, _, options) = prepare(samples, samples_per_pixel, options);
+    options.gain(&samples)
+}
+
+/// The `samples` as they are drawn, with the samples per internal column
+/// and the options to draw them with: filled spans at the output width,
+/// long columns decimated, short ones interpolated and rectified where
+/// requested. This works on the whole audio, so an image rendered in strips
+/// prepares it once
+pub fn prepare<'a>(samples: &'a [f32], samples_per_pixel: f64, options: &RenderOptions) -> (Cow<'a, [f32]>, f64, RenderOptions) {
+    let (samples_per_pixel, options) = match options.mode.fills_spans() && options.oversample > 1 {
+        true => (samples_per_pixel * options.oversample as f64, RenderOptions { oversample: 1, ..options.clone() }),
+        false => (samples_per_pixel, options.clone()),
+    };
+    let (samples, samples_per_pixel) = match decimation_block(samples_per_pixel, &options) {
+        Some(block) => (Cow::Owned(decimate(samples, options.channels, block)), samples_per_pixel * 2.0 / block as f64),
+        None => (Cow::Borrowed(samples), samples_per_pixel),
+    };
+    let (samples, samples_per_pixel, options) = match interpolation_factor(samples.len(), samples_per_pixel, &options) {
+        Some(factor) => {
+            let interpolated = upsample(&samples, options.channels, factor, options.interpolation);
+            // The modes measuring time in samples see the higher rate
+            let sample_rate = options.sample_rate.saturating_mul(factor as u32);
+            (Cow::Owned(interpolated), samples_per_pixel * factor as f64, RenderOptions { sample_rate, ..options })
+        },
+        None => (samples, samples_per_pixel, options),
+    };
+
+    // Rectified samples are drawn from a baseline at the bottom edge in every
+    // mode that doesn't need the signal itself. The multiband mode rectifies
+    // its bands after filtering
+    let rectify = options.rectify && !options.mode.analyzes_signal();
+    match rectify {
+        true => {
+            let samples = match options.mode {
+                RenderMode::Multiband => samples,
+                _ => samples.iter().map(|s| s.abs()).collect(),
+            };
+            (samples, samples_per_pixel, RenderOptions { baseline: 1.0, layout: Layout::Bottom, ..options })
+        },
+        false => (samples, samples_per_pixel, options),
+    }
+}
+
+/// Draw the output columns `first..first + options.width` of the image of
+/// the `samples` made ready by `prepare`
+pub fn render_columns(samples: &[f32], samples_per_pixel: f64, options: &RenderOptions, first: u32) -> RgbaImage {
+    let width = options.width * options.oversample;
+    let gain = options.gain(samples);
+    let axis = TimeAxis { first: first * options.oversample, ..options.time_axis(width, samples_per_pixel) };
+
+    // Over a spectrogram the waveform is a layer of its own, which only
+    // covers the spectrogram where it is drawn
+    let spectrogram = options.over_spectrogram.filter(|_| options.mode.draws_over_spectrogram());
+    let requested = options;
+    let layer = RenderOptions { background: Rgba([0, 0, 0, 0]), ..options.clone() };
+    let options = if spectrogram.is_some() { &layer } else { options };
+
+    let img = match options.mode {
+        RenderMode::MinMax if options.rasterizer == Rasterizer::Analytic => render_min_max_analytic(samples, &axis, gain, options),
+        RenderMode::MinMax => render_min_max(samples, &axis, gain, options),
+        RenderMode::Mean => match options.layout {
+            Layout::Bottom => render_mean(samples, &axis, gain, options),
+            Layout::Centered => render_mean_centered(samples, &axis, gain, options),
+        },
+        RenderMode::Asymmetry => render_asymmetry(samples, &axis, gain, options),
+        RenderMode::Line => render_line(samples, &axis, gain, options),
+        RenderMode::Dots => render_dots(samples, &axis, gain, options),
+        RenderMode::Bars => render_bars(samples, &axis, gain, options),
+        RenderMode::Envelope => render_envelope(samples, &axis, gain, options),
+        RenderMode::Radial => render_radial(samples, &axis, gain, options),
+        RenderMode::Multiband => render_multiband(samples, &axis, gain, options),
+        RenderMode::Density => render_density(samples, &axis, gain, options),
+        RenderMode::Vectorscope => render_vectorscope(samples, gain, options),
+        RenderMode::Spectrogram => render_spectrogram(samples, &axis, gain, options),
+        RenderMode::Waterfall => render_waterfall(samples, gain, options),
+        RenderMode::Chroma => render_chroma(samples, &axis, gain, options),
+        RenderMode::Loudness => render_loudness(samples, &axis, options),
+        RenderMode::Filmstrip => render_filmstrip(samples, samples_per_pixel, gain, options),
+    };
+
+    let img = match options.rms_alpha && options.mode.has_columns() {
+        true => {
+            // Pooled over the internal columns of every output column, which
+            // may each hold less than a period
+            let pooled: Vec<f64> = column_rms(samples, &axis).chunks(options.oversample.max(1) as usize)
+                .flat_map(|chunk| {
+                    let rms = (chunk.iter().map(|r| r * r).sum::<f64>() / chunk.len() as f64).sqrt();
+                    std::iter::repeat_n(rms, chunk.len())
+                })
+                .collect();
+            fade_columns(&img, &pooled, gain, options)
+        },
+        false => img,
+    };
+
+    // The radial mode, the vectorscope, the waterfall and the rows of the
+    // filmstrip are drawn at the output size, the columns of the radial mode
+    // only set its angular resolution
+    let img = match options.mode.drawn_at_output_size() {
+        true => img,
+        false => dither::resize(&img, options.width, options.height, options.filter, options.dither),
+    };
+
+    match spectrogram {
+        // The spectrogram is rendered at the output width, as it gains
+        // nothing from oversampling
+        Some(opacity) => {
+            let axis = TimeAxis { first, ..options.time_axis(options.width, samples_per_pixel * options.oversample as f64) };
+            let mut layers = render_spectrogram(samples, &axis, gain, requested);
+            for (bottom, top) in layers.pixels_mut().zip(img.pixels()) {
+                *bottom = composite(*top, *bottom, opacity);
+            }
+            layers
+        },
+        _ => img,
+    }
+}
+
+/// Frames per block the samples are decimated to before rendering, None
+/// where the columns are short enough or the mode needs every sample (RMS
+/// levels, spectra, the timing of the envelope) or a nonlinear time scale
+/// would make early columns shorter than a block
+fn decimation_block(samples_per_pixel: f64, options: &RenderOptions) -> Option<usize> {
+    let frames_per_column = samples_per_pixel / options.channels.max(1) as f64;
+    let peaks_only = match options.mode {
+        RenderMode::MinMax => options.rms.is_none(),
+        RenderMode::Asymmetry | RenderMode::Line | RenderMode::Dots | RenderMode::Bars | RenderMode::Radial => true,
+        _ => false,
+    };
+    let exact = !options.decimate || !peaks_only || options.rms_alpha || options.over_spectrogram.is_some() || options.time_scale != TimeScale::Linear;
+    let block = (frames_per_column / BLOCKS_PER_COLUMN) as usize;
+    (!exact && frames_per_column * options.oversample as f64 > MIN_FRAMES_PER_PIXEL && block > 1).then_some(block)
+}
+
+/// Frames every original frame is interpolated to, None where the shortest
+/// internal column holds a frame or more or the mode needs the signal itself
+/// (the spectral modes, the vectorscope). The filmstrip interpolates every
+/// row on its own
+fn interpolation_factor(sample_count: usize, samples_per_pixel: f64, options: &RenderOptions) -> Option<usize> {
+    let timed = !options.mode.analyzes_signal() && options.mode != RenderMode::Filmstrip;
+    if !timed || options.over_spectrogram.is_some() || sample_count == 0 {
+        return None;
+    }
+    let axis = options.time_axis(options.width * options.oversample, samples_per_pixel);
+    let shortest = (0..axis.columns)
+        .map(|x| axis.position((x + 1) as f64) - axis.position(x as f64))
+        .fold(f64::INFINITY, f64::min) / options.channels.max(1) as f64;
+    if !(shortest > 0.0 && shortest < interpolate::MIN_FRAMES_PER_PIXEL) {
+        return None;
+    }
+    let limit = (MAX_INTERPOLATED_SAMPLES / sample_count).max(1);
+    Some(((INTERPOLATED_FRAMES_PER_PIXEL / shortest).ceil() as usize).min(limit)).filter(|&factor| factor > 1)
+}
+
+/// Decode a complete media file from memory and render it with `options`,
+/// whose channel count and sample rate are taken from the file. Malformed
+/// input results in an error instead of a panic, which makes this the entry
+/// point for fuzzing (see fuzz/fuzz_targets/render_bytes.rs)
+pub fn render_bytes(bytes: &[u8], options: &RenderOptions) -> Result<RgbaImage, String> {
+    if options.width == 0 || options.height == 0 || options.oversample == 0 {
+        return Err("the image needs a width, a height and an oversampling of at least 1".to_string());
+    }
+    let audio = crate::audio::decode_bytes(bytes)?;
+    let options = RenderOptions { channels: audio.channels, sample_rate: audio.sample_rate, ..options.clone() };
+    let samples_per_pixel = audio.samples.len() as f64 / (options.width * options.oversample) as f64;
+    Ok(render_waveform(&audio.samples, samples_per_pixel, &options))
+}
+
+/// Mirror the column index when time flows from right to left
+pub fn time_column(x: u32, width: u32, direction: TimeDirection) -> u32 {
+    match direction {
+        TimeDirection::Ltr => x,
+        TimeDirection::Rtl => width - 1 - x,
+    }
+}
+
+/// Rectified waveform growing from the bottom, each column showing the mean
+/// height of its samples
+fn render_mean(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = axis.columns;
+    let height = options.height;
+    let sample_count = samples.len();
+
+    let (minimum, maximum) = (-1.0, 1.0);
+
+    // Times two because we render half the waveform here
+    let factor = 2.0 * gain;
+
+    // Only the samples of the columns drawn, which are a strip of the whole
+    // audio when the image is rendered in strips
+    let covered = axis.range(0, sample_count).start..axis.range(width.saturating_sub(1), sample_count).end;
+    let graph: Vec<u32> = 
+    samples[covered.clone()].par_iter()
+           // .step_by(channels.into())
+           .map(|s| {
+                let sample = if s < &0.0 {
+                    // (4.0 * (s as f64 / minimum as f64)).tanh()
+                    factor * *s as f64 / minimum
+                } else {
+                    // (4.0 * ( s as f64 / maximum as f64)).tanh()
+                    factor * *s as f64 / maximum
+                };
+                let pixel_height = (sample * height as f64).round();
+                pixel_height as u32
+            })
+           .collect();
+
+    // Aggregate the per-sample heights into one height per column
+    let column_heights: Vec<Option<f64>> = (0..width).into_par_iter()
+        .map(|x| {
+            let range = axis.range(x, sample_count);
+            // Columns past the end of the audio stay empty
+            if range.is_empty() {
+                return None;
+            }
+            let count = range.len();
+            Some(graph[range.start - covered.start..range.end - covered.start].iter().sum::<u32>() as f64 / count as f64)
+        })
+        .collect();
+    let column_heights: Vec<u32> = smoothing::smooth(&column_heights, options.smooth_columns * options.oversample as f64, options.smooth_kernel)
+        .into_iter()
+        .map(|height| height.map_or(0, |h| h.round() as u32))
+        .collect();
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let x = time_column(x, width, options.time_direction);
+        if (height - (y+1)) < column_heights[x as usize] {
+            options.foreground
+        } else {
+            options.background
+        }
+    })
+}
+
+/// Bipolar variant of the mean mode: the mean of the positive samples of every
+/// column grows up from the baseline and the mean of the negative samples
+/// grows down from it, both scaled like the rectified variant
+fn render_mean_centered(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = axis.columns;
+    let height = options.height;
+    let base = options.baseline_y();
+
+    // Sums of the positive and the negative samples of every column divided
+    // by all of its samples, so a column that only swings one way is drawn
+    // as large as in the rectified variant
+    let column_means: Vec<(f64, f64)> = (0..width).into_par_iter()
+        .map(|x| {
+            let bucket = &samples[axis.range(x, samples.len())];
+            if bucket.is_empty() {
+                return (0.0, 0.0);
+            }
+            let (positive, negative) = bucket.iter()
+                .fold((0.0f64, 0.0f64), |(p, n), &s| if s >= 0.0 { (p + s as f64, n) } else { (p, n + s as f64) });
+            (positive / bucket.len() as f64, negative / bucket.len() as f64)
+        })
+        .collect();
+    let spans = column_means.into_iter().map(|(p, n)| Some(ColumnValue { low: n as f32, high: p as f32 })).collect();
+    let column_means: Vec<(f64, f64)> = smooth_spans(spans, options).into_iter().flatten()
+        .map(|v| (v.high as f64, v.low as f64))
+        .collect();
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (positive, negative) = column_means[time_column(x, width, options.time_direction) as usize];
+        // Times two like the rectified variant, which shows half the waveform
+        let top = options.sample_y(2.0 * gain * positive).round();
+        let bottom = options.sample_y(2.0 * gain * negative).round();
+        let y = y as f64;
+        if (y >= top && y < base.round()) || (y >= base.round() && y < bottom) {
+            options.foreground
+        } else {
+            options.background
+        }
+    })
+}
+
+/// Span of a column around the baseline, in the units of samples (1.0
+/// reaches the edge of the image)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnValue {
+    pub low: f32,
+    pub high: f32,
+}
+
+impl ColumnValue {
+    /// Span from `-value` to `value`, for aggregations without a sign
+    pub fn symmetric(value: f32) -> ColumnValue {
+        ColumnValue { low: -value.abs(), high: value.abs() }
+    }
+}
+
+/// `aggregate` applied to the interleaved samples of every column, None for
+/// columns past the end of the audio
+pub fn column_values<F>(samples: &[f32], axis: &TimeAxis, aggregate: F) -> Vec<Option<ColumnValue>>
+where
+    F: Fn(&[f32]) -> ColumnValue + Sync,
+{
+    (0..axis.columns).into_par_iter()
+        .map(|x| {
+            let bucket = &samples[axis.range(x, samples.len())];
+            (!bucket.is_empty()).then(|| aggregate(bucket))
+        })
+        .collect()
+}
+
+/// Lowest and highest sample of a column
+fn peaks(bucket: &[f32]) -> ColumnValue {
+    let (low, high) = bucket.iter().fold((f32::MAX, f32::MIN), |(min, max), &s| (min.min(s), max.max(s)));
+    ColumnValue { low, high }
+}
+
+/// Signed minimum and maximum sample of every column, None for columns
+/// past the end of the audio
+pub fn column_extremes(samples: &[f32], axis: &TimeAxis) -> Vec<Option<(f32, f32)>> {
+    column_values(samples, axis, peaks).into_iter()
+        .map(|value| value.map(|v| (v.low, v.high)))
+        .collect()
+}
+
+/// The `spans` averaged over the neighbouring columns as set by
+/// --smooth-columns, the lows and the highs on their own
+fn smooth_spans(spans: Vec<Option<ColumnValue>>, options: &RenderOptions) -> Vec<Option<ColumnValue>> {
+    let window = options.smooth_columns * options.oversample as f64;
+    if window < 2.0 {
+        return spans;
+    }
+    let smooth = |part: fn(&ColumnValue) -> f32| {
+        let values: Vec<Option<f64>> = spans.iter().map(|v| v.as_ref().map(|v| part(v) as f64)).collect();
+        smoothing::smooth(&values, window, options.smooth_kernel)
+    };
+    let (lows, highs) = (smooth(|v| v.low), smooth(|v| v.high));
+    lows.into_iter().zip(highs)
+        .map(|(low, high)| Some(ColumnValue { low: low? as f32, high: high? as f32 }))
+        .collect()
+}
+
+/// Signed minimum and maximum sample of every column smoothed as set by
+/// --smooth-columns, None for columns past the end of the audio
+fn smoothed_extremes(samples: &[f32], axis: &TimeAxis, options: &RenderOptions) -> Vec<Option<(f32, f32)>> {
+    smooth_spans(column_values(samples, axis, peaks), options).into_iter()
+        .map(|value| value.map(|v| (v.low, v.high)))
+        .collect()
+}
+
+/// Root mean square of the samples of every column
+pub fn column_rms(samples: &[f32], axis: &TimeAxis) -> Vec<f64> {
+    (0..axis.columns).into_par_iter()
+        .map(|x| {
+            let bucket = &samples[axis.range(x, samples.len())];
+            if bucket.is_empty() {
+                return 0.0;
+            }
+            (bucket.iter().map(|&s| s as f64 * s as f64).sum::<f64>() / bucket.len() as f64).sqrt()
+        })
+        .collect()
+}
+
+/// Every column filled from its lowest to its highest sample around the
+/// baseline, so short transients keep their full height. With an RMS color
+/// the RMS level of the column is drawn on top in both directions
+fn render_min_max(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    // Rectified columns are filled down to the baseline
+    let spans = match options.rectify {
+        true => column_values(samples, axis, |bucket| ColumnValue { low: 0.0, ..peaks(bucket) }),
+        false => column_values(samples, axis, peaks),
+    };
+    let rms = options.rms.map(|_| column_rms(samples, axis));
+    render_spans(&smooth_spans(spans, options), rms.as_deref(), gain, options)
+}
+
+/// The min/max mode with every pixel covered by the share of its row the
+/// span of its column overlaps, so the ends of the span are anti-aliased
+/// instead of filling the rows they touch
+fn render_min_max_analytic(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let spans = match options.rectify {
+        true => column_values(samples, axis, |bucket| ColumnValue { low: 0.0, ..peaks(bucket) }),
+        false => column_values(samples, axis, peaks),
+    };
+    let spans = smooth_spans(spans, options);
+    let rms = options.rms.map(|_| column_rms(samples, axis));
+    let width = axis.columns;
+
+    ImageBuffer::from_fn(width, options.height, |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        let Some(ColumnValue { low, high }) = spans[column] else {
+            return options.background;
+        };
+        let row = y as f64;
+        let top = options.sample_y(high as f64 * gain);
+        // At least a pixel's worth, so silence draws the baseline
+        let bottom = options.sample_y(low as f64 * gain).max(top + 1.0);
+        let peak = span_coverage(top, bottom, row);
+        let level = match &rms {
+            Some(rms) if rms[column] > 0.0 => span_coverage(options.sample_y(rms[column] * gain), options.sample_y(-rms[column] * gain), row),
+            _ => 0.0,
+        };
+        let foreground = (peak - level).max(0.0);
+        let background = (1.0 - foreground - level).max(0.0);
+        let rms_color = options.rms.unwrap_or(options.foreground);
+        Rgba(std::array::from_fn(|i| {
+            let value = options.background[i] as f64 * background + options.foreground[i] as f64 * foreground + rms_color[i] as f64 * level;
+            options.dither.quantize(value as f32, x, y)
+        }))
+    })
+}
+
+/// Peaks of the low, mid and high band of every column drawn like the
+/// min/max mode, the higher bands on top of the lower ones
+fn render_multiband(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let mut bands = filterbank::split(samples, options.channels, options.sample_rate, options.crossovers);
+    if options.rectify {
+        bands.iter_mut().flatten().for_each(|s| *s = s.abs());
+    }
+    let spans: Vec<Vec<Option<ColumnValue>>> = bands.iter()
+        .map(|band| match options.rectify {
+            true => column_values(band, axis, |bucket| ColumnValue { low: 0.0, ..peaks(bucket) }),
+            false => column_values(band, axis, peaks),
+        })
+        .map(|spans| smooth_spans(spans, options))
+        .collect();
+    let width = axis.columns;
+
+    ImageBuffer::from_fn(width, options.height, |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        spans.iter().zip(options.band_colors).rev()
+            .find(|(spans, _)| {
+                let Some(ColumnValue { low, high }) = spans[column] else {
+                    return false;
+                };
+                let top = options.sample_y(high as f64 * gain);
+                let bottom = options.sample_y(low as f64 * gain).max(top.floor() + 1.0);
+                (y as f64) < bottom && (y + 1) as f64 > top
+            })
+            .map_or(options.background, |(_, color)| color)
+    })
+}
+
+/// Every sample counted in the row it falls into in its column, and every
+/// pixel colored by its count relative to the largest one (on a logarithmic
+/// scale, so rare peaks still show)
+fn render_density(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let (width, height) = (axis.columns, options.height);
+    let histograms: Vec<Vec<u32>> = (0..width).into_par_iter()
+        .map(|x| {
+            let mut counts = vec![0; height as usize];
+            for &s in &samples[axis.range(x, samples.len())] {
+                let y = options.sample_y(s as f64 * gain).floor().clamp(0.0, (height - 1) as f64);
+                counts[y as usize] += 1;
+            }
+            counts
+        })
+        .collect();
+    let most = (histograms.iter().flatten().copied().max().unwrap_or(0) as f64).ln_1p().max(f64::MIN_POSITIVE);
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        let density = (histograms[column][y as usize] as f64).ln_1p() / most;
+        blend(options.background, options.foreground, density)
+    })
+}
+
+/// Every column filled over its span, with the RMS level (if given) drawn
+/// on top in both directions in the RMS color
+fn render_spans(spans: &[Option<ColumnValue>], rms: Option<&[f64]>, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = spans.len() as u32;
+    let height = options.height;
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        let Some(ColumnValue { low, high }) = spans[column] else {
+            return options.background;
+        };
+        let top = options.sample_y(high as f64 * gain);
+        // At least the row the span lies in, so silence draws the baseline
+        let bottom = options.sample_y(low as f64 * gain).max(top.floor() + 1.0);
+        let covers = |top: f64, bottom: f64| (y as f64) < bottom && (y + 1) as f64 > top;
+        match (rms, options.rms) {
+            (Some(rms), Some(color)) if rms[column] > 0.0 && covers(options.sample_y(rms[column] * gain), options.sample_y(-rms[column] * gain)) => color,
+            _ if covers(top, bottom) => options.foreground,
+            _ => options.background,
+        }
+    })
+}
+
+/// Render the interleaved `samples` like the min/max mode, but with every
+/// column spanning the value `aggregate` computes from its samples instead
+/// of the peaks. This is the hook for experimental visualizations (entropy,
+/// kurtosis, …) that need no changes to the renderers. Normalizing scales
+/// the largest value to the edge of the image
+pub fn render_aggregated<F>(samples: &[f32], samples_per_pixel: f64, options: &RenderOptions, aggregate: F) -> RgbaImage
+where
+    F: Fn(&[f32]) -> ColumnValue + Sync,
+{
+    let axis = options.time_axis(options.width * options.oversample, samples_per_pixel);
+    let spans = column_values(samples, &axis, aggregate);
+    let largest = spans.iter().flatten().map(|v| v.low.abs().max(v.high.abs())).fold(0.0, f32::max);
+    let gain = if options.normalize && largest > 0.0 { 1.0 / largest as f64 } else { 1.0 };
+    let img = render_spans(&spans, None, gain, options);
+    dither::resize(&img, options.width, options.height, options.filter, options.dither)
+}
+
+/// Opacity of the quietest columns with --rms-alpha, so they don't vanish
+const MIN_RMS_OPACITY: f64 = 0.15;
+
+/// `img` with every column composited over the background with an opacity
+/// following its `rms` level, full for the RMS of a full scale sine
+pub fn fade_columns(img: &RgbaImage, rms: &[f64], gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = img.width();
+    let full = std::f64::consts::FRAC_1_SQRT_2;
+    ImageBuffer::from_fn(width, img.height(), |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        let opacity = (rms[column] * gain / full).clamp(MIN_RMS_OPACITY, 1.0);
+        composite(*img.get_pixel(x, y), options.background, opacity)
+    })
+}
+
+/// Share of the release time the envelope follower takes to rise
+const ENVELOPE_ATTACK: f64 = 0.1;
+
+/// Peak level of every column followed by an envelope that rises within a
+/// tenth of the smoothing time and falls within the smoothing time, so
+/// single hits swell into a smooth blob instead of a spike
+fn render_envelope(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let peaks = column_values(samples, axis, |bucket| ColumnValue::symmetric(bucket.iter().fold(0.0, |a, &s| a.max(s.abs()))));
+    let samples_per_second = options.sample_rate.max(1) as f64 * options.channels.max(1) as f64;
+    let coefficient = |seconds: f64, time: f64| if time > 0.0 { 1.0 - (-seconds / time).exp() } else { 1.0 };
+    let mut level = 0.0;
+    let spans: Vec<Option<ColumnValue>> = peaks.iter().enumerate()
+        .map(|(x, peak)| {
+            peak.map(|peak| {
+                let seconds = axis.range(x as u32, samples.len()).len() as f64 / samples_per_second;
+                let time = if peak.high as f64 > level { options.smooth * ENVELOPE_ATTACK } else { options.smooth };
+                level += (peak.high as f64 - level) * coefficient(seconds, time);
+                ColumnValue::symmetric(level as f32)
+            })
+        })
+        .collect();
+    render_spans(&spans, None, gain, options)
+}
+
+/// Peak level of every column drawn as a ring growing outwards from a circle
+/// of the inner radius, with the columns spread clockwise (counterclockwise
+/// for right to left time) around the center from the start angle
+fn render_radial(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let (width, height) = (options.width, options.height);
+    let columns = axis.columns.max(1) as usize;
+    let peaks: Vec<f64> = column_values(samples, axis, |bucket| ColumnValue::symmetric(bucket.iter().fold(0.0, |a, &s| a.max(s.abs()))))
+        .into_iter()
+        .map(|value| value.map(|v| (v.high as f64 * gain).min(1.0)).unwrap_or(0.0))
+        .collect();
+    let center: Point = (width as f64 / 2.0, height as f64 / 2.0);
+    // One pixel of room for the anti-aliased edge
+    let outer = (width.min(height) as f64 / 2.0 - 1.0).max(0.0);
+    let inner = outer * options.inner_radius.clamp(0.0, 1.0);
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f64 + 0.5 - center.0, y as f64 + 0.5 - center.1);
+        let radius = dx.hypot(dy);
+        let angle = (dx.atan2(-dy).to_degrees() - options.start_angle).rem_euclid(360.0) / 360.0;
+        let fraction = match options.time_direction {
+            TimeDirection::Ltr => angle,
+            TimeDirection::Rtl => 1.0 - angle,
+        };
+        let column = ((fraction * columns as f64) as usize).min(columns - 1);
+        let extent = inner + peaks[column] * (outer - inner);
+        // Anti-aliased at the inner and the outer edge, silence leaves a
+        // circle one pixel wide like the baseline of the other modes
+        let coverage = (radius - inner + 1.0).clamp(0.0, 1.0).min((extent - radius + 1.0).clamp(0.0, 1.0));
+        blend(options.background, options.foreground, coverage)
+    })
+}
+
+/// The covered samples cut into equal stretches of whole frames, each drawn
+/// in the minmax mode at the output width into one row of the image, from
+/// the top to the bottom. All rows share the same gain and a linear time
+/// scale, so levels and durations compare across them
+fn render_filmstrip(samples: &[f32], samples_per_pixel: f64, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let channels = options.channels.max(1);
+    let rows = options.filmstrip_rows.clamp(1, options.height.max(1));
+    let covered = ((samples_per_pixel * (options.width * options.oversample) as f64).round() as usize).min(samples.len());
+    let frames = covered / channels;
+    let row_options = RenderOptions {
+        mode: RenderMode::MinMax,
+        normalize: false,
+        scale_factor: Some(gain),
+        time_scale: TimeScale::Linear,
+        ..options.clone()
+    };
+
+    let mut img = RgbaImage::new(options.width, options.height);
+    for row in 0..rows {
+        let (top, bottom) = (row * options.height / rows, (row + 1) * options.height / rows);
+        let (start, end) = (row as usize * frames / rows as usize, (row + 1) as usize * frames / rows as usize);
+        let row_options = RenderOptions { height: bottom - top, ..row_options.clone() };
+        let strip = render_waveform(&samples[start * channels..end * channels], samples_per_pixel / rows as f64, &row_options);
+        image::imageops::replace(&mut img, &strip, 0, top as i64);
+    }
+    img
+}
+
+/// The first two channels plotted against each other like on a goniometer:
+/// mono content forms a vertical line, content out of phase a horizontal one
+/// and wide stereo a round cloud. Left leans to the upper left, right to the
+/// upper right. Every pixel is colored by the number of frames falling on it
+/// (on a logarithmic scale), so dense areas stand out. Mono input is treated
+/// as identical channels
+fn render_vectorscope(samples: &[f32], gain: f64, options: &RenderOptions) -> RgbaImage {
+    let (width, height) = (options.width, options.height);
+    let channels = options.channels.max(1);
+    let scale = width.min(height) as f64 / 2.0;
+    let center: Point = (width as f64 / 2.0, height as f64 / 2.0);
+    let mut hits = vec![0u32; width as usize * height as usize];
+    for frame in samples.chunks_exact(channels) {
+        let left = frame[0] as f64 * gain;
+        let right = frame.get(1).map_or(left, |&s| s as f64 * gain);
+        // Rotated by 45°, full scale on both channels reaches the edge
+        let (side, mid) = ((right - left) / 2.0, (left + right) / 2.0);
+        let (x, y) = ((center.0 + side * scale).floor(), (center.1 - mid * scale).floor());
+        if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+            hits[y as usize * width as usize + x as usize] += 1;
+        }
+    }
+    let most = (*hits.iter().max().unwrap_or(&0) as f64).ln_1p().max(f64::MIN_POSITIVE);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let density = (hits[y as usize * width as usize + x as usize] as f64).ln_1p() / most;
+        options.dither.blend(options.background, options.foreground, density, x, y)
+    })
+}
+
+/// Positive and negative excursions drawn above and below the baseline in
+/// different colors, without mirroring one onto the other
+fn render_asymmetry(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = axis.columns;
+    let height = options.height;
+    let center = options.baseline_y();
+    let extremes = smoothed_extremes(samples, axis, options);
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (min, max) = extremes[time_column(x, width, options.time_direction) as usize].unwrap_or((0.0, 0.0));
+        let top = options.sample_y(max.max(0.0) as f64 * gain);
+        let bottom = options.sample_y(min.min(0.0) as f64 * gain);
+        let y = y as f64 + 0.5;
+        if y >= top && y < center {
+            options.foreground
+        } else if y >= center && y < bottom {
+            options.negative
+        } else {
+            options.background
+        }
+    })
+}
+
+/// Polylines through the highest and through the lowest sample of every
+/// column. Rendered at the oversampled width, so distances are measured in
+/// output pixels with the horizontal coordinates divided by the oversampling
+fn render_line(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = axis.columns;
+    let height = options.height;
+    let oversample = options.oversample.max(1) as f64;
+    let extremes = smoothed_extremes(samples, axis, options);
+    let stroke = options.stroke_width.max(0.0);
+
+    // Upper and lower point of every column in output pixels, in image order
+    let points: Vec<Option<(Point, Point)>> = (0..width)
+        .map(|x| {
+            extremes[time_column(x, width, options.time_direction) as usize].map(|(min, max)| {
+                let x = (x as f64 + 0.5) / oversample;
+                ((x, options.sample_y(max as f64 * gain)), (x, options.sample_y(min as f64 * gain)))
+            })
+        })
+        .collect();
+    // Columns on either side whose segments can reach a pixel
+    let reach = ((stroke / 2.0 + 1.0) * oversample).ceil() as i64;
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let p = ((x as f64 + 0.5) / oversample, y as f64 + 0.5);
+        let mut coverage = 0.0f64;
+        let first = (x as i64 - reach).max(0) as usize;
+        let last = ((x as i64 + reach) as usize).min(width as usize - 1);
+        for i in first..=last {
+            let Some((upper, lower)) = points[i] else { continue };
+            // A lone column is drawn as a dot
+            let (next_upper, next_lower) = points.get(i + 1).copied().flatten().unwrap_or((upper, lower));
+            let distance = distance_to_segment(p, upper, next_upper).min(distance_to_segment(p, lower, next_lower));
+            coverage = coverage.max(stroke_coverage(distance, stroke));
+        }
+        blend(options.background, options.foreground, coverage)
+    })
+}
+
+/// Distance between the centers of neighbouring dots in radii
+const DOT_SPACING: f64 = 3.0;
+
+/// Dots at the highest and the lowest sample of every slice of time, the
+/// slices being as wide as the distance between the dots. Rendered at the
+/// oversampled width like the line mode
+fn render_dots(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = axis.columns;
+    let height = options.height;
+    let oversample = options.oversample.max(1) as f64;
+    let radius = options.dot_radius.max(0.1);
+    // Internal columns per slice
+    let slice = (DOT_SPACING * radius * oversample).max(1.0);
+    let slices = (width as f64 / slice).ceil() as usize;
+
+    let dots: Vec<Option<(Point, Point)>> = (0..slices).into_par_iter()
+        .map(|i| {
+            let first = (i as f64 * slice).round() as u32;
+            let last = (((i + 1) as f64 * slice).round() as u32).min(width);
+            if first >= last {
+                return None;
+            }
+            let start = axis.range(first, samples.len()).start;
+            let end = axis.range(last - 1, samples.len()).end;
+            let bucket = &samples[start..end];
+            (!bucket.is_empty()).then(|| {
+                let (min, max) = bucket.iter().fold((f32::MAX, f32::MIN), |(min, max), &s| (min.min(s), max.max(s)));
+                let x = (first + last) as f64 / 2.0 / oversample;
+                ((x, options.sample_y(max as f64 * gain)), (x, options.sample_y(min as f64 * gain)))
+            })
+        })
+        .collect();
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let column = time_column(x, width, options.time_direction);
+        let p = ((column as f64 + 0.5) / oversample, y as f64 + 0.5);
+        // Only the slice of the pixel and its neighbours can reach it
+        let nearest = (column as f64 / slice) as usize;
+        let coverage = (nearest.saturating_sub(1)..(nearest + 2).min(slices))
+            .filter_map(|i| dots[i])
+            .flat_map(|(upper, lower)| [upper, lower])
+            .map(|center| stroke_coverage(distance_to_segment(p, center, center), 2.0 * radius))
+            .fold(0.0f64, f64::max);
+        blend(options.background, options.foreground, coverage)
+    })
+}
+
+/// Vertical subsamples per pixel when computing the coverage of bars
+const BAR_SUBSAMPLES: u32 = 4;
+
+/// Rounded bars around the baseline, each showing the peak of the slice of
+/// time below it and its gap. Rendered at the oversampled width, so the
+/// horizontal coordinates are stretched by the oversampling factor
+fn render_bars(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let width = axis.columns;
+    let height = options.height;
+    let oversample = options.oversample.max(1);
+    let bar_width = options.bar_width.max(1);
+    let slot = (bar_width + options.bar_gap) * oversample;
+    // The first bar is cut off by the shift
+    let shift = ((options.bar_phase.rem_euclid(1.0) * slot as f64) as u32).min(slot - 1);
+    let bars = (width + shift).div_ceil(slot);
+
+    let peaks: Vec<Option<f64>> = (0..bars).into_par_iter()
+        .map(|i| {
+            let first = axis.range((i * slot).saturating_sub(shift), samples.len()).start;
+            let last = axis.range(((i + 1) * slot - shift).min(width) - 1, samples.len()).end;
+            let bucket = &samples[first..last];
+            (!bucket.is_empty()).then(|| bucket.iter().fold(0.0f64, |a, &s| a.max((s as f64 * gain).abs())))
+        })
+        .collect();
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let column = time_column(x, width, options.time_direction) + shift;
+        let (bar, offset) = (column / slot, column % slot);
+        let Some(peak) = peaks[bar as usize] else {
+            return options.background;
+        };
+        if offset >= bar_width * oversample {
+            return options.background;
+        }
+        // At least a round dot for silent slices
+        let (top, bottom) = (options.sample_y(peak), options.sample_y(-peak));
+        let center = (top + bottom) / 2.0;
+        let half = ((bottom - top) / 2.0).max(bar_width as f64 / 2.0);
+        let (top, bottom) = (center - half, center + half);
+        let radius = bar_width as f64 / 2.0;
+        // Horizontal distance to the bar's edge in output pixels
+        let u = (offset as f64 + 0.5) / oversample as f64;
+        let dx = (radius - u).max(u - (bar_width as f64 - radius)).max(0.0);
+        let covered = (0..BAR_SUBSAMPLES)
+            .filter(|&i| {
+                let v = y as f64 + (i as f64 + 0.5) / BAR_SUBSAMPLES as f64;
+                let dy = (top + radius - v).max(v - (bottom - radius)).max(0.0);
+                v >= top && v <= bottom && dx * dx + dy * dy <= radius * radius
+            })
+            .count();
+        blend(options.background, options.foreground, covered as f64 / BAR_SUBSAMPLES as f64)
+    })
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::raster::Rasterizer;
+    use crate::render::{parse_baseline, parse_inner_radius, parse_oversample, render_aggregated, render_bytes, render_waveform, ColumnValue, Oversample, RenderMode, RenderOptions, TimeAxis, TimeScale};
+
+    #[test]
+    fn log_axis_covers_all_samples() {
+        let linear = TimeAxis::linear(100, 10.0);
+        let log = TimeAxis { scale: TimeScale::Log, ..linear };
+        assert_eq!(linear.range(0, 1000), 0..10);
+        assert_eq!(log.range(0, 1000).start, 0);
+        assert_eq!(log.range(99, 1000).end, 1000);
+        // Early columns cover less time than late ones
+        assert!(log.range(0, 1000).len() < log.range(99, 1000).len());
+        for x in 0..99 {
+            assert_eq!(log.range(x, 1000).end, log.range(x + 1, 1000).start);
+        }
+    }
+
+    #[test]
+    fn auto_oversampling() {
+        assert_eq!(parse_oversample("AUTO"), Ok(Oversample::Auto));
+        assert_eq!(parse_oversample("8"), Ok(Oversample::Factor(8)));
+        assert!(parse_oversample("0").is_err());
+        let auto = Oversample::Auto;
+        // Short clips keep enough frames in every column
+        assert_eq!(auto.factor(56.0, 1000, 100, RenderMode::Line), 3);
+        assert_eq!(auto.factor(0.2, 1000, 100, RenderMode::Line), 1);
+        // Long ones are capped by the mode and by the size of the image
+        assert_eq!(auto.factor(10000.0, 1000, 100, RenderMode::Line), 32);
+        assert_eq!(auto.factor(10000.0, 1000, 100, RenderMode::Spectrogram), 4);
+        assert_eq!(auto.factor(10000.0, 20000, 1000, RenderMode::Line), 3);
+        assert_eq!(Oversample::Factor(64).factor(1.0, 1000, 100, RenderMode::Bars), 64);
+        // Filled spans are never oversampled
+        assert_eq!(auto.factor(10000.0, 1000, 100, RenderMode::MinMax), 1);
+        assert_eq!(Oversample::Factor(8).factor(10000.0, 1000, 100, RenderMode::Asymmetry), 1);
+    }
+
+    #[test]
+    fn malformed_bytes_are_an_error() {
+        let options = RenderOptions::new(RenderMode::Mean);
+        assert!(render_bytes(b"", &options).is_err());
+        assert!(render_bytes(b"RIFF\x10\x00\x00\x00WAVEfmt garbage", &options).is_err());
+        // A truncated but otherwise valid WAV header followed by one frame
+        let mut wav = b"RIFF\x28\x00\x00\x00WAVEfmt \x10\x00\x00\x00\x01\x00\x01\x00\x40\x1f\x00\x00\x80\x3e\x00\x00\x02\x00\x10\x00data\x04\x00\x00\x00".to_vec();
+        wav.extend_from_slice(&[0x00, 0x40]);
+        let img = render_bytes(&wav, &options).unwrap();
+        assert_eq!(img.dimensions(), (options.width, options.height));
+    }
+
+    #[test]
+    fn baselines() {
+        let mut random = crate::testing::random(0x2545F4914F6CDD1D);
+        let mut next = move || random.next().unwrap();
+        let junk: Vec<char> = "0123456789.,-%e ".chars().collect();
+        for _ in 0..10000 {
+            // Percentages and fractions of the same baseline agree
+            let percent = next() % 10001;
+            let fraction = percent as f64 / 10000.0;
+            let from_percent = parse_baseline(&format!("{}%", percent as f64 / 100.0)).unwrap();
+            assert!((from_percent - fraction).abs() < 1e-12);
+            assert!((parse_baseline(&format!(" {fraction} ")).unwrap() - fraction).abs() < 1e-12);
+            // Nothing outside the image is accepted
+            let outside = 1.0 + (next() % 10000 + 1) as f64 / 100.0;
+            assert!(parse_baseline(&format!("{outside}")).is_err());
+            assert!(parse_baseline(&format!("-{fraction}%")).map_or(true, |v| v == 0.0));
+            // Arbitrary input never panics and never leaves the image
+            let length = next() % 10;
+            let text: String = (0..length).map(|_| junk[next() as usize % junk.len()]).collect();
+            assert!(parse_baseline(&text).map_or(true, |v| (0.0..=1.0).contains(&v)), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn custom_aggregation() {
+        // Share of positive samples, which no built-in mode draws
+        let positive = |bucket: &[f32]| ColumnValue::symmetric(bucket.iter().filter(|&&s| s > 0.0).count() as f32 / bucket.len() as f32);
+        let samples: Vec<f32> = (0..400).map(|i| if i < 200 || i % 2 == 0 { 1.0 } else { -1.0 }).collect();
+        let options = RenderOptions { width: 4, height: 20, oversample: 1, normalize: false, ..RenderOptions::new(RenderMode::MinMax) };
+        let img = render_aggregated(&samples, 100.0, &options, positive);
+        let filled = |x: u32| (0..20).filter(|&y| img.get_pixel(x, y)[3] > 128).count();
+        assert_eq!((filled(0), filled(1)), (20, 20));
+        assert!((9..=11).contains(&filled(3)), "{}", filled(3));
+    }
+
+    #[test]
+    fn radial_grows_outwards() {
+        assert_eq!(parse_inner_radius("40%"), Ok(0.4));
+        assert!(parse_inner_radius("1.0").is_err());
+        // Full scale in the first half, silence in the second
+        let samples: Vec<f32> = (0..400).map(|i| if i < 200 { 1.0 - 2.0 * (i % 2) as f32 } else { 0.0 }).collect();
+        let options = RenderOptions { width: 41, height: 41, oversample: 1, normalize: false, inner_radius: 0.5, ..RenderOptions::new(RenderMode::Radial) };
+        let img = render_waveform(&samples, 10.0, &options);
+        assert_eq!(img.dimensions(), (41, 41));
+        let ink = |x: u32, y: u32| img.get_pixel(x, y)[3] > 128;
+        // Clockwise from the top the right half is loud and the left silent,
+        // leaving only the inner circle
+        assert!(!ink(20, 20));
+        assert!(ink(35, 20) && ink(38, 20));
+        assert!(ink(10, 20) && !ink(8, 20));
+    }
+
+    #[test]
+    fn vectorscope_shows_correlation() {
+        let tone = |i: usize| (i as f32 * 0.05).sin() * 0.8;
+        let stereo = |right: &dyn Fn(usize) -> f32| -> Vec<f32> { (0..4000).flat_map(|i| [tone(i), right(i)]).collect() };
+        let options = RenderOptions { width: 41, height: 41, channels: 2, normalize: false, ..RenderOptions::new(RenderMode::Vectorscope) };
+        let ink = |img: &image::RgbaImage| -> Vec<(u32, u32)> {
+            img.enumerate_pixels().filter(|(_, _, p)| p[3] > 128).map(|(x, y, _)| (x, y)).collect()
+        };
+        // Identical channels stay on the vertical center line
+        let mono = ink(&render_waveform(&stereo(&tone), 100.0, &options));
+        assert!(mono.len() > 20 && mono.iter().all(|&(x, _)| x == 20));
+        // Inverted channels stay on the horizontal one
+        let inverted = ink(&render_waveform(&stereo(&|i| -tone(i)), 100.0, &options));
+        assert!(inverted.len() > 20 && inverted.iter().all(|&(_, y)| y == 20));
+    }
+
+    #[test]
+    fn rectified_bands_keep_their_content() {
+        // A tone in the high band, rectified after filtering so its magnitude
+        // doesn't leak into the low band
+        let options = RenderOptions { width: 4, height: 40, oversample: 1, normalize: false, rectify: true, ..RenderOptions::new(RenderMode::Multiband) };
+        let rate = options.sample_rate as f32;
+        let samples: Vec<f32> = (0..8000).map(|i| (std::f32::consts::TAU * 3000.0 * i as f32 / rate).sin() * 0.9).collect();
+        let img = render_waveform(&samples, 2000.0, &options);
+        let [low, _, high] = options.band_colors;
+        assert!((0..40).all(|y| *img.get_pixel(2, y) != low));
+        assert_eq!(*img.get_pixel(2, 39), high);
+        assert_eq!(*img.get_pixel(2, 6), high);
+    }
+
+    #[test]
+    fn density_glows_where_samples_dwell() {
+        // A sine dwells near its peaks and passes the center quickly
+        let samples: Vec<f32> = (0..4000).map(|i| (i as f32 * 0.01).sin()).collect();
+        let options = RenderOptions { width: 4, height: 40, oversample: 1, normalize: false, ..RenderOptions::new(RenderMode::Density) };
+        let img = render_waveform(&samples, 1000.0, &options);
+        let alpha = |y: u32| img.get_pixel(1, y)[3];
+        assert!(alpha(0) > alpha(20) && alpha(39) > alpha(20));
+        assert!(alpha(20) > 0);
+    }
+
+    #[test]
+    fn rms_alpha_fades_sparse_columns() {
+        // A full scale square wave, then single full scale clicks in silence
+        let samples: Vec<f32> = (0..2000)
+            .map(|i| if i < 1000 { if i % 2 == 0 { 1.0 } else { -1.0 } } else if i % 100 == 0 { 1.0 } else { 0.0 })
+            .collect();
+        let options = RenderOptions { width: 2, height: 20, oversample: 1, normalize: false, rms_alpha: true, ..RenderOptions::new(RenderMode::MinMax) };
+        let img = render_waveform(&samples, 1000.0, &options);
+        // Both columns reach the top edge, only the square wave is opaque
+        assert_eq!(img.get_pixel(0, 1)[3], 255);
+        let faint = img.get_pixel(1, 1)[3];
+        assert!(faint > 0 && faint < 64, "{faint}");
+    }
+
+    #[test]
+    fn filmstrip_rows_follow_each_other() {
+        // Silence with a full scale square wave in the third of four quarters
+        let samples: Vec<f32> = (0..4000).map(|i| if (2000..3000).contains(&i) { if i % 2 == 0 { 1.0 } else { -1.0 } } else { 0.0 }).collect();
+        let options = RenderOptions { width: 10, height: 40, oversample: 1, normalize: false, filmstrip_rows: 4, ..RenderOptions::new(RenderMode::Filmstrip) };
+        let img = render_waveform(&samples, 400.0, &options);
+        // Only the third row of ten pixels is filled from edge to edge
+        let inked = |y: u32| (0..10).all(|x| img.get_pixel(x, y)[3] == 255);
+        assert!((20..30).all(inked));
+        assert!(!inked(1) && !inked(11) && !inked(31));
+    }
+
+    #[test]
+    fn decimation_keeps_the_peaks() {
+        // A sine rising in level, the columns of 102400 frames split into
+        // blocks of 1600
+        let samples: Vec<f32> = (0..2_048_000).map(|i| (i as f32 / 2e6) * (i as f32 * 0.05).sin()).collect();
+        let exact = RenderOptions { width: 20, height: 40, oversample: 1, normalize: false, decimate: false, ..RenderOptions::new(RenderMode::MinMax) };
+        let decimated = RenderOptions { decimate: true, ..exact.clone() };
+        assert_eq!(render_waveform(&samples, 102_400.0, &exact), render_waveform(&samples, 102_400.0, &decimated));
+    }
+
+    #[test]
+    fn analytic_edges_cover_part_of_a_pixel() {
+        // A square wave between +0.5 and -0.5 spans rows 2.5 to 7.5
+        let samples: Vec<f32> = (0..80).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        let options = RenderOptions { width: 4, height: 10, oversample: 4, normalize: false, rasterizer: Rasterizer::Analytic, ..RenderOptions::new(RenderMode::MinMax) };
+        let img = render_waveform(&samples, 5.0, &options);
+        assert_eq!(img.dimensions(), (4, 10));
+        let alpha: Vec<u8> = (0..10).map(|y| img.get_pixel(1, y)[3]).collect();
+        assert_eq!(alpha, vec![0, 0, 128, 255, 255, 255, 255, 128, 0, 0]);
+
+        // A single sample keeps the full strength of its span
+        let mut click = vec![0.0; 80];
+        click[30] = 0.5;
+        let img = render_waveform(&click, 5.0, &options);
+        let alpha: Vec<u8> = (0..10).map(|y| img.get_pixel(1, y)[3]).collect();
+        assert_eq!(alpha, vec![0, 0, 128, 255, 255, 0, 0, 0, 0, 0]);
+    }
+}
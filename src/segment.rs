@@ -0,0 +1,182 @@
+//! Simple energy and zero crossing rate based speech/music/silence
+//! segmentation. No training data or models involved, so expect it to be
+//! a rough guide for skimming recordings rather than a precise classifier.
+
+
+/// Length of the short analysis frames in seconds
+const FRAME_DURATION: f64 = 0.02;
+
+/// Length of the windows that get classified in seconds
+pub const WINDOW_DURATION: f64 = 1.0;
+
+/// RMS below which a window is considered silent (roughly -45 dBFS)
+const SILENCE_THRESHOLD: f32 = 0.0056;
+
+/// Share of low energy frames above which a window is considered speech
+const SPEECH_LOW_ENERGY_RATIO: f32 = 0.3;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Silence,
+    Speech,
+    Music,
+}
+
+/// A continuous region of the same segment kind, times in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub start: f64,
+    pub end: f64,
+    pub kind: Segment,
+}
+
+/// Classify the interleaved `samples` and return merged regions
+pub fn segment(samples: &[f32], channels: usize, sample_rate: u32) -> Vec<Region> {
+    let channels = channels.max(1);
+    let frame_length = ((FRAME_DURATION * sample_rate as f64) as usize).max(1);
+    let frames_per_window = (WINDOW_DURATION / FRAME_DURATION).round() as usize;
+
+    // Downmix to mono and compute energy and zero crossings per frame
+    let mono: Vec<f32> = samples.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    let frames: Vec<(f32, f32)> = mono.chunks(frame_length)
+        .map(|frame| {
+            let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt();
+            let crossings = frame.windows(2).filter(|w| (w[0] < 0.0) != (w[1] < 0.0)).count();
+            (rms, crossings as f32 / frame.len() as f32)
+        })
+        .collect();
+
+    let mut regions: Vec<Region> = vec![];
+    for (i, window) in frames.chunks(frames_per_window).enumerate() {
+        let kind = classify(window);
+        let start = i as f64 * WINDOW_DURATION;
+        let end = start + window.len() as f64 * FRAME_DURATION;
+        match regions.last_mut() {
+            Some(last) if last.kind == kind => last.end = end,
+            _ => regions.push(Region { start, end, kind }),
+        }
+    }
+    regions
+}
+
+/// Classify one window of (rms, zero crossing rate) frames
+fn classify(frames: &[(f32, f32)]) -> Segment {
+    let count = frames.len() as f32;
+    let mean_energy = frames.iter().map(|f| f.0).sum::<f32>() / count;
+    if mean_energy < SILENCE_THRESHOLD {
+        return Segment::Silence;
+    }
+
+    // Speech alternates between voiced, unvoiced and short pauses, which shows
+    // up as many low energy frames and a strongly varying zero crossing rate
+    let low_energy_ratio = frames.iter().filter(|f| f.0 < 0.5 * mean_energy).count() as f32 / count;
+    let mean_zcr = frames.iter().map(|f| f.1).sum::<f32>() / count;
+    let zcr_deviation = (frames.iter().map(|f| (f.1 - mean_zcr).powi(2)).sum::<f32>() / count).sqrt();
+
+    if low_energy_ratio > SPEECH_LOW_ENERGY_RATIO || zcr_deviation > mean_zcr {
+        Segment::Speech
+    } else {
+        Segment::Music
+    }
+}
+
+/// Kind of the region at the given time, silence if outside of all regions
+pub fn kind_at(regions: &[Region], time: f64) -> Segment {
+    regions.iter()
+        .find(|r| time >= r.start && time < r.end)
+        .map(|r| r.kind)
+        .unwrap_or(Segment::Silence)
+}
+
+/// Why a chapter boundary was suggested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterReason {
+    Start,
+    Pause,
+    Music,
+}
+
+impl ChapterReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChapterReason::Start => "start",
+            ChapterReason::Pause => "pause",
+            ChapterReason::Music => "music",
+        }
+    }
+}
+
+/// Suggest chapter starts after pauses of at least `min_pause` seconds and at
+/// the start of music beds. Suggestions closer than `min_chapter` seconds to
+/// the previous one are dropped.
+pub fn suggest_chapters(regions: &[Region], min_pause: f64, min_chapter: f64) -> Vec<(f64, ChapterReason)> {
+    let mut chapters = vec![(0.0, ChapterReason::Start)];
+    for region in regions {
+        let suggestion = match region.kind {
+            Segment::Silence if region.end - region.start >= min_pause => (region.end, ChapterReason::Pause),
+            Segment::Music => (region.start, ChapterReason::Music),
+            _ => continue,
+        };
+        let last = chapters.last().unwrap().0;
+        if suggestion.0 - last >= min_chapter {
+            chapters.push(suggestion);
+        }
+    }
+    // A trailing pause at the very end is not the start of anything
+    let end = regions.last().map(|r| r.end).unwrap_or(0.0);
+    chapters.retain(|c| c.0 == 0.0 || c.0 < end);
+    chapters
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::segment::{kind_at, segment, suggest_chapters, ChapterReason, Region, Segment};
+
+    #[test]
+    fn chapters_after_pauses_and_at_music() {
+        let region = |start, end, kind| Region { start, end, kind };
+        let regions = [
+            region(0.0, 40.0, Segment::Speech),
+            region(40.0, 43.0, Segment::Silence),
+            region(43.0, 50.0, Segment::Speech),
+            region(50.0, 51.0, Segment::Silence),
+            region(51.0, 90.0, Segment::Speech),
+            region(90.0, 130.0, Segment::Music),
+            region(130.0, 135.0, Segment::Silence),
+        ];
+        let chapters = suggest_chapters(&regions, 2.0, 30.0);
+        assert_eq!(chapters, vec![
+            (0.0, ChapterReason::Start),
+            (43.0, ChapterReason::Pause),
+            (90.0, ChapterReason::Music),
+        ]);
+    }
+    #[test]
+    fn silence_music_and_speech() {
+        // Two seconds each of silence, a steady tone and a tone that keeps
+        // pausing like syllables, in stereo
+        let sample_rate = 8000;
+        let tone = |i: usize| 0.5 * (i as f32 * 440.0 * std::f32::consts::TAU / sample_rate as f32).sin();
+        let mono: Vec<f32> = (0..6 * sample_rate)
+            .map(|i| match i / (2 * sample_rate) {
+                0 => 0.0,
+                1 => tone(i),
+                _ if i % 1600 < 800 => tone(i),
+                _ => 0.0,
+            })
+            .collect();
+        let stereo: Vec<f32> = mono.iter().flat_map(|&s| [s, s]).collect();
+        let regions = segment(&stereo, 2, sample_rate as u32);
+        let kinds: Vec<Segment> = regions.iter().map(|r| r.kind).collect();
+        assert_eq!(kinds, [Segment::Silence, Segment::Music, Segment::Speech]);
+        assert!((regions[1].start - 2.0).abs() < 1e-9 && (regions[2].end - 6.0).abs() < 1e-9);
+        assert_eq!(kind_at(&regions, 3.0), Segment::Music);
+        assert_eq!(kind_at(&regions, 7.0), Segment::Silence);
+    }
+}
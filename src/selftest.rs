@@ -0,0 +1,314 @@
+//! Renders of synthetic signals with known shapes (silence, DC, square,
+//! sine, impulses) and checks of their pixels, so rendering can be verified
+//! after changes with `--selftest` and in the unit tests
+use std::f64::consts::PI;
+use colored::Colorize;
+use image::{Rgba, RgbaImage};
+
+use crate::render::{render_waveform, Layout, RenderMode, RenderOptions, TimeDirection};
+
+
+const FOREGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 0]);
+const NEGATIVE: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const WIDTH: u32 = 100;
+const HEIGHT: u32 = 40;
+const SAMPLE_RATE: u32 = 8000;
+/// Samples per column of the test renders
+const SAMPLES_PER_COLUMN: usize = 80;
+
+/// One named check, returning a description of what went wrong on failure
+pub struct Check {
+    pub name: &'static str,
+    pub run: fn() -> Result<(), String>,
+}
+
+/// Options of the test renders
+fn options(mode: RenderMode) -> RenderOptions {
+    RenderOptions {
+        width: WIDTH,
+        height: HEIGHT,
+        sample_rate: SAMPLE_RATE,
+        foreground: FOREGROUND,
+        background: BACKGROUND,
+        negative: NEGATIVE,
+        ..RenderOptions::new(mode)
+    }
+}
+
+/// Render one sample per `SAMPLES_PER_COLUMN` of `signal(i)` over all columns
+fn render(options: &RenderOptions, signal: impl Fn(usize) -> f32) -> RgbaImage {
+    let samples: Vec<f32> = (0..WIDTH as usize * SAMPLES_PER_COLUMN).map(signal).collect();
+    render_waveform(&samples, SAMPLES_PER_COLUMN as f64, options)
+}
+
+/// Number of pixels in column `x` that are (mostly) `color`
+fn count(img: &RgbaImage, x: u32, color: Rgba<u8>) -> u32 {
+    let close = |p: &Rgba<u8>| (0..4).all(|i| (p[i] as i32 - color[i] as i32).abs() < 64);
+    (0..img.height()).filter(|&y| close(img.get_pixel(x, y))).count() as u32
+}
+
+/// Fail with `message` unless `condition` holds
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+    if condition { Ok(()) } else { Err(message()) }
+}
+
+fn silence_is_empty() -> Result<(), String> {
+    let img = render(&options(RenderMode::Mean), |_| 0.0);
+    let drawn: u32 = (0..WIDTH).map(|x| count(&img, x, FOREGROUND)).sum();
+    ensure(drawn == 0, || format!("{drawn} pixels drawn for silence"))
+}
+
+fn dc_has_constant_height() -> Result<(), String> {
+    // The mean mode draws the rectified half, so a quarter of full scale
+    // reaches half the height
+    let img = render(&options(RenderMode::Mean), |_| 0.25);
+    for x in 0..WIDTH {
+        let height = count(&img, x, FOREGROUND);
+        ensure(height == HEIGHT / 2, || format!("column {x} is {height} px high instead of {}", HEIGHT / 2))?;
+    }
+    Ok(())
+}
+
+fn centered_mean_splits_at_baseline() -> Result<(), String> {
+    // Square between +0.5 and -0.5 with the baseline at a quarter of the
+    // height: each half of the square has a mean of a quarter over the
+    // column, which fills half of the space on its side
+    let options = RenderOptions { layout: Layout::Centered, baseline: 0.25, ..options(RenderMode::Mean) };
+    let img = render(&options, |i| if i % 16 < 8 { 0.5 } else { -0.5 });
+    let base = HEIGHT / 4;
+    for x in 0..WIDTH {
+        let above = (0..base).filter(|&y| img.get_pixel(x, y) == &FOREGROUND).count() as u32;
+        let below = (base..HEIGHT).filter(|&y| img.get_pixel(x, y) == &FOREGROUND).count() as u32;
+        ensure(above == base / 2 && below == (HEIGHT - base) / 2, || format!("column {x} has {above} px above and {below} px below the baseline"))?;
+    }
+    Ok(())
+}
+
+fn min_max_spans_extremes() -> Result<(), String> {
+    // Asymmetric square between -0.5 and +1.0 spans three quarters of the height
+    let img = render(&options(RenderMode::MinMax), |i| if i % 16 < 8 { 1.0 } else { -0.5 });
+    for x in 0..WIDTH {
+        let height = count(&img, x, FOREGROUND);
+        ensure(height == HEIGHT * 3 / 4, || format!("column {x} is {height} px high instead of {}", HEIGHT * 3 / 4))?;
+    }
+    Ok(())
+}
+
+fn min_max_keeps_transients() -> Result<(), String> {
+    let position = 25 * SAMPLES_PER_COLUMN + SAMPLES_PER_COLUMN / 2;
+    let img = render(&options(RenderMode::MinMax), |i| if i == position { 1.0 } else { 0.0 });
+    let height = count(&img, 25, FOREGROUND);
+    // The impulse reaches the top edge from the baseline
+    ensure(height >= HEIGHT / 2, || format!("impulse is {height} px high instead of at least {}", HEIGHT / 2))
+}
+
+fn oversampled_peaks_keep_their_strength() -> Result<(), String> {
+    // Oversampling must not average the span of a column with the columns
+    // around it: an impulse and a steady full scale tone stay solid
+    let options = RenderOptions { oversample: 4, ..options(RenderMode::MinMax) };
+    let oversampled = |signal: &dyn Fn(usize) -> f32| {
+        let samples: Vec<f32> = (0..WIDTH as usize * SAMPLES_PER_COLUMN).map(signal).collect();
+        render_waveform(&samples, SAMPLES_PER_COLUMN as f64 / 4.0, &options)
+    };
+    let position = 25 * SAMPLES_PER_COLUMN + SAMPLES_PER_COLUMN / 2;
+    let img = oversampled(&|i| if i == position { 1.0 } else { 0.0 });
+    let height = count(&img, 25, FOREGROUND);
+    ensure(height >= HEIGHT / 2, || format!("impulse is {height} px high instead of at least {}", HEIGHT / 2))?;
+    let img = oversampled(&|i| (2.0 * PI * i as f64 * 440.0 / SAMPLE_RATE as f64).sin() as f32);
+    for x in 0..WIDTH {
+        let height = count(&img, x, FOREGROUND);
+        ensure(height == HEIGHT, || format!("column {x} of a full scale tone is {height} px high instead of {HEIGHT}"))?;
+    }
+    Ok(())
+}
+
+fn rms_is_drawn_inside_peaks() -> Result<(), String> {
+    // A full scale sine has an RMS of 1/√2, leaving the peaks above and below.
+    // The rows the RMS band only touches are drawn as well
+    let options = RenderOptions { rms: Some(NEGATIVE), ..options(RenderMode::MinMax) };
+    let img = render(&options, |i| (2.0 * PI * i as f64 / 20.0).sin() as f32);
+    let expected = (HEIGHT as f64 * std::f64::consts::FRAC_1_SQRT_2).round() as u32;
+    for x in 0..WIDTH {
+        let (rms, peaks) = (count(&img, x, NEGATIVE), count(&img, x, FOREGROUND));
+        ensure(rms.abs_diff(expected) <= 2 && rms + peaks == HEIGHT, || format!("column {x} has {rms} px RMS and {peaks} px peaks"))?;
+    }
+    Ok(())
+}
+
+fn rectify_grows_from_the_bottom() -> Result<(), String> {
+    // The magnitude of a half scale sine fills the lower half of the image
+    let options = RenderOptions { rectify: true, ..options(RenderMode::MinMax) };
+    let img = render(&options, |i| (0.5 * (2.0 * PI * i as f64 / 20.0).sin()) as f32);
+    for x in 0..WIDTH {
+        let lower = (HEIGHT / 2..HEIGHT).filter(|&y| img.get_pixel(x, y) == &FOREGROUND).count() as u32;
+        let upper = count(&img, x, FOREGROUND) - lower;
+        ensure(lower == HEIGHT / 2 && upper == 0, || format!("column {x} has {lower} px in the lower and {upper} px in the upper half"))?;
+    }
+    Ok(())
+}
+
+fn line_traces_extremes() -> Result<(), String> {
+    // A sine of half scale gives straight lines at a quarter and three
+    // quarters of the height, each touching two rows, with nothing between
+    let img = render(&options(RenderMode::Line), |i| (0.5 * (2.0 * PI * i as f64 / 20.0).sin()) as f32);
+    for x in 0..WIDTH {
+        let drawn: Vec<u32> = (0..HEIGHT).filter(|&y| img.get_pixel(x, y)[3] > 0).collect();
+        let expected = [HEIGHT / 4 - 1, HEIGHT / 4, HEIGHT * 3 / 4 - 1, HEIGHT * 3 / 4];
+        ensure(drawn == expected, || format!("column {x} has rows {drawn:?} drawn instead of {expected:?}"))?;
+    }
+    Ok(())
+}
+
+fn dots_are_spaced() -> Result<(), String> {
+    // Dots of 1.5 px radius are 4.5 px apart, so the columns between them
+    // stay empty and every dot is drawn above and below the center
+    let img = render(&options(RenderMode::Dots), |i| (0.5 * (2.0 * PI * i as f64 / 20.0).sin()) as f32);
+    let drawn = drawn_columns(&img);
+    ensure(drawn.len() < WIDTH as usize * 9 / 10, || format!("{} of {WIDTH} columns drawn", drawn.len()))?;
+    let x = drawn[drawn.len() / 2];
+    let rows: Vec<u32> = (0..HEIGHT).filter(|&y| img.get_pixel(x, y)[3] > 0).collect();
+    ensure(rows.iter().all(|y| y.abs_diff(HEIGHT / 4) <= 2 || y.abs_diff(HEIGHT * 3 / 4) <= 2), || format!("column {x} has rows {rows:?} drawn"))
+}
+
+fn bars_leave_gaps() -> Result<(), String> {
+    // Bars of 3 px with gaps of 2 px, a square of half scale makes them
+    // half as high as the image
+    let img = render(&options(RenderMode::Bars), |i| if i % 16 < 8 { 0.5 } else { -0.5 });
+    for x in 0..WIDTH {
+        let height = count(&img, x, FOREGROUND);
+        match x % 5 {
+            1 => ensure(height.abs_diff(HEIGHT / 2) <= 1, || format!("bar in column {x} is {height} px high instead of {}", HEIGHT / 2))?,
+            3 | 4 => ensure(height == 0, || format!("gap in column {x} has {height} px drawn"))?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn envelope_swells_and_decays() -> Result<(), String> {
+    // A full scale burst over columns 20 to 39 (200 ms) with the default
+    // release of 200 ms, so the level falls to 1/e after another 20 columns
+    let img = render(&options(RenderMode::Envelope), |i| if (20..40).contains(&(i / SAMPLES_PER_COLUMN)) { 1.0 } else { 0.0 });
+    let height = |x: u32| count(&img, x, FOREGROUND);
+    ensure(height(20) < HEIGHT && height(39) + 2 >= HEIGHT, || format!("burst rises to {} px and reaches {} px", height(20), height(39)))?;
+    let expected = (HEIGHT as f64 / std::f64::consts::E).round() as u32;
+    ensure(height(59).abs_diff(expected) <= 2, || format!("release is at {} px instead of {expected} px after 200 ms", height(59)))?;
+    for x in 40..WIDTH - 1 {
+        ensure(height(x + 1) <= height(x), || format!("envelope rises from column {x} to {} after the burst", x + 1))?;
+    }
+    Ok(())
+}
+
+fn square_fills_both_halves() -> Result<(), String> {
+    let img = render(&options(RenderMode::Asymmetry), |i| if i % 16 < 8 { 1.0 } else { -1.0 });
+    for x in 0..WIDTH {
+        let (top, bottom) = (count(&img, x, FOREGROUND), count(&img, x, NEGATIVE));
+        ensure(top == HEIGHT / 2 && bottom == HEIGHT / 2, || format!("column {x} has {top} px above and {bottom} px below the center"))?;
+    }
+    Ok(())
+}
+
+fn sine_is_symmetric() -> Result<(), String> {
+    let img = render(&options(RenderMode::Asymmetry), |i| (0.5 * (2.0 * PI * i as f64 / 20.0).sin()) as f32);
+    for x in 0..WIDTH {
+        let (top, bottom) = (count(&img, x, FOREGROUND), count(&img, x, NEGATIVE));
+        ensure(top == bottom && top == HEIGHT / 4, || format!("column {x} has {top} px above and {bottom} px below the center"))?;
+    }
+    Ok(())
+}
+
+/// Columns with anything drawn in them
+fn drawn_columns(img: &RgbaImage) -> Vec<u32> {
+    (0..img.width()).filter(|&x| count(img, x, BACKGROUND) < img.height()).collect()
+}
+
+fn impulse_lands_in_its_column() -> Result<(), String> {
+    let position = 25 * SAMPLES_PER_COLUMN + SAMPLES_PER_COLUMN / 2;
+    let img = render(&options(RenderMode::Asymmetry), |i| if i == position { 1.0 } else { 0.0 });
+    let columns = drawn_columns(&img);
+    ensure(columns == vec![25], || format!("impulse drawn in columns {columns:?} instead of [25]"))
+}
+
+fn right_to_left_mirrors() -> Result<(), String> {
+    let position = 25 * SAMPLES_PER_COLUMN + SAMPLES_PER_COLUMN / 2;
+    let options = RenderOptions { time_direction: TimeDirection::Rtl, ..options(RenderMode::Asymmetry) };
+    let img = render(&options, |i| if i == position { 1.0 } else { 0.0 });
+    let columns = drawn_columns(&img);
+    ensure(columns == vec![WIDTH - 1 - 25], || format!("impulse drawn in columns {columns:?} instead of [{}]", WIDTH - 1 - 25))
+}
+
+fn normalize_fills_height() -> Result<(), String> {
+    let options = RenderOptions { normalize: true, ..options(RenderMode::Asymmetry) };
+    let img = render(&options, |i| (0.1 * (2.0 * PI * i as f64 / 20.0).sin()) as f32);
+    let top = (0..WIDTH).map(|x| count(&img, x, FOREGROUND)).max().unwrap_or(0);
+    ensure(top == HEIGHT / 2, || format!("normalized sine reaches {top} px instead of {}", HEIGHT / 2))
+}
+
+fn spectrogram_finds_tone() -> Result<(), String> {
+    // A quarter of the sample rate sits halfway up the frequency axis
+    let img = render(&options(RenderMode::Spectrogram), |i| (0.5 * (PI / 2.0 * i as f64).sin()) as f32);
+    let x = WIDTH / 2;
+    let brightest = (0..HEIGHT).max_by_key(|&y| img.get_pixel(x, y)[3]).unwrap_or(0);
+    let expected = HEIGHT / 2;
+    ensure(brightest.abs_diff(expected) <= 1, || format!("tone found in row {brightest} instead of {expected}"))
+}
+
+/// All rendering checks
+pub fn checks() -> Vec<Check> {
+    vec![
+        Check { name: "silence is empty", run: silence_is_empty },
+        Check { name: "DC has a constant height", run: dc_has_constant_height },
+        Check { name: "centered mean splits at the baseline", run: centered_mean_splits_at_baseline },
+        Check { name: "min/max spans the extremes", run: min_max_spans_extremes },
+        Check { name: "min/max keeps transients", run: min_max_keeps_transients },
+        Check { name: "oversampled peaks keep their strength", run: oversampled_peaks_keep_their_strength },
+        Check { name: "RMS is drawn inside the peaks", run: rms_is_drawn_inside_peaks },
+        Check { name: "rectify grows from the bottom", run: rectify_grows_from_the_bottom },
+        Check { name: "line traces the extremes", run: line_traces_extremes },
+        Check { name: "dots are spaced", run: dots_are_spaced },
+        Check { name: "bars leave gaps", run: bars_leave_gaps },
+        Check { name: "envelope swells and decays", run: envelope_swells_and_decays },
+        Check { name: "square wave fills both halves", run: square_fills_both_halves },
+        Check { name: "sine is symmetric", run: sine_is_symmetric },
+        Check { name: "impulse lands in its column", run: impulse_lands_in_its_column },
+        Check { name: "right to left mirrors time", run: right_to_left_mirrors },
+        Check { name: "normalize fills the height", run: normalize_fills_height },
+        Check { name: "spectrogram finds a tone", run: spectrogram_finds_tone },
+    ]
+}
+
+/// Run all checks, print the results and exit with an error if one failed
+pub fn run() {
+    let mut failed = 0;
+    for check in checks() {
+        match (check.run)() {
+            Ok(()) => println!("{} {}", "ok  ".green(), check.name),
+            Err(e) => {
+                failed += 1;
+                println!("{} {}: {}", "FAIL".bold().red(), check.name, e);
+            },
+        }
+    }
+    if failed > 0 {
+        let error = "Error: ".bold().red();
+        eprintln!("{error}{failed} of {} self tests failed", checks().len());
+        std::process::exit(1);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::selftest::checks;
+
+    #[test]
+    fn rendering_checks_pass() {
+        for check in checks() {
+            if let Err(e) = (check.run)() {
+                panic!("{}: {}", check.name, e);
+            }
+        }
+    }
+}
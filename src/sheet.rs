@@ -0,0 +1,225 @@
+//! Contact sheets: the takes of a folder as a grid of small waveforms with
+//! their file names and durations, all on one time scale so the length of
+//! every take can be compared at a glance
+use std::path::{Path, PathBuf};
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::color::composite;
+use crate::font::{draw_text, ADVANCE, GLYPH_HEIGHT};
+use crate::raster::stroke_coverage;
+
+
+/// Extensions (compared case insensitively) of the files taken from the folder
+const AUDIO_EXTENSIONS: [&str; 9] = ["wav", "wave", "flac", "mp3", "ogg", "oga", "aac", "m4a", "mp4"];
+
+/// Space between the cells and around the grid in pixels
+pub const GAP: u32 = 6;
+
+/// Height of the label under every waveform in pixels
+pub const LABEL_HEIGHT: u32 = GLYPH_HEIGHT + 6;
+
+/// Height of the badge under the label in pixels
+pub const BADGE_HEIGHT: u32 = GLYPH_HEIGHT + 4;
+
+/// Diameter of the dot of a badge in pixels
+const DOT_DIAMETER: f64 = 7.0;
+
+/// Colors of the dots of takes that clip, that are off the target loudness
+/// and that are fine
+const CLIPPING_COLOR: Rgba<u8> = Rgba([220, 50, 47, 255]);
+const OFF_TARGET_COLOR: Rgba<u8> = Rgba([230, 170, 20, 255]);
+const FINE_COLOR: Rgba<u8> = Rgba([60, 170, 80, 255]);
+
+/// Distance from the target loudness in LU up to which a take is on target
+const TARGET_TOLERANCE: f64 = 1.0;
+
+/// Opacity of the foreground over the part of a cell the take doesn't reach
+const UNUSED_OPACITY: f64 = 0.06;
+
+/// Opacity of the foreground for the durations
+const DURATION_OPACITY: f64 = 0.6;
+
+/// A take in the grid
+pub struct Cell {
+    pub name: String,
+    pub duration: String,
+    /// Waveform as wide as the share of the cell the take spans, the
+    /// longest take spanning all of it
+    pub waveform: RgbaImage,
+    pub badge: Option<Badge>,
+}
+
+/// Loudness and clipping of a take, shown under its name as a colored dot
+/// and numbers
+pub struct Badge {
+    /// Integrated loudness in LUFS, None if everything was gated
+    pub loudness: Option<f64>,
+    /// Samples at or beyond full scale
+    pub clipped: usize,
+}
+
+impl Badge {
+    /// Red if the take clips, yellow if it is off the `target` loudness and
+    /// green otherwise
+    fn color(&self, target: Option<f64>) -> Rgba<u8> {
+        let off_target = target.is_some_and(|target| self.loudness.is_none_or(|l| (l - target).abs() > TARGET_TOLERANCE));
+        match (self.clipped > 0, off_target) {
+            (true, _) => CLIPPING_COLOR,
+            (false, true) => OFF_TARGET_COLOR,
+            (false, false) => FINE_COLOR,
+        }
+    }
+
+    fn text(&self) -> String {
+        let loudness = self.loudness.map_or("-inf LUFS".to_string(), |l| format!("{l:.1} LUFS"));
+        match self.clipped {
+            0 => loudness,
+            clipped => format!("{loudness}, {clipped} clipped"),
+        }
+    }
+}
+
+/// Audio files in `directory` sorted by name
+pub fn audio_files(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+            extension.is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.as_str()))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Width of the cells of a grid `width` pixels wide with `columns` cells
+/// in every row
+pub fn cell_width(width: u32, columns: u32) -> u32 {
+    let columns = columns.max(1);
+    width.saturating_sub((columns + 1) * GAP).checked_div(columns).unwrap_or(0).max(1)
+}
+
+/// The `cells` in rows of `columns` cells `cell_width` pixels wide, the
+/// waveforms `height` pixels tall. The badges judge the loudness against the
+/// `target` in LUFS if there is one
+pub fn compose(cells: &[Cell], columns: u32, cell_width: u32, height: u32, target: Option<f64>, foreground: Rgba<u8>, background: Rgba<u8>) -> RgbaImage {
+    let columns = columns.max(1);
+    let cell_width = cell_width.max(1);
+    let rows = (cells.len() as u32).div_ceil(columns).max(1);
+    let label_height = match cells.iter().any(|cell| cell.badge.is_some()) {
+        true => LABEL_HEIGHT + BADGE_HEIGHT,
+        false => LABEL_HEIGHT,
+    };
+    let width = columns * cell_width + (columns + 1) * GAP;
+    let sheet_height = rows * (height + label_height) + (rows + 1) * GAP;
+    let mut sheet = RgbaImage::from_pixel(width, sheet_height, background);
+    let unused = composite(foreground, background, UNUSED_OPACITY);
+    let dimmed = composite(foreground, background, DURATION_OPACITY);
+
+    for (i, cell) in cells.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let left = GAP + column * (cell_width + GAP);
+        let top = GAP + row * (height + label_height + GAP);
+        let right = (left + cell_width) as i64;
+
+        let span = cell.waveform.width().min(cell_width);
+        for x in span..cell_width {
+            for y in 0..height {
+                sheet.put_pixel(left + x, top + y, unused);
+            }
+        }
+        let waveform = imageops::crop_imm(&cell.waveform, 0, 0, span, height).to_image();
+        imageops::overlay(&mut sheet, &waveform, left as i64, top as i64);
+
+        // The duration is right aligned and the name gives way to it
+        let label_top = (top + height + (LABEL_HEIGHT - GLYPH_HEIGHT) / 2) as i64;
+        let duration_left = right - (cell.duration.chars().count() as u32 * ADVANCE) as i64 + 1;
+        draw_text(&mut sheet, duration_left, label_top, &cell.duration, dimmed, right);
+        draw_text(&mut sheet, left as i64, label_top, &cell.name, foreground, duration_left - ADVANCE as i64);
+
+        if let Some(badge) = &cell.badge {
+            let badge_top = label_top + LABEL_HEIGHT as i64;
+            let center = (left as f64 + DOT_DIAMETER / 2.0, badge_top as f64 + GLYPH_HEIGHT as f64 / 2.0);
+            let color = badge.color(target);
+            for x in left..left + DOT_DIAMETER as u32 + 1 {
+                for y in badge_top as u32..badge_top as u32 + GLYPH_HEIGHT + 1 {
+                    let distance = ((x as f64 + 0.5 - center.0).powi(2) + (y as f64 + 0.5 - center.1).powi(2)).sqrt();
+                    let coverage = stroke_coverage(distance, DOT_DIAMETER - 1.0);
+                    if coverage > 0.0 && x < left + cell_width {
+                        let pixel = sheet.get_pixel_mut(x, y);
+                        *pixel = composite(color, *pixel, coverage);
+                    }
+                }
+            }
+            let text_left = left as i64 + DOT_DIAMETER as i64 + ADVANCE as i64 / 2;
+            draw_text(&mut sheet, text_left, badge_top, &badge.text(), dimmed, right);
+        }
+    }
+    sheet
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::sheet::{cell_width, compose, Badge, Cell, BADGE_HEIGHT, CLIPPING_COLOR, FINE_COLOR, GAP, LABEL_HEIGHT, OFF_TARGET_COLOR};
+
+    #[test]
+    fn takes_share_the_time_scale() {
+        let (white, black) = (Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255]));
+        assert_eq!(cell_width(4 * 100 + 5 * GAP, 4), 100);
+
+        let cell = |width: u32| Cell {
+            name: "take".to_string(),
+            duration: "0:01".to_string(),
+            waveform: RgbaImage::from_pixel(width, 20, white),
+            badge: None,
+        };
+        let cells = [cell(100), cell(50), cell(25)];
+        let sheet = compose(&cells, 2, 100, 20, None, white, black);
+        assert_eq!(sheet.dimensions(), (2 * 100 + 3 * GAP, 2 * (20 + LABEL_HEIGHT) + 3 * GAP));
+
+        // The longest take fills its cell, the shorter ones a share of theirs
+        let filled = |column: u32, row: u32, x: u32| {
+            let left = GAP + column * (100 + GAP);
+            let top = GAP + row * (20 + LABEL_HEIGHT + GAP);
+            *sheet.get_pixel(left + x, top + 10) == white
+        };
+        assert!(filled(0, 0, 99));
+        assert!(filled(1, 0, 49) && !filled(1, 0, 50));
+        assert!(filled(0, 1, 24) && !filled(0, 1, 25));
+        // Every cell is labeled
+        let label = |column: u32, row: u32| {
+            let left = GAP + column * (100 + GAP);
+            let top = GAP + row * (20 + LABEL_HEIGHT + GAP) + 20;
+            (left..left + 100).any(|x| (top..top + LABEL_HEIGHT).any(|y| *sheet.get_pixel(x, y) != black))
+        };
+        assert!(label(0, 0) && label(1, 0) && label(0, 1) && !label(1, 1));
+    }
+    #[test]
+    fn badges_judge_the_takes() {
+        let badge = |loudness: Option<f64>, clipped: usize| Badge { loudness, clipped };
+        assert_eq!(badge(Some(-23.04), 0).text(), "-23.0 LUFS");
+        assert_eq!(badge(None, 3).text(), "-inf LUFS, 3 clipped");
+        assert_eq!(badge(Some(-16.0), 0).color(None), FINE_COLOR);
+        assert_eq!(badge(Some(-16.5), 0).color(Some(-16.0)), FINE_COLOR);
+        assert_eq!(badge(Some(-20.0), 0).color(Some(-16.0)), OFF_TARGET_COLOR);
+        assert_eq!(badge(None, 0).color(Some(-16.0)), OFF_TARGET_COLOR);
+        assert_eq!(badge(Some(-16.0), 1).color(Some(-16.0)), CLIPPING_COLOR);
+
+        // The badge gets a line of its own with the dot at the left
+        let (white, black) = (Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255]));
+        let cells = [Cell {
+            name: "take".to_string(),
+            duration: "0:01".to_string(),
+            waveform: RgbaImage::from_pixel(100, 20, white),
+            badge: Some(badge(Some(-10.0), 2)),
+        }];
+        let sheet = compose(&cells, 1, 100, 20, None, white, black);
+        assert_eq!(sheet.height(), 20 + LABEL_HEIGHT + BADGE_HEIGHT + 2 * GAP);
+        let dot_top = GAP + 20 + LABEL_HEIGHT;
+        assert!((dot_top..dot_top + BADGE_HEIGHT).any(|y| *sheet.get_pixel(GAP + 3, y) == CLIPPING_COLOR));
+    }
+}
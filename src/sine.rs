@@ -0,0 +1,120 @@
+//! Measurements on recordings of a single test tone
+use crate::spectrum::{blackman_harris, power_spectrum};
+
+
+/// Largest number of samples analyzed
+const MAX_LENGTH: usize = 1 << 18;
+
+/// Bins around a spectral peak that belong to its main lobe
+const LOBE: usize = 8;
+
+/// Number of harmonics included in the THD
+const HARMONICS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SineMeasurement {
+    /// Measured frequency of the fundamental in Hz
+    pub frequency: f64,
+    /// RMS level of the whole signal in dBFS
+    pub level: f64,
+    /// Total harmonic distortion plus noise as a ratio
+    pub thd_n: f64,
+    /// Total harmonic distortion (harmonics 2 to 10) as a ratio
+    pub thd: f64,
+    /// Power spectrum with the fundamental removed
+    pub residual: Vec<f64>,
+    /// Power of the fundamental
+    pub fundamental_power: f64,
+}
+
+/// Measure a test tone close to `expected` Hz in a mono signal
+pub fn measure(mono: &[f32], sample_rate: u32, expected: f64) -> Result<SineMeasurement, String> {
+    let nyquist = sample_rate as f64 / 2.0;
+    if !(expected > 0.0 && expected < nyquist) {
+        return Err(format!("A test tone at {expected} Hz can't be recorded at {sample_rate} Hz, which holds frequencies above 0 and below {nyquist} Hz"));
+    }
+    // Use the middle of the recording to skip fades at start and end
+    let length = MAX_LENGTH.min(prev_power_of_two(mono.len()));
+    if length < 1024 {
+        return Err("The input is too short to measure a test tone".to_string());
+    }
+    let start = (mono.len() - length) / 2;
+    let signal = &mono[start..start + length];
+    let window = blackman_harris(length);
+    let mut power = power_spectrum(signal, &window);
+    let bin_width = sample_rate as f64 / length as f64;
+
+    // Strongest bin within 10% of the expected frequency
+    let low = ((expected * 0.9 / bin_width) as usize).max(1);
+    let high = ((expected * 1.1 / bin_width) as usize + 1).min(power.len() - 2);
+    let peak = (low..=high).max_by(|&a, &b| power[a].total_cmp(&power[b]))
+        .ok_or_else(|| format!("There is no room for a test tone at {expected} Hz in the spectrum"))?;
+
+    // Parabolic interpolation on the log magnitude for sub-bin precision
+    let (a, b, c) = (power[peak - 1].ln(), power[peak].ln(), power[peak + 1].ln());
+    let offset = 0.5 * (a - c) / (a - 2.0 * b + c);
+    let frequency = (peak as f64 + if offset.is_finite() { offset } else { 0.0 }) * bin_width;
+
+    let lobe = |center: usize| center.saturating_sub(LOBE)..(center + LOBE + 1).min(power.len());
+    let fundamental_power: f64 = power[lobe(peak)].iter().sum();
+
+    let thd_power: f64 = (2..=HARMONICS)
+        .map(|h| (frequency * h as f64 / bin_width).round() as usize)
+        .filter(|&bin| bin + 1 < power.len())
+        .map(|bin| power[lobe(bin)].iter().sum::<f64>())
+        .sum();
+
+    // Remove DC and the fundamental, everything left is distortion and noise
+    for bin in lobe(peak).chain(0..LOBE.min(power.len())) {
+        power[bin] = 0.0;
+    }
+    let noise_power: f64 = power.iter().sum();
+
+    let rms = (mono.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / mono.len() as f64).sqrt();
+    Ok(SineMeasurement {
+        frequency,
+        level: 20.0 * rms.max(1e-10).log10(),
+        thd_n: (noise_power / fundamental_power).sqrt(),
+        thd: (thd_power / fundamental_power).sqrt(),
+        residual: power,
+        fundamental_power,
+    })
+}
+
+fn prev_power_of_two(n: usize) -> usize {
+    if n == 0 { 0 } else { 1 << (usize::BITS - 1 - n.leading_zeros()) }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::sine::measure;
+
+    #[test]
+    fn clean_and_distorted_sine() {
+        let sample_rate = 48000;
+        let tone = |i: usize| (2.0 * std::f64::consts::PI * 1003.0 * i as f64 / sample_rate as f64).sin();
+        let clean: Vec<f32> = (0..48000).map(|i| (0.5 * tone(i)) as f32).collect();
+        let m = measure(&clean, sample_rate, 1000.0).unwrap();
+        assert!((m.frequency - 1003.0).abs() < 0.5);
+        assert!(m.thd_n < 1e-3);
+
+        // One percent of third harmonic
+        let distorted: Vec<f32> = (0..48000)
+            .map(|i| {
+                let t = 2.0 * std::f64::consts::PI * 1003.0 * i as f64 / sample_rate as f64;
+                (0.5 * t.sin() + 0.005 * (3.0 * t).sin()) as f32
+            })
+            .collect();
+        let m = measure(&distorted, sample_rate, 1000.0).unwrap();
+        assert!((m.thd - 0.01).abs() < 0.001);
+
+        // Tones the sample rate can't hold are not mistaken for short input
+        assert!(measure(&clean, sample_rate, 30000.0).unwrap_err().contains("below 24000 Hz"));
+        assert!(measure(&clean, sample_rate, 0.0).is_err());
+        assert!(measure(&clean[..500], sample_rate, 1000.0).unwrap_err().contains("too short"));
+        assert!(measure(&clean, sample_rate, 23990.0).is_ok());
+    }
+}
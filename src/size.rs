@@ -0,0 +1,97 @@
+//! Image size presets for common platforms, with safe margins that keep the
+//! waveform clear of cropping and overlaid UI
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage};
+
+
+/// Platform specific image size
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizePreset {
+    /// Twitter/X summary card with a large image (1200×628)
+    TwitterCard,
+    /// Open Graph preview image used by most link previews (1200×630)
+    OgImage,
+    /// LinkedIn shared image (1200×627)
+    LinkedinPost,
+    /// Square Instagram post (1080×1080)
+    InstagramSquare,
+    /// Portrait Instagram post (1080×1350)
+    InstagramPortrait,
+    /// Instagram/Facebook story, keeping clear of the top and bottom UI (1080×1920)
+    InstagramStory,
+    /// YouTube video thumbnail (1280×720)
+    YoutubeThumb,
+    /// YouTube channel banner, drawn inside the area visible on all devices (2560×1440)
+    YoutubeBanner,
+    /// Facebook page cover, inside the area visible on phones (820×312)
+    FacebookCover,
+}
+
+impl SizePreset {
+    /// Width and height of the whole image in pixels
+    pub fn dimensions(self) -> (u32, u32) {
+        match self {
+            SizePreset::TwitterCard => (1200, 628),
+            SizePreset::OgImage => (1200, 630),
+            SizePreset::LinkedinPost => (1200, 627),
+            SizePreset::InstagramSquare => (1080, 1080),
+            SizePreset::InstagramPortrait => (1080, 1350),
+            SizePreset::InstagramStory => (1080, 1920),
+            SizePreset::YoutubeThumb => (1280, 720),
+            SizePreset::YoutubeBanner => (2560, 1440),
+            SizePreset::FacebookCover => (820, 312),
+        }
+    }
+
+    /// Horizontal and vertical margin on every side in pixels
+    pub fn margins(self) -> (u32, u32) {
+        match self {
+            SizePreset::TwitterCard | SizePreset::OgImage | SizePreset::LinkedinPost => (60, 60),
+            SizePreset::InstagramSquare | SizePreset::InstagramPortrait => (54, 54),
+            SizePreset::InstagramStory => (54, 250),
+            SizePreset::YoutubeThumb => (64, 64),
+            // The 1546×423 area in the middle is the only part shown everywhere
+            SizePreset::YoutubeBanner => (507, 508),
+            SizePreset::FacebookCover => (90, 16),
+        }
+    }
+
+    /// Size of the area inside the margins
+    pub fn inner(self) -> (u32, u32) {
+        let ((width, height), (x, y)) = (self.dimensions(), self.margins());
+        (width - 2 * x, height - 2 * y)
+    }
+}
+
+/// Center `img` on a canvas of `width` × `height` filled with `background`
+pub fn place(img: &RgbaImage, width: u32, height: u32, background: Rgba<u8>) -> RgbaImage {
+    let mut canvas = RgbaImage::from_pixel(width, height, background);
+    let x = (width as i64 - img.width() as i64) / 2;
+    let y = (height as i64 - img.height() as i64) / 2;
+    image::imageops::replace(&mut canvas, img, x, y);
+    canvas
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+    use image::{Rgba, RgbaImage};
+    use crate::size::{place, SizePreset};
+
+    #[test]
+    fn presets_fit() {
+        for preset in SizePreset::value_variants() {
+            let ((width, height), (x, y)) = (preset.dimensions(), preset.margins());
+            assert!(2 * x < width && 2 * y < height, "{preset:?}");
+        }
+        assert_eq!(SizePreset::YoutubeBanner.inner(), (1546, 424));
+
+        let (on, off) = (Rgba([0, 0, 0, 255]), Rgba([0, 0, 0, 0]));
+        let canvas = place(&RgbaImage::from_pixel(2, 2, on), 6, 4, off);
+        assert_eq!(canvas.dimensions(), (6, 4));
+        assert_eq!(canvas.get_pixel(2, 1), &on);
+        assert_eq!(canvas.get_pixel(1, 1), &off);
+    }
+}
@@ -0,0 +1,84 @@
+//! Smoothing of the per-column values across neighbouring columns, which
+//! tames the jagged look of very short files drawn much wider than they
+//! have detail
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+
+/// Weighting of the neighbouring columns
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    /// Bell shaped weights with a standard deviation of a quarter of the
+    /// window, which keeps the shape of transients better
+    Gaussian,
+    /// Equal weights over the window (a moving average)
+    Average,
+}
+
+/// Offsets and weights of the neighbours averaged over a window of `window`
+/// columns, empty for windows of less than two columns
+fn weights(window: f64, kernel: Kernel) -> Vec<(isize, f64)> {
+    if window.is_nan() || window < 2.0 {
+        return vec![];
+    }
+    match kernel {
+        Kernel::Average => {
+            let reach = ((window - 1.0) / 2.0).round() as isize;
+            (-reach..=reach).map(|d| (d, 1.0)).collect()
+        },
+        Kernel::Gaussian => {
+            let sigma = window / 4.0;
+            let reach = (3.0 * sigma).ceil() as isize;
+            (-reach..=reach).map(|d| (d, (-(d * d) as f64 / (2.0 * sigma * sigma)).exp())).collect()
+        },
+    }
+}
+
+/// The `values` averaged over a window of `window` columns with the
+/// `kernel`. Missing values (past the end of the audio) stay missing and
+/// don't count for their neighbours
+pub fn smooth(values: &[Option<f64>], window: f64, kernel: Kernel) -> Vec<Option<f64>> {
+    // Wider windows than twice the values reach all of them from everywhere
+    let weights = weights(window.min(2.0 * values.len() as f64), kernel);
+    if weights.len() < 2 {
+        return values.to_vec();
+    }
+    (0..values.len()).into_par_iter()
+        .map(|i| {
+            values[i]?;
+            let (sum, total) = weights.iter()
+                .filter_map(|&(d, w)| {
+                    let j = i.checked_add_signed(d)?;
+                    values.get(j).copied().flatten().map(|v| (v * w, w))
+                })
+                .fold((0.0, 0.0), |(sum, total), (v, w)| (sum + v, total + w));
+            Some(sum / total)
+        })
+        .collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::smoothing::{smooth, Kernel};
+
+    #[test]
+    fn spikes_are_spread() {
+        let values = [Some(0.0), Some(0.0), Some(3.0), Some(0.0), Some(0.0), None];
+        assert_eq!(smooth(&values, 3.0, Kernel::Average), vec![Some(0.0), Some(1.0), Some(1.0), Some(1.0), Some(0.0), None]);
+        // Windows below two columns change nothing
+        assert_eq!(smooth(&values, 1.0, Kernel::Gaussian), values.to_vec());
+
+        let smoothed = smooth(&values, 4.0, Kernel::Gaussian);
+        assert!(smoothed[2].unwrap() < 3.0 && smoothed[1].unwrap() > 0.0);
+        assert!((smoothed[1].unwrap() - smoothed[3].unwrap()).abs() < 1e-12);
+        assert_eq!(smoothed[5], None);
+        // A constant stays constant up to the edges
+        let flat = smooth(&[Some(0.5); 10], 8.0, Kernel::Gaussian);
+        assert!(flat.iter().all(|v| (v.unwrap() - 0.5).abs() < 1e-12));
+        // Windows far wider than the values average all of them
+        let wide = smooth(&values, 1e15, Kernel::Average);
+        assert_eq!(wide, vec![Some(0.6), Some(0.6), Some(0.6), Some(0.6), Some(0.6), None]);
+    }
+}
@@ -0,0 +1,558 @@
+//! Short-time spectra of audio rendered as an image with time on the
+//! horizontal and frequency on the vertical axis, or as a waterfall of
+//! spectra stacked in perspective
+use std::f64::consts::PI;
+use clap::ValueEnum;
+use image::{ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::font::{draw_text, GLYPH_HEIGHT};
+use crate::number::{parse_fraction, parse_number};
+use crate::render::{time_column, RenderOptions, TimeAxis, TimeDirection};
+use crate::spectrum::{hann, power_spectrum};
+
+
+/// Number of samples analyzed per frame
+pub const FRAME_SIZE: usize = 2048;
+
+/// Levels this far below full scale (or the peak when normalizing) are drawn
+/// in the background color
+pub const DYNAMIC_RANGE_DB: f64 = 100.0;
+
+/// Window applied to every frame before the FFT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Hann,
+    Hamming,
+    Blackman,
+    /// Kaiser window with the given beta (higher means lower side lobes and
+    /// a wider main lobe)
+    Kaiser(f64),
+}
+
+impl Window {
+    /// Coefficients of the window for a frame of `n` samples
+    pub fn coefficients(&self, n: usize) -> Vec<f64> {
+        let x = |i: usize| i as f64 / (n - 1).max(1) as f64;
+        match *self {
+            Window::Hann => hann(n),
+            Window::Hamming => (0..n).map(|i| 0.54 - 0.46 * (2.0 * PI * x(i)).cos()).collect(),
+            Window::Blackman => (0..n)
+                .map(|i| 0.42 - 0.5 * (2.0 * PI * x(i)).cos() + 0.08 * (4.0 * PI * x(i)).cos())
+                .collect(),
+            Window::Kaiser(beta) => {
+                let norm = bessel_i0(beta);
+                (0..n)
+                    .map(|i| {
+                        let t = 2.0 * x(i) - 1.0;
+                        bessel_i0(beta * (1.0 - t * t).max(0.0).sqrt()) / norm
+                    })
+                    .collect()
+            },
+        }
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Parse a window name ("hann", "hamming", "blackman" or "kaiser:<beta>")
+pub fn parse_window(argument: &str) -> Result<Window, String> {
+    let argument = argument.trim().to_lowercase();
+    match argument.split_once(':') {
+        None if argument == "hann" => Ok(Window::Hann),
+        None if argument == "hamming" => Ok(Window::Hamming),
+        None if argument == "blackman" => Ok(Window::Blackman),
+        None if argument == "kaiser" => Ok(Window::Kaiser(8.6)),
+        Some(("kaiser", beta)) => match parse_number(beta) {
+            Ok(beta) if beta >= 0.0 => Ok(Window::Kaiser(beta)),
+            _ => Err(format!("\"{beta}\" is not a valid Kaiser beta (expected e.g. \"kaiser:8.6\")")),
+        },
+        _ => Err(format!("\"{argument}\" is not a window (expected hann, hamming, blackman or kaiser:<beta>)")),
+    }
+}
+
+/// Parse an overlap given in percent ("75%") or as a fraction ("0.75")
+pub fn parse_overlap(argument: &str) -> Result<f64, String> {
+    parse_fraction(argument, "overlap", 0.0..1.0)
+}
+
+/// Parse the depth of the waterfall in percent ("30%") or as a fraction
+/// ("0.3") of the image size
+pub fn parse_perspective(argument: &str) -> Result<f64, String> {
+    parse_fraction(argument, "perspective", 0.0..1.0)
+}
+
+/// Lowest frequency of the logarithmic axis when --fmin is below it, since
+/// a logarithmic axis cannot reach 0 Hz
+const LOG_MIN_FREQUENCY: f64 = 20.0;
+
+/// How frequencies are spread over the rows
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreqScale {
+    /// Equal distance for equal differences in Hz
+    Linear,
+    /// Equal distance for every octave
+    Log,
+    /// Perceptual mel scale, linear below about 1 kHz and logarithmic above,
+    /// with energies summed over triangular mel bands
+    Mel,
+}
+
+impl FreqScale {
+    /// Position of `frequency` on the scale
+    fn forward(self, frequency: f64) -> f64 {
+        match self {
+            FreqScale::Linear => frequency,
+            FreqScale::Log => frequency.max(f64::MIN_POSITIVE).log2(),
+            FreqScale::Mel => 2595.0 * (1.0 + frequency / 700.0).log10(),
+        }
+    }
+
+    /// Frequency at `position` on the scale
+    fn inverse(self, position: f64) -> f64 {
+        match self {
+            FreqScale::Linear => position,
+            FreqScale::Log => position.exp2(),
+            FreqScale::Mel => 700.0 * (10f64.powf(position / 2595.0) - 1.0),
+        }
+    }
+
+    /// `count + 1` frequencies evenly spaced on the scale from `low` to `high`
+    fn edges(self, low: f64, high: f64, count: u32) -> Vec<f64> {
+        let (low, high) = (self.forward(low), self.forward(high));
+        (0..=count).map(|i| self.inverse(low + (high - low) * i as f64 / count.max(1) as f64)).collect()
+    }
+}
+
+/// How the short-time spectra are computed
+#[derive(Debug, Clone, Copy)]
+pub struct SpectrogramOptions {
+    pub window: Window,
+    /// Share of each frame that overlaps with the next one (0.0 to below 1.0)
+    pub overlap: f64,
+    /// Frequency at the bottom edge in Hz
+    pub min_frequency: f64,
+    /// Frequency at the top edge in Hz, Nyquist if unset
+    pub max_frequency: Option<f64>,
+    pub scale: FreqScale,
+    /// Number of mel bands for the mel scale, one per row if unset
+    pub mel_bands: Option<u32>,
+    /// Color of the loudest levels, the foreground color if unset
+    pub color: Option<Rgba<u8>>,
+    /// Number of spectra stacked by the waterfall mode
+    pub slices: u32,
+    /// Share of the width and height the waterfall recedes into the
+    /// distance (0.0 to below 1.0)
+    pub perspective: f64,
+}
+
+impl Default for SpectrogramOptions {
+    fn default() -> SpectrogramOptions {
+        SpectrogramOptions { window: Window::Hann, overlap: 0.75, min_frequency: 0.0, max_frequency: None, scale: FreqScale::Linear, mel_bands: None, color: None, slices: 32, perspective: 0.3 }
+    }
+}
+
+/// Level in dB (relative to a full scale sine) of every bin of every frame,
+/// with frames starting `hop` samples apart
+pub fn frames(mono: &[f32], window: &[f64], hop: usize) -> Vec<Vec<f64>> {
+    // Scale so a full scale sine in the middle of a bin reads 0 dB
+    let coherent_gain: f64 = window.iter().sum::<f64>() / 2.0;
+    let count = mono.len().div_ceil(hop).max(1);
+    (0..count).into_par_iter()
+        .map(|i| {
+            let start = (i * hop).min(mono.len());
+            power_spectrum(&mono[start..], window).iter()
+                .map(|p| 10.0 * (p.max(1e-30) / (coherent_gain * coherent_gain)).log10())
+                .collect()
+        })
+        .collect()
+}
+
+/// The interleaved `samples` averaged over their `channels`
+pub fn mono(samples: &[f32], channels: usize) -> Vec<f32> {
+    samples.chunks(channels.max(1))
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// The frame (of `count` frames of `frame_size` samples, `hop` apart) closest
+/// to the middle of every column of `axis`, none for columns without samples
+pub fn column_frames(axis: &TimeAxis, sample_count: usize, channels: usize, frame_size: usize, hop: usize, count: usize) -> Vec<Option<usize>> {
+    (0..axis.columns)
+        .map(|x| {
+            let range = axis.range(x, sample_count);
+            if range.is_empty() {
+                return None;
+            }
+            let center = (range.start + range.end) as f64 / 2.0 / channels as f64;
+            let frame = ((center - frame_size as f64 / 2.0) / hop as f64).round().max(0.0) as usize;
+            Some(frame.min(count - 1))
+        })
+        .collect()
+}
+
+/// Triangular mel filter as weights of consecutive bins
+struct MelFilter {
+    first: usize,
+    weights: Vec<f64>,
+}
+
+impl MelFilter {
+    /// Level in dB of the weighted power sum of the bins in the filter
+    fn level(&self, spectrum: &[f64]) -> f64 {
+        let power: f64 = self.weights.iter().zip(&spectrum[self.first..])
+            .map(|(weight, db)| weight * 10f64.powf(db / 10.0))
+            .sum();
+        10.0 * power.max(1e-30).log10()
+    }
+}
+
+/// Triangular filters rising from one edge frequency to the next and falling
+/// to the one after, so `edges` holds two more frequencies than there are
+/// bands. Bands too narrow to contain a bin use the bin closest to their center
+fn mel_filters(edges: &[f64], to_bin: &dyn Fn(f64) -> f64, bins: usize) -> Vec<MelFilter> {
+    edges.windows(3)
+        .map(|edge| {
+            let (low, center, high) = (to_bin(edge[0]), to_bin(edge[1]), to_bin(edge[2]));
+            let first = (low.floor() as usize + 1).min(bins);
+            let last = (high.ceil() as usize).min(bins + 1).max(first);
+            let weights: Vec<f64> = (first..last)
+                .map(|bin| {
+                    let bin = bin as f64;
+                    if bin <= center { (bin - low) / (center - low) } else { (high - bin) / (high - center) }
+                })
+                .map(|weight| if weight.is_finite() { weight.max(0.0) } else { 0.0 })
+                .collect();
+            if weights.iter().sum::<f64>() > 0.0 {
+                MelFilter { first, weights }
+            } else {
+                MelFilter { first: (center.round() as usize).min(bins), weights: vec![1.0] }
+            }
+        })
+        .collect()
+}
+
+/// Lowest and highest frequency shown, cropped to what the scale can show
+/// below the `nyquist` frequency
+fn frequency_range(settings: &SpectrogramOptions, nyquist: f64) -> (f64, f64) {
+    let min_frequency = settings.min_frequency.clamp(0.0, nyquist);
+    let max_frequency = settings.max_frequency.unwrap_or(nyquist).clamp(min_frequency, nyquist);
+    let min_frequency = match settings.scale {
+        FreqScale::Log => min_frequency.max(LOG_MIN_FREQUENCY.min(max_frequency / 2.0)),
+        _ => min_frequency,
+    };
+    (min_frequency, max_frequency)
+}
+
+/// Spectrogram of the interleaved `samples` (averaged over channels) with
+/// time following the columns of `axis` and frequency rising from the bottom
+pub fn render_spectrogram(samples: &[f32], axis: &TimeAxis, gain: f64, options: &RenderOptions) -> RgbaImage {
+    let channels = options.channels.max(1);
+    let mono = mono(samples, channels);
+
+    let settings = options.spectrogram;
+    let window = settings.window.coefficients(FRAME_SIZE);
+    let hop = ((FRAME_SIZE as f64 * (1.0 - settings.overlap)).round() as usize).max(1);
+    let spectra = frames(&mono, &window, hop);
+    let gain_db = 20.0 * gain.log10();
+
+    let width = axis.columns;
+    let height = options.height;
+    let bins = FRAME_SIZE / 2;
+    let nyquist = options.sample_rate as f64 / 2.0;
+    let (min_frequency, max_frequency) = frequency_range(&settings, nyquist);
+    let to_bin = |frequency: f64| frequency / nyquist * bins as f64;
+    // Fractional bin at the lower edge of every row counted from the bottom
+    let row_edges: Vec<f64> = settings.scale.edges(min_frequency, max_frequency, height)
+        .into_iter()
+        .map(to_bin)
+        .collect();
+    let bands = settings.mel_bands.unwrap_or(height).max(1);
+    let filters = match settings.scale {
+        FreqScale::Mel => mel_filters(&settings.scale.edges(min_frequency, max_frequency, bands + 1), &to_bin, bins),
+        _ => vec![],
+    };
+
+    let column_frames = column_frames(axis, samples.len(), channels, FRAME_SIZE, hop, spectra.len());
+
+    // Level of every mel band for the frames that are drawn
+    let band_levels: Vec<Vec<f64>> = match settings.scale {
+        FreqScale::Mel => column_frames.par_iter()
+            .map(|frame| frame.map(|frame| filters.iter().map(|filter| filter.level(&spectra[frame])).collect()).unwrap_or_default())
+            .collect(),
+        _ => vec![],
+    };
+
+    let color = settings.color.unwrap_or(options.foreground);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let column = time_column(x, width, options.time_direction) as usize;
+        let Some(frame) = column_frames[column] else {
+            return options.background;
+        };
+        let row = height - 1 - y;
+        let level = match settings.scale {
+            FreqScale::Mel => band_levels[column][(row as u64 * bands as u64 / height as u64) as usize],
+            // Loudest bin within the frequency band of the row
+            _ => {
+                let low = (row_edges[row as usize].floor() as usize).min(bins);
+                let high = (row_edges[row as usize + 1].ceil() as usize).clamp(low + 1, bins + 1);
+                spectra[frame][low..high].iter().cloned().fold(f64::MIN, f64::max)
+            },
+        } + gain_db;
+        let t = ((level + DYNAMIC_RANGE_DB) / DYNAMIC_RANGE_DB).clamp(0.0, 1.0);
+        options.dither.blend(options.background, color, t, x, y)
+    })
+}
+
+/// Least distance between the labels of the frequency axis in pixels
+const LABEL_SPACING: f64 = (GLYPH_HEIGHT + 5) as f64;
+
+/// Length of the tick in front of a frequency label in pixels
+const LABEL_TICK: u32 = 3;
+
+/// Frequency as a short label ("500", "2.5k")
+fn frequency_label(frequency: f64) -> String {
+    match frequency >= 1000.0 {
+        true => format!("{}k", (frequency / 100.0).round() / 10.0),
+        false => format!("{}", frequency.round()),
+    }
+}
+
+/// Round frequencies from `low` to `high` Hz worth a label and the rows
+/// (counted from the top of an image `height` pixels tall) they are shown
+/// at. The linear scale is labeled in even steps, the others at powers of
+/// ten and then at 5 and 2 times them where there is room
+fn frequency_labels(scale: FreqScale, low: f64, high: f64, height: u32) -> Vec<(f64, f64)> {
+    let (bottom, top) = (scale.forward(low), scale.forward(high));
+    if top <= bottom {
+        return vec![];
+    }
+    let y = |frequency: f64| height as f64 * (1.0 - (scale.forward(frequency) - bottom) / (top - bottom));
+    let multiples = |factor: f64| (0..7).map(move |decade| factor * 10f64.powi(decade));
+    let tiers: Vec<Vec<f64>> = match scale {
+        FreqScale::Linear => {
+            // The smallest round step that leaves room between the labels
+            let room = (high - low) * LABEL_SPACING / height.max(1) as f64;
+            let step = [1.0, 2.0, 5.0].into_iter().flat_map(multiples).filter(|&step| step >= room).fold(f64::INFINITY, f64::min);
+            let first = (low / step).ceil() as u64;
+            vec![(first..).map(|i| i as f64 * step).take_while(|&f| f <= high).collect()]
+        },
+        _ => [1.0, 5.0, 2.0].map(|factor| multiples(factor).filter(|f| (low..=high).contains(f)).collect()).to_vec(),
+    };
+    let mut labels: Vec<(f64, f64)> = vec![];
+    for frequency in tiers.into_iter().flatten() {
+        let row = y(frequency);
+        if labels.iter().all(|&(_, other)| (other - row).abs() >= LABEL_SPACING) {
+            labels.push((frequency, row));
+        }
+    }
+    labels.sort_by(|a, b| a.0.total_cmp(&b.0));
+    labels
+}
+
+/// Label the frequency axis of a spectrogram rendered with `options` along
+/// its left edge, a tick at every labeled frequency
+pub fn draw_frequency_labels(img: &mut RgbaImage, options: &RenderOptions, color: Rgba<u8>) {
+    let height = img.height();
+    let (low, high) = frequency_range(&options.spectrogram, options.sample_rate as f64 / 2.0);
+    for (frequency, row) in frequency_labels(options.spectrogram.scale, low, high, height) {
+        let y = (row.round() as u32).min(height.saturating_sub(1));
+        for x in 0..LABEL_TICK.min(img.width()) {
+            img.put_pixel(x, y, color);
+        }
+        // Next to the tick, but inside the image at the edges
+        let top = (y as i64 - GLYPH_HEIGHT as i64 / 2).clamp(0, (height as i64 - GLYPH_HEIGHT as i64).max(0));
+        draw_text(img, (LABEL_TICK + 2) as i64, top, &frequency_label(frequency), color, img.width() as i64);
+    }
+}
+
+/// Brightness of the slice furthest back relative to the one in front
+const WATERFALL_FADE: f64 = 0.35;
+
+/// Spectra of the interleaved `samples` (averaged over channels) stacked in
+/// perspective, frequency rising to the right and level upwards. Every slice
+/// shows the loudest level of each frequency within its share of the time,
+/// the first one in front (the last one with right to left time), and is
+/// hidden wherever slices in front of it rise above it
+pub fn render_waterfall(samples: &[f32], gain: f64, options: &RenderOptions) -> RgbaImage {
+    let channels = options.channels.max(1);
+    let mono = mono(samples, channels);
+
+    let settings = options.spectrogram;
+    let window = settings.window.coefficients(FRAME_SIZE);
+    let hop = ((FRAME_SIZE as f64 * (1.0 - settings.overlap)).round() as usize).max(1);
+    let spectra = frames(&mono, &window, hop);
+    let gain_db = 20.0 * gain.log10();
+
+    let (width, height) = (options.width, options.height);
+    let mut img = RgbaImage::from_pixel(width, height, options.background);
+    if width == 0 || height == 0 {
+        return img;
+    }
+    let slices = settings.slices.max(1);
+    let depth = |size: u32| (size as f64 * settings.perspective).round() as u32;
+    let (depth_x, depth_y) = (depth(width).min(width - 1), depth(height).min(height - 1));
+    let (slice_width, amplitude) = (width - depth_x, (height - depth_y) as f64);
+
+    let bins = FRAME_SIZE / 2;
+    let nyquist = options.sample_rate as f64 / 2.0;
+    let (min_frequency, max_frequency) = frequency_range(&settings, nyquist);
+    let to_bin = |frequency: f64| frequency / nyquist * bins as f64;
+    // Fractional bin at the left edge of every column of a slice
+    let column_edges: Vec<f64> = settings.scale.edges(min_frequency, max_frequency, slice_width)
+        .into_iter()
+        .map(to_bin)
+        .collect();
+    let bands = settings.mel_bands.unwrap_or(slice_width).max(1);
+    let filters = match settings.scale {
+        FreqScale::Mel => mel_filters(&settings.scale.edges(min_frequency, max_frequency, bands + 1), &to_bin, bins),
+        _ => vec![],
+    };
+
+    // Loudest level of every bin over the frames of every slice, in time order
+    let spans: Vec<Vec<f64>> = (0..slices as usize).into_par_iter()
+        .map(|slice| {
+            let start = slice * spectra.len() / slices as usize;
+            let end = ((slice + 1) * spectra.len() / slices as usize).max(start + 1).min(spectra.len());
+            (0..=bins)
+                .map(|bin| spectra[start..end].iter().map(|frame| frame[bin]).fold(f64::MIN, f64::max))
+                .collect()
+        })
+        .collect();
+
+    let color = settings.color.unwrap_or(options.foreground);
+    // Highest row drawn so far in every column, slices further back only
+    // show above it
+    let mut horizon = vec![height; width as usize];
+    for depth_index in 0..slices {
+        let slice = match options.time_direction {
+            TimeDirection::Ltr => depth_index,
+            TimeDirection::Rtl => slices - 1 - depth_index,
+        };
+        let spectrum = &spans[slice as usize];
+        let share = if slices > 1 { depth_index as f64 / (slices - 1) as f64 } else { 0.0 };
+        let left = (share * depth_x as f64).round() as u32;
+        let base = height as f64 - share * depth_y as f64;
+        let fade = 1.0 - (1.0 - WATERFALL_FADE) * share;
+
+        let tops: Vec<u32> = (0..slice_width)
+            .map(|column| {
+                let level = match settings.scale {
+                    FreqScale::Mel => filters[(column as u64 * bands as u64 / slice_width as u64) as usize].level(spectrum),
+                    _ => {
+                        let low = (column_edges[column as usize].floor() as usize).min(bins);
+                        let high = (column_edges[column as usize + 1].ceil() as usize).clamp(low + 1, bins + 1);
+                        spectrum[low..high].iter().cloned().fold(f64::MIN, f64::max)
+                    },
+                } + gain_db;
+                let t = ((level + DYNAMIC_RANGE_DB) / DYNAMIC_RANGE_DB).clamp(0.0, 1.0);
+                (base - 1.0 - t * (amplitude - 1.0)).round().max(0.0) as u32
+            })
+            .collect();
+
+        // The ridge of the slice, connected from column to column
+        for (column, &top) in tops.iter().enumerate() {
+            let x = left + column as u32;
+            let previous = if column > 0 { tops[column - 1] } else { top };
+            let (from, to) = (top.min(previous), top.max(previous));
+            let x_horizon = &mut horizon[x as usize];
+            for y in from..=to.min(height - 1) {
+                if y < *x_horizon {
+                    img.put_pixel(x, y, options.dither.blend(options.background, color, fade, x, y));
+                }
+            }
+            *x_horizon = (*x_horizon).min(from);
+        }
+    }
+    img
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::render::{render_waveform, RenderMode, RenderOptions};
+    use crate::spectrogram::{frequency_label, frequency_labels, mel_filters, LABEL_SPACING, parse_overlap, parse_perspective, parse_window, FreqScale, SpectrogramOptions, Window};
+
+    #[test]
+    fn window_and_overlap_arguments() {
+        assert_eq!(parse_window("Hamming"), Ok(Window::Hamming));
+        assert_eq!(parse_window("kaiser:5"), Ok(Window::Kaiser(5.0)));
+        assert!(parse_window("kaiser:x").is_err());
+        assert!(parse_window("rectangle").is_err());
+        assert_eq!(parse_overlap("75%"), Ok(0.75));
+        assert_eq!(parse_overlap("0.5"), Ok(0.5));
+        assert!(parse_overlap("100%").is_err());
+
+        // Windows taper towards the edges and peak in the middle
+        for window in [Window::Hann, Window::Hamming, Window::Blackman, Window::Kaiser(8.6)] {
+            let c = window.coefficients(65);
+            assert!(c[0] < 0.1 && (c[32] - 1.0).abs() < 1e-9, "{window:?}");
+        }
+    }
+
+    #[test]
+    fn frequency_scales() {
+        assert!((FreqScale::Mel.forward(1000.0) - 1000.0).abs() < 0.5);
+        for scale in [FreqScale::Linear, FreqScale::Log, FreqScale::Mel] {
+            assert!((scale.inverse(scale.forward(440.0)) - 440.0).abs() < 1e-9, "{scale:?}");
+        }
+        // Every octave gets the same number of rows on the logarithmic scale
+        let edges = FreqScale::Log.edges(100.0, 800.0, 3);
+        assert!(edges.iter().zip([100.0, 200.0, 400.0, 800.0]).all(|(a, b)| (a - b).abs() < 1e-9));
+
+        // A tone only shows in the band around it, with about its own level
+        let filters = mel_filters(&FreqScale::Mel.edges(0.0, 1000.0, 11), &|f| f / 10.0, 100);
+        let mut spectrum = vec![-200.0; 101];
+        spectrum[50] = 0.0;
+        let levels: Vec<f64> = filters.iter().map(|filter| filter.level(&spectrum)).collect();
+        let loudest = (0..levels.len()).max_by(|&a, &b| levels[a].total_cmp(&levels[b])).unwrap();
+        assert!(levels[loudest] > -3.0 && levels[loudest] <= 0.0);
+        assert!(levels.iter().filter(|&&level| level > -100.0).count() <= 2);
+    }
+
+    #[test]
+    fn frequency_axis_labels() {
+        assert_eq!((frequency_label(500.0), frequency_label(2500.0), frequency_label(96000.0)), ("500".to_string(), "2.5k".to_string(), "96k".to_string()));
+        // Even steps from the bottom edge on a linear scale
+        let linear: Vec<f64> = frequency_labels(FreqScale::Linear, 0.0, 4000.0, 120).into_iter().map(|(f, _)| f).collect();
+        assert_eq!(linear, (0..=8).map(|i| i as f64 * 500.0).collect::<Vec<_>>());
+        // Round values on the other scales, never closer than the spacing
+        let log = frequency_labels(FreqScale::Log, 20.0, 20000.0, 100);
+        assert_eq!((log[0].0, log[0].1), (20.0, 100.0));
+        assert!(log.windows(2).all(|pair| pair[0].1 - pair[1].1 >= LABEL_SPACING));
+        let frequencies: Vec<f64> = log.iter().map(|&(f, _)| f).collect();
+        assert_eq!(frequencies, vec![20.0, 100.0, 1000.0, 10000.0]);
+        assert!(frequency_labels(FreqScale::Mel, 100.0, 100.0, 100).is_empty());
+    }
+
+    #[test]
+    fn waterfall_slices_recede() {
+        assert_eq!(parse_perspective("25%"), Ok(0.25));
+        assert!(parse_perspective("1").is_err());
+
+        // A tone at a quarter of the sample rate, silent in the second half
+        let samples: Vec<f32> = (0..16384).map(|i| if i < 8192 { [0.0, 0.5, 0.0, -0.5][i % 4] } else { 0.0 }).collect();
+        let spectrogram = SpectrogramOptions { slices: 2, perspective: 0.4, max_frequency: Some(12000.0), ..SpectrogramOptions::default() };
+        let options = RenderOptions { width: 40, height: 40, oversample: 1, sample_rate: 44100, normalize: false, spectrogram, ..RenderOptions::new(RenderMode::Waterfall) };
+        let img = render_waveform(&samples, 16384.0 / 40.0, &options);
+        let ink = |x: u32, y: u32| img.get_pixel(x, y)[3] > 0;
+        // The front slice peaks near the right end of its 24 columns, the
+        // silent one behind it is a flat line 16 rows up and 16 columns
+        // right, hidden where the peak rises in front of it
+        assert!(ink(22, 17));
+        assert!(ink(17, 23) && ink(30, 23) && !ink(22, 23));
+        assert!(!ink(30, 39));
+    }
+}
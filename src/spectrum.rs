@@ -0,0 +1,134 @@
+//! Windowed magnitude spectra of audio signals
+use std::f64::consts::PI;
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::fft::{fft, Complex};
+
+
+/// 4-term Blackman-Harris window, low leakage for measurements
+pub fn blackman_harris(n: usize) -> Vec<f64> {
+    let (a0, a1, a2, a3) = (0.35875, 0.48829, 0.14128, 0.01168);
+    (0..n)
+        .map(|i| {
+            let x = 2.0 * PI * i as f64 / (n - 1).max(1) as f64;
+            a0 - a1 * x.cos() + a2 * (2.0 * x).cos() - a3 * (3.0 * x).cos()
+        })
+        .collect()
+}
+
+/// Power spectrum (bins 0..=n/2) of `signal` multiplied with `window`,
+/// where n is the window length (a power of two). Missing samples count as zero
+pub fn power_spectrum(signal: &[f32], window: &[f64]) -> Vec<f64> {
+    let n = window.len();
+    let mut data: Vec<Complex> = (0..n)
+        .map(|i| Complex::new(*signal.get(i).unwrap_or(&0.0) as f64 * window[i], 0.0))
+        .collect();
+    fft(&mut data, false);
+    data[..=n / 2].iter().map(|c| c.re * c.re + c.im * c.im).collect()
+}
+
+/// Hann window
+pub fn hann(n: usize) -> Vec<f64> {
+    (0..n)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / (n - 1).max(1) as f64).cos())
+        .collect()
+}
+
+/// Power spectrum averaged over overlapping frames of `window.len()` samples
+/// (Welch's method)
+pub fn averaged_power_spectrum(signal: &[f32], window: &[f64], hop: usize) -> Vec<f64> {
+    let n = window.len();
+    let mut sum = vec![0.0; n / 2 + 1];
+    let mut frames = 0;
+    let mut start = 0;
+    loop {
+        let power = power_spectrum(&signal[start.min(signal.len())..], window);
+        for (s, p) in sum.iter_mut().zip(power.iter()) {
+            *s += p;
+        }
+        frames += 1;
+        start += hop.max(1);
+        if start + n > signal.len() {
+            break;
+        }
+    }
+    sum.iter().map(|s| s / frames as f64).collect()
+}
+
+/// Smooth a power spectrum over a window of `fraction` octaves around each bin
+pub fn smooth_octaves(power: &[f64], fraction: f64) -> Vec<f64> {
+    // Prefix sums make every average a constant time lookup
+    let mut prefix = vec![0.0; power.len() + 1];
+    for (i, p) in power.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + p;
+    }
+    let factor = 2f64.powf(fraction / 2.0);
+    (0..power.len())
+        .map(|i| {
+            let low = ((i as f64 / factor).floor() as usize).min(i);
+            let high = ((i as f64 * factor).ceil() as usize).max(i).min(power.len() - 1);
+            (prefix[high + 1] - prefix[low]) / (high + 1 - low) as f64
+        })
+        .collect()
+}
+
+/// Plot a power spectrum with a logarithmic frequency axis from `min_frequency`
+/// to `max_frequency` and a dB axis from 0 dB (relative to `reference`) at the
+/// top down to `range` dB at the bottom
+pub fn render_spectrum(power: &[f64], sample_rate: u32, reference: f64, frequencies: (f64, f64), size: (u32, u32), range: f64, colors: (Rgba<u8>, Rgba<u8>)) -> RgbaImage {
+    let (min_frequency, max_frequency) = frequencies;
+    let (width, height) = size;
+    let (foreground, background) = colors;
+    let bin_width = sample_rate as f64 / (2 * (power.len() - 1)) as f64;
+    let ratio = (max_frequency / min_frequency).ln();
+    let frequency = |x: f64| min_frequency * (ratio * x / width as f64).exp();
+
+    let mut img = ImageBuffer::from_pixel(width, height, background);
+    for x in 0..width {
+        // Highest bin within the column, at least the nearest one
+        let start = (frequency(x as f64) / bin_width).round() as usize;
+        let end = ((frequency((x + 1) as f64) / bin_width).round() as usize).max(start + 1).min(power.len());
+        if start >= end {
+            continue;
+        }
+        let value = power[start..end].iter().fold(0.0f64, |a, &b| a.max(b));
+        let db = 10.0 * (value / reference).max(1e-30).log10();
+        let top = ((db / range).clamp(0.0, 1.0) * height as f64).round() as u32;
+        for y in top..height {
+            img.put_pixel(x, y, foreground);
+        }
+    }
+    img
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::spectrum::{averaged_power_spectrum, hann, smooth_octaves};
+
+    #[test]
+    fn noise_has_a_flat_response() {
+        let mut state = 1u32;
+        let noise: Vec<f32> = (0..1 << 16)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        let power = smooth_octaves(&averaged_power_spectrum(&noise, &hann(1024), 512), 1.0 / 3.0);
+        assert_eq!(power.len(), 513);
+        // Away from DC white noise stays within a decibel or two everywhere
+        let db: Vec<f64> = power[20..500].iter().map(|p| 10.0 * p.log10()).collect();
+        let (low, high) = db.iter().fold((f64::MAX, f64::MIN), |(l, h), &d| (l.min(d), h.max(d)));
+        assert!(high - low < 2.0, "{low} to {high}");
+
+        // A single bin spreads over a third of an octave around it
+        let mut spike = vec![0.0; 513];
+        spike[200] = 1.0;
+        let smoothed = smooth_octaves(&spike, 1.0 / 3.0);
+        assert!(smoothed[190] > 0.0 && smoothed[210] > 0.0);
+        assert_eq!((smoothed[170], smoothed[230]), (0.0, 0.0));
+        assert!(smoothed[200] < 0.1);
+    }
+}
@@ -0,0 +1,136 @@
+//! Rendering of very wide images in strips that are written to a PNG row by
+//! row, so memory use does not grow with the width of the image
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use image::imageops;
+
+use crate::metadata::{add_to_png, ImageMetadata};
+use crate::output::{with_scratch_file, write_atomically};
+use crate::render::{prepare, render_columns, RenderOptions, TimeDirection};
+use crate::tiles::Tile;
+
+
+/// Output columns rendered at a time
+const STRIP_WIDTH: u32 = 4096;
+
+/// Multiple of the columns rendered on either side of a strip and cut off
+/// again, so the neighbours of its edge columns are there. A multiple of
+/// the sizes of the dither patterns, which keep their place
+const MARGIN_STEP: u32 = 64;
+
+/// Placement of a rendered strip in the final image
+struct Strip {
+    /// Column of the left edge
+    left: u32,
+    width: u32,
+}
+
+/// Render the interleaved `samples` like `render_waveform` (on a linear time
+/// scale, in the modes rendering in strips) and save the `tiles` of the
+/// image as PNGs, with the `metadata` if given. Strips of the image are kept
+/// in a scratch file until all of them are rendered, then every tile is
+/// encoded one row at a time
+pub fn save_png(samples: &[f32], samples_per_pixel: f64, options: &RenderOptions, tiles: &[Tile], metadata: Option<&ImageMetadata>) -> Result<(), String> {
+    let (width, height) = (options.width, options.height);
+    let (samples, samples_per_pixel, options) = prepare(samples, samples_per_pixel, options);
+    // Normalizing has to consider the whole audio, not only a strip
+    let gain = options.gain(&samples);
+    // Enough for the downscale, the smoothing and the strokes of the line mode
+    let reach = 4.0 + options.smooth_columns.max(0.0) + options.stroke_width.max(0.0);
+    let margin = (reach / MARGIN_STEP as f64).ceil() as u32 * MARGIN_STEP;
+
+    let Some(first) = tiles.first() else {
+        return Ok(());
+    };
+    with_scratch_file(&first.path, |scratch| {
+        let mut file = File::options().read(true).write(true).create(true).truncate(true).open(scratch).map_err(|e| e.to_string())?;
+        // Strips are stored one after another from left to right
+        let mut strips: Vec<(u64, Strip)> = vec![];
+        let mut offset = 0;
+        for left in (0..width).step_by(STRIP_WIDTH as usize) {
+            let right = (left + STRIP_WIDTH).min(width);
+            // Columns of time shown by the strip and those rendered with it
+            let (start, end) = match options.time_direction {
+                TimeDirection::Ltr => (left, right),
+                TimeDirection::Rtl => (width - right, width - left),
+            };
+            let (from, to) = (start.saturating_sub(margin), (end + margin).min(width));
+            let strip_options = RenderOptions { width: to - from, normalize: false, scale_factor: Some(gain), ..options.clone() };
+            let img = render_columns(&samples, samples_per_pixel, &strip_options, from);
+            let skipped = match options.time_direction {
+                TimeDirection::Ltr => start - from,
+                TimeDirection::Rtl => to - end,
+            };
+            let img = imageops::crop_imm(&img, skipped, 0, right - left, height).to_image();
+            file.write_all(img.as_raw()).map_err(|e| e.to_string())?;
+            strips.push((offset, Strip { left, width: img.width() }));
+            offset += img.width() as u64 * height as u64 * 4;
+        }
+
+        for tile in tiles {
+            write_atomically(&tile.path, |temporary| {
+                let writer = BufWriter::new(File::create(temporary).map_err(|e| e.to_string())?);
+                let mut encoder = png::Encoder::new(writer, tile.width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                if let Some(metadata) = metadata {
+                    add_to_png(&mut encoder, metadata)?;
+                }
+                let mut png = encoder.write_header().map_err(|e| e.to_string())?;
+                let mut stream = png.stream_writer().map_err(|e| e.to_string())?;
+
+                let (tile_left, tile_right) = (tile.left, tile.left + tile.width);
+                let mut row = vec![0; tile.width as usize * 4];
+                for y in 0..height {
+                    // Only the part of every strip that falls into the tile
+                    for (offset, strip) in strips.iter().filter(|(_, s)| s.left < tile_right && s.left + s.width > tile_left) {
+                        let (from, to) = (strip.left.max(tile_left), (strip.left + strip.width).min(tile_right));
+                        let position = offset + (y as u64 * strip.width as u64 + (from - strip.left) as u64) * 4;
+                        file.seek(SeekFrom::Start(position)).map_err(|e| e.to_string())?;
+                        let left = (from - tile_left) as usize * 4;
+                        file.read_exact(&mut row[left..(to - tile_left) as usize * 4]).map_err(|e| e.to_string())?;
+                    }
+                    stream.write_all(&row).map_err(|e| e.to_string())?;
+                }
+                stream.finish().map_err(|e| e.to_string())
+            })?;
+        }
+        Ok(())
+    })
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::render::{render_waveform, RenderMode, RenderOptions, TimeDirection};
+    use crate::stream::{save_png, STRIP_WIDTH};
+    use crate::tiles::split;
+
+    #[test]
+    fn strips_match_a_single_render() {
+        let width = STRIP_WIDTH * 2 + 100;
+        let samples: Vec<f32> = (0..width as usize * 5).map(|i| ((i as f32 * 0.01).sin() * (i as f32 * 0.0003).cos()) * 0.5).collect();
+        let path = std::env::temp_dir().join(format!("wellenformer-stream-{}.png", std::process::id()));
+        // Columns of a fractional number of samples, and of less than one,
+        // which are interpolated
+        for (mode, samples_per_pixel, oversample) in [(RenderMode::MinMax, 4.37, 1), (RenderMode::Mean, 1.13, 3), (RenderMode::Line, 0.37, 4), (RenderMode::Asymmetry, 4.37, 1)] {
+            for time_direction in [TimeDirection::Ltr, TimeDirection::Rtl] {
+                let options = RenderOptions { width, normalize: true, oversample, smooth_columns: 3.0, time_direction, ..RenderOptions::new(mode) };
+                let whole = render_waveform(&samples, samples_per_pixel, &options);
+                // In one piece and in tiles that don't line up with the strips
+                for tiles in [split(&path, width, width), split(&path, width, 3000)] {
+                    save_png(&samples, samples_per_pixel, &options, &tiles, None).unwrap();
+                    for tile in &tiles {
+                        let streamed = image::open(&tile.path).unwrap().to_rgba8();
+                        let expected = image::imageops::crop_imm(&whole, tile.left, 0, tile.width, whole.height()).to_image();
+                        assert_eq!(streamed.dimensions(), expected.dimensions());
+                        let differing = streamed.pixels().zip(expected.pixels()).filter(|(a, b)| a != b).count();
+                        assert_eq!(differing, 0, "{mode:?} {time_direction:?}");
+                        let _ = std::fs::remove_file(&tile.path);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Rendering images as text for quick looks in a terminal
+use image::{Rgba, RgbaImage};
+
+
+/// First character of the Unicode Braille patterns block
+const BRAILLE_BASE: u32 = 0x2800;
+
+/// Bit of each dot within a Braille cell, indexed by [row][column]
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Squared distance between two colors
+fn distance(a: Rgba<u8>, b: Rgba<u8>) -> i32 {
+    (0..4).map(|i| (a[i] as i32 - b[i] as i32).pow(2)).sum()
+}
+
+/// Turn `img` into lines of Braille characters, each covering 2×4 pixels.
+/// A dot is raised where the pixel is closer to `foreground` than to
+/// `background`
+pub fn to_braille(img: &RgbaImage, foreground: Rgba<u8>, background: Rgba<u8>) -> String {
+    let (width, height) = img.dimensions();
+    let mut text = String::new();
+    for cell_y in 0..height.div_ceil(4) {
+        for cell_x in 0..width.div_ceil(2) {
+            let mut bits = 0;
+            for (row, dots) in BRAILLE_DOTS.iter().enumerate() {
+                for (column, bit) in dots.iter().enumerate() {
+                    let (x, y) = (cell_x * 2 + column as u32, cell_y * 4 + row as u32);
+                    if x < width && y < height {
+                        let pixel = *img.get_pixel(x, y);
+                        if distance(pixel, foreground) < distance(pixel, background) {
+                            bits |= bit;
+                        }
+                    }
+                }
+            }
+            text.push(char::from_u32(BRAILLE_BASE + bits).unwrap());
+        }
+        text.push('\n');
+    }
+    text
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgba};
+    use crate::terminal::to_braille;
+
+    #[test]
+    fn braille_cells() {
+        let (on, off) = (Rgba([0, 0, 0, 255]), Rgba([0, 0, 0, 0]));
+        // Left column fully set, right column only at the bottom
+        let img = ImageBuffer::from_fn(2, 4, |x, y| if x == 0 || y == 3 { on } else { off });
+        assert_eq!(to_braille(&img, on, off), "⣇\n");
+        let img = ImageBuffer::from_fn(3, 1, |_, _| on);
+        assert_eq!(to_braille(&img, on, off), "⠉⠁\n");
+    }
+}
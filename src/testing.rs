@@ -0,0 +1,15 @@
+//! Helpers shared by the unit tests of the library and of the binary, which
+//! both compile this file
+
+/// Endless deterministic pseudo random numbers (xorshift) starting from
+/// `seed`, for property tests whose failures can be reproduced
+pub fn random(seed: u64) -> impl Iterator<Item = u64> {
+    std::iter::successors(Some(seed), |&state| {
+        let mut state = state;
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        Some(state)
+    })
+    .skip(1)
+}
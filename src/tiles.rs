@@ -0,0 +1,103 @@
+//! Splitting of images that are wider than the output format (or the viewers
+//! opening it) can handle into numbered tiles, listed in a manifest
+use std::path::{Path, PathBuf};
+
+use crate::json::Json;
+use crate::output::OutputFormat;
+
+
+/// Widest JPEG the format can describe
+const JPEG_MAX_WIDTH: u32 = 65535;
+
+/// Widest PNG that common viewers and browsers still open
+const PNG_PRACTICAL_WIDTH: u32 = 32767;
+
+/// A part of the image saved as a file of its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub path: PathBuf,
+    /// Column of the full image at the left edge of the tile
+    pub left: u32,
+    pub width: u32,
+}
+
+/// Width up to which an image in `format` is saved in one piece, lowered to
+/// `requested` if that is given
+pub fn max_width(format: OutputFormat, requested: Option<u32>) -> u32 {
+    let limit = match format {
+        OutputFormat::Jpeg => JPEG_MAX_WIDTH,
+        _ => PNG_PRACTICAL_WIDTH,
+    };
+    requested.map_or(limit, |requested| requested.min(limit))
+}
+
+/// Tiles of at most `max_width` columns covering an image of `width`,
+/// numbered from left to right (e.g. "wave-001.png", "wave-002.png"). An
+/// image that fits is a single tile at `output`
+pub fn split(output: &Path, width: u32, max_width: u32) -> Vec<Tile> {
+    if width <= max_width {
+        return vec![Tile { path: output.to_path_buf(), left: 0, width }];
+    }
+    let count = width.div_ceil(max_width);
+    let digits = count.to_string().len().max(3);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    (0..count)
+        .map(|i| {
+            let name = match output.extension() {
+                Some(ext) => format!("{stem}-{:0digits$}.{}", i + 1, ext.to_string_lossy()),
+                None => format!("{stem}-{:0digits$}", i + 1),
+            };
+            let left = i * max_width;
+            Tile { path: output.with_file_name(name), left, width: max_width.min(width - left) }
+        })
+        .collect()
+}
+
+/// Path of the manifest listing the tiles of `output` ("wave.tiles.json")
+pub fn manifest_path(output: &Path) -> PathBuf {
+    output.with_extension("tiles.json")
+}
+
+/// Manifest of the tiles of an image of `width` × `height`, with the time in
+/// seconds shown by the first and last column of every tile (`column_time`)
+pub fn manifest(tiles: &[Tile], width: u32, height: u32, column_time: impl Fn(u32) -> f64) -> Json {
+    let tiles = tiles.iter()
+        .map(|tile| {
+            let (a, b) = (column_time(tile.left), column_time(tile.left + tile.width - 1));
+            let file = tile.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            Json::object(vec![
+                ("file", Json::from(file)),
+                ("x", Json::from(tile.left as f64)),
+                ("width", Json::from(tile.width as f64)),
+                ("start", Json::from(a.min(b))),
+                ("end", Json::from(a.max(b))),
+            ])
+        })
+        .collect();
+    Json::document("tiles", vec![
+        ("width", Json::from(width as f64)),
+        ("height", Json::from(height as f64)),
+        ("tiles", Json::Array(tiles)),
+    ])
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use crate::output::OutputFormat;
+    use crate::tiles::{manifest_path, max_width, split, Tile};
+
+    #[test]
+    fn wide_images_are_split() {
+        assert_eq!(max_width(OutputFormat::Png, None), 32767);
+        assert_eq!(max_width(OutputFormat::Jpeg, Some(100_000)), 65535);
+        let output = Path::new("out/wave.png");
+        assert_eq!(split(output, 100, 100), vec![Tile { path: output.to_path_buf(), left: 0, width: 100 }]);
+        let tiles = split(output, 250, 100);
+        assert_eq!(tiles.iter().map(|t| (t.left, t.width)).collect::<Vec<_>>(), vec![(0, 100), (100, 100), (200, 50)]);
+        assert_eq!(tiles[2].path, PathBuf::from("out/wave-003.png"));
+        assert_eq!(manifest_path(output), PathBuf::from("out/wave.tiles.json"));
+    }
+}
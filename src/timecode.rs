@@ -0,0 +1,91 @@
+//! SMPTE timecode (HH:MM:SS:FF) for labelling time axes the way broadcast
+//! deliverables are labelled instead of in plain seconds
+use crate::number::parse_number;
+
+
+/// Frame rate and timecode of the first frame of the audio. Fractional rates
+/// like 29.97 count frames at the nominal rate (30) without dropping frame
+/// numbers, so their timecode runs slightly slower than the wall clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timecode {
+    /// Frames per second
+    pub rate: f64,
+    /// Frame number of the start of the audio
+    pub start: u64,
+}
+
+impl Timecode {
+    /// Frames counted per timecode second
+    fn nominal_rate(&self) -> u64 {
+        self.rate.round() as u64
+    }
+
+    /// Timecode of the frame shown `seconds` into the audio, wrapping after
+    /// 24 hours like SMPTE timecode does
+    pub fn format(&self, seconds: f64) -> String {
+        let rate = self.nominal_rate();
+        // The tiny tolerance keeps exact frame boundaries on their frame
+        let frame = self.start + (seconds.max(0.0) * self.rate + 1e-6).floor() as u64;
+        let seconds = frame / rate;
+        format!("{:02}:{:02}:{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60, seconds % 60, frame % rate)
+    }
+}
+
+/// Parse a frame rate with an optional start timecode, e.g. "25fps" or
+/// "29.97fps@01:00:00:00"
+pub fn parse_timecode(argument: &str) -> Result<Timecode, String> {
+    let invalid = || format!("\"{argument}\" is not a valid timecode (expected e.g. \"25fps\" or \"25fps@01:00:00:00\")");
+    let (rate, start) = match argument.trim().split_once('@') {
+        Some((rate, start)) => (rate, Some(start)),
+        None => (argument.trim(), None),
+    };
+    let rate = rate.trim();
+    let rate = rate.strip_suffix("fps").or_else(|| rate.strip_suffix("FPS")).unwrap_or(rate);
+    let rate = parse_number(rate).map_err(|_| invalid())?;
+    if !(1.0..=1000.0).contains(&rate) {
+        return Err(format!("\"{argument}\" has a frame rate outside of 1 to 1000 fps"));
+    }
+    let nominal = rate.round() as u64;
+    let start = match start {
+        None => 0,
+        Some(start) => {
+            let fields: Vec<u64> = start.trim().split([':', ';']).map(|f| f.parse().map_err(|_| invalid())).collect::<Result<_, _>>()?;
+            let [hours, minutes, seconds, frames] = fields[..] else {
+                return Err(invalid());
+            };
+            if hours >= 24 || minutes >= 60 || seconds >= 60 || frames >= nominal {
+                return Err(format!("\"{argument}\" starts at a timecode that does not exist at {rate} fps"));
+            }
+            ((hours * 60 + minutes) * 60 + seconds) * nominal + frames
+        },
+    };
+    Ok(Timecode { rate, start })
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::timecode::{parse_timecode, Timecode};
+
+    #[test]
+    fn frames_and_start() {
+        assert_eq!(parse_timecode("25fps"), Ok(Timecode { rate: 25.0, start: 0 }));
+        assert_eq!(parse_timecode("25"), Ok(Timecode { rate: 25.0, start: 0 }));
+        assert_eq!(parse_timecode("29,97fps@00:00:01:02"), Ok(Timecode { rate: 29.97, start: 32 }));
+        assert!(parse_timecode("0fps").is_err());
+        assert!(parse_timecode("fast").is_err());
+        assert!(parse_timecode("25fps@01:00:00").is_err());
+        assert!(parse_timecode("25fps@01:00:00:25").is_err());
+        assert!(parse_timecode("25fps@24:00:00:00").is_err());
+
+        let timecode = parse_timecode("25fps@01:00:00:00").unwrap();
+        assert_eq!(timecode.format(0.0), "01:00:00:00");
+        assert_eq!(timecode.format(0.04), "01:00:00:01");
+        assert_eq!(timecode.format(61.5), "01:01:01:12");
+        // Wraps around after a day
+        assert_eq!(parse_timecode("25fps@23:59:59:24").unwrap().format(0.04), "00:00:00:00");
+        // Non-drop-frame timecode falls behind the wall clock at 29.97 fps
+        assert_eq!(parse_timecode("29.97fps").unwrap().format(60.0), "00:00:59:28");
+    }
+}
@@ -0,0 +1,99 @@
+//! Song boundaries of radio stream recordings, taken from the stream titles
+//! (ICY or tag revisions) met while decoding and drawn along the time axis
+use image::{Rgba, RgbaImage};
+
+use crate::audio::MetadataEvent;
+use crate::font::{draw_text, GLYPH_HEIGHT};
+
+
+/// Keys (compared case insensitively) of tags that hold the current title
+const TITLE_KEYS: [&str; 5] = ["streamtitle", "icy-title", "title", "tit2", "inam"];
+
+/// Height of the lane holding the titles in pixels
+pub const LANE_HEIGHT: u32 = GLYPH_HEIGHT + 4;
+
+/// Space between a boundary and the start of the title in pixels
+const PADDING: i64 = 2;
+
+/// A title and the frame from which on it is playing
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamTitle {
+    pub frame: usize,
+    pub title: String,
+}
+
+/// Titles in the order they start, leaving out revisions that repeat the
+/// title playing at the time
+pub fn stream_titles(events: &[MetadataEvent]) -> Vec<StreamTitle> {
+    let mut titles: Vec<StreamTitle> = vec![];
+    for event in events {
+        let title = event.tags.iter()
+            .find(|(key, value)| TITLE_KEYS.contains(&&key.to_lowercase()[..]) && !value.trim().is_empty())
+            .map(|(_, value)| value.trim().to_string());
+        match title {
+            Some(title) if titles.last().map(|t| &t.title) != Some(&title) => titles.push(StreamTitle { frame: event.frame, title }),
+            _ => (),
+        }
+    }
+    titles
+}
+
+/// Columns at which every title starts and ends. `column_at` maps a frame
+/// to the column showing it
+pub fn spans(titles: &[StreamTitle], frames: usize, column_at: impl Fn(usize) -> u32) -> Vec<(u32, u32)> {
+    titles.iter().enumerate()
+        .map(|(i, title)| {
+            let end = titles.get(i + 1).map(|next| next.frame).unwrap_or(frames);
+            let (a, b) = (column_at(title.frame), column_at(end));
+            (a.min(b), a.max(b))
+        })
+        .collect()
+}
+
+/// Vertical lines in `color` at the boundaries between songs
+pub fn draw_boundaries(img: &mut RgbaImage, titles: &[StreamTitle], column_at: impl Fn(usize) -> u32, color: Rgba<u8>) {
+    for title in titles.iter().filter(|t| t.frame > 0) {
+        let x = column_at(title.frame).min(img.width().saturating_sub(1));
+        for y in 0..img.height() {
+            img.put_pixel(x, y, color);
+        }
+    }
+}
+
+/// Lane of `width` pixels with every title written from the start of its
+/// span, cut off where the span ends
+pub fn title_lane(width: u32, titles: &[StreamTitle], spans: &[(u32, u32)], color: Rgba<u8>, background: Rgba<u8>) -> RgbaImage {
+    let mut lane = RgbaImage::from_pixel(width, LANE_HEIGHT, background);
+    for (title, &(start, end)) in titles.iter().zip(spans) {
+        draw_text(&mut lane, start as i64 + PADDING, 2, &title.title, color, end as i64 - PADDING);
+    }
+    lane
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::MetadataEvent;
+    use crate::titles::{spans, stream_titles, StreamTitle};
+
+    #[test]
+    fn titles_from_revisions() {
+        let event = |frame: usize, key: &str, value: &str| MetadataEvent { frame, tags: vec![(key.to_string(), value.to_string())] };
+        let events = [
+            event(0, "StreamTitle", "Intro"),
+            event(100, "Artist", "Nobody"),
+            event(200, "StreamTitle", "Intro"),
+            event(300, "TITLE", "Song - Band"),
+            event(400, "StreamTitle", " "),
+        ];
+        let titles = stream_titles(&events);
+        assert_eq!(titles, vec![
+            StreamTitle { frame: 0, title: "Intro".to_string() },
+            StreamTitle { frame: 300, title: "Song - Band".to_string() },
+        ]);
+        // Ten frames per column, mirrored for right to left time
+        assert_eq!(spans(&titles, 1000, |frame| (frame / 10) as u32), vec![(0, 30), (30, 100)]);
+        assert_eq!(spans(&titles, 1000, |frame| 100 - (frame / 10) as u32), vec![(70, 100), (0, 70)]);
+    }
+}
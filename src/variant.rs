@@ -0,0 +1,70 @@
+//! Small reproducible variations of the style picked from a seed, so images
+//! generated in bulk (e.g. episode cards) look individually distinct
+use image::Rgba;
+
+use crate::color::rotate_hue;
+
+
+/// Largest hue rotation in degrees in either direction
+const MAX_HUE_SHIFT: f64 = 12.0;
+
+/// Stylistic parameters derived from a seed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variant {
+    /// Rotation of the hue of the drawn colors in degrees
+    pub hue_shift: f64,
+    /// Offset of the bars against the left edge as a share of a bar and its gap
+    pub bar_phase: f64,
+    /// Start angle of the radial mode in degrees
+    pub start_angle: f64,
+}
+
+impl Variant {
+    /// Variant for `seed`, always the same for the same seed
+    pub fn from_seed(seed: u64) -> Variant {
+        // SplitMix64, one output per parameter
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            (z ^ (z >> 31)) as f64 / u64::MAX as f64
+        };
+        Variant {
+            hue_shift: (next() * 2.0 - 1.0) * MAX_HUE_SHIFT,
+            bar_phase: next(),
+            start_angle: next() * 360.0,
+        }
+    }
+
+    /// `color` with the hue shift applied
+    pub fn color(&self, color: Rgba<u8>) -> Rgba<u8> {
+        rotate_hue(color, self.hue_shift)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use crate::color::rotate_hue;
+    use crate::variant::{Variant, MAX_HUE_SHIFT};
+
+    #[test]
+    fn seeds_are_reproducible() {
+        assert_eq!(Variant::from_seed(7), Variant::from_seed(7));
+        let variants: Vec<Variant> = (0..50).map(Variant::from_seed).collect();
+        assert!(variants.windows(2).all(|w| w[0] != w[1]));
+        assert!(variants.iter().all(|v| v.hue_shift.abs() <= MAX_HUE_SHIFT && (0.0..=1.0).contains(&v.bar_phase)));
+
+        // Grays keep their color, hues turn but stay close
+        let gray = Rgba([90, 90, 90, 255]);
+        assert_eq!(rotate_hue(gray, 10.0), gray);
+        let orange = Rgba([240, 140, 20, 200]);
+        assert_eq!(rotate_hue(orange, 0.0), orange);
+        let turned = rotate_hue(orange, 10.0);
+        assert!(turned != orange && turned[3] == 200 && (0..3).all(|i| (turned[i] as i32 - orange[i] as i32).abs() < 40));
+    }
+}
@@ -0,0 +1,324 @@
+//! Waveform outlines as vector paths in physical units for plotters, laser
+//! cutters and 3D printers
+use std::fmt::Write;
+use clap::ValueEnum;
+use image::Rgba;
+
+use crate::number::parse_number;
+use crate::render::{column_extremes, time_column, TimeAxis, TimeDirection};
+
+
+/// Thickness in mm the outline keeps where the audio is silent, so the shape
+/// stays in one piece
+const MIN_THICKNESS: f64 = 0.5;
+
+/// Parse a length in mm, cm or inches ("200mm", "20cm", "8in"), where bare
+/// numbers are taken as mm
+pub fn parse_length(argument: &str) -> Result<f64, String> {
+    let trimmed = argument.trim().to_lowercase();
+    let (number, factor) = if let Some(n) = trimmed.strip_suffix("mm") {
+        (n, 1.0)
+    } else if let Some(n) = trimmed.strip_suffix("cm") {
+        (n, 10.0)
+    } else if let Some(n) = trimmed.strip_suffix("in") {
+        (n, 25.4)
+    } else {
+        (&trimmed[..], 1.0)
+    };
+    match parse_number(number) {
+        Ok(v) if v >= 0.0 => Ok(v * factor),
+        _ => Err(format!("\"{argument}\" is not a valid length (expected e.g. \"200mm\", \"20cm\" or \"8in\")")),
+    }
+}
+
+/// Closed outline of the mirrored waveform in mm, with y growing downwards.
+/// The first half of the points runs along the upper edge, the second half
+/// mirrors it back along the lower edge
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub width: f64,
+    pub height: f64,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Outline of the mirrored peak envelope of `samples` along `axis`, scaled
+/// to `width` × `height` mm and simplified so that no point deviates more
+/// than `tolerance` mm from the exact shape
+pub fn outline(samples: &[f32], axis: &TimeAxis, gain: f64, direction: TimeDirection, size: (f64, f64), tolerance: f64) -> Outline {
+    let (width, height) = size;
+    let center = height / 2.0;
+    let extremes = column_extremes(samples, axis);
+    let column_width = width / axis.columns as f64;
+
+    let top: Vec<(f64, f64)> = (0..axis.columns)
+        .map(|x| {
+            let (min, max) = extremes[time_column(x, axis.columns, direction) as usize].unwrap_or((0.0, 0.0));
+            let amplitude = (min.abs().max(max.abs()) as f64 * gain).min(1.0);
+            let half = (amplitude * center).max(MIN_THICKNESS / 2.0).min(center);
+            ((x as f64 + 0.5) * column_width, center - half)
+        })
+        .collect();
+    let top = simplify(&top, tolerance);
+
+    // The lower edge mirrors the upper one and runs back to the start
+    let mut points = top.clone();
+    points.extend(top.iter().rev().map(|&(x, y)| (x, height - y)));
+    Outline { width, height, points }
+}
+
+/// Ramer–Douglas–Peucker simplification of an open polyline
+pub fn simplify(points: &[(f64, f64)], tolerance: f64) -> Vec<(f64, f64)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let (ax, ay) = points[first];
+        let (bx, by) = points[last];
+        let length = (bx - ax).hypot(by - ay);
+        let mut farthest = (first, 0.0);
+        for (i, &(px, py)) in points.iter().enumerate().take(last).skip(first + 1) {
+            let distance = if length > 0.0 {
+                ((bx - ax) * (ay - py) - (ax - px) * (by - ay)).abs() / length
+            } else {
+                (px - ax).hypot(py - ay)
+            };
+            if distance > farthest.1 {
+                farthest = (i, distance);
+            }
+        }
+        if farthest.1 > tolerance {
+            keep[farthest.0] = true;
+            stack.push((first, farthest.0));
+            stack.push((farthest.0, last));
+        }
+    }
+    points.iter().zip(keep).filter(|(_, k)| *k).map(|(p, _)| *p).collect()
+}
+
+/// How the area of the outline is filled in SVG outputs
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SvgStyle {
+    /// One filled path
+    Solid,
+    /// The outline plus evenly spaced parallel lines, for pen plotters
+    Hatch,
+}
+
+/// Parallel lines filling the outline
+#[derive(Debug, Clone, Copy)]
+pub struct Hatch {
+    /// Distance between neighbouring lines in mm
+    pub spacing: f64,
+    /// Angle of the lines in degrees, counterclockwise from horizontal
+    pub angle: f64,
+}
+
+/// Line segments of `hatch` that lie inside the outline. Every other line
+/// runs backwards so a plotter does not have to travel back for each line
+pub fn hatch_lines(outline: &Outline, hatch: Hatch) -> Vec<[(f64, f64); 2]> {
+    // Rotate the outline so the hatch lines become horizontal scanlines
+    let (sin, cos) = (-hatch.angle.to_radians()).sin_cos();
+    let rotate = |(x, y): (f64, f64), sin: f64| (x * cos + y * sin, y * cos - x * sin);
+    let points: Vec<(f64, f64)> = outline.points.iter().map(|&p| rotate(p, sin)).collect();
+    let (top, bottom) = points.iter().fold((f64::MAX, f64::MIN), |(t, b), p| (t.min(p.1), b.max(p.1)));
+
+    let mut lines = vec![];
+    let spacing = hatch.spacing.max(0.01);
+    let mut y = top + spacing / 2.0;
+    while y < bottom {
+        let mut crossings: Vec<f64> = points.iter()
+            .zip(points.iter().cycle().skip(1))
+            .filter(|(a, b)| (a.1 <= y) != (b.1 <= y))
+            .map(|(a, b)| a.0 + (y - a.1) / (b.1 - a.1) * (b.0 - a.0))
+            .collect();
+        crossings.sort_by(|a, b| a.total_cmp(b));
+        if lines.len() % 2 == 1 {
+            crossings.reverse();
+        }
+        for pair in crossings.chunks_exact(2) {
+            // Rotating by the opposite angle brings the segment back
+            lines.push([rotate((pair[0], y), -sin), rotate((pair[1], y), -sin)]);
+        }
+        y += spacing;
+    }
+    lines
+}
+
+/// SVG document with the outline as a filled path or, with `hatch`, as
+/// strokes only, sized in mm
+pub fn to_svg(outline: &Outline, color: Rgba<u8>, hatch: Option<Hatch>) -> String {
+    let [r, g, b, a] = color.0;
+    let mut path = String::new();
+    for (i, (x, y)) in outline.points.iter().enumerate() {
+        let command = if i == 0 { 'M' } else { 'L' };
+        write!(path, "{command}{x:.4},{y:.4} ").unwrap();
+    }
+    path.push('Z');
+    let color = format!("#{r:02x}{g:02x}{b:02x}");
+    let opacity = a as f64 / 255.0;
+    let body = match hatch {
+        None => format!("<path d=\"{path}\" fill=\"{color}\" fill-opacity=\"{opacity:.3}\" stroke=\"none\"/>\n"),
+        Some(hatch) => {
+            let mut lines = String::new();
+            for [(x0, y0), (x1, y1)] in hatch_lines(outline, hatch) {
+                write!(lines, "M{x0:.4},{y0:.4} L{x1:.4},{y1:.4} ").unwrap();
+            }
+            let stroke = format!("fill=\"none\" stroke=\"{color}\" stroke-opacity=\"{opacity:.3}\" stroke-width=\"0.3\" stroke-linecap=\"round\"");
+            format!("<path d=\"{path}\" {stroke}/>\n<path d=\"{}\" {stroke}/>\n", lines.trim_end())
+        },
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n\
+         {body}\
+         </svg>\n",
+        w = outline.width,
+        h = outline.height,
+    )
+}
+
+/// DXF (R12) drawing with the outline as a closed polyline in mm
+pub fn to_dxf(outline: &Outline) -> String {
+    let mut dxf = String::from("0\nSECTION\n2\nHEADER\n9\n$INSUNITS\n70\n4\n0\nENDSEC\n");
+    dxf.push_str("0\nSECTION\n2\nENTITIES\n0\nPOLYLINE\n8\n0\n66\n1\n70\n1\n10\n0.0\n20\n0.0\n30\n0.0\n");
+    for (x, y) in &outline.points {
+        // The y axis of DXF points upwards
+        write!(dxf, "0\nVERTEX\n8\n0\n10\n{x:.4}\n20\n{:.4}\n30\n0.0\n", outline.height - y).unwrap();
+    }
+    dxf.push_str("0\nSEQEND\n8\n0\n0\nENDSEC\n0\nEOF\n");
+    dxf
+}
+
+/// Outline points in mm as CSV, with the origin in the lower left corner
+pub fn to_csv(outline: &Outline) -> String {
+    let mut csv = String::from("x_mm,y_mm\n");
+    for (x, y) in &outline.points {
+        writeln!(csv, "{x:.4},{:.4}", outline.height - y).unwrap();
+    }
+    csv
+}
+
+/// Height in mm above the work piece for moves between cuts
+const SAFE_HEIGHT: f64 = 5.0;
+
+/// G-code tracing the outline once at `cut_depth` mm below the surface with
+/// `feed_rate` mm/min, with the origin in the lower left corner
+pub fn to_gcode(outline: &Outline, cut_depth: f64, feed_rate: f64) -> String {
+    let y = |y: f64| outline.height - y;
+    let mut gcode = String::from("; waveform outline by wellenformer\nG21\nG90\n");
+    writeln!(gcode, "G0 Z{SAFE_HEIGHT:.3}").unwrap();
+    if let Some(&(x0, y0)) = outline.points.first() {
+        writeln!(gcode, "G0 X{x0:.4} Y{:.4}", y(y0)).unwrap();
+        writeln!(gcode, "G1 Z{:.4} F{:.0}", -cut_depth, feed_rate / 2.0).unwrap();
+        for (i, &(x, py)) in outline.points.iter().chain(std::iter::once(&(x0, y0))).enumerate().skip(1) {
+            let feed = if i == 1 { format!(" F{feed_rate:.0}") } else { String::new() };
+            writeln!(gcode, "G1 X{x:.4} Y{:.4}{feed}", y(py)).unwrap();
+        }
+        writeln!(gcode, "G0 Z{SAFE_HEIGHT:.3}").unwrap();
+    }
+    gcode.push_str("M2\n");
+    gcode
+}
+
+/// Binary STL mesh of the outline extruded by `depth` mm
+pub fn to_stl(outline: &Outline, depth: f64) -> Vec<u8> {
+    // Points with the y axis pointing upwards at the bottom (z = 0) and the
+    // top (z = depth) of the extrusion
+    let point = |i: usize, z: f64| {
+        let (x, y) = outline.points[i % outline.points.len()];
+        [x, outline.height - y, z]
+    };
+    let count = outline.points.len();
+    let mut triangles = vec![];
+
+    // The outline runs clockwise, so the outward side is left of every edge
+    for i in 0..count {
+        let (a0, b0, a1, b1) = (point(i, 0.0), point(i + 1, 0.0), point(i, depth), point(i + 1, depth));
+        triangles.push([a0, b1, b0]);
+        triangles.push([a0, a1, b1]);
+    }
+    // Both caps are strips between an upper point and its mirror image below
+    let half = count / 2;
+    for i in 0..half.saturating_sub(1) {
+        let (top, next_top) = (i, i + 1);
+        let (bottom, next_bottom) = (count - 1 - i, count - 2 - i);
+        let z = depth;
+        triangles.push([point(bottom, z), point(next_bottom, z), point(next_top, z)]);
+        triangles.push([point(bottom, z), point(next_top, z), point(top, z)]);
+        triangles.push([point(bottom, 0.0), point(next_top, 0.0), point(next_bottom, 0.0)]);
+        triangles.push([point(bottom, 0.0), point(top, 0.0), point(next_top, 0.0)]);
+    }
+
+    let mut stl = vec![0u8; 80];
+    stl[..22].copy_from_slice(b"wellenformer waveform ");
+    stl.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for [a, b, c] in triangles {
+        let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+        let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt().max(f64::MIN_POSITIVE);
+        for value in normal.iter().map(|n| n / length).chain(a).chain(b).chain(c) {
+            stl.extend_from_slice(&(value as f32).to_le_bytes());
+        }
+        stl.extend_from_slice(&[0, 0]);
+    }
+    stl
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::vector::{hatch_lines, parse_length, simplify, to_csv, to_gcode, to_stl, Hatch, Outline};
+
+    #[test]
+    fn lengths_and_simplification() {
+        assert_eq!(parse_length("200"), Ok(200.0));
+        assert_eq!(parse_length("2cm"), Ok(20.0));
+        assert_eq!(parse_length("1in"), Ok(25.4));
+        assert!(parse_length("-3mm").is_err());
+
+        // Points on a straight line collapse, the corner stays
+        let points = [(0.0, 0.0), (1.0, 0.01), (2.0, 0.0), (3.0, 5.0), (4.0, 10.0)];
+        assert_eq!(simplify(&points, 0.1), vec![(0.0, 0.0), (2.0, 0.0), (4.0, 10.0)]);
+    }
+
+    #[test]
+    fn extruded_rectangle() {
+        let outline = Outline { width: 2.0, height: 1.0, points: vec![(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (0.0, 1.0)] };
+        let stl = to_stl(&outline, 5.0);
+        // Four sides and one quad per cap, two triangles each
+        assert_eq!(u32::from_le_bytes(stl[80..84].try_into().unwrap()), 12);
+        assert_eq!(stl.len(), 84 + 12 * 50);
+    }
+
+    #[test]
+    fn points_and_toolpath() {
+        let outline = Outline { width: 2.0, height: 1.0, points: vec![(0.0, 0.0), (2.0, 0.0), (1.0, 1.0)] };
+        // The origin is in the lower left corner
+        assert_eq!(to_csv(&outline), "x_mm,y_mm\n0.0000,1.0000\n2.0000,1.0000\n1.0000,0.0000\n");
+        // Plunge at half the feed rate, trace the closed outline and lift
+        assert_eq!(to_gcode(&outline, 0.5, 600.0), "; waveform outline by wellenformer\nG21\nG90\nG0 Z5.000\n\
+            G0 X0.0000 Y1.0000\nG1 Z-0.5000 F300\nG1 X2.0000 Y1.0000 F600\nG1 X1.0000 Y0.0000\nG1 X0.0000 Y1.0000\nG0 Z5.000\nM2\n");
+        let empty = Outline { points: vec![], ..outline };
+        assert_eq!(to_gcode(&empty, 0.5, 600.0), "; waveform outline by wellenformer\nG21\nG90\nG0 Z5.000\nM2\n");
+    }
+
+    #[test]
+    fn hatching_stays_inside() {
+        let outline = Outline { width: 10.0, height: 2.0, points: vec![(0.0, 0.0), (10.0, 0.0), (10.0, 2.0), (0.0, 2.0)] };
+        let lines = hatch_lines(&outline, Hatch { spacing: 0.5, angle: 0.0 });
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], [(0.0, 0.25), (10.0, 0.25)]);
+        // The next line runs back
+        assert_eq!(lines[1], [(10.0, 0.75), (0.0, 0.75)]);
+        for [a, b] in hatch_lines(&outline, Hatch { spacing: 0.5, angle: 45.0 }) {
+            for (x, y) in [a, b] {
+                assert!((-1e-9..=10.0 + 1e-9).contains(&x) && (-1e-9..=2.0 + 1e-9).contains(&y));
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+
+/// Write `values` as a mono 32-bit float WAV file with the given sample rate.
+pub fn write_mono_f32(path: &Path, sample_rate: u32, values: &[f32]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let data_size = (values.len() * 4) as u32;
+
+    // RIFF header
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_size).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    // Format chunk: IEEE float (3), one channel, 32 bits per sample
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&3u16.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * 4).to_le_bytes())?;
+    w.write_all(&4u16.to_le_bytes())?;
+    w.write_all(&32u16.to_le_bytes())?;
+
+    // Data chunk
+    w.write_all(b"data")?;
+    w.write_all(&data_size.to_le_bytes())?;
+    for value in values {
+        w.write_all(&value.to_le_bytes())?;
+    }
+    w.flush()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::decode_file;
+    use crate::wav::write_mono_f32;
+
+    #[test]
+    fn float_wav_round_trips() {
+        let values = [0.0, 0.25, -0.5, 1.0, -1.0, 0.125];
+        let path = std::env::temp_dir().join(format!("wellenformer-envelope-{}.wav", std::process::id()));
+        write_mono_f32(&path, 441, &values).unwrap();
+        let audio = decode_file(&path, &mut |_| ()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((audio.channels, audio.sample_rate), (1, 441));
+        assert_eq!(audio.samples, values);
+    }
+}
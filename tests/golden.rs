@@ -0,0 +1,211 @@
+//! Golden image tests: renders synthetic fixtures with a matrix of options
+//! through the binary and compares the results to the reference images in
+//! tests/goldens with a small perceptual tolerance.
+//!
+//! After an intended change of the rendering, update the references with
+//!
+//!     cargo test --test golden -- --update-goldens
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use image::RgbaImage;
+
+
+const SAMPLE_RATE: u32 = 8000;
+
+/// Largest mean difference in perceived brightness (0-255) between a render
+/// and its reference
+const MAX_MEAN_DIFFERENCE: f64 = 1.0;
+
+/// Largest share of pixels whose perceived brightness differs noticeably
+const MAX_CHANGED_PIXELS: f64 = 0.005;
+
+/// Brightness difference from which a pixel counts as changed
+const CHANGED_THRESHOLD: f64 = 24.0;
+
+/// Name, fixture and extra arguments of every case
+const CASES: &[(&str, &str, &[&str])] = &[
+    ("minmax", "bursts", &[]),
+    ("minmax-normalized", "sweep", &["--normalize"]),
+    ("minmax-rtl", "bursts", &["--time-direction", "rtl"]),
+    ("minmax-rms", "bursts", &["--rms-color", "rgb(120, 120, 255)"]),
+    ("minmax-rms-alpha", "sweep", &["--rms-alpha", "--normalize"]),
+    ("minmax-peak-hold", "bursts", &["--peak-hold", "250ms", "--peak-hold-color", "200,0,0,255"]),
+    ("minmax-pitch", "sweep", &["--overlay", "pitch", "--pitch-color", "0,120,255,255"]),
+    ("minmax-onsets", "bursts", &["--overlay", "onsets", "--onset-sensitivity", "70%"]),
+    ("minmax-rectified", "sweep", &["--rectify"]),
+    ("minmax-analytic", "bursts", &["--rasterizer", "analytic", "--rms-color", "rgb(120, 120, 255)"]),
+    ("minmax-baseline", "bursts", &["--baseline", "70%"]),
+    ("minmax-smoothed", "bursts", &["--smooth-columns", "12"]),
+    ("line-zoomed-in", "sweep", &["--mode", "line", "--bucket-duration", "0.05ms"]),
+    ("minmax-zoomed-in-hold", "sweep", &["--bucket-duration", "0.05ms", "--interpolation", "hold"]),
+    ("mean-smoothed-average", "bursts", &["--mode", "mean", "--smooth-columns", "6", "--smooth-kernel", "average"]),
+    ("mean", "bursts", &["--mode", "mean"]),
+    ("mean-normalized", "sweep", &["--mode", "mean", "--normalize"]),
+    ("mean-rtl", "bursts", &["--mode", "mean", "--time-direction", "rtl"]),
+    ("mean-log", "bursts", &["--mode", "mean", "--time-scale", "log"]),
+    ("mean-full-detail", "sweep", &["--mode", "mean", "--detail", "full"]),
+    ("mean-centered", "bursts", &["--mode", "mean", "--layout", "centered", "--baseline", "40%"]),
+    ("asymmetry", "bursts", &["--mode", "asymmetry"]),
+    ("line", "sweep", &["--mode", "line", "--stroke-width", "2"]),
+    ("dots", "bursts", &["--mode", "dots", "--dot-radius", "2"]),
+    ("bars", "bursts", &["--mode", "bars", "--bar-width", "6", "--bar-gap", "3"]),
+    ("bars-triangle", "bursts", &["--mode", "bars", "--bar-width", "6", "--bar-gap", "3", "--filter", "triangle"]),
+    ("envelope", "bursts", &["--mode", "envelope", "--smooth", "50"]),
+    ("density", "bursts", &["--mode", "density"]),
+    ("multiband", "sweep", &["--mode", "multiband", "--crossovers", "200,1000"]),
+    ("vectorscope", "stereo", &["--mode", "vectorscope"]),
+    ("radial", "bursts", &["--mode", "radial", "--inner-radius", "30%", "--start-angle", "-90"]),
+    ("spectrogram", "sweep", &["--mode", "spectrogram", "--background", "0,0,0,255", "--foreground", "255,255,255,255"]),
+    ("spectrogram-kaiser", "sweep", &["--mode", "spectrogram", "--window", "kaiser:12", "--fmax", "2000"]),
+    ("spectrogram-mel", "sweep", &["--mode", "spectrogram", "--freq-scale", "mel", "--mel-bands", "40"]),
+    ("spectrogram-log", "sweep", &["--mode", "spectrogram", "--freq-scale", "log"]),
+    ("waterfall", "sweep", &["--mode", "waterfall", "--slices", "12", "--perspective", "40%", "--fmax", "4000"]),
+    ("chroma", "sweep", &["--mode", "chroma"]),
+    ("filmstrip", "sweep", &["--mode", "filmstrip", "--filmstrip-rows", "4"]),
+    ("loudness", "bursts", &["--mode", "loudness", "--target-lufs", "-20", "--stroke-width", "2"]),
+    ("minmax-over-spectrogram", "sweep", &["--over-spectrogram", "70%", "--background", "black", "--foreground", "white"]),
+    ("spectrogram-dither", "sweep", &["--mode", "spectrogram", "--dither", "blue-noise", "--background", "black", "--foreground", "#203040"]),
+    ("crest", "bursts", &["--mode", "mean", "--crest"]),
+    ("segments", "bursts", &["--mode", "mean", "--segments"]),
+];
+
+/// Tone bursts of rising level separated by silence
+fn bursts() -> Vec<f32> {
+    (0..2 * SAMPLE_RATE as usize)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE as f64;
+            let burst = (t * 4.0) as usize;
+            if (t * 4.0).fract() > 0.6 {
+                0.0
+            } else {
+                let level = (burst + 1) as f64 / 8.0;
+                (level * (2.0 * std::f64::consts::PI * 440.0 * t).sin()) as f32
+            }
+        })
+        .collect()
+}
+
+/// Logarithmic sweep from 50 Hz to 3.5 kHz
+fn sweep() -> Vec<f32> {
+    let (f0, f1, duration) = (50.0f64, 3500.0f64, 2.0);
+    let k = (f1 / f0).ln();
+    (0..(duration * SAMPLE_RATE as f64) as usize)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE as f64;
+            let phase = 2.0 * std::f64::consts::PI * f0 * duration / k * ((t / duration * k).exp() - 1.0);
+            (0.5 * phase.sin()) as f32
+        })
+        .collect()
+}
+
+/// Tone whose right channel drifts from in phase to out of phase with the
+/// left one, as interleaved stereo
+fn stereo() -> Vec<f32> {
+    (0..2 * SAMPLE_RATE as usize)
+        .flat_map(|i| {
+            let t = i as f64 / SAMPLE_RATE as f64;
+            let phase = 2.0 * std::f64::consts::PI * 440.0 * t;
+            let drift = std::f64::consts::PI * t / 2.0;
+            [(0.6 * phase.sin()) as f32, (0.6 * (phase + drift).sin()) as f32]
+        })
+        .collect()
+}
+
+/// Write 16 bit PCM with `channels` interleaved channels
+fn write_wav(path: &Path, channels: u16, samples: &[f32]) {
+    let data_length = samples.len() as u32 * 2;
+    let mut wav = vec![];
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2 * channels as u32).to_le_bytes());
+    wav.extend_from_slice(&(2 * channels).to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_length.to_le_bytes());
+    for s in samples {
+        wav.extend_from_slice(&((s.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes());
+    }
+    std::fs::write(path, wav).unwrap();
+}
+
+/// Brightness of every pixel composited onto white
+fn brightness(img: &RgbaImage) -> Vec<f64> {
+    img.pixels()
+        .map(|p| {
+            let alpha = p[3] as f64 / 255.0;
+            let luma = 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64;
+            luma * alpha + 255.0 * (1.0 - alpha)
+        })
+        .collect()
+}
+
+/// Describe how `actual` differs from `expected` if it is beyond tolerance
+fn compare(actual: &RgbaImage, expected: &RgbaImage) -> Result<(), String> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(format!("size is {:?} instead of {:?}", actual.dimensions(), expected.dimensions()));
+    }
+    let differences: Vec<f64> = brightness(actual).iter()
+        .zip(brightness(expected))
+        .map(|(a, b)| (a - b).abs())
+        .collect();
+    let mean = differences.iter().sum::<f64>() / differences.len() as f64;
+    let changed = differences.iter().filter(|&&d| d > CHANGED_THRESHOLD).count() as f64 / differences.len() as f64;
+    if mean > MAX_MEAN_DIFFERENCE || changed > MAX_CHANGED_PIXELS {
+        return Err(format!("mean difference {mean:.2}, {:.2}% of the pixels changed", changed * 100.0));
+    }
+    Ok(())
+}
+
+fn main() {
+    let update = std::env::args().any(|a| a == "--update-goldens");
+    let goldens = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("goldens");
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&scratch).unwrap();
+    write_wav(&scratch.join("bursts.wav"), 1, &bursts());
+    write_wav(&scratch.join("sweep.wav"), 1, &sweep());
+    write_wav(&scratch.join("stereo.wav"), 2, &stereo());
+
+    let mut failures = vec![];
+    for (name, fixture, extra) in CASES {
+        let output = scratch.join(format!("{name}.png"));
+        let status = Command::new(env!("CARGO_BIN_EXE_wellenformer"))
+            .arg("-i").arg(scratch.join(format!("{fixture}.wav")))
+            .arg("-o").arg(&output)
+            .args(["-y", "--oversample", "4", "--width", "400", "--height", "80"])
+            .args(*extra)
+            .output()
+            .unwrap();
+        if !status.status.success() {
+            failures.push(format!("{name}: rendering failed: {}", String::from_utf8_lossy(&status.stderr)));
+            continue;
+        }
+        let actual = image::open(&output).unwrap().to_rgba8();
+        let golden = goldens.join(format!("{name}.png"));
+        if update {
+            std::fs::create_dir_all(&goldens).unwrap();
+            actual.save(&golden).unwrap();
+            println!("updated {}", golden.display());
+            continue;
+        }
+        let result = match image::open(&golden) {
+            Ok(expected) => compare(&actual, &expected.to_rgba8()),
+            Err(_) => Err("no reference image, run with --update-goldens".to_string()),
+        };
+        match result {
+            Ok(()) => println!("golden {name} ... ok"),
+            Err(e) => failures.push(format!("{name}: {e} (see {})", output.display())),
+        }
+    }
+
+    if !failures.is_empty() {
+        for failure in &failures {
+            eprintln!("golden {failure}");
+        }
+        std::process::exit(1);
+    }
+}